@@ -0,0 +1,16 @@
+use primitives::AuthorityId;
+use sp_core::H256;
+use substrate_api_client::ApiResult;
+
+use crate::AnyConnection;
+
+/// Returns the emergency finalizer set in the state of the given block (or the best block if
+/// `block_hash` is `None`).
+pub fn get_emergency_finalizer<C: AnyConnection>(
+    connection: &C,
+    block_hash: Option<H256>,
+) -> ApiResult<Option<AuthorityId>> {
+    connection
+        .as_connection()
+        .get_storage_value("Aleph", "EmergencyFinalizer", block_hash)
+}
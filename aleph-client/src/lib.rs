@@ -2,6 +2,7 @@ use std::{default::Default, thread::sleep, time::Duration};
 
 use ac_primitives::SubstrateDefaultSignedExtra;
 pub use account::{get_free_balance, locks};
+pub use aleph::get_emergency_finalizer;
 pub use balances::total_issuance;
 use codec::{Decode, Encode};
 pub use debug::print_storages;
@@ -11,7 +12,7 @@ pub use multisig::{
     compute_call_hash, perform_multisig_with_threshold_1, MultisigError, MultisigParty,
     SignatureAggregation,
 };
-pub use rpc::{rotate_keys, rotate_keys_raw_result, state_query_storage_at};
+pub use rpc::{emergency_finalize, rotate_keys, rotate_keys_raw_result, state_query_storage_at};
 pub use session::{
     change_next_era_reserved_validators, change_validators, get_current_session, get_session,
     get_session_period, set_keys, wait_for as wait_for_session,
@@ -46,9 +47,12 @@ pub use vesting::{
     get_schedules, merge_schedules, vest, vest_other, vested_transfer, VestingError,
     VestingSchedule,
 };
-pub use waiting::{wait_for_event, wait_for_finalized_block};
+pub use waiting::{
+    wait_for_event, wait_for_finalized_block, wait_for_finalized_block_with_timeout,
+};
 
 mod account;
+mod aleph;
 mod balances;
 mod debug;
 mod fee;
@@ -1,8 +1,9 @@
+use primitives::AuthoritySignature;
 use serde_json::{json, Value};
 use sp_core::storage::{StorageChangeSet, StorageData};
 use substrate_api_client::StorageKey;
 
-use crate::{AnyConnection, SessionKeys, H256};
+use crate::{AnyConnection, BlockNumber, SessionKeys, H256};
 
 fn json_req(method: &str, params: Value, id: u32) -> Value {
     json!({
@@ -17,6 +18,19 @@ pub fn author_rotate_keys_json() -> Value {
     json_req("author_rotateKeys", Value::Null, 1)
 }
 
+fn emergency_finalize_json(
+    signature: &AuthoritySignature,
+    hash: H256,
+    number: BlockNumber,
+) -> Value {
+    let signature: &[u8] = signature.as_ref();
+    json_req(
+        "alephNode_emergencyFinalize",
+        json!([signature, hash, number]),
+        1,
+    )
+}
+
 fn state_query_storage_at_json(storage_keys: &[StorageKey]) -> Value {
     json_req(
         "state_queryStorageAt",
@@ -85,6 +99,26 @@ pub fn state_query_storage_at<C: AnyConnection>(
     }
 }
 
+/// Submits an emergency justification for the block with the given hash and number. The signature
+/// has to be made by the emergency finalizer over the encoded block hash.
+pub fn emergency_finalize<C: AnyConnection>(
+    connection: &C,
+    number: BlockNumber,
+    hash: H256,
+    signature: &AuthoritySignature,
+) -> Result<(), String> {
+    connection
+        .as_connection()
+        .get_request(emergency_finalize_json(signature, hash, number))
+        .map(|_| ())
+        .map_err(|e| {
+            format!(
+                "Emergency finalization of block #{} failed: {:?}",
+                number, e
+            )
+        })
+}
+
 pub fn rotate_keys_base<C: AnyConnection, F, R>(
     connection: &C,
     rpc_result_mapper: F,
@@ -141,6 +175,23 @@ mod tests {
         assert_eq!(expected_json, state_query_storage_at_json(&storage_keys));
     }
 
+    #[test]
+    fn given_some_input_when_emergency_finalize_json_then_json_is_as_expected() {
+        let signature = AuthoritySignature::from(sp_core::ed25519::Signature::from_raw([7; 64]));
+        let hash = H256::repeat_byte(1);
+        let expected_json = json!({
+            "id": "1",
+            "jsonrpc": "2.0",
+            "method": "alephNode_emergencyFinalize",
+            "params": [
+                vec![7u8; 64],
+                "0x0101010101010101010101010101010101010101010101010101010101010101",
+                13
+            ]
+        });
+        assert_eq!(expected_json, emergency_finalize_json(&signature, hash, 13));
+    }
+
     #[test]
     fn given_expected_input_when_parse_query_storage_at_result_then_json_is_as_expected() {
         let expected_json_string = r#"
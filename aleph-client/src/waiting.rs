@@ -1,4 +1,7 @@
-use std::sync::mpsc::channel;
+use std::{
+    sync::mpsc::{channel, RecvTimeoutError},
+    time::{Duration, Instant},
+};
 
 use anyhow::{anyhow, Result as AnyResult};
 use codec::Decode;
@@ -54,3 +57,39 @@ pub fn wait_for_finalized_block<C: AnyConnection>(
 
     Err(anyhow!("Waiting for finalization is no longer possible"))
 }
+
+/// Like `wait_for_finalized_block`, but gives up once `timeout` passes without the block being
+/// finalized.
+pub fn wait_for_finalized_block_with_timeout<C: AnyConnection>(
+    connection: &C,
+    block_number: u32,
+    timeout: Duration,
+) -> AnyResult<u32> {
+    let deadline = Instant::now() + timeout;
+    let (sender, receiver) = channel();
+    connection
+        .as_connection()
+        .subscribe_finalized_heads(sender)?;
+
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let header = match receiver.recv_timeout(remaining) {
+            Ok(header) => serde_json::from_str::<Header>(&header)?,
+            Err(RecvTimeoutError::Timeout) => {
+                return Err(anyhow!(
+                    "Block #{} was not finalized within {:?}",
+                    block_number,
+                    timeout
+                ))
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(anyhow!("Waiting for finalization is no longer possible"))
+            }
+        };
+        info!(target: "aleph-client", "Received header for a block number {:?}", header.number);
+
+        if header.number.ge(&block_number) {
+            return Ok(block_number);
+        }
+    }
+}
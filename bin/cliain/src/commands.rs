@@ -116,6 +116,30 @@ pub enum Command {
     /// Force new era in staking world. Requires sudo.
     ForceNewEra,
 
+    /// Finalize the block with the given number using the emergency finalizer key of its session.
+    /// The key is taken either from a seed or from a local keystore.
+    Finalize {
+        /// Number of the block to finalize
+        #[clap(long)]
+        block: BlockNumber,
+
+        /// Seed of the emergency finalizer key
+        #[clap(
+            long,
+            conflicts_with = "keystore-path",
+            required_unless_present = "keystore-path"
+        )]
+        finalizer_seed: Option<String>,
+
+        /// Path to the local keystore containing the emergency finalizer key
+        #[clap(long, parse(from_os_str))]
+        keystore_path: Option<PathBuf>,
+
+        /// How many seconds to wait for the block to be finalized before giving up
+        #[clap(long, default_value = "60")]
+        timeout: u64,
+    },
+
     /// Declare the desire to nominate target account
     Nominate {
         #[clap(long)]
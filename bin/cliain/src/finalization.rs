@@ -0,0 +1,113 @@
+use std::{fs, path::PathBuf, time::Duration};
+
+use aleph_client::{
+    emergency_finalize, get_emergency_finalizer, wait_for_finalized_block_with_timeout,
+    AnyConnection, BlockNumber,
+};
+use anyhow::{anyhow, Result};
+use codec::Encode;
+use log::info;
+use primitives::{AuthorityId, AuthorityPair, KEY_TYPE};
+use sp_core::{hexdisplay::HexDisplay, Pair, H256};
+
+/// Where to take the emergency finalizer key from.
+pub enum FinalizerKey {
+    /// Secret URI of the key, e.g. `//Alice`.
+    Seed(String),
+    /// Path to a local keystore holding the key.
+    Keystore(PathBuf),
+}
+
+impl FinalizerKey {
+    fn key_pair(self, expected: &AuthorityId) -> Result<AuthorityPair> {
+        match self {
+            FinalizerKey::Seed(seed) => key_pair_from_seed(&seed, expected),
+            FinalizerKey::Keystore(path) => {
+                // A local keystore keeps every key in a file named after its key type and public
+                // key, holding the secret URI as a JSON string.
+                let file = path.join(format!(
+                    "{}{}",
+                    HexDisplay::from(&KEY_TYPE.0),
+                    HexDisplay::from(&expected.as_ref())
+                ));
+                let content = fs::read_to_string(&file).map_err(|e| {
+                    anyhow!(
+                        "Keystore at {:?} does not contain the emergency finalizer key {}: {}",
+                        path,
+                        expected,
+                        e
+                    )
+                })?;
+                let seed = serde_json::from_str::<String>(&content)
+                    .map_err(|e| anyhow!("Failed to read keystore file {:?}: {}", file, e))?;
+                key_pair_from_seed(&seed, expected)
+            }
+        }
+    }
+}
+
+fn key_pair_from_seed(seed: &str, expected: &AuthorityId) -> Result<AuthorityPair> {
+    let key_pair = AuthorityPair::from_string(seed, None)
+        .map_err(|e| anyhow!("Invalid emergency finalizer seed: {:?}", e))?;
+    match &key_pair.public() == expected {
+        true => Ok(key_pair),
+        false => Err(anyhow!(
+            "Provided key {} is not the emergency finalizer {}",
+            key_pair.public(),
+            expected
+        )),
+    }
+}
+
+fn block_hash<C: AnyConnection>(connection: &C, number: BlockNumber) -> Result<H256> {
+    connection
+        .as_connection()
+        .get_block_hash(Some(number))
+        .map_err(|e| anyhow!("Failed to obtain hash of block #{}: {:?}", number, e))?
+        .ok_or_else(|| anyhow!("Block #{} does not exist", number))
+}
+
+/// Finalizes the block with the given number using a locally held emergency finalizer key.
+///
+/// The key has to match the emergency finalizer of the session the block belongs to. Returns
+/// after the node reports the block as finalized, or fails if that does not happen within
+/// `timeout`.
+pub fn finalize<C: AnyConnection>(
+    connection: C,
+    number: BlockNumber,
+    key: FinalizerKey,
+    timeout: Duration,
+) -> Result<()> {
+    let hash = block_hash(&connection, number)?;
+    let session_period: u32 = connection
+        .as_connection()
+        .get_constant("Elections", "SessionPeriod")
+        .map_err(|e| anyhow!("Failed to read the session period: {:?}", e))?;
+    let session = number / session_period;
+    // The finalizer of a session is the one set in the state of its first block.
+    let session_start = block_hash(&connection, session * session_period)?;
+    let emergency_finalizer = get_emergency_finalizer(&connection, Some(session_start))
+        .map_err(|e| anyhow!("Failed to read the emergency finalizer: {:?}", e))?
+        .ok_or_else(|| anyhow!("Session {} has no emergency finalizer", session))?;
+
+    let key_pair = key.key_pair(&emergency_finalizer)?;
+    let signature = key_pair.sign(&hash.encode());
+
+    info!(
+        "Submitting emergency justification for block #{} {:?}",
+        number, hash
+    );
+    emergency_finalize(&connection, number, hash, &signature).map_err(|e| anyhow!(e))?;
+    wait_for_finalized_block_with_timeout(&connection, number, timeout)?;
+
+    match block_hash(&connection, number)? == hash {
+        true => {
+            info!("Block #{} {:?} finalized", number, hash);
+            Ok(())
+        }
+        false => Err(anyhow!(
+            "A different block was finalized at height #{}",
+            number
+        )),
+    }
+}
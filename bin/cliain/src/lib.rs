@@ -1,5 +1,6 @@
 mod commands;
 mod contracts;
+mod finalization;
 mod keys;
 mod runtime;
 mod secret;
@@ -9,9 +10,12 @@ mod treasury;
 mod validators;
 mod vesting;
 
-use aleph_client::{keypair_from_string, RootConnection, SignedConnection};
+use aleph_client::{
+    create_connection, keypair_from_string, Connection, RootConnection, SignedConnection,
+};
 pub use commands::Command;
 pub use contracts::{call, instantiate, instantiate_with_code, remove_code, upload_code};
+pub use finalization::{finalize, FinalizerKey};
 pub use keys::{prepare_keys, rotate_keys, set_keys};
 pub use runtime::update_runtime;
 pub use secret::prompt_password_hidden;
@@ -44,6 +48,12 @@ impl From<ConnectionConfig> for SignedConnection {
     }
 }
 
+impl From<ConnectionConfig> for Connection {
+    fn from(cfg: ConnectionConfig) -> Self {
+        create_connection(cfg.node_endpoint.as_str())
+    }
+}
+
 impl From<ConnectionConfig> for RootConnection {
     fn from(cfg: ConnectionConfig) -> Self {
         RootConnection::from(Into::<SignedConnection>::into(cfg))
@@ -1,12 +1,13 @@
-use std::env;
+use std::{env, time::Duration};
 
-use aleph_client::{keypair_from_string, print_storages, SignedConnection};
+use aleph_client::{keypair_from_string, print_storages, Connection, SignedConnection};
 use clap::Parser;
 use cliain::{
-    bond, call, change_validators, force_new_era, instantiate, instantiate_with_code, nominate,
-    prepare_keys, prompt_password_hidden, remove_code, rotate_keys, set_keys, set_staking_limits,
-    transfer, treasury_approve, treasury_propose, treasury_reject, update_runtime, upload_code,
-    validate, vest, vest_other, vested_transfer, Command, ConnectionConfig,
+    bond, call, change_validators, finalize, force_new_era, instantiate, instantiate_with_code,
+    nominate, prepare_keys, prompt_password_hidden, remove_code, rotate_keys, set_keys,
+    set_staking_limits, transfer, treasury_approve, treasury_propose, treasury_reject,
+    update_runtime, upload_code, validate, vest, vest_other, vested_transfer, Command,
+    ConnectionConfig, FinalizerKey,
 };
 use log::{error, info};
 use sp_core::Pair;
@@ -90,6 +91,23 @@ fn main() {
         Command::ForceNewEra => {
             force_new_era(cfg.into());
         }
+        Command::Finalize {
+            block,
+            finalizer_seed,
+            keystore_path,
+            timeout,
+        } => {
+            let key = match (finalizer_seed, keystore_path) {
+                (Some(seed), _) => FinalizerKey::Seed(seed),
+                (None, Some(path)) => FinalizerKey::Keystore(path),
+                (None, None) => unreachable!("clap requires one of the key sources"),
+            };
+            if let Err(why) =
+                finalize::<Connection>(cfg.into(), block, key, Duration::from_secs(timeout))
+            {
+                error!("Emergency finalization failed {:?}", why);
+            }
+        }
         Command::SeedToSS58 => info!(
             "SS58 Address: {}",
             keypair_from_string(&seed).public().to_string()
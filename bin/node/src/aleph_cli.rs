@@ -1,8 +1,33 @@
-use std::path::PathBuf;
+use std::{fmt::Display, net::SocketAddr, path::PathBuf, str::FromStr, sync::Arc, time::Duration};
 
 use aleph_primitives::DEFAULT_UNIT_CREATION_DELAY;
-use clap::{ArgGroup, Parser};
-use finality_aleph::UnitCreationDelay;
+use clap::{ArgEnum, ArgGroup, Parser};
+use finality_aleph::{
+    AppendOnlyBackupStore, BackupStore, ChainTrackerConfig, DataStoreConfig, DirectoryBackupStore,
+    NoBackupStore, UnitCreationDelay, UnitCreationDelayBounds, ValidatorNetworkConfig,
+    DEFAULT_COMPRESSION_THRESHOLD,
+};
+
+fn parse_positive<T>(value: &str) -> Result<T, String>
+where
+    T: FromStr + Default + PartialOrd,
+    T::Err: Display,
+{
+    match value.parse::<T>() {
+        Ok(value) if value > T::default() => Ok(value),
+        Ok(_) => Err(String::from("the value must be positive")),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// How backups are laid out under the backup path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+pub enum BackupLayout {
+    /// A directory per session, with a separate file for every run of the node.
+    Directory,
+    /// A single append-only file of checksummed records per session.
+    SingleFile,
+}
 
 #[derive(Debug, Parser, Clone)]
 #[clap(group(ArgGroup::new("backup").required(true)))]
@@ -10,6 +35,19 @@ pub struct AlephCli {
     #[clap(long)]
     unit_creation_delay: Option<u64>,
 
+    /// Adapt the unit creation delay every session to the observed round latency and the size of
+    /// the committee, starting from `--unit-creation-delay`.
+    #[clap(long)]
+    adaptive_unit_creation_delay: bool,
+
+    /// The lowest unit creation delay in milliseconds chosen in the adaptive mode.
+    #[clap(long, value_name = "MILLIS", default_value_t = 200)]
+    min_unit_creation_delay: u64,
+
+    /// The highest unit creation delay in milliseconds chosen in the adaptive mode.
+    #[clap(long, value_name = "MILLIS", default_value_t = 1000)]
+    max_unit_creation_delay: u64,
+
     /// Turn off backups, at the cost of limiting crash recoverability.
     ///
     /// If backups are turned off and the node crashes, it most likely will not be able to continue
@@ -24,9 +62,99 @@ pub struct AlephCli {
     /// with `--no-backup`, but note that that limits crash recoverability.
     #[clap(long, value_name = "PATH", group = "backup")]
     backup_path: Option<PathBuf>,
+
+    /// The layout of the backups saved under the backup path.
+    #[clap(long, arg_enum, default_value = "directory")]
+    backup_layout: BackupLayout,
+
+    /// How many sessions before the session of the last finalized block keep their backups.
+    ///
+    /// Backups of older sessions, including ones left behind by crashes, are removed at startup
+    /// and after every session. By default only the backups of the session of the last finalized
+    /// block and later ones are kept.
+    #[clap(long, value_name = "SESSIONS", default_value_t = 0)]
+    backup_retention: u32,
+
+    /// Keep only the justifications of the last blocks of sessions.
+    ///
+    /// Justifications of the remaining blocks are removed once the last block of their session is
+    /// finalized, which significantly reduces the size of the database. Such a node can still
+    /// serve justification sync requests. Requires a RocksDb database. Justifications stored
+    /// before this flag was set can be removed with the `prune-justifications` command.
+    #[clap(long)]
+    prune_justifications: bool,
+
+    /// How many blocks the finalized head may lag behind the best head before the node reports
+    /// itself as unhealthy through the `alephNode_health` RPC.
+    #[clap(long, value_name = "BLOCKS", default_value_t = 20)]
+    max_finality_lag: u32,
+
+    /// Maximum number of proposals waiting in the data store for their blocks or justifications.
+    ///
+    /// Finality parameters governed on chain take precedence over this value.
+    #[clap(long, value_name = "COUNT", parse(try_from_str = parse_positive))]
+    max_proposals_pending: Option<usize>,
+
+    /// Maximum number of AlephBFT messages waiting in the data store for the data they contain.
+    ///
+    /// Finality parameters governed on chain take precedence over this value.
+    #[clap(long, value_name = "COUNT", parse(try_from_str = parse_positive))]
+    max_messages_pending: Option<usize>,
+
+    /// How many proposals already known to be available the data store remembers.
+    #[clap(long, value_name = "COUNT", parse(try_from_str = parse_positive))]
+    available_proposals_cache_capacity: Option<usize>,
+
+    /// How often in milliseconds the data store prunes pending messages and requests missing
+    /// blocks.
+    #[clap(long, value_name = "MILLIS", parse(try_from_str = parse_positive))]
+    data_store_maintenance_interval: Option<u64>,
+
+    /// How long in milliseconds the data store waits for the blocks of a proposal before
+    /// requesting them, or their justifications, from the network.
+    #[clap(long, value_name = "MILLIS", parse(try_from_str = parse_positive))]
+    request_block_after: Option<u64>,
+
+    /// How often in milliseconds the best block is checked to update the proposed data.
+    #[clap(long, value_name = "MILLIS", parse(try_from_str = parse_positive))]
+    chain_tracker_refresh_interval: Option<u64>,
+
+    /// Compress AlephBFT messages encoded to at least this many bytes, for peers that accept it.
+    #[clap(long, value_name = "BYTES", parse(try_from_str = parse_positive))]
+    compression_threshold: Option<usize>,
+
+    /// Never compress AlephBFT messages. Compressed messages from other nodes are still accepted.
+    #[clap(long, conflicts_with = "compression-threshold")]
+    no_compression: bool,
+
+    /// Do not use the validator addresses registered on chain, nor register our own, relying only
+    /// on gossip to find the committee.
+    #[clap(long)]
+    no_address_registry: bool,
+
+    /// Connect to other validators directly over TCP, listening on this address, instead of using
+    /// the validator protocol of the p2p network. Relies on the address registry to find them.
+    #[clap(long, value_name = "ADDRESS")]
+    validator_network_address: Option<SocketAddr>,
+
+    /// The address other validators should use to connect to our validator network, as
+    /// `host:port`. Can be given multiple times, defaults to the listening address.
+    #[clap(long, value_name = "ADDRESS", requires = "validator-network-address")]
+    validator_network_external_address: Vec<String>,
 }
 
 impl AlephCli {
+    /// Checks the constraints between flags that clap cannot express.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.min_unit_creation_delay > self.max_unit_creation_delay {
+            return Err(format!(
+                "--min-unit-creation-delay ({}) must not be greater than --max-unit-creation-delay ({})",
+                self.min_unit_creation_delay, self.max_unit_creation_delay
+            ));
+        }
+        Ok(())
+    }
+
     pub fn unit_creation_delay(&self) -> UnitCreationDelay {
         UnitCreationDelay(
             self.unit_creation_delay
@@ -34,7 +162,94 @@ impl AlephCli {
         )
     }
 
+    pub fn unit_creation_delay_bounds(&self) -> Option<UnitCreationDelayBounds> {
+        match self.adaptive_unit_creation_delay {
+            true => Some(UnitCreationDelayBounds {
+                min: UnitCreationDelay(self.min_unit_creation_delay),
+                max: UnitCreationDelay(self.max_unit_creation_delay),
+            }),
+            false => None,
+        }
+    }
+
     pub fn backup_path(&self) -> Option<PathBuf> {
         self.backup_path.clone()
     }
+
+    pub fn backup_store(&self) -> Arc<dyn BackupStore> {
+        match (self.backup_path(), self.backup_layout) {
+            (None, _) => Arc::new(NoBackupStore),
+            (Some(path), BackupLayout::Directory) => Arc::new(DirectoryBackupStore::new(path)),
+            (Some(path), BackupLayout::SingleFile) => Arc::new(AppendOnlyBackupStore::new(path)),
+        }
+    }
+
+    pub fn backup_retention(&self) -> u32 {
+        self.backup_retention
+    }
+
+    pub fn prune_justifications(&self) -> bool {
+        self.prune_justifications
+    }
+
+    pub fn max_finality_lag(&self) -> u32 {
+        self.max_finality_lag
+    }
+
+    pub fn data_store_config(&self) -> DataStoreConfig {
+        let default = DataStoreConfig::default();
+        DataStoreConfig {
+            max_proposals_pending: self
+                .max_proposals_pending
+                .unwrap_or(default.max_proposals_pending),
+            max_messages_pending: self
+                .max_messages_pending
+                .unwrap_or(default.max_messages_pending),
+            available_proposals_cache_capacity: self
+                .available_proposals_cache_capacity
+                .unwrap_or(default.available_proposals_cache_capacity),
+            periodic_maintenance_interval: self
+                .data_store_maintenance_interval
+                .map(Duration::from_millis)
+                .unwrap_or(default.periodic_maintenance_interval),
+            request_block_after: self
+                .request_block_after
+                .map(Duration::from_millis)
+                .unwrap_or(default.request_block_after),
+            ..default
+        }
+    }
+
+    pub fn chain_tracker_config(&self) -> ChainTrackerConfig {
+        let default = ChainTrackerConfig::default();
+        ChainTrackerConfig {
+            refresh_interval: self
+                .chain_tracker_refresh_interval
+                .map(Duration::from_millis)
+                .unwrap_or(default.refresh_interval),
+            ..default
+        }
+    }
+
+    pub fn compression_threshold(&self) -> Option<usize> {
+        match self.no_compression {
+            true => None,
+            false => Some(
+                self.compression_threshold
+                    .unwrap_or(DEFAULT_COMPRESSION_THRESHOLD),
+            ),
+        }
+    }
+
+    pub fn use_address_registry(&self) -> bool {
+        !self.no_address_registry
+    }
+
+    pub fn validator_network(&self) -> Option<ValidatorNetworkConfig> {
+        self.validator_network_address
+            .map(|listen_address| ValidatorNetworkConfig {
+                listen_address,
+                external_addresses: self.validator_network_external_address.clone(),
+            })
+    }
 }
@@ -1,10 +1,16 @@
-use futures::channel::mpsc;
+use std::{collections::HashSet, sync::Arc};
+
+use aleph_primitives::{AuthorityId, SessionAuthorityData, KEY_TYPE};
+use futures::{channel::mpsc, FutureExt, StreamExt};
 use jsonrpsee::{
-    core::{error::Error as JsonRpseeError, RpcResult},
+    core::{async_trait, error::Error as JsonRpseeError, RpcResult},
     proc_macros::rpc,
     types::error::{CallError, ErrorObject},
+    SubscriptionSink,
 };
-use serde::Serialize;
+use sc_rpc::SubscriptionTaskExecutor;
+use serde::{Deserialize, Serialize};
+use sp_core::Bytes;
 
 /// System RPC errors.
 #[derive(Debug, thiserror::Error)]
@@ -15,6 +21,18 @@ pub enum Error {
     /// Provided block range couldn't be resolved to a list of blocks.
     #[error("Node is not fully functional: {}", .0)]
     FailedJustificationSend(String),
+    /// Stored justification couldn't be decoded.
+    #[error("Failed to decode stored justification: {}", .0)]
+    MalformattedStoredJustification(String),
+    /// Failed to read from the database.
+    #[error("Failed to read from the database: {}", .0)]
+    Blockchain(String),
+    /// Authority data of the session is not known to this node.
+    #[error("Authority data of session {} is not available", .0)]
+    UnknownSessionAuthorities(u32),
+    /// Failed to read keys from the keystore.
+    #[error("Failed to read from the keystore: {}", .0)]
+    Keystore(String),
 }
 
 // Base code for all system errors.
@@ -23,25 +41,151 @@ const BASE_ERROR: i32 = 2000;
 const MALFORMATTED_JUSTIFICATION_ARG_ERROR: i32 = BASE_ERROR + 1;
 // AlephNodeApiServer is failed to send JustificationNotification.
 const FAILED_JUSTIFICATION_SEND_ERROR: i32 = BASE_ERROR + 2;
+// Stored justification couldn't be decoded.
+const MALFORMATTED_STORED_JUSTIFICATION_ERROR: i32 = BASE_ERROR + 3;
+// Reading from the database failed.
+const BLOCKCHAIN_ERROR: i32 = BASE_ERROR + 4;
+// Authority data of the session is not known.
+const UNKNOWN_SESSION_AUTHORITIES_ERROR: i32 = BASE_ERROR + 5;
+// Reading from the keystore failed.
+const KEYSTORE_ERROR: i32 = BASE_ERROR + 6;
 
 impl From<Error> for JsonRpseeError {
     fn from(e: Error) -> Self {
-        match e {
-            Error::FailedJustificationSend(e) => CallError::Custom(ErrorObject::owned(
-                FAILED_JUSTIFICATION_SEND_ERROR,
-                e,
-                None::<()>,
-            )),
-            Error::MalformattedJustificationArg(e) => CallError::Custom(ErrorObject::owned(
-                MALFORMATTED_JUSTIFICATION_ARG_ERROR,
-                e,
-                None::<()>,
-            )),
+        let code = match e {
+            Error::FailedJustificationSend(_) => FAILED_JUSTIFICATION_SEND_ERROR,
+            Error::MalformattedJustificationArg(_) => MALFORMATTED_JUSTIFICATION_ARG_ERROR,
+            Error::MalformattedStoredJustification(_) => MALFORMATTED_STORED_JUSTIFICATION_ERROR,
+            Error::Blockchain(_) => BLOCKCHAIN_ERROR,
+            Error::UnknownSessionAuthorities(_) => UNKNOWN_SESSION_AUTHORITIES_ERROR,
+            Error::Keystore(_) => KEYSTORE_ERROR,
+        };
+        let message = match e {
+            Error::FailedJustificationSend(e) | Error::MalformattedJustificationArg(e) => e,
+            e => e.to_string(),
+        };
+        CallError::Custom(ErrorObject::owned(code, message, None::<()>)).into()
+    }
+}
+
+/// The session the best block belongs to, together with its boundaries.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionInfo<Number> {
+    pub session_id: u32,
+    pub first_block: Number,
+    pub last_block: Number,
+}
+
+/// Authorities of a session, in the order of their node indices.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionAuthorities {
+    pub authorities: Vec<AuthorityId>,
+    pub emergency_finalizer: Option<AuthorityId>,
+}
+
+/// A decoded justification, without the signatures themselves.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum JustificationInfo {
+    /// Signed by the committee, the signers are given by their node indices and as a bitmap.
+    #[serde(rename_all = "camelCase")]
+    CommitteeMultisignature {
+        signers: Vec<usize>,
+        signer_bitmap: Bytes,
+    },
+    /// Signed by the committee using aggregated signatures, the signers are given by their node
+    /// indices and as a bitmap.
+    #[serde(rename_all = "camelCase")]
+    AggregatedMultisignature {
+        signers: Vec<usize>,
+        signer_bitmap: Bytes,
+    },
+    /// Signed by the emergency finalizer.
+    EmergencySignature,
+}
+
+/// Bit `i % 8` of byte `i / 8` is set iff the node with index `i` signed.
+fn signer_bitmap(signers: &[usize]) -> Bytes {
+    let mut bitmap = vec![0u8; signers.iter().max().map_or(0, |max| max / 8 + 1)];
+    for signer in signers {
+        bitmap[signer / 8] |= 1 << (signer % 8);
+    }
+    bitmap.into()
+}
+
+impl From<&AlephJustification> for JustificationInfo {
+    fn from(justification: &AlephJustification) -> Self {
+        use AlephJustification::*;
+        match justification {
+            CommitteeMultisignature(signature_set) => {
+                let signers: Vec<_> = signature_set.iter().map(|(index, _)| index.0).collect();
+                JustificationInfo::CommitteeMultisignature {
+                    signer_bitmap: signer_bitmap(&signers),
+                    signers,
+                }
+            }
+            AggregatedMultisignature(signature) => {
+                let signers: Vec<_> = signature.signers().map(|index| index.0).collect();
+                JustificationInfo::AggregatedMultisignature {
+                    signer_bitmap: signer_bitmap(&signers),
+                    signers,
+                }
+            }
+            EmergencySignature(_) => JustificationInfo::EmergencySignature,
         }
-        .into()
     }
 }
 
+/// A block finalized by the finality gadget, together with its justification.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FinalizedBlock<Hash, Number> {
+    pub hash: Hash,
+    pub number: Number,
+    pub justification: JustificationInfo,
+}
+
+/// A reason for the node to consider itself unhealthy.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum UnhealthyReason<Number> {
+    /// The finalized head lags behind the best head by more than allowed.
+    #[serde(rename_all = "camelCase")]
+    FinalizationLag {
+        best_number: Number,
+        finalized_number: Number,
+        max_lag: u32,
+    },
+    /// We are a member of the committee, but have open validator connections with too few other
+    /// members to reach a quorum.
+    #[serde(rename_all = "camelCase")]
+    TooFewConnections {
+        session_id: u32,
+        connected: usize,
+        required: usize,
+    },
+    /// Authority data of the current session is not known to the finality gadget.
+    #[serde(rename_all = "camelCase")]
+    MissingSessionAuthorities { session_id: u32 },
+}
+
+/// The health of the node with respect to finality, healthy iff there are no reasons otherwise.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Health<Number> {
+    pub healthy: bool,
+    pub reasons: Vec<UnhealthyReason<Number>>,
+}
+
+/// How many other committee members we need to be connected to, so that together with us they
+/// form a quorum of more than two thirds of the committee.
+fn required_connections(committee_size: usize) -> usize {
+    let quorum = committee_size - committee_size.saturating_sub(1) / 3;
+    quorum.saturating_sub(1)
+}
+
 /// Aleph Node RPC API
 #[rpc(client, server)]
 pub trait AlephNodeApi<Hash, Number> {
@@ -53,42 +197,134 @@ pub trait AlephNodeApi<Hash, Number> {
         hash: Hash,
         number: Number,
     ) -> RpcResult<()>;
+
+    /// Returns the session of the best block and its boundaries.
+    #[method(name = "alephNode_currentSession")]
+    fn aleph_node_current_session(&self) -> RpcResult<SessionInfo<Number>>;
+
+    /// Returns the authorities and the emergency finalizer of the given session. Only sessions
+    /// close to the current one are known.
+    #[method(name = "alephNode_sessionAuthorities")]
+    async fn aleph_node_session_authorities(
+        &self,
+        session_id: u32,
+    ) -> RpcResult<SessionAuthorities>;
+
+    /// Returns the decoded justification of the block with the given hash, if it is stored.
+    #[method(name = "alephNode_justification")]
+    fn aleph_node_justification(&self, hash: Hash) -> RpcResult<Option<JustificationInfo>>;
+
+    /// Returns whether this node is a member of the committee of the current session.
+    #[method(name = "alephNode_isCommitteeMember")]
+    async fn aleph_node_is_committee_member(&self) -> RpcResult<bool>;
+
+    /// Returns whether the node is healthy with respect to finality, and if not, why. Suitable
+    /// for readiness and liveness probes. A committee member is only healthy when it is actually
+    /// connected to enough other members, knowing their addresses is not enough.
+    #[method(name = "alephNode_health")]
+    async fn aleph_node_health(&self) -> RpcResult<Health<Number>>;
+
+    /// Subscribes to the blocks finalized by the finality gadget, both the ones finalized by the
+    /// committee of this node and the ones with justifications received from other nodes.
+    #[subscription(
+        name = "alephNode_subscribeFinalizedBlocks" => "alephNode_finalizedBlock",
+        unsubscribe = "alephNode_unsubscribeFinalizedBlocks",
+        item = FinalizedBlock<Hash, Number>,
+    )]
+    fn aleph_node_subscribe_finalized_blocks(&self);
 }
 
-use finality_aleph::{AlephJustification, JustificationNotification};
+use aleph_primitives::ALEPH_ENGINE_ID;
+use finality_aleph::{
+    backwards_compatible_decode, session_id_from_block_num, AlephJustification,
+    FinalizedBlockStream, JustificationNotification, ReadOnlySessionMap, SessionBoundaries,
+    SessionId, SessionPeriod, ValidatorConnections,
+};
+use sc_client_api::{BlockBackend, HeaderBackend};
 use sp_api::BlockT;
-use sp_runtime::traits::NumberFor;
+use sp_keystore::CryptoStore;
+use sp_runtime::{
+    generic::BlockId,
+    traits::{NumberFor, Saturating},
+};
 
 /// Aleph Node API implementation
-pub struct AlephNode<B>
+pub struct AlephNode<B, C>
 where
     B: BlockT,
     B::Hash: Serialize + for<'de> serde::Deserialize<'de>,
     NumberFor<B>: Serialize + for<'de> serde::Deserialize<'de>,
+    C: HeaderBackend<B> + BlockBackend<B> + Send + Sync + 'static,
 {
     import_justification_tx: mpsc::UnboundedSender<JustificationNotification<B>>,
+    client: Arc<C>,
+    session_map: ReadOnlySessionMap,
+    keystore: Arc<dyn CryptoStore>,
+    session_period: SessionPeriod,
+    finalized_block_stream: FinalizedBlockStream<B>,
+    executor: SubscriptionTaskExecutor,
+    validator_connections: ValidatorConnections,
+    max_finality_lag: u32,
 }
 
-impl<B> AlephNode<B>
+impl<B, C> AlephNode<B, C>
 where
     B: BlockT,
     B::Hash: Serialize + for<'de> serde::Deserialize<'de>,
     NumberFor<B>: Serialize + for<'de> serde::Deserialize<'de>,
+    C: HeaderBackend<B> + BlockBackend<B> + Send + Sync + 'static,
 {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         import_justification_tx: mpsc::UnboundedSender<JustificationNotification<B>>,
+        client: Arc<C>,
+        session_map: ReadOnlySessionMap,
+        keystore: Arc<dyn CryptoStore>,
+        session_period: SessionPeriod,
+        finalized_block_stream: FinalizedBlockStream<B>,
+        executor: SubscriptionTaskExecutor,
+        validator_connections: ValidatorConnections,
+        max_finality_lag: u32,
     ) -> Self {
         AlephNode {
             import_justification_tx,
+            client,
+            session_map,
+            keystore,
+            session_period,
+            finalized_block_stream,
+            executor,
+            validator_connections,
+            max_finality_lag,
         }
     }
+
+    fn current_session(&self) -> SessionId {
+        session_id_from_block_num::<B>(self.client.info().best_number, self.session_period)
+    }
+
+    async fn is_committee_member(&self, authority_data: &SessionAuthorityData) -> RpcResult<bool> {
+        let our_keys: HashSet<_> = self
+            .keystore
+            .keys(KEY_TYPE)
+            .await
+            .map_err(|e| Error::Keystore(e.to_string()))?
+            .into_iter()
+            .collect();
+        Ok(authority_data
+            .authorities()
+            .iter()
+            .any(|authority| our_keys.contains(&authority.into())))
+    }
 }
 
-impl<B> AlephNodeApiServer<B::Hash, NumberFor<B>> for AlephNode<B>
+#[async_trait]
+impl<B, C> AlephNodeApiServer<B::Hash, NumberFor<B>> for AlephNode<B, C>
 where
     B: BlockT,
     B::Hash: Serialize + for<'de> serde::Deserialize<'de>,
     NumberFor<B>: Serialize + for<'de> serde::Deserialize<'de>,
+    C: HeaderBackend<B> + BlockBackend<B> + Send + Sync + 'static,
 {
     fn aleph_node_emergency_finalize(
         &self,
@@ -116,4 +352,105 @@ where
                 .into()
             })
     }
+
+    fn aleph_node_current_session(&self) -> RpcResult<SessionInfo<NumberFor<B>>> {
+        let session_id = self.current_session();
+        let boundaries = SessionBoundaries::<B>::new(session_id, self.session_period);
+        Ok(SessionInfo {
+            session_id: session_id.0,
+            first_block: boundaries.first_block(),
+            last_block: boundaries.last_block(),
+        })
+    }
+
+    async fn aleph_node_session_authorities(
+        &self,
+        session_id: u32,
+    ) -> RpcResult<SessionAuthorities> {
+        let authority_data = self
+            .session_map
+            .get(SessionId(session_id))
+            .await
+            .ok_or(Error::UnknownSessionAuthorities(session_id))?;
+        Ok(SessionAuthorities {
+            authorities: authority_data.authorities().clone(),
+            emergency_finalizer: authority_data.emergency_finalizer().clone(),
+        })
+    }
+
+    fn aleph_node_justification(&self, hash: B::Hash) -> RpcResult<Option<JustificationInfo>> {
+        let justification = match self
+            .client
+            .justifications(&BlockId::Hash(hash))
+            .map_err(|e| Error::Blockchain(e.to_string()))?
+            .and_then(|justifications| justifications.get(ALEPH_ENGINE_ID).cloned())
+        {
+            Some(justification) => justification,
+            None => return Ok(None),
+        };
+        let justification = backwards_compatible_decode(justification)
+            .map_err(|e| Error::MalformattedStoredJustification(e.to_string()))?;
+        Ok(Some((&justification).into()))
+    }
+
+    async fn aleph_node_is_committee_member(&self) -> RpcResult<bool> {
+        let session_id = self.current_session();
+        let authority_data = self
+            .session_map
+            .get(session_id)
+            .await
+            .ok_or(Error::UnknownSessionAuthorities(session_id.0))?;
+        self.is_committee_member(&authority_data).await
+    }
+
+    async fn aleph_node_health(&self) -> RpcResult<Health<NumberFor<B>>> {
+        let mut reasons = Vec::new();
+        let info = self.client.info();
+        if info.best_number.saturating_sub(info.finalized_number) > self.max_finality_lag.into() {
+            reasons.push(UnhealthyReason::FinalizationLag {
+                best_number: info.best_number,
+                finalized_number: info.finalized_number,
+                max_lag: self.max_finality_lag,
+            });
+        }
+        let session_id = self.current_session();
+        match self.session_map.get(session_id).await {
+            Some(authority_data) => {
+                if self.is_committee_member(&authority_data).await? {
+                    let connected = self.validator_connections.get(session_id).unwrap_or(0);
+                    let required = required_connections(authority_data.authorities().len());
+                    if connected < required {
+                        reasons.push(UnhealthyReason::TooFewConnections {
+                            session_id: session_id.0,
+                            connected,
+                            required,
+                        });
+                    }
+                }
+            }
+            None => reasons.push(UnhealthyReason::MissingSessionAuthorities {
+                session_id: session_id.0,
+            }),
+        }
+        Ok(Health {
+            healthy: reasons.is_empty(),
+            reasons,
+        })
+    }
+
+    fn aleph_node_subscribe_finalized_blocks(&self, mut sink: SubscriptionSink) -> RpcResult<()> {
+        let stream = self.finalized_block_stream.subscribe().map(
+            |notification: JustificationNotification<B>| FinalizedBlock {
+                hash: notification.hash,
+                number: notification.number,
+                justification: (&notification.justification).into(),
+            },
+        );
+        let fut = async move {
+            sink.pipe_from_stream(stream).await;
+        };
+        self.executor
+            .spawn("aleph-rpc-subscription", Some("rpc"), fut.boxed());
+        Ok(())
+    }
 }
@@ -4,7 +4,11 @@ use sc_cli::{ChainSpec, RunCmd, RuntimeVersion, SubstrateCli};
 use crate::{
     aleph_cli::AlephCli,
     chain_spec,
-    commands::{BootstrapChainCmd, BootstrapNodeCmd, ConvertChainspecToRawCmd, PurgeChainCmd},
+    commands::{
+        BootstrapChainCmd, BootstrapNodeCmd, ConvertChainspecToRawCmd, ExportAuthorityHandoversCmd,
+        ExportFinalityProofCmd, PruneJustificationsCmd, PurgeChainCmd, StandalonePurgeBackupCmd,
+        VerifyFinalityProofCmd,
+    },
 };
 
 #[derive(Debug, Parser)]
@@ -89,6 +93,12 @@ pub enum Subcommand {
     /// Export blocks.
     ExportBlocks(sc_cli::ExportBlocksCmd),
 
+    /// Export proofs of authority set changes between consecutive sessions.
+    ExportAuthorityHandovers(ExportAuthorityHandoversCmd),
+
+    /// Export a self-contained proof of finality of a block.
+    ExportFinalityProof(ExportFinalityProofCmd),
+
     /// Export the state of a given block into a chain spec.
     ExportState(sc_cli::ExportStateCmd),
 
@@ -98,9 +108,18 @@ pub enum Subcommand {
     /// Remove the whole chain.
     PurgeChain(PurgeChainCmd),
 
+    /// Remove the AlephBFT backup, or only check it with `--verify`.
+    PurgeBackup(StandalonePurgeBackupCmd),
+
+    /// Remove the justifications of blocks that do not end a session.
+    PruneJustifications(PruneJustificationsCmd),
+
     /// Revert the chain to a previous state.
     Revert(sc_cli::RevertCmd),
 
+    /// Verify a proof of finality exported with `export-finality-proof`.
+    VerifyFinalityProof(VerifyFinalityProofCmd),
+
     /// Try some command against runtime state.
     #[cfg(feature = "try-runtime")]
     TryRuntime(try_runtime_cli::TryRuntimeCmd),
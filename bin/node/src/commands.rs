@@ -2,24 +2,38 @@ use std::{
     fs,
     io::{self, Write},
     path::{Path, PathBuf},
+    sync::Arc,
 };
 
-use aleph_primitives::AuthorityId as AlephId;
-use aleph_runtime::AccountId;
+use aleph_primitives::{AlephSessionApi, AuthorityId as AlephId};
+use aleph_runtime::{opaque::Block, AccountId, BlockNumber};
 use clap::Parser;
+use codec::{Decode, Encode};
+use finality_aleph::{
+    authority_handovers, finality_proof, verify_backups, AlephJustification, BackupFileState,
+    FinalityProof, JustificationPruner, SessionBackupState, SessionBoundaries, SessionId,
+    SessionPeriod,
+};
 use libp2p::identity::{ed25519 as libp2p_ed25519, PublicKey};
 use sc_cli::{CliConfiguration, DatabaseParams, Error, KeystoreParams, SharedParams};
+use sc_client_api::{BlockBackend, HeaderBackend, ProofProvider};
 use sc_keystore::LocalKeystore;
 use sc_service::{
     config::{BasePath, KeystoreConfig},
-    DatabaseSource,
+    Configuration, DatabaseSource, PartialComponents,
 };
+use sp_api::ProvideRuntimeApi;
 use sp_application_crypto::{key_types, Ss58Codec};
 use sp_consensus_aura::sr25519::AuthorityId as AuraId;
 use sp_keystore::SyncCryptoStore;
-
-use crate::chain_spec::{
-    self, account_id_from_string, AuthorityKeys, ChainParams, ChainSpec, SerializablePeerId,
+use sp_runtime::{generic::BlockId, traits::Header};
+
+use crate::{
+    chain_spec::{
+        self, account_id_from_string, AuthorityKeys, ChainParams, ChainSpec, SerializablePeerId,
+    },
+    justification_pruning::open_for_pruning,
+    service::new_partial,
 };
 
 /// returns Aura key, if absent a new key is generated
@@ -69,6 +83,16 @@ fn p2p_key(chain_params: &ChainParams, account_id: &AccountId) -> SerializablePe
     }
 }
 
+/// Asks the user a yes or no question, the answer is no unless explicitly yes.
+fn confirm(question: &str) -> Result<bool, Error> {
+    print!("{} [y/N]: ", question);
+    io::stdout().flush().expect("failed to flush stdout");
+
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    Ok(matches!(input.trim().chars().next(), Some('y') | Some('Y')))
+}
+
 fn backup_path(base_path: &Path, backup_dir: &str) -> PathBuf {
     base_path.join(backup_dir)
 }
@@ -288,29 +312,24 @@ impl PurgeBackupCmd {
             self.chain_params.backup_dir(),
         );
 
-        if !self.yes {
-            print!(
-                "Are you sure to inside of remove {:?}? [y/N]: ",
+        if !self.yes
+            && !confirm(&format!(
+                "Are you sure to inside of remove {:?}?",
                 &backup_path
-            );
-            io::stdout().flush().expect("failed to flush stdout");
-
-            let mut input = String::new();
-            io::stdin().read_line(&mut input)?;
-            let input = input.trim();
-
-            match input.chars().next() {
-                Some('y') | Some('Y') => {}
-                _ => {
-                    println!("Aborted");
-                    return Ok(());
-                }
-            }
+            ))?
+        {
+            println!("Aborted");
+            return Ok(());
         }
 
         for entry in fs::read_dir(&backup_path)? {
             let path = entry?.path();
-            match fs::remove_dir_all(&path) {
+            // Depending on the layout, backups of sessions are either directories or files.
+            let removed = match path.is_dir() {
+                true => fs::remove_dir_all(&path),
+                false => fs::remove_file(&path),
+            };
+            match removed {
                 Ok(_) => {
                     println!("{:?} removed.", &path);
                 }
@@ -323,3 +342,303 @@ impl PurgeBackupCmd {
         Ok(())
     }
 }
+
+/// The `purge-backup` command removes the backup made by AlephBFT. With `--verify` it only reports
+/// the state of the backup of every session, without removing anything.
+#[derive(Debug, Parser)]
+pub struct StandalonePurgeBackupCmd {
+    /// Check the backups and report their state instead of removing them.
+    #[clap(long)]
+    pub verify: bool,
+    #[clap(flatten)]
+    pub purge_backup: PurgeBackupCmd,
+}
+
+impl StandalonePurgeBackupCmd {
+    pub fn run(&self) -> Result<(), Error> {
+        match self.verify {
+            true => self.verify(),
+            false => self.purge_backup.run(),
+        }
+    }
+
+    fn verify(&self) -> Result<(), Error> {
+        let chain_params = &self.purge_backup.chain_params;
+        let backup_path = backup_path(chain_params.base_path().path(), chain_params.backup_dir());
+
+        let mut problems = 0;
+        for (session_id, state) in verify_backups(&backup_path)? {
+            match state {
+                SessionBackupState::Runs(files) => {
+                    println!("Session {}: {} run(s)", session_id, files.len());
+                    for (path, state) in files {
+                        match state {
+                            BackupFileState::Intact { records } => {
+                                println!("  {:?}: intact, {} record(s)", path, records)
+                            }
+                            BackupFileState::Damaged {
+                                records,
+                                dropped_bytes,
+                            } => {
+                                problems += 1;
+                                println!(
+                                    "  {:?}: damaged, {} valid record(s), {} trailing byte(s) will be cut off on restart",
+                                    path, records, dropped_bytes
+                                )
+                            }
+                            BackupFileState::Unframed => println!(
+                                "  {:?}: written by an older version, cannot be checked",
+                                path
+                            ),
+                        }
+                    }
+                }
+                SessionBackupState::Incomplete(runs) => {
+                    problems += 1;
+                    println!(
+                        "Session {}: incomplete, only runs {:?} are present, the backup will not be used",
+                        session_id, runs
+                    );
+                }
+            }
+        }
+        match problems {
+            0 => println!("No problems found in {:?}.", backup_path),
+            _ => println!("Found {} problem(s) in {:?}.", problems, backup_path),
+        }
+        Ok(())
+    }
+}
+
+/// The `prune-justifications` command removes the justifications of the blocks that do not end a
+/// session from the database, in sessions that are already finalized. It is meant for reclaiming
+/// space on nodes that were started with `--prune-justifications` after having stored all the
+/// justifications. The node has to be stopped, and has to use a RocksDb database.
+#[derive(Debug, Parser)]
+pub struct PruneJustificationsCmd {
+    /// Skip interactive prompt by answering yes automatically.
+    #[clap(short = 'y')]
+    pub yes: bool,
+
+    #[clap(flatten)]
+    pub shared_params: SharedParams,
+
+    #[clap(flatten)]
+    pub database_params: DatabaseParams,
+}
+
+impl PruneJustificationsCmd {
+    pub fn run(&self, mut config: Configuration) -> Result<(), Error> {
+        if !self.yes
+            && !confirm(&format!(
+                "Are you sure to remove justifications of blocks not ending sessions of chain {}?",
+                config.chain_spec.id()
+            ))?
+        {
+            println!("Aborted");
+            return Ok(());
+        }
+
+        let pruner = open_for_pruning(&mut config.database)?;
+        let PartialComponents { client, .. } = new_partial(&config)?;
+        let info = client.info();
+        // The session period is a runtime constant, so any block will do.
+        let session_period = SessionPeriod(
+            client
+                .runtime_api()
+                .session_period(&BlockId::Hash(info.finalized_hash))
+                .map_err(|e| format!("Failed to read the session period: {}", e))?,
+        );
+        // Only sessions whose last block is finalized, until then the justifications of their
+        // blocks are the only proof of their finality.
+        let finalized_sessions = (info.finalized_number + 1) / session_period.0;
+
+        for session_id in (0..finalized_sessions).map(SessionId) {
+            let boundaries = SessionBoundaries::<Block>::new(session_id, session_period);
+            let blocks = (boundaries.first_block()..boundaries.last_block())
+                .map(|number| match client.hash(number) {
+                    Ok(Some(hash)) => Ok((number, hash)),
+                    _ => Err(format!("Missing hash of finalized block {}.", number)),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            pruner.remove_justifications(blocks).map_err(|e| {
+                format!(
+                    "Failed to prune justifications of session {}: {}",
+                    session_id.0, e
+                )
+            })?;
+        }
+
+        println!(
+            "Pruned justifications of {} finalized sessions, kept the justifications of their last blocks.",
+            finalized_sessions
+        );
+        Ok(())
+    }
+}
+
+impl CliConfiguration for PruneJustificationsCmd {
+    fn shared_params(&self) -> &SharedParams {
+        &self.shared_params
+    }
+
+    fn database_params(&self) -> Option<&DatabaseParams> {
+        Some(&self.database_params)
+    }
+}
+
+/// The `export-finality-proof` command exports a self-contained proof of finality of a block,
+/// containing its header, justification and the authorities of its session. The proof can be
+/// checked offline with the `verify-finality-proof` command. The authorities are read from the
+/// state of an earlier block, so the node has to keep the state of all blocks.
+#[derive(Debug, Parser)]
+pub struct ExportFinalityProofCmd {
+    /// The number of the finalized block to prove finality of.
+    #[clap(long)]
+    pub block: BlockNumber,
+
+    /// Write the hex encoded proof to this file instead of stdout.
+    #[clap(long, parse(from_os_str))]
+    pub output: Option<PathBuf>,
+
+    #[clap(flatten)]
+    pub shared_params: SharedParams,
+
+    #[clap(flatten)]
+    pub database_params: DatabaseParams,
+}
+
+impl ExportFinalityProofCmd {
+    pub fn run<C>(&self, client: Arc<C>) -> Result<(), Error>
+    where
+        C: HeaderBackend<Block>
+            + BlockBackend<Block>
+            + ProvideRuntimeApi<Block>
+            + Send
+            + Sync
+            + 'static,
+        C::Api: AlephSessionApi<Block>,
+    {
+        let proof = finality_proof::<Block, _>(client, self.block)
+            .map_err(|e| format!("Failed to export finality proof: {}", e))?;
+        let encoded = format!("0x{}", hex::encode(proof.encode()));
+        match &self.output {
+            Some(path) => fs::write(path, encoded)?,
+            None => println!("{}", encoded),
+        }
+        Ok(())
+    }
+}
+
+impl CliConfiguration for ExportFinalityProofCmd {
+    fn shared_params(&self) -> &SharedParams {
+        &self.shared_params
+    }
+
+    fn database_params(&self) -> Option<&DatabaseParams> {
+        Some(&self.database_params)
+    }
+}
+
+/// The `export-authority-handovers` command exports a chain of proofs of authority set changes,
+/// which lets a light client follow the authorities of consecutive sessions without state access.
+/// The proofs are made from the state at the end of every session, so the node has to keep the
+/// state of all blocks.
+#[derive(Debug, Parser)]
+pub struct ExportAuthorityHandoversCmd {
+    /// The session whose authorities hand over to the next session in the first proof.
+    #[clap(long, default_value = "0")]
+    pub first_session: u32,
+
+    /// The number of consecutive sessions to export handovers for.
+    #[clap(long)]
+    pub count: u32,
+
+    /// Write the hex encoded handovers to this file instead of stdout.
+    #[clap(long, parse(from_os_str))]
+    pub output: Option<PathBuf>,
+
+    #[clap(flatten)]
+    pub shared_params: SharedParams,
+
+    #[clap(flatten)]
+    pub database_params: DatabaseParams,
+}
+
+impl ExportAuthorityHandoversCmd {
+    pub fn run<C>(&self, client: Arc<C>) -> Result<(), Error>
+    where
+        C: HeaderBackend<Block>
+            + BlockBackend<Block>
+            + ProofProvider<Block>
+            + ProvideRuntimeApi<Block>
+            + Send
+            + Sync
+            + 'static,
+        C::Api: AlephSessionApi<Block>,
+    {
+        let handovers =
+            authority_handovers::<Block, _>(client, SessionId(self.first_session), self.count)
+                .map_err(|e| format!("Failed to export authority handovers: {}", e))?;
+        let encoded = format!("0x{}", hex::encode(handovers.encode()));
+        match &self.output {
+            Some(path) => fs::write(path, encoded)?,
+            None => println!("{}", encoded),
+        }
+        Ok(())
+    }
+}
+
+impl CliConfiguration for ExportAuthorityHandoversCmd {
+    fn shared_params(&self) -> &SharedParams {
+        &self.shared_params
+    }
+
+    fn database_params(&self) -> Option<&DatabaseParams> {
+        Some(&self.database_params)
+    }
+}
+
+/// The `verify-finality-proof` command checks a proof exported with `export-finality-proof`
+/// without running a node. It prints the finalized block and the authorities the proof relies on,
+/// which have to be compared with a trusted source.
+#[derive(Debug, Parser)]
+pub struct VerifyFinalityProofCmd {
+    /// Path to the file containing the hex encoded proof.
+    #[clap(long, parse(from_os_str))]
+    pub proof: PathBuf,
+}
+
+impl VerifyFinalityProofCmd {
+    pub fn run(&self) -> Result<(), Error> {
+        let encoded = fs::read_to_string(&self.proof)?;
+        let encoded = hex::decode(encoded.trim().trim_start_matches("0x"))
+            .map_err(|e| format!("The proof is not hex encoded: {}", e))?;
+        let proof = FinalityProof::<Block>::decode(&mut encoded.as_slice())
+            .map_err(|e| format!("The proof is malformed: {}", e))?;
+        let justification = proof
+            .verify()
+            .map_err(|e| format!("The proof is invalid: {}", e))?;
+
+        let header = proof.header();
+        println!(
+            "Block #{} with hash {:?} is finalized.",
+            header.number(),
+            header.hash()
+        );
+        match justification {
+            AlephJustification::CommitteeMultisignature(_)
+            | AlephJustification::AggregatedMultisignature(_) => {
+                println!("Finalized by the committee:");
+                for authority in proof.authority_data().authorities() {
+                    println!("  {:?}", authority);
+                }
+            }
+            AlephJustification::EmergencySignature(_) => println!(
+                "Finalized by the emergency finalizer {:?}.",
+                proof.authority_data().emergency_finalizer()
+            ),
+        }
+        Ok(())
+    }
+}
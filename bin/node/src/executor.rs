@@ -7,7 +7,7 @@ use sc_executor::NativeElseWasmExecutor;
 pub struct ExecutorDispatch;
 
 impl sc_executor::NativeExecutionDispatch for ExecutorDispatch {
-    type ExtendHostFunctions = ();
+    type ExtendHostFunctions = aleph_primitives::aleph_bls::HostFunctions;
 
     fn dispatch(method: &str, data: &[u8]) -> Option<Vec<u8>> {
         aleph_runtime::api::dispatch(method, data)
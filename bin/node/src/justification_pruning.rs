@@ -0,0 +1,281 @@
+//! Removing justifications directly from the RocksDB database of the node.
+//!
+//! Substrate offers no way of removing justifications, so for pruning them we open the database
+//! ourselves and pass it to Substrate as a custom one, keeping a handle for the removals. The
+//! layout of the database is private to Substrate, so we only work with the version of the
+//! database whose layout we know, and refuse to touch anything else. The tests check the layout
+//! against the database created by the Substrate version we depend on.
+use std::{fs, io, path::Path, sync::Arc};
+
+use aleph_runtime::{opaque::Block, BlockNumber};
+use finality_aleph::JustificationPruner;
+use kvdb_rocksdb::{Database, DatabaseConfig};
+use sc_service::DatabaseSource;
+use sp_core::H256;
+use sp_database::Transaction;
+
+/// The version of the Substrate database with the layout described below.
+const DATABASE_VERSION: u32 = 4;
+/// The file in the database directory Substrate keeps the version of the database in.
+const VERSION_FILE: &str = "db_version";
+/// The number of columns in the Substrate database.
+const DATABASE_COLUMNS: u32 = 13;
+/// Column of the Substrate database holding the state, which gets most of the memory budget.
+const STATE_COLUMN: u32 = 1;
+/// Column of the Substrate database holding justifications, keyed by block number and hash.
+const JUSTIFICATIONS_COLUMN: u32 = 6;
+
+/// Removes justifications from the database shared with the client.
+pub struct DatabasePruner(Arc<dyn sp_database::Database<H256>>);
+
+impl JustificationPruner<Block> for DatabasePruner {
+    fn remove_justifications(
+        &self,
+        blocks: Vec<(BlockNumber, H256)>,
+    ) -> Result<(), sp_blockchain::Error> {
+        let mut transaction = Transaction::new();
+        for (number, hash) in blocks {
+            transaction.remove(JUSTIFICATIONS_COLUMN, &justification_key(number, hash));
+        }
+        self.0
+            .commit(transaction)
+            .map_err(|e| sp_blockchain::Error::Backend(format!("{:?}", e)))
+    }
+}
+
+/// The key of the justification of a block, the big endian encoded number followed by the hash.
+fn justification_key(number: BlockNumber, hash: H256) -> Vec<u8> {
+    let mut key = number.to_be_bytes().to_vec();
+    key.extend_from_slice(hash.as_ref());
+    key
+}
+
+/// Returns the path and cache size of the RocksDB database the node would use.
+fn rocksdb_source(source: &DatabaseSource) -> Result<(&Path, usize), String> {
+    match source {
+        DatabaseSource::RocksDb { path, cache_size } => Ok((path, *cache_size)),
+        // Substrate only uses RocksDb if it finds an existing database, ParityDb otherwise.
+        DatabaseSource::Auto {
+            rocksdb_path,
+            cache_size,
+            ..
+        } if rocksdb_path.exists() => Ok((rocksdb_path, *cache_size)),
+        DatabaseSource::Auto { .. } | DatabaseSource::ParityDb { .. } => Err(
+            "Pruning justifications requires a RocksDb database, use `--database rocksdb`.".into(),
+        ),
+        DatabaseSource::Custom(_) => {
+            Err("Pruning justifications is not supported for custom databases.".into())
+        }
+    }
+}
+
+/// Checks whether we know the layout of the database at the path. Like Substrate, we assume a
+/// database without the version file is a new one.
+fn check_version(path: &Path) -> Result<(), String> {
+    match fs::read_to_string(path.join(VERSION_FILE)) {
+        Ok(version) if version.trim() == DATABASE_VERSION.to_string() => Ok(()),
+        Ok(version) => Err(format!(
+            "Pruning justifications is not supported for version {} of the database, start the node once without pruning to upgrade it.",
+            version.trim()
+        )),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(format!("Failed to read the version of the database: {}", e)),
+    }
+}
+
+/// Opens the database the same way Substrate would, dividing the memory budget between the
+/// columns like it does.
+fn open_rocksdb(path: &Path, cache_size: usize) -> Result<Database, String> {
+    check_version(path)?;
+    fs::create_dir_all(path).map_err(|e| format!("Failed to create {:?}: {}", path, e))?;
+    let state_budget = cache_size * 9 / 10;
+    let other_budget = (cache_size - state_budget) / (DATABASE_COLUMNS as usize - 1);
+    let mut config = DatabaseConfig::with_columns(DATABASE_COLUMNS);
+    config.memory_budget = (0..DATABASE_COLUMNS)
+        .map(|column| match column {
+            STATE_COLUMN => (column, state_budget),
+            _ => (column, other_budget),
+        })
+        .collect();
+    let db = Database::open(&config, path)
+        .map_err(|e| format!("Failed to open the database at {:?}: {}", path, e))?;
+    fs::write(path.join(VERSION_FILE), DATABASE_VERSION.to_string())
+        .map_err(|e| format!("Failed to write the version of the database: {}", e))?;
+    Ok(db)
+}
+
+/// Opens the database of the node for pruning justifications. The source is replaced with the
+/// opened database, so that the client built from it shares the database with the pruner.
+pub fn open_for_pruning(source: &mut DatabaseSource) -> Result<Arc<DatabasePruner>, String> {
+    let (path, cache_size) = rocksdb_source(source)?;
+    let db: Arc<dyn sp_database::Database<H256>> =
+        sp_database::as_database(open_rocksdb(path, cache_size)?);
+    *source = DatabaseSource::Custom(db.clone());
+    Ok(Arc::new(DatabasePruner(db)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, sync::Arc};
+
+    use aleph_primitives::ALEPH_ENGINE_ID;
+    use aleph_runtime::opaque::{Block, Header};
+    use codec::Encode;
+    use finality_aleph::JustificationPruner;
+    use kvdb_rocksdb::{Database as RocksDb, DatabaseConfig};
+    use sc_client_api::backend::{Backend as _, BlockImportOperation as _, NewBlockState};
+    use sc_client_db::{Backend, DatabaseSettings, KeepBlocks};
+    use sc_service::DatabaseSource;
+    use sp_core::H256;
+    use sp_database::{Database, MemDb, Transaction};
+    use sp_runtime::{
+        generic::BlockId,
+        traits::{BlakeTwo256, Hash as _, Header as _},
+        Justifications, StateVersion,
+    };
+
+    use super::{
+        check_version, justification_key, open_for_pruning, rocksdb_source, DatabasePruner,
+        DATABASE_COLUMNS, DATABASE_VERSION, JUSTIFICATIONS_COLUMN, VERSION_FILE,
+    };
+
+    /// Creates a database with Substrate, containing a genesis block with the given
+    /// justifications. Returns the hash of the block.
+    fn substrate_database(source: DatabaseSource, justifications: Justifications) -> H256 {
+        let backend = Backend::<Block>::new(
+            DatabaseSettings {
+                state_cache_size: 0,
+                state_cache_child_ratio: None,
+                state_pruning: None,
+                source,
+                keep_blocks: KeepBlocks::All,
+            },
+            0,
+        )
+        .unwrap();
+        let header = Header::new(
+            0,
+            Default::default(),
+            BlakeTwo256::trie_root(Vec::new(), StateVersion::V1),
+            Default::default(),
+            Default::default(),
+        );
+        let hash = header.hash();
+        let mut operation = backend.begin_operation().unwrap();
+        backend
+            .begin_state_operation(&mut operation, BlockId::Hash(Default::default()))
+            .unwrap();
+        operation
+            .set_block_data(
+                header,
+                Some(Vec::new()),
+                None,
+                Some(justifications),
+                NewBlockState::Final,
+            )
+            .unwrap();
+        backend.commit_operation(operation).unwrap();
+        hash
+    }
+
+    #[test]
+    fn matches_layout_of_substrate_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("full");
+        let mut source = DatabaseSource::RocksDb {
+            path: path.clone(),
+            cache_size: 16,
+        };
+        let justifications = Justifications::from((ALEPH_ENGINE_ID, vec![1, 2, 3]));
+        let hash = substrate_database(source.clone(), justifications.clone());
+
+        assert_eq!(
+            fs::read_to_string(path.join(VERSION_FILE)).unwrap().trim(),
+            DATABASE_VERSION.to_string()
+        );
+        // RocksDb refuses to open a database without opening all its columns.
+        assert!(RocksDb::open(&DatabaseConfig::with_columns(DATABASE_COLUMNS - 1), &path).is_err());
+        let pruner = open_for_pruning(&mut source).unwrap();
+        let key = justification_key(0, hash);
+        assert_eq!(
+            pruner.0.get(JUSTIFICATIONS_COLUMN, &key),
+            Some(justifications.encode())
+        );
+        pruner.remove_justifications(vec![(0, hash)]).unwrap();
+        assert!(pruner.0.get(JUSTIFICATIONS_COLUMN, &key).is_none());
+    }
+
+    #[test]
+    fn keys_start_with_big_endian_number() {
+        let hash = H256::repeat_byte(7);
+        let key = justification_key(0x01020304, hash);
+        assert_eq!(&key[..4], &[1, 2, 3, 4]);
+        assert_eq!(&key[4..], hash.as_bytes());
+    }
+
+    #[test]
+    fn removes_only_given_justifications() {
+        let db = Arc::new(MemDb::default());
+        let blocks: Vec<_> = (0..4).map(|n| (n, H256::repeat_byte(n as u8))).collect();
+        let mut transaction = Transaction::<H256>::new();
+        for (number, hash) in &blocks {
+            transaction.set(
+                JUSTIFICATIONS_COLUMN,
+                &justification_key(*number, *hash),
+                &[1, 2, 3],
+            );
+        }
+        db.commit(transaction).unwrap();
+        let pruner = DatabasePruner(db.clone());
+        pruner.remove_justifications(blocks[..3].to_vec()).unwrap();
+        for (number, hash) in &blocks[..3] {
+            let key = justification_key(*number, *hash);
+            assert!(<MemDb as Database<H256>>::get(&db, JUSTIFICATIONS_COLUMN, &key).is_none());
+        }
+        let (number, hash) = blocks[3];
+        let key = justification_key(number, hash);
+        assert!(<MemDb as Database<H256>>::get(&db, JUSTIFICATIONS_COLUMN, &key).is_some());
+    }
+
+    #[test]
+    fn accepts_only_known_database_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(check_version(dir.path()).is_ok());
+        fs::write(dir.path().join(VERSION_FILE), "4").unwrap();
+        assert!(check_version(dir.path()).is_ok());
+        fs::write(dir.path().join(VERSION_FILE), "3").unwrap();
+        assert!(check_version(dir.path()).is_err());
+        fs::write(dir.path().join(VERSION_FILE), "5").unwrap();
+        assert!(check_version(dir.path()).is_err());
+    }
+
+    #[test]
+    fn requires_rocksdb() {
+        let dir = tempfile::tempdir().unwrap();
+        let rocksdb_path = dir.path().join("full");
+        let paritydb_path = dir.path().join("paritydb");
+        assert!(rocksdb_source(&DatabaseSource::ParityDb {
+            path: paritydb_path.clone()
+        })
+        .is_err());
+        let auto = DatabaseSource::Auto {
+            rocksdb_path: rocksdb_path.clone(),
+            paritydb_path,
+            cache_size: 128,
+        };
+        assert!(rocksdb_source(&auto).is_err());
+        fs::create_dir(&rocksdb_path).unwrap();
+        assert_eq!(
+            rocksdb_source(&auto).unwrap(),
+            (rocksdb_path.as_path(), 128)
+        );
+        let rocksdb = DatabaseSource::RocksDb {
+            path: rocksdb_path.clone(),
+            cache_size: 64,
+        };
+        assert_eq!(
+            rocksdb_source(&rocksdb).unwrap(),
+            (rocksdb_path.as_path(), 64)
+        );
+    }
+}
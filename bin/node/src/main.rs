@@ -50,6 +50,20 @@ fn main() -> sc_cli::Result<()> {
                 Ok((cmd.run(client, config.database), task_manager))
             })
         }
+        Some(Subcommand::ExportAuthorityHandovers(cmd)) => {
+            let runner = cli.create_runner(cmd)?;
+            runner.sync_run(|config| {
+                let PartialComponents { client, .. } = new_partial(&config)?;
+                cmd.run(client)
+            })
+        }
+        Some(Subcommand::ExportFinalityProof(cmd)) => {
+            let runner = cli.create_runner(cmd)?;
+            runner.sync_run(|config| {
+                let PartialComponents { client, .. } = new_partial(&config)?;
+                cmd.run(client)
+            })
+        }
         Some(Subcommand::ExportState(cmd)) => {
             let runner = cli.create_runner(cmd)?;
             runner.async_run(|config| {
@@ -77,6 +91,11 @@ fn main() -> sc_cli::Result<()> {
             let runner = cli.create_runner(cmd)?;
             runner.sync_run(|config| cmd.run(config.database))
         }
+        Some(Subcommand::PurgeBackup(cmd)) => cmd.run(),
+        Some(Subcommand::PruneJustifications(cmd)) => {
+            let runner = cli.create_runner(cmd)?;
+            runner.sync_run(|config| cmd.run(config))
+        }
         Some(Subcommand::Revert(cmd)) => {
             let runner = cli.create_runner(cmd)?;
             runner.async_run(|config| {
@@ -89,6 +108,7 @@ fn main() -> sc_cli::Result<()> {
                 Ok((cmd.run(client, backend, None), task_manager))
             })
         }
+        Some(Subcommand::VerifyFinalityProof(cmd)) => cmd.run(),
         #[cfg(feature = "try-runtime")]
         Some(Subcommand::TryRuntime(cmd)) => {
             let runner = cli.create_runner(cmd)?;
@@ -106,6 +126,7 @@ fn main() -> sc_cli::Result<()> {
         You can enable it with `--features try-runtime`."
             .into()),
         None => {
+            cli.aleph.validate()?;
             let runner = cli.create_runner(&cli.run)?;
             let aleph_cli_config = cli.aleph;
             runner.run_node_until_exit(|config| async move {
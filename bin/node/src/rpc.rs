@@ -8,14 +8,20 @@
 use std::sync::Arc;
 
 use aleph_runtime::{opaque::Block, AccountId, Balance, BlockNumber, Hash, Index};
-use finality_aleph::JustificationNotification;
+use finality_aleph::{
+    FinalizedBlockStream, JustificationNotification, ReadOnlySessionMap, SessionPeriod,
+    ValidatorConnections,
+};
 use futures::channel::mpsc;
 use jsonrpsee::RpcModule;
+use sc_client_api::BlockBackend;
+use sc_rpc::SubscriptionTaskExecutor;
 pub use sc_rpc_api::DenyUnsafe;
 use sc_transaction_pool_api::TransactionPool;
 use sp_api::{BlockT, ProvideRuntimeApi};
 use sp_block_builder::BlockBuilder;
 use sp_blockchain::{Error as BlockChainError, HeaderBackend, HeaderMetadata};
+use sp_keystore::CryptoStore;
 
 /// Full client dependencies.
 pub struct FullDeps<B: BlockT, C, P> {
@@ -26,22 +32,36 @@ pub struct FullDeps<B: BlockT, C, P> {
     /// Whether to deny unsafe calls
     pub deny_unsafe: DenyUnsafe,
     pub import_justification_tx: mpsc::UnboundedSender<JustificationNotification<B>>,
+    /// Authorities of the sessions known to the finality gadget.
+    pub session_map: ReadOnlySessionMap,
+    /// Keystore of the node, to check whether it is a committee member.
+    pub keystore: Arc<dyn CryptoStore>,
+    /// The session period of the chain.
+    pub session_period: SessionPeriod,
+    /// Blocks finalized by the finality gadget.
+    pub finalized_block_stream: FinalizedBlockStream<B>,
+    /// Executor for subscription tasks.
+    pub subscription_executor: SubscriptionTaskExecutor,
+    /// Connections to other validators maintained by the finality gadget.
+    pub validator_connections: ValidatorConnections,
+    /// How far the finalized head may lag behind the best head in a healthy node.
+    pub max_finality_lag: u32,
 }
 
 /// Instantiate all full RPC extensions.
-pub fn create_full<B, C, P>(
-    deps: FullDeps<B, C, P>,
+pub fn create_full<C, P>(
+    deps: FullDeps<Block, C, P>,
 ) -> Result<RpcModule<()>, Box<dyn std::error::Error + Send + Sync>>
 where
     C: ProvideRuntimeApi<Block>,
     C: HeaderBackend<Block> + HeaderMetadata<Block, Error = BlockChainError> + 'static,
+    C: BlockBackend<Block>,
     C: Send + Sync + 'static,
     C::Api: pallet_contracts_rpc::ContractsRuntimeApi<Block, AccountId, Balance, BlockNumber, Hash>,
     C::Api: substrate_frame_rpc_system::AccountNonceApi<Block, AccountId, Index>,
     C::Api: pallet_transaction_payment_rpc::TransactionPaymentRuntimeApi<Block, Balance>,
     C::Api: BlockBuilder<Block>,
     P: TransactionPool + 'static,
-    B: BlockT,
 {
     use pallet_contracts_rpc::{Contracts, ContractsApiServer};
     use pallet_transaction_payment_rpc::{TransactionPayment, TransactionPaymentApiServer};
@@ -53,16 +73,36 @@ where
         pool,
         deny_unsafe,
         import_justification_tx,
+        session_map,
+        keystore,
+        session_period,
+        finalized_block_stream,
+        subscription_executor,
+        validator_connections,
+        max_finality_lag,
     } = deps;
 
     module.merge(System::new(client.clone(), pool, deny_unsafe).into_rpc())?;
 
     module.merge(TransactionPayment::new(client.clone()).into_rpc())?;
 
-    module.merge(Contracts::new(client).into_rpc())?;
+    module.merge(Contracts::new(client.clone()).into_rpc())?;
 
     use crate::aleph_node_rpc::{AlephNode, AlephNodeApiServer};
-    module.merge(AlephNode::new(import_justification_tx).into_rpc())?;
+    module.merge(
+        AlephNode::new(
+            import_justification_tx,
+            client,
+            session_map,
+            keystore,
+            session_period,
+            finalized_block_stream,
+            subscription_executor,
+            validator_connections,
+            max_finality_lag,
+        )
+        .into_rpc(),
+    )?;
 
     Ok(module)
 }
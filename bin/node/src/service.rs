@@ -5,17 +5,19 @@ use std::sync::Arc;
 use aleph_primitives::AlephSessionApi;
 use aleph_runtime::{self, opaque::Block, RuntimeApi, MAX_BLOCK_SIZE};
 use finality_aleph::{
-    run_nonvalidator_node, run_validator_node, AlephBlockImport, AlephConfig,
-    JustificationNotification, Metrics, MillisecsPerBlock, Protocol, SessionPeriod,
+    run_nonvalidator_node, run_validator_node, AlephBlockImport, AlephConfig, BlsKeystore,
+    FinalizedBlockStream, JustificationNotification, JustificationPruner, Metrics,
+    MillisecsPerBlock, Protocol, ReadOnlySessionMap, SessionPeriod, SharedSessionMap,
+    ValidatorConnections,
 };
 use futures::channel::mpsc;
 use log::warn;
 use sc_client_api::ExecutorProvider;
 use sc_consensus_aura::{ImportQueueParams, SlotProportion, StartAuraParams};
-use sc_network::NetworkService;
+use sc_network::{config::IncomingRequest, NetworkService};
 use sc_service::{
-    error::Error as ServiceError, Configuration, KeystoreContainer, NetworkStarter, RpcHandlers,
-    TFullClient, TaskManager,
+    config::KeystoreConfig, error::Error as ServiceError, Configuration, KeystoreContainer,
+    NetworkStarter, RpcHandlers, TFullClient, TaskManager,
 };
 use sc_telemetry::{Telemetry, TelemetryWorker};
 use sp_api::ProvideRuntimeApi;
@@ -25,7 +27,9 @@ use sp_runtime::{
     traits::{Block as BlockT, Header as HeaderT, Zero},
 };
 
-use crate::{aleph_cli::AlephCli, executor::AlephExecutor};
+use crate::{
+    aleph_cli::AlephCli, executor::AlephExecutor, justification_pruning::open_for_pruning,
+};
 
 type FullClient = sc_service::TFullClient<Block, RuntimeApi, AlephExecutor>;
 type FullBackend = sc_service::TFullBackend<Block>;
@@ -168,11 +172,17 @@ fn setup(
     client: Arc<FullClient>,
     telemetry: &mut Option<Telemetry>,
     import_justification_tx: mpsc::UnboundedSender<JustificationNotification<Block>>,
+    session_map: ReadOnlySessionMap,
+    session_period: SessionPeriod,
+    finalized_block_stream: FinalizedBlockStream<Block>,
+    validator_connections: ValidatorConnections,
+    max_finality_lag: u32,
 ) -> Result<
     (
         RpcHandlers,
         Arc<NetworkService<Block, <Block as BlockT>::Hash>>,
         NetworkStarter,
+        mpsc::Receiver<IncomingRequest>,
     ),
     ServiceError,
 > {
@@ -180,6 +190,12 @@ fn setup(
         .network
         .extra_sets
         .push(finality_aleph::peers_set_config(Protocol::Generic));
+    let (justification_sync_config, justification_sync_requests) =
+        finality_aleph::justification_sync_config();
+    config
+        .network
+        .request_response_protocols
+        .push(justification_sync_config);
 
     let (network, system_rpc_tx, network_starter) =
         sc_service::build_network(sc_service::BuildNetworkParams {
@@ -195,13 +211,21 @@ fn setup(
     let rpc_builder = {
         let client = client.clone();
         let pool = transaction_pool.clone();
+        let keystore = keystore_container.keystore();
 
-        Box::new(move |deny_unsafe, _| {
+        Box::new(move |deny_unsafe, subscription_executor| {
             let deps = crate::rpc::FullDeps {
                 client: client.clone(),
                 pool: pool.clone(),
                 deny_unsafe,
                 import_justification_tx: import_justification_tx.clone(),
+                session_map: session_map.clone(),
+                keystore: keystore.clone(),
+                session_period,
+                finalized_block_stream: finalized_block_stream.clone(),
+                subscription_executor,
+                validator_connections: validator_connections.clone(),
+                max_finality_lag,
             };
 
             Ok(crate::rpc::create_full(deps)?)
@@ -221,7 +245,37 @@ fn setup(
         telemetry: telemetry.as_mut(),
     })?;
 
-    Ok((rpc_handlers, network, network_starter))
+    Ok((
+        rpc_handlers,
+        network,
+        network_starter,
+        justification_sync_requests,
+    ))
+}
+
+/// Opens the database for pruning justifications, if requested. This has to happen before the
+/// client is built, as it has to use the same database.
+fn justification_pruner(
+    config: &mut Configuration,
+    aleph_config: &AlephCli,
+) -> Result<Option<Arc<dyn JustificationPruner<Block>>>, ServiceError> {
+    match aleph_config.prune_justifications() {
+        true => Ok(Some(
+            open_for_pruning(&mut config.database).map_err(ServiceError::Other)?,
+        )),
+        false => Ok(None),
+    }
+}
+
+/// Opens the keystore of BLS keys, kept in the directory of the keystore of the node, if any.
+fn bls_keystore(config: &Configuration) -> Result<Arc<BlsKeystore>, ServiceError> {
+    let path = match &config.keystore {
+        KeystoreConfig::Path { path, .. } => Some(path.clone()),
+        KeystoreConfig::InMemory => None,
+    };
+    BlsKeystore::open(path)
+        .map(Arc::new)
+        .map_err(|e| ServiceError::Other(format!("Failed to open the BLS keystore: {:?}", e)))
 }
 
 /// Builds a new service for a full client.
@@ -229,6 +283,8 @@ pub fn new_authority(
     mut config: Configuration,
     aleph_config: AlephCli,
 ) -> Result<TaskManager, ServiceError> {
+    let justification_pruner = justification_pruner(&mut config, &aleph_config)?;
+    let bls_keystore = bls_keystore(&config)?;
     let sc_service::PartialComponents {
         client,
         backend,
@@ -261,8 +317,11 @@ pub fn new_authority(
     let force_authoring = config.force_authoring;
     let backoff_authoring_blocks: Option<()> = None;
     let prometheus_registry = config.prometheus_registry().cloned();
+    let session_map = SharedSessionMap::new();
+    let (finalized_block_sender, finalized_block_stream) = FinalizedBlockStream::channel();
+    let validator_connections = ValidatorConnections::new();
 
-    let (_rpc_handlers, network, network_starter) = setup(
+    let (_rpc_handlers, network, network_starter, justification_sync_requests) = setup(
         config,
         backend,
         &keystore_container,
@@ -272,6 +331,11 @@ pub fn new_authority(
         client.clone(),
         &mut telemetry,
         justification_tx,
+        session_map.read_only(),
+        session_period,
+        finalized_block_stream,
+        validator_connections.clone(),
+        aleph_config.max_finality_lag(),
     )?;
 
     let mut proposer_factory = sc_basic_authorship::ProposerFactory::new(
@@ -329,10 +393,23 @@ pub fn new_authority(
         millisecs_per_block,
         spawn_handle: task_manager.spawn_handle(),
         keystore: keystore_container.keystore(),
+        bls_keystore,
         justification_rx,
         metrics,
         unit_creation_delay: aleph_config.unit_creation_delay(),
-        backup_saving_path: aleph_config.backup_path(),
+        unit_creation_delay_bounds: aleph_config.unit_creation_delay_bounds(),
+        backup_store: aleph_config.backup_store(),
+        backup_retention: aleph_config.backup_retention(),
+        data_store_config: aleph_config.data_store_config(),
+        chain_tracker_config: aleph_config.chain_tracker_config(),
+        compression_threshold: aleph_config.compression_threshold(),
+        use_address_registry: aleph_config.use_address_registry(),
+        validator_network: aleph_config.validator_network(),
+        justification_sync_requests,
+        justification_pruner,
+        session_map,
+        finalized_block_sender,
+        validator_connections,
     };
     task_manager.spawn_essential_handle().spawn_blocking(
         "aleph",
@@ -345,9 +422,10 @@ pub fn new_authority(
 }
 
 pub fn new_full(
-    config: Configuration,
+    mut config: Configuration,
     aleph_config: AlephCli,
 ) -> Result<TaskManager, ServiceError> {
+    let justification_pruner = justification_pruner(&mut config, &aleph_config)?;
     let sc_service::PartialComponents {
         client,
         backend,
@@ -359,7 +437,17 @@ pub fn new_full(
         other: (_, justification_tx, justification_rx, mut telemetry, metrics),
     } = new_partial(&config)?;
 
-    let (_rpc_handlers, network, network_starter) = setup(
+    let session_period = SessionPeriod(
+        client
+            .runtime_api()
+            .session_period(&BlockId::Number(Zero::zero()))
+            .unwrap(),
+    );
+    let session_map = SharedSessionMap::new();
+    let (finalized_block_sender, finalized_block_stream) = FinalizedBlockStream::channel();
+    let validator_connections = ValidatorConnections::new();
+
+    let (_rpc_handlers, network, network_starter, justification_sync_requests) = setup(
         config,
         backend,
         &keystore_container,
@@ -369,15 +457,13 @@ pub fn new_full(
         client.clone(),
         &mut telemetry,
         justification_tx,
+        session_map.read_only(),
+        session_period,
+        finalized_block_stream,
+        validator_connections.clone(),
+        aleph_config.max_finality_lag(),
     )?;
 
-    let session_period = SessionPeriod(
-        client
-            .runtime_api()
-            .session_period(&BlockId::Number(Zero::zero()))
-            .unwrap(),
-    );
-
     let millisecs_per_block = MillisecsPerBlock(
         client
             .runtime_api()
@@ -393,10 +479,23 @@ pub fn new_full(
         millisecs_per_block,
         spawn_handle: task_manager.spawn_handle(),
         keystore: keystore_container.keystore(),
+        bls_keystore: Arc::new(BlsKeystore::in_memory()),
         justification_rx,
         metrics,
         unit_creation_delay: aleph_config.unit_creation_delay(),
-        backup_saving_path: aleph_config.backup_path(),
+        unit_creation_delay_bounds: aleph_config.unit_creation_delay_bounds(),
+        backup_store: aleph_config.backup_store(),
+        backup_retention: aleph_config.backup_retention(),
+        data_store_config: aleph_config.data_store_config(),
+        chain_tracker_config: aleph_config.chain_tracker_config(),
+        compression_threshold: aleph_config.compression_threshold(),
+        use_address_registry: aleph_config.use_address_registry(),
+        validator_network: None,
+        justification_sync_requests,
+        justification_pruner,
+        session_map,
+        finalized_block_sender,
+        validator_connections,
     };
 
     task_manager.spawn_essential_handle().spawn_blocking(
@@ -35,8 +35,10 @@ use pallet_transaction_payment::{CurrencyAdapter, Multiplier, TargetedFeeAdjustm
 pub use primitives::Balance;
 use primitives::{
     staking::MAX_NOMINATORS_REWARDED_PER_VALIDATOR, wrap_methods, ApiError as AlephApiError,
-    AuthorityId as AlephId, SessionAuthorityData, ADDRESSES_ENCODING, DEFAULT_SESSIONS_PER_ERA,
-    DEFAULT_SESSION_PERIOD, MILLISECS_PER_BLOCK, TOKEN,
+    AuthorityId as AlephId, AuthoritySignature as AlephSignature, BlsKey, EquivocationProof,
+    FinalityParameters, SessionAuthorityData, SignedBlsKeyRegistration, SignedValidatorAddresses,
+    ADDRESSES_ENCODING, DEFAULT_SESSIONS_PER_ERA, DEFAULT_SESSION_PERIOD, MILLISECS_PER_BLOCK,
+    TOKEN,
 };
 use sp_api::impl_runtime_apis;
 use sp_consensus_aura::{sr25519::AuthorityId as AuraId, SlotDuration};
@@ -107,7 +109,7 @@ pub const VERSION: RuntimeVersion = RuntimeVersion {
     spec_name: create_runtime_str!("aleph-node"),
     impl_name: create_runtime_str!("aleph-node"),
     authoring_version: 1,
-    spec_version: 26,
+    spec_version: 32,
     impl_version: 1,
     apis: RUNTIME_API_VERSIONS,
     transaction_version: 9,
@@ -311,6 +313,14 @@ impl pallet_sudo::Config for Runtime {
 impl pallet_aleph::Config for Runtime {
     type AuthorityId = AlephId;
     type Event = Event;
+    type SessionInfoProvider = Session;
+    type EquivocationHandler = pallet_aleph::EquivocationHandler<Self, Offences, Elections>;
+}
+
+impl pallet_offences::Config for Runtime {
+    type Event = Event;
+    type IdentificationTuple = pallet_session::historical::IdentificationTuple<Self>;
+    type OnOffenceHandler = Staking;
 }
 
 impl_opaque_keys! {
@@ -322,6 +332,8 @@ impl_opaque_keys! {
 
 parameter_types! {
     pub const SessionPeriod: u32 = DEFAULT_SESSION_PERIOD;
+    // Validators banned for equivocating sit out 10 eras.
+    pub const BanPeriod: u32 = 10;
 }
 
 impl pallet_elections::Config for Runtime {
@@ -332,6 +344,7 @@ impl pallet_elections::Config for Runtime {
     type SessionPeriod = SessionPeriod;
     type SessionManager = pallet_session::historical::NoteHistoricalRoot<Runtime, Staking>;
     type ValidatorRewardsHandler = Staking;
+    type BanPeriod = BanPeriod;
 }
 
 impl pallet_randomness_collective_flip::Config for Runtime {}
@@ -723,6 +736,7 @@ construct_runtime!(
         Contracts: pallet_contracts,
         NominationPools: pallet_nomination_pools,
         Identity: pallet_identity,
+        Offences: pallet_offences,
     }
 );
 
@@ -882,6 +896,7 @@ impl_runtime_apis! {
 
         fn authority_data() -> SessionAuthorityData {
             SessionAuthorityData::new(Aleph::authorities(), Aleph::emergency_finalizer())
+                .with_bls_keys(Aleph::session_bls_keys())
         }
 
         fn next_session_authority_data() -> Result<SessionAuthorityData, AlephApiError> {
@@ -890,7 +905,40 @@ impl_runtime_apis! {
                 .map(|(_, key)| key.get(AlephId::ID).ok_or(AlephApiError::DecodeKey))
                 .collect::<Result<Vec<AlephId>, AlephApiError>>()?,
                 Aleph::queued_emergency_finalizer(),
-            ))
+            )
+            .with_bls_keys(Aleph::next_session_bls_keys()))
+        }
+
+        fn submit_report_equivocation_unsigned_extrinsic(
+            equivocation_proof: EquivocationProof<AlephSignature>,
+        ) -> Option<()> {
+            Aleph::submit_unsigned_equivocation_report(equivocation_proof)
+        }
+
+        fn next_session_finality_params() -> Option<FinalityParameters> {
+            Aleph::next_session_finality_params()
+        }
+
+        fn validator_addresses() -> Vec<(AlephId, SignedValidatorAddresses<AlephSignature>)> {
+            Aleph::registered_validator_addresses()
+        }
+
+        fn submit_validator_addresses_unsigned_extrinsic(
+            authority: AlephId,
+            addresses: SignedValidatorAddresses<AlephSignature>,
+        ) -> Option<()> {
+            Aleph::submit_unsigned_validator_addresses(authority, addresses)
+        }
+
+        fn registered_bls_key(authority: AlephId) -> Option<BlsKey> {
+            Aleph::registered_bls_key(&authority)
+        }
+
+        fn submit_bls_key_unsigned_extrinsic(
+            authority: AlephId,
+            registration: SignedBlsKeyRegistration<AlephSignature>,
+        ) -> Option<()> {
+            Aleph::submit_unsigned_bls_key(authority, registration)
         }
     }
 
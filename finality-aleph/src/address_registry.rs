@@ -0,0 +1,93 @@
+use std::{collections::HashMap, marker::PhantomData, sync::Arc};
+
+use aleph_primitives::{AlephSessionApi, AuthorityId};
+use log::{debug, warn};
+use parking_lot::Mutex;
+use sc_client_api::HeaderBackend;
+use sp_api::{BlockId, ProvideRuntimeApi};
+use sp_runtime::traits::Block;
+
+use crate::network::{AddressRegistry, RegisteredAddresses};
+
+/// The validator addresses registered in `pallet_aleph`, read from the state of the best block.
+/// The runtime is only called when the best block changes, the addresses read at the previous
+/// one are returned otherwise.
+pub struct ChainAddressRegistry<B, C>
+where
+    B: Block,
+    C: HeaderBackend<B> + ProvideRuntimeApi<B>,
+    C::Api: AlephSessionApi<B>,
+{
+    client: Arc<C>,
+    last_read: Mutex<Option<(B::Hash, HashMap<AuthorityId, RegisteredAddresses>)>>,
+    _phantom: PhantomData<B>,
+}
+
+impl<B, C> ChainAddressRegistry<B, C>
+where
+    B: Block,
+    C: HeaderBackend<B> + ProvideRuntimeApi<B>,
+    C::Api: AlephSessionApi<B>,
+{
+    pub fn new(client: Arc<C>) -> Self {
+        ChainAddressRegistry {
+            client,
+            last_read: Mutex::new(None),
+            _phantom: PhantomData,
+        }
+    }
+
+    fn best_block(&self) -> BlockId<B> {
+        BlockId::Hash(self.client.info().best_hash)
+    }
+}
+
+impl<B, C> AddressRegistry for ChainAddressRegistry<B, C>
+where
+    B: Block,
+    C: HeaderBackend<B> + ProvideRuntimeApi<B> + Send + Sync,
+    C::Api: AlephSessionApi<B>,
+{
+    fn registered(&self) -> HashMap<AuthorityId, RegisteredAddresses> {
+        let best_hash = self.client.info().best_hash;
+        let mut last_read = self.last_read.lock();
+        if let Some((hash, registered)) = &*last_read {
+            if *hash == best_hash {
+                return registered.clone();
+            }
+        }
+        match self
+            .client
+            .runtime_api()
+            .validator_addresses(&BlockId::Hash(best_hash))
+        {
+            Ok(registered) => {
+                let registered: HashMap<_, _> = registered.into_iter().collect();
+                *last_read = Some((best_hash, registered.clone()));
+                registered
+            }
+            Err(e) => {
+                debug!(target: "aleph-network", "Could not read registered validator addresses: {:?}", e);
+                HashMap::new()
+            }
+        }
+    }
+
+    fn register(&self, authority: AuthorityId, addresses: RegisteredAddresses) {
+        match self
+            .client
+            .runtime_api()
+            .submit_validator_addresses_unsigned_extrinsic(&self.best_block(), authority, addresses)
+        {
+            Ok(Some(())) => {
+                debug!(target: "aleph-network", "Submitted our addresses for registration.")
+            }
+            Ok(None) => {
+                warn!(target: "aleph-network", "Address registration was not accepted by the transaction pool.")
+            }
+            Err(e) => {
+                warn!(target: "aleph-network", "Failed to submit address registration: {:?}", e)
+            }
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use std::{
     collections::{HashMap, HashSet, VecDeque},
     fmt::Debug,
+    time::Instant,
 };
 
 use aleph_bft::Recipient;
@@ -34,6 +35,7 @@ pub struct BlockSignatureAggregator<H: Hash + Copy, PMS> {
     hash_queue: VecDeque<H>,
     last_hash_placed: bool,
     started_hashes: HashSet<H>,
+    started_at: HashMap<H, Instant>,
     metrics: Option<Metrics<H>>,
 }
 
@@ -44,6 +46,7 @@ impl<H: Copy + Hash, PMS> BlockSignatureAggregator<H, PMS> {
             hash_queue: VecDeque::new(),
             last_hash_placed: false,
             started_hashes: HashSet::new(),
+            started_at: HashMap::new(),
             metrics,
         }
     }
@@ -53,7 +56,9 @@ impl<H: Copy + Hash, PMS> BlockSignatureAggregator<H, PMS> {
             return Err(AggregatorError::DuplicateHash);
         }
         if let Some(metrics) = &self.metrics {
-            metrics.report_block(hash, std::time::Instant::now(), Checkpoint::Aggregating);
+            let now = Instant::now();
+            metrics.report_block(hash, now, Checkpoint::Aggregating);
+            self.started_at.insert(hash, now);
         }
         self.hash_queue.push_back(hash);
 
@@ -66,6 +71,9 @@ impl<H: Copy + Hash, PMS> BlockSignatureAggregator<H, PMS> {
 
     fn on_multisigned_hash(&mut self, hash: H, signature: PMS) {
         debug!(target: "aleph-aggregator", "New multisigned_hash {:?}.", hash);
+        if let (Some(metrics), Some(start)) = (&self.metrics, self.started_at.remove(&hash)) {
+            metrics.events().report_multisignature(start.elapsed());
+        }
         self.signatures.insert(hash, signature);
     }
 
@@ -90,15 +98,18 @@ impl<H: Copy + Hash, PMS> BlockSignatureAggregator<H, PMS> {
     }
 }
 
+/// Passes messages between the network and the multicast. The network data `D` might contain
+/// messages of other kinds than `M`, the ones of the multicast, which get ignored.
 pub struct IO<
     H: Hash + Copy,
-    D: Clone + Codec + Debug + Send + Sync + 'static,
+    D: Clone + Codec + Debug + Send + Sync + 'static + TryInto<M>,
+    M: Debug + Into<D>,
     N: DataNetwork<D>,
     PMS,
     RMC: Multicast<H, PMS>,
 > {
-    messages_for_rmc: mpsc::UnboundedSender<D>,
-    messages_from_rmc: mpsc::UnboundedReceiver<D>,
+    messages_for_rmc: mpsc::UnboundedSender<M>,
+    messages_from_rmc: mpsc::UnboundedReceiver<M>,
     network: N,
     multicast: RMC,
     aggregator: BlockSignatureAggregator<H, PMS>,
@@ -106,15 +117,16 @@ pub struct IO<
 
 impl<
         H: Copy + Hash,
-        D: Clone + Codec + Debug + Send + Sync,
+        D: Clone + Codec + Debug + Send + Sync + TryInto<M>,
+        M: Debug + Into<D>,
         N: DataNetwork<D>,
         PMS,
         RMC: Multicast<H, PMS>,
-    > IO<H, D, N, PMS, RMC>
+    > IO<H, D, M, N, PMS, RMC>
 {
     pub(crate) fn new(
-        messages_for_rmc: mpsc::UnboundedSender<D>,
-        messages_from_rmc: mpsc::UnboundedReceiver<D>,
+        messages_for_rmc: mpsc::UnboundedSender<M>,
+        messages_from_rmc: mpsc::UnboundedReceiver<M>,
         network: N,
         multicast: RMC,
         aggregator: BlockSignatureAggregator<H, PMS>,
@@ -154,7 +166,7 @@ impl<
                     trace!(target: "aleph-aggregator", "Our rmc message {:?}.", message_from_rmc);
                     match message_from_rmc {
                         Some(message_from_rmc) => {
-                            self.network.send(message_from_rmc, Recipient::Everyone)
+                            self.network.send(message_from_rmc.into(), Recipient::Everyone)
                                         .expect("sending message from rmc failed");
                         },
                         None => {
@@ -166,8 +178,11 @@ impl<
                     match message_from_network {
                         Some(message_from_network) => {
                             trace!(target: "aleph-aggregator", "Received message for rmc: {:?}", message_from_network);
-                            self.messages_for_rmc.unbounded_send(message_from_network)
-                                                 .expect("sending message to rmc failed");
+                            match message_from_network.try_into() {
+                                Ok(message) => self.messages_for_rmc.unbounded_send(message)
+                                                   .expect("sending message to rmc failed"),
+                                Err(_) => debug!(target: "aleph-aggregator", "Ignoring a message of a multicast with different signatures than ours."),
+                            }
                         },
                         None => {
                             // In case the network is down we can terminate (?).
@@ -1,8 +1,9 @@
-use aleph_bft::SignatureSet;
+use aleph_bft::{Keychain, MultiKeychain, SignatureSet};
 use aleph_bft_rmc::Message;
+use codec::{Decode, Encode, Error as CodecError, Input as CodecInput, Output as CodecOutput};
 use sp_runtime::traits::Block;
 
-use crate::crypto::Signature;
+use crate::crypto::{AggregatedSignature, BlsSignature, Signature};
 
 mod aggregator;
 mod multicast;
@@ -10,5 +11,216 @@ mod multicast;
 pub use aggregator::{BlockSignatureAggregator, IO};
 pub use multicast::SignableHash;
 
-pub type RmcNetworkData<B> =
+/// Messages of the reliable multicast run with the keychain.
+pub type RmcMessage<B, K> = Message<
+    SignableHash<<B as Block>::Hash>,
+    <K as Keychain>::Signature,
+    <K as MultiKeychain>::PartialMultisignature,
+>;
+
+/// Messages of the multicast gathering signatures of all the committee members separately.
+pub type CommitteeRmcMessage<B> =
     Message<SignableHash<<B as Block>::Hash>, Signature, SignatureSet<Signature>>;
+
+/// Messages of the multicast aggregating BLS signatures of the committee members.
+pub type AggregatedRmcMessage<B> =
+    Message<SignableHash<<B as Block>::Hash>, BlsSignature, AggregatedSignature>;
+
+/// The first byte of the encoding of aggregated messages. Committee messages start with their
+/// variant index, which is far smaller.
+const AGGREGATED_TAG: u8 = u8::MAX;
+
+/// Messages of the multicast signing blocks. A session uses aggregated BLS signatures if all its
+/// committee members registered BLS keys, and separate signatures otherwise.
+///
+/// Committee messages are encoded exactly like they were before aggregated signatures, so that
+/// sessions without BLS keys can include older nodes.
+#[derive(Clone, Debug)]
+pub enum RmcNetworkData<B: Block> {
+    Committee(CommitteeRmcMessage<B>),
+    Aggregated(AggregatedRmcMessage<B>),
+}
+
+impl<B: Block> Encode for RmcNetworkData<B> {
+    fn size_hint(&self) -> usize {
+        match self {
+            RmcNetworkData::Committee(message) => message.size_hint(),
+            RmcNetworkData::Aggregated(message) => AGGREGATED_TAG.size_hint() + message.size_hint(),
+        }
+    }
+
+    fn encode_to<T: CodecOutput + ?Sized>(&self, dest: &mut T) {
+        match self {
+            RmcNetworkData::Committee(message) => message.encode_to(dest),
+            RmcNetworkData::Aggregated(message) => {
+                AGGREGATED_TAG.encode_to(dest);
+                message.encode_to(dest);
+            }
+        }
+    }
+}
+
+/// An input with a single byte put back in front of it.
+struct PrefixedInput<'a, I: CodecInput> {
+    prefix: Option<u8>,
+    input: &'a mut I,
+}
+
+impl<'a, I: CodecInput> CodecInput for PrefixedInput<'a, I> {
+    fn remaining_len(&mut self) -> Result<Option<usize>, CodecError> {
+        let prefix_len = match self.prefix {
+            Some(_) => 1,
+            None => 0,
+        };
+        Ok(self.input.remaining_len()?.map(|len| len + prefix_len))
+    }
+
+    fn read(&mut self, into: &mut [u8]) -> Result<(), CodecError> {
+        match (self.prefix, into.is_empty()) {
+            (Some(byte), false) => {
+                self.prefix = None;
+                into[0] = byte;
+                self.input.read(&mut into[1..])
+            }
+            _ => self.input.read(into),
+        }
+    }
+}
+
+impl<B: Block> Decode for RmcNetworkData<B> {
+    fn decode<I: CodecInput>(input: &mut I) -> Result<Self, CodecError> {
+        match input.read_byte()? {
+            AGGREGATED_TAG => Ok(RmcNetworkData::Aggregated(Decode::decode(input)?)),
+            byte => Ok(RmcNetworkData::Committee(Decode::decode(
+                &mut PrefixedInput {
+                    prefix: Some(byte),
+                    input,
+                },
+            )?)),
+        }
+    }
+}
+
+impl<B: Block> From<CommitteeRmcMessage<B>> for RmcNetworkData<B> {
+    fn from(message: CommitteeRmcMessage<B>) -> Self {
+        RmcNetworkData::Committee(message)
+    }
+}
+
+impl<B: Block> From<AggregatedRmcMessage<B>> for RmcNetworkData<B> {
+    fn from(message: AggregatedRmcMessage<B>) -> Self {
+        RmcNetworkData::Aggregated(message)
+    }
+}
+
+impl<B: Block> TryFrom<RmcNetworkData<B>> for CommitteeRmcMessage<B> {
+    type Error = ();
+
+    fn try_from(data: RmcNetworkData<B>) -> Result<Self, ()> {
+        match data {
+            RmcNetworkData::Committee(message) => Ok(message),
+            RmcNetworkData::Aggregated(_) => Err(()),
+        }
+    }
+}
+
+impl<B: Block> TryFrom<RmcNetworkData<B>> for AggregatedRmcMessage<B> {
+    type Error = ();
+
+    fn try_from(data: RmcNetworkData<B>) -> Result<Self, ()> {
+        match data {
+            RmcNetworkData::Aggregated(message) => Ok(message),
+            RmcNetworkData::Committee(_) => Err(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use aleph_bft::{Keychain as BftKeychain, MultiKeychain, NodeIndex};
+    use aleph_bft_rmc::{DoublingDelayScheduler, ReliableMulticast};
+    use codec::{Decode, Encode};
+    use futures::{channel::mpsc, StreamExt};
+
+    use super::{AggregatedRmcMessage, CommitteeRmcMessage, RmcMessage, RmcNetworkData};
+    use crate::{
+        aggregation::SignableHash,
+        crypto::{generate_bls_pens, AggregatingKeychain, Keychain},
+        network::mock::crypto_basics,
+        testing::mocks::{TBlock, THash},
+    };
+
+    /// The first message the multicast sends after starting to sign a hash.
+    async fn first_message<K: MultiKeychain>(keychain: &K) -> RmcMessage<TBlock, K> {
+        let (_messages_for_rmc, messages_from_network) = mpsc::unbounded();
+        let (messages_for_network, mut messages_from_rmc) = mpsc::unbounded();
+        let mut rmc = ReliableMulticast::new(
+            messages_from_network,
+            messages_for_network,
+            keychain,
+            keychain.node_count(),
+            DoublingDelayScheduler::new(Duration::from_millis(10)),
+        );
+        rmc.start_rmc(SignableHash::new(THash::repeat_byte(1)))
+            .await;
+        tokio::select! {
+            message = messages_from_rmc.next() => message.expect("the multicast should send a message"),
+            _ = rmc.next_multisigned_hash() => panic!("a single signature should not be complete"),
+        }
+    }
+
+    async fn committee_message() -> CommitteeRmcMessage<TBlock> {
+        let (mut crypto_basics, verifier) = crypto_basics(4).await;
+        let (node_id, pen) = crypto_basics.remove(0);
+        first_message(&Keychain::new(node_id, verifier, pen)).await
+    }
+
+    async fn aggregated_message() -> AggregatedRmcMessage<TBlock> {
+        let (mut pens, verifier) = generate_bls_pens(4).await;
+        first_message(&AggregatingKeychain::new(
+            NodeIndex(0),
+            verifier,
+            pens.remove(0),
+        ))
+        .await
+    }
+
+    #[tokio::test]
+    async fn encodes_committee_messages_like_before() {
+        let message = committee_message().await;
+        let data = RmcNetworkData::<TBlock>::from(message.clone());
+        assert_eq!(data.encode(), message.encode());
+        let decoded = RmcNetworkData::<TBlock>::decode(&mut message.encode().as_slice())
+            .expect("the message should decode");
+        assert_eq!(
+            CommitteeRmcMessage::<TBlock>::try_from(decoded)
+                .expect("the message should be a committee one")
+                .encode(),
+            message.encode()
+        );
+    }
+
+    #[tokio::test]
+    async fn decodes_aggregated_messages() {
+        let message = aggregated_message().await;
+        let encoded = RmcNetworkData::<TBlock>::from(message.clone()).encode();
+        assert_ne!(encoded, message.encode());
+        let decoded = RmcNetworkData::<TBlock>::decode(&mut encoded.as_slice())
+            .expect("the message should decode");
+        assert!(CommitteeRmcMessage::<TBlock>::try_from(decoded.clone()).is_err());
+        assert_eq!(
+            AggregatedRmcMessage::<TBlock>::try_from(decoded)
+                .expect("the message should be an aggregated one")
+                .encode(),
+            message.encode()
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_aggregated_messages_as_committee_ones() {
+        let encoded = RmcNetworkData::<TBlock>::from(aggregated_message().await).encode();
+        assert!(CommitteeRmcMessage::<TBlock>::decode(&mut encoded.as_slice()).is_err());
+    }
+}
@@ -5,12 +5,10 @@
 
 use std::{fmt::Debug, hash::Hash as StdHash};
 
-use aleph_bft::{Signable, SignatureSet};
+use aleph_bft::{MultiKeychain, Signable};
 use aleph_bft_rmc::ReliableMulticast;
 use codec::{Codec, Decode, Encode};
 
-use crate::crypto::{Keychain, Signature};
-
 /// A convenience trait for gathering all of the desired hash characteristics.
 pub trait Hash: AsRef<[u8]> + StdHash + Eq + Clone + Codec + Debug + Send + Sync {}
 
@@ -50,14 +48,14 @@ pub trait Multicast<H: Hash, PMS>: Send + Sync {
 }
 
 #[async_trait::async_trait]
-impl<'a, H: Hash> Multicast<H, SignatureSet<Signature>>
-    for ReliableMulticast<'a, SignableHash<H>, Keychain>
+impl<'a, H: Hash, K: MultiKeychain> Multicast<H, K::PartialMultisignature>
+    for ReliableMulticast<'a, SignableHash<H>, K>
 {
     async fn start_multicast(&mut self, hash: SignableHash<H>) {
         self.start_rmc(hash).await;
     }
 
-    async fn next_signed_pair(&mut self) -> (H, SignatureSet<Signature>) {
+    async fn next_signed_pair(&mut self) -> (H, K::PartialMultisignature) {
         let ms = self.next_multisigned_hash().await.into_unchecked();
         (ms.as_signable().get_hash(), ms.signature())
     }
@@ -0,0 +1,544 @@
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+};
+
+use aleph_bft::{
+    Index, Keychain as AlephKeychain, MultiKeychain, NodeCount, NodeIndex, PartialMultisignature,
+};
+use aleph_primitives::{
+    BlsKey, BLS_KEY_TYPE, BLS_POP_DST, BLS_PUBLIC_KEY_SIZE as PUBLIC_KEY_SIZE,
+    BLS_SIGNATURE_SIZE as SIGNATURE_SIZE,
+};
+use blst::{
+    min_pk::{AggregateSignature, PublicKey, SecretKey, Signature},
+    BLST_ERROR,
+};
+use codec::{Decode, Encode, Error as CodecError, Input as CodecInput, Output as CodecOutput};
+use log::warn;
+use parking_lot::RwLock;
+use rand::{rngs::OsRng, RngCore};
+use sp_core::{
+    bytes::{from_hex, to_hex},
+    hexdisplay::HexDisplay,
+};
+
+use crate::crypto::Error;
+
+/// Domain separation tag of the BLS signatures, as in the proof of possession ciphersuite for
+/// signatures in G2 and public keys in G1 from the IETF BLS signature draft. Aggregating
+/// signatures of the same message is only safe with keys whose possession was proven.
+const DST: &[u8] = b"BLS_SIG_BLS12381G2_XMD:SHA-256_SSWU_RO_POP_";
+
+const SECRET_KEY_SIZE: usize = 32;
+
+/// A BLS public key, in G1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlsPublicKey(PublicKey);
+
+impl From<BlsPublicKey> for BlsKey {
+    fn from(key: BlsPublicKey) -> Self {
+        BlsKey(key.0.to_bytes())
+    }
+}
+
+impl TryFrom<&BlsKey> for BlsPublicKey {
+    type Error = Error;
+
+    fn try_from(key: &BlsKey) -> Result<Self, Error> {
+        PublicKey::key_validate(&key.0)
+            .map(BlsPublicKey)
+            .map_err(|_| Error::Conversion)
+    }
+}
+
+impl Encode for BlsPublicKey {
+    fn size_hint(&self) -> usize {
+        PUBLIC_KEY_SIZE
+    }
+
+    fn encode_to<T: CodecOutput + ?Sized>(&self, dest: &mut T) {
+        dest.write(&self.0.to_bytes())
+    }
+}
+
+impl Decode for BlsPublicKey {
+    fn decode<I: CodecInput>(input: &mut I) -> Result<Self, CodecError> {
+        let bytes = <[u8; PUBLIC_KEY_SIZE]>::decode(input)?;
+        PublicKey::key_validate(&bytes)
+            .map(BlsPublicKey)
+            .map_err(|_| "invalid BLS public key".into())
+    }
+}
+
+/// A BLS signature, in G2. Might be an aggregate of several signatures.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlsSignature(Signature);
+
+impl Encode for BlsSignature {
+    fn size_hint(&self) -> usize {
+        SIGNATURE_SIZE
+    }
+
+    fn encode_to<T: CodecOutput + ?Sized>(&self, dest: &mut T) {
+        dest.write(&self.0.to_bytes())
+    }
+}
+
+impl Decode for BlsSignature {
+    fn decode<I: CodecInput>(input: &mut I) -> Result<Self, CodecError> {
+        let bytes = <[u8; SIGNATURE_SIZE]>::decode(input)?;
+        Signature::from_bytes(&bytes)
+            .map(BlsSignature)
+            .map_err(|_| "invalid BLS signature".into())
+    }
+}
+
+/// Signs messages with a BLS secret key.
+#[derive(Clone)]
+pub struct BlsPen {
+    secret: SecretKey,
+}
+
+impl BlsPen {
+    /// Generates a new secret key from system randomness.
+    pub fn generate() -> Self {
+        let mut seed = [0u8; SECRET_KEY_SIZE];
+        OsRng.fill_bytes(&mut seed);
+        let secret = SecretKey::key_gen(&seed, &[]).expect("the seed is long enough");
+        BlsPen { secret }
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        SecretKey::from_bytes(bytes)
+            .map(|secret| BlsPen { secret })
+            .map_err(|_| Error::Conversion)
+    }
+
+    pub fn public(&self) -> BlsPublicKey {
+        BlsPublicKey(self.secret.sk_to_pk())
+    }
+
+    pub fn sign(&self, msg: &[u8]) -> BlsSignature {
+        BlsSignature(self.secret.sign(msg, DST, &[]))
+    }
+
+    /// Proves that we know the secret key, by signing the public key in the proof of possession
+    /// domain. The proofs are checked when registering keys on chain.
+    pub fn prove_possession(&self) -> [u8; SIGNATURE_SIZE] {
+        let public = self.public().0.to_bytes();
+        self.secret.sign(&public, BLS_POP_DST, &[]).to_bytes()
+    }
+}
+
+/// Keeps the BLS secret keys of the node. Every key is stored in its own file in the keystore
+/// directory, named like the files of the Substrate keystore: the hex encoded key type followed
+/// by the hex encoded public key. Without a directory the keys are only kept in memory.
+pub struct BlsKeystore {
+    path: Option<PathBuf>,
+    pens: RwLock<HashMap<BlsKey, BlsPen>>,
+}
+
+impl BlsKeystore {
+    /// Opens the keystore in the directory, loading all the BLS keys in it.
+    pub fn open(path: Option<PathBuf>) -> Result<Self, Error> {
+        let mut pens = HashMap::new();
+        if let Some(path) = &path {
+            fs::create_dir_all(path).map_err(Error::Io)?;
+            let prefix = HexDisplay::from(&BLS_KEY_TYPE.0).to_string();
+            for entry in fs::read_dir(path).map_err(Error::Io)? {
+                let entry = entry.map_err(Error::Io)?;
+                if !entry.file_name().to_string_lossy().starts_with(&prefix) {
+                    continue;
+                }
+                let contents = fs::read_to_string(entry.path()).map_err(Error::Io)?;
+                let bytes = from_hex(contents.trim()).map_err(|_| Error::Conversion)?;
+                let pen = BlsPen::from_bytes(&bytes)?;
+                pens.insert(pen.public().into(), pen);
+            }
+        }
+        Ok(BlsKeystore {
+            path,
+            pens: RwLock::new(pens),
+        })
+    }
+
+    /// A keystore keeping the keys only in memory.
+    pub fn in_memory() -> Self {
+        BlsKeystore {
+            path: None,
+            pens: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// The pen for the key, if we have its secret.
+    pub fn pen(&self, key: &BlsKey) -> Option<BlsPen> {
+        self.pens.read().get(key).cloned()
+    }
+
+    /// Some pen of ours, a newly generated one if we have none. The keys are never removed, so
+    /// this returns the same key every time for a given keystore.
+    pub fn any_pen(&self) -> Result<BlsPen, Error> {
+        let mut pens = self.pens.write();
+        if let Some(pen) = pens
+            .iter()
+            .min_by_key(|(key, _)| key.0)
+            .map(|(_, pen)| pen.clone())
+        {
+            return Ok(pen);
+        }
+        let pen = BlsPen::generate();
+        let key = pen.public().into();
+        self.store(&key, &pen)?;
+        pens.insert(key, pen.clone());
+        Ok(pen)
+    }
+
+    fn store(&self, key: &BlsKey, pen: &BlsPen) -> Result<(), Error> {
+        let path = match &self.path {
+            Some(path) => path.join(format!(
+                "{}{}",
+                HexDisplay::from(&BLS_KEY_TYPE.0),
+                HexDisplay::from(&key.0)
+            )),
+            None => return Ok(()),
+        };
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(path).map_err(Error::Io)?;
+        file.write_all(to_hex(&pen.secret.to_bytes(), false).as_bytes())
+            .and_then(|_| file.sync_all())
+            .map_err(Error::Io)
+    }
+}
+
+/// A single signature aggregated from the signatures of a number of nodes, together with a bitmap
+/// of the nodes that signed. Its size does not depend on the number of signers, apart from the
+/// bitmap.
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct AggregatedSignature {
+    signers: Vec<u8>,
+    signature: BlsSignature,
+}
+
+impl AggregatedSignature {
+    pub fn new(signature: &BlsSignature, index: NodeIndex) -> Self {
+        let mut result = AggregatedSignature {
+            signers: Vec::new(),
+            signature: *signature,
+        };
+        result.mark_signer(index);
+        result
+    }
+
+    fn mark_signer(&mut self, index: NodeIndex) {
+        let byte = index.0 / 8;
+        if self.signers.len() <= byte {
+            self.signers.resize(byte + 1, 0);
+        }
+        self.signers[byte] |= 1 << (index.0 % 8);
+    }
+
+    /// Whether the node with the given index contributed to the signature.
+    pub fn has_signer(&self, index: NodeIndex) -> bool {
+        match self.signers.get(index.0 / 8) {
+            Some(byte) => byte & (1 << (index.0 % 8)) != 0,
+            None => false,
+        }
+    }
+
+    /// The indices of all the nodes that contributed to the signature, in increasing order.
+    pub fn signers(&self) -> impl Iterator<Item = NodeIndex> + '_ {
+        (0..self.signers.len() * 8)
+            .map(NodeIndex)
+            .filter(|index| self.has_signer(*index))
+    }
+}
+
+impl PartialMultisignature for AggregatedSignature {
+    type Signature = BlsSignature;
+
+    fn add_signature(mut self, signature: &BlsSignature, index: NodeIndex) -> Self {
+        if self.has_signer(index) {
+            return self;
+        }
+        let mut aggregate = AggregateSignature::from_signature(&self.signature.0);
+        if let Err(e) = aggregate.add_signature(&signature.0, true) {
+            warn!(target: "aleph-justification", "Not aggregating an invalid BLS signature of node {:?}: {:?}", index, e);
+            return self;
+        }
+        self.signature = BlsSignature(aggregate.to_signature());
+        self.mark_signer(index);
+        self
+    }
+}
+
+/// Holds the BLS public keys of the authorities of a session, allowing for verification of
+/// individual and aggregated signatures from that session.
+#[derive(Clone)]
+pub struct BlsVerifier {
+    keys: Vec<BlsPublicKey>,
+}
+
+impl BlsVerifier {
+    pub fn new(keys: Vec<BlsPublicKey>) -> Self {
+        BlsVerifier { keys }
+    }
+
+    /// A verifier for the keys registered on chain, if all of them are valid.
+    pub fn from_keys(keys: &[BlsKey]) -> Option<Self> {
+        keys.iter()
+            .map(BlsPublicKey::try_from)
+            .collect::<Result<_, _>>()
+            .map(BlsVerifier::new)
+            .ok()
+    }
+
+    /// Verifies whether the message is correctly signed by the node of the given index.
+    pub fn verify(&self, msg: &[u8], sgn: &BlsSignature, index: NodeIndex) -> bool {
+        match self.keys.get(index.0) {
+            Some(key) => {
+                sgn.0.verify(true, msg, DST, &[], &key.0, false) == BLST_ERROR::BLST_SUCCESS
+            }
+            None => false,
+        }
+    }
+
+    pub fn node_count(&self) -> NodeCount {
+        self.keys.len().into()
+    }
+
+    fn threshold(&self) -> usize {
+        2 * self.node_count().0 / 3 + 1
+    }
+
+    /// Verifies whether the given aggregated signature is a correct and complete multisignature of
+    /// the message. Completeness requires more than 2/3 of all authorities.
+    pub fn is_complete(&self, msg: &[u8], partial: &AggregatedSignature) -> bool {
+        let keys: Option<Vec<_>> = partial
+            .signers()
+            .map(|index| self.keys.get(index.0).map(|key| &key.0))
+            .collect();
+        let keys = match keys {
+            Some(keys) => keys,
+            None => return false,
+        };
+        if keys.len() < self.threshold() {
+            return false;
+        }
+        partial
+            .signature
+            .0
+            .fast_aggregate_verify(true, msg, DST, &keys)
+            == BLST_ERROR::BLST_SUCCESS
+    }
+}
+
+/// An AlephBFT MultiKeychain producing aggregated BLS multisignatures, whose size is independent
+/// of the committee size.
+#[derive(Clone)]
+pub struct AggregatingKeychain {
+    id: NodeIndex,
+    pen: BlsPen,
+    verifier: BlsVerifier,
+}
+
+impl AggregatingKeychain {
+    pub fn new(id: NodeIndex, verifier: BlsVerifier, pen: BlsPen) -> Self {
+        AggregatingKeychain { id, pen, verifier }
+    }
+}
+
+impl Index for AggregatingKeychain {
+    fn index(&self) -> NodeIndex {
+        self.id
+    }
+}
+
+#[async_trait::async_trait]
+impl AlephKeychain for AggregatingKeychain {
+    type Signature = BlsSignature;
+
+    fn node_count(&self) -> NodeCount {
+        self.verifier.node_count()
+    }
+
+    async fn sign(&self, msg: &[u8]) -> BlsSignature {
+        self.pen.sign(msg)
+    }
+
+    fn verify(&self, msg: &[u8], sgn: &BlsSignature, index: NodeIndex) -> bool {
+        self.verifier.verify(msg, sgn, index)
+    }
+}
+
+impl MultiKeychain for AggregatingKeychain {
+    type PartialMultisignature = AggregatedSignature;
+
+    fn bootstrap_multi(&self, signature: &BlsSignature, index: NodeIndex) -> AggregatedSignature {
+        AggregatedSignature::new(signature, index)
+    }
+
+    fn is_complete(&self, msg: &[u8], partial: &AggregatedSignature) -> bool {
+        self.verifier.is_complete(msg, partial)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use aleph_bft::{MultiKeychain, NodeIndex, PartialMultisignature};
+    use aleph_primitives::{aleph_bls, BlsKey};
+    use codec::{Decode, Encode};
+
+    use super::{
+        AggregatedSignature, AggregatingKeychain, BlsKeystore, BlsPen, BlsSignature, BlsVerifier,
+    };
+
+    pub async fn generate_pens(count: usize) -> (Vec<BlsPen>, BlsVerifier) {
+        let pens: Vec<_> = (0..count).map(|_| BlsPen::generate()).collect();
+        let verifier = BlsVerifier::new(pens.iter().map(|pen| pen.public()).collect());
+        (pens, verifier)
+    }
+
+    fn aggregate(pens: &[BlsPen], signers: &[usize], msg: &[u8]) -> AggregatedSignature {
+        let mut signers = signers.iter();
+        let first = *signers.next().expect("there is at least one signer");
+        signers.fold(
+            AggregatedSignature::new(&pens[first].sign(msg), NodeIndex(first)),
+            |aggregated, signer| {
+                aggregated.add_signature(&pens[*signer].sign(msg), NodeIndex(*signer))
+            },
+        )
+    }
+
+    #[test]
+    fn proves_possession_of_keys() {
+        let pen = BlsPen::generate();
+        let other = BlsPen::generate();
+        let key = BlsKey::from(pen.public());
+        assert!(aleph_bls::verify_proof_of_possession(
+            &key.0,
+            &pen.prove_possession()
+        ));
+        assert!(!aleph_bls::verify_proof_of_possession(
+            &key.0,
+            &other.prove_possession()
+        ));
+        // An ordinary signature of the key is not a proof of possession.
+        assert!(!aleph_bls::verify_proof_of_possession(
+            &key.0,
+            &pen.sign(&key.0).encode()
+        ));
+    }
+
+    #[test]
+    fn keeps_keys_between_restarts() {
+        let dir = tempfile::tempdir().unwrap();
+        let keystore = BlsKeystore::open(Some(dir.path().to_path_buf())).unwrap();
+        let key = BlsKey::from(keystore.any_pen().unwrap().public());
+        assert_eq!(BlsKey::from(keystore.any_pen().unwrap().public()), key);
+
+        let reopened = BlsKeystore::open(Some(dir.path().to_path_buf())).unwrap();
+        let pen = reopened.pen(&key).expect("the key is stored");
+        assert_eq!(BlsKey::from(pen.public()), key);
+        assert_eq!(BlsKey::from(reopened.any_pen().unwrap().public()), key);
+        assert!(reopened
+            .pen(&BlsKey::from(BlsPen::generate().public()))
+            .is_none());
+    }
+
+    #[test]
+    fn generates_different_keys() {
+        let first = BlsKeystore::in_memory().any_pen().unwrap();
+        let second = BlsKeystore::in_memory().any_pen().unwrap();
+        assert_ne!(first.public(), second.public());
+    }
+
+    #[tokio::test]
+    async fn verifies_individual_signatures() {
+        let (pens, verifier) = generate_pens(3).await;
+        let msg = b"test";
+        for (i, pen) in pens.iter().enumerate() {
+            let signature = pen.sign(msg);
+            assert!(verifier.verify(msg, &signature, NodeIndex(i)));
+            assert!(!verifier.verify(b"not test", &signature, NodeIndex(i)));
+            assert!(!verifier.verify(msg, &signature, NodeIndex((i + 1) % pens.len())));
+        }
+    }
+
+    #[tokio::test]
+    async fn accepts_complete_aggregated_signatures() {
+        let (pens, verifier) = generate_pens(7).await;
+        let msg = b"test";
+        let aggregated = aggregate(&pens, &[0, 2, 3, 5, 6], msg);
+        assert!(verifier.is_complete(msg, &aggregated));
+        assert_eq!(
+            aggregated.signers().collect::<Vec<_>>(),
+            vec![
+                NodeIndex(0),
+                NodeIndex(2),
+                NodeIndex(3),
+                NodeIndex(5),
+                NodeIndex(6)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_incomplete_or_wrong_aggregated_signatures() {
+        let (pens, verifier) = generate_pens(7).await;
+        let msg = b"test";
+        let incomplete = aggregate(&pens, &[0, 2, 3, 5], msg);
+        assert!(!verifier.is_complete(msg, &incomplete));
+        let complete = aggregate(&pens, &[0, 2, 3, 5, 6], msg);
+        assert!(!verifier.is_complete(b"not test", &complete));
+        let mut wrong_signers = complete;
+        wrong_signers.signers = vec![0b01111100];
+        assert!(!verifier.is_complete(msg, &wrong_signers));
+    }
+
+    #[tokio::test]
+    async fn ignores_repeated_signatures() {
+        let (pens, verifier) = generate_pens(4).await;
+        let msg = b"test";
+        let aggregated = aggregate(&pens, &[0, 1, 1, 1, 2], msg);
+        assert_eq!(aggregated, aggregate(&pens, &[0, 1, 2], msg));
+        assert!(verifier.is_complete(msg, &aggregated));
+    }
+
+    #[tokio::test]
+    async fn works_as_multikeychain() {
+        let (mut pens, verifier) = generate_pens(4).await;
+        let msg = b"test";
+        let keychain = AggregatingKeychain::new(NodeIndex(0), verifier, pens.remove(0));
+        let multisignature = keychain.bootstrap_multi(&keychain.pen.sign(msg), NodeIndex(0));
+        assert!(!keychain.is_complete(msg, &multisignature));
+        let multisignature =
+            pens.iter()
+                .enumerate()
+                .fold(multisignature, |multisignature, (i, pen)| {
+                    multisignature.add_signature(&pen.sign(msg), NodeIndex(i + 1))
+                });
+        assert!(keychain.is_complete(msg, &multisignature));
+    }
+
+    #[tokio::test]
+    async fn encodes_and_decodes() {
+        let (pens, _) = generate_pens(4).await;
+        let msg = b"test";
+        let aggregated = aggregate(&pens, &[0, 1, 3], msg);
+        let encoded = aggregated.encode();
+        assert_eq!(
+            AggregatedSignature::decode(&mut encoded.as_slice()),
+            Ok(aggregated)
+        );
+        assert!(BlsSignature::decode(&mut [7u8; 96].as_slice()).is_err());
+    }
+}
@@ -1,20 +1,30 @@
-use std::{convert::TryInto, sync::Arc};
+use std::{borrow::Cow, convert::TryInto, io, sync::Arc};
 
 use aleph_bft::{
     Keychain as AlephKeychain, MultiKeychain, NodeCount, NodeIndex, PartialMultisignature,
     SignatureSet,
 };
-use aleph_primitives::{AuthorityId, AuthoritySignature, KEY_TYPE};
+use aleph_primitives::{aleph_bft_signing_payload, AuthorityId, AuthoritySignature, KEY_TYPE};
 use codec::{Decode, Encode};
 use sp_core::crypto::KeyTypeId;
 use sp_keystore::{CryptoStore, Error as KeystoreError};
 use sp_runtime::RuntimeAppPublic;
 
+mod bls;
+
+#[cfg(test)]
+pub use bls::tests::generate_pens as generate_bls_pens;
+pub use bls::{
+    AggregatedSignature, AggregatingKeychain, BlsKeystore, BlsPen, BlsPublicKey, BlsSignature,
+    BlsVerifier,
+};
+
 #[derive(Debug)]
 pub enum Error {
     KeyMissing(AuthorityId),
     Keystore(KeystoreError),
     Conversion,
+    Io(io::Error),
 }
 
 #[derive(PartialEq, Eq, Clone, Debug, Decode, Encode)]
@@ -26,6 +36,12 @@ impl From<AuthoritySignature> for Signature {
     }
 }
 
+impl From<Signature> for AuthoritySignature {
+    fn from(signature: Signature) -> AuthoritySignature {
+        signature.0
+    }
+}
+
 /// Ties an authority identification and a cryptography keystore together for use in
 /// signing that requires an authority.
 #[derive(Clone)]
@@ -59,6 +75,11 @@ impl AuthorityPen {
         })
     }
 
+    /// The authority whose key signs the messages.
+    pub fn authority_id(&self) -> AuthorityId {
+        self.authority_id.clone()
+    }
+
     /// Cryptographically signs the message.
     pub async fn sign(&self, msg: &[u8]) -> Signature {
         Signature(
@@ -99,6 +120,14 @@ impl AuthorityVerifier {
         self.authorities.len().into()
     }
 
+    /// The index of the node using the given authority key, if any.
+    pub fn index_of(&self, authority: &AuthorityId) -> Option<NodeIndex> {
+        self.authorities
+            .iter()
+            .position(|candidate| candidate == authority)
+            .map(NodeIndex)
+    }
+
     fn threshold(&self) -> usize {
         2 * self.node_count().0 / 3 + 1
     }
@@ -121,6 +150,7 @@ pub struct Keychain {
     id: NodeIndex,
     authority_pen: AuthorityPen,
     authority_verifier: AuthorityVerifier,
+    with_aleph_bft_context: bool,
 }
 
 impl Keychain {
@@ -135,6 +165,24 @@ impl Keychain {
             id,
             authority_pen,
             authority_verifier,
+            with_aleph_bft_context: false,
+        }
+    }
+
+    /// Makes the keychain sign and verify messages with the AlephBFT signing context, so that its
+    /// signatures cannot be passed off as signatures of anything else. Only for the keychain
+    /// of the AlephBFT member, as the whole committee has to agree on it.
+    pub fn with_aleph_bft_context(self) -> Self {
+        Keychain {
+            with_aleph_bft_context: true,
+            ..self
+        }
+    }
+
+    fn payload<'a>(&self, msg: &'a [u8]) -> Cow<'a, [u8]> {
+        match self.with_aleph_bft_context {
+            true => Cow::Owned(aleph_bft_signing_payload(msg)),
+            false => Cow::Borrowed(msg),
         }
     }
 }
@@ -154,17 +202,18 @@ impl AlephKeychain for Keychain {
     }
 
     async fn sign(&self, msg: &[u8]) -> Signature {
-        self.authority_pen.sign(msg).await
+        self.authority_pen.sign(&self.payload(msg)).await
     }
 
     fn verify(&self, msg: &[u8], sgn: &Signature, index: NodeIndex) -> bool {
-        self.authority_verifier.verify(msg, sgn, index)
+        self.authority_verifier
+            .verify(&self.payload(msg), sgn, index)
     }
 }
 
 impl MultiKeychain for Keychain {
-    // Using `SignatureSet` is slow, but Substrate has not yet implemented aggregation.
-    // We probably should do this for them at some point.
+    // Using `SignatureSet` is slow, see `AggregatingKeychain` for a keychain using aggregated BLS
+    // signatures instead.
     type PartialMultisignature = SignatureSet<Signature>;
 
     fn bootstrap_multi(
@@ -176,7 +225,8 @@ impl MultiKeychain for Keychain {
     }
 
     fn is_complete(&self, msg: &[u8], partial: &Self::PartialMultisignature) -> bool {
-        self.authority_verifier.is_complete(msg, partial)
+        self.authority_verifier
+            .is_complete(&self.payload(msg), partial)
     }
 }
 
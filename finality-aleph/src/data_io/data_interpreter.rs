@@ -26,6 +26,7 @@ pub struct OrderedDataInterpreter<B: BlockT, C: HeaderBackend<B>> {
     chain_info_provider: InterpretersChainInfoProvider<B, C>,
     last_finalized_by_aleph: BlockHashNum<B>,
     session_boundaries: SessionBoundaries<B>,
+    max_branch_len: usize,
 }
 
 fn get_last_block_prev_session<B: BlockT, C: HeaderBackend<B>>(
@@ -51,6 +52,7 @@ impl<B: BlockT, C: HeaderBackend<B>> OrderedDataInterpreter<B, C> {
         blocks_to_finalize_tx: mpsc::UnboundedSender<BlockHashNum<B>>,
         client: Arc<C>,
         session_boundaries: SessionBoundaries<B>,
+        max_branch_len: usize,
     ) -> Self {
         let last_finalized_by_aleph =
             get_last_block_prev_session(session_boundaries.clone(), client.clone());
@@ -64,6 +66,7 @@ impl<B: BlockT, C: HeaderBackend<B>> OrderedDataInterpreter<B, C> {
             chain_info_provider,
             last_finalized_by_aleph,
             session_boundaries,
+            max_branch_len,
         }
     }
 
@@ -71,7 +74,8 @@ impl<B: BlockT, C: HeaderBackend<B>> OrderedDataInterpreter<B, C> {
         match new_data {
             AlephData::Empty => None,
             AlephData::HeadProposal(unvalidated_proposal) => {
-                let proposal = match unvalidated_proposal.validate_bounds(&self.session_boundaries)
+                let proposal = match unvalidated_proposal
+                    .validate_bounds(&self.session_boundaries, self.max_branch_len)
                 {
                     Ok(proposal) => proposal,
                     Err(error) => {
@@ -63,6 +63,7 @@ pub fn get_proposal<B, C>(
     client: &C,
     best_block: BlockHashNum<B>,
     finalized_block: BlockHashNum<B>,
+    max_branch_len: usize,
 ) -> Result<AlephData<B>, ()>
 where
     B: BlockT,
@@ -71,9 +72,7 @@ where
     let mut curr_block = best_block;
     let mut branch: Vec<B::Hash> = Vec::new();
     while curr_block.num > finalized_block.num {
-        if curr_block.num - finalized_block.num
-            <= <NumberFor<B>>::saturated_from(MAX_DATA_BRANCH_LEN)
-        {
+        if curr_block.num - finalized_block.num <= <NumberFor<B>>::saturated_from(max_branch_len) {
             branch.push(curr_block.hash);
         }
         curr_block = get_parent(client, &curr_block).expect("block of num >= 1 must have a parent")
@@ -95,14 +94,17 @@ where
 
 const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_millis(100);
 
+#[derive(Clone, Debug)]
 pub struct ChainTrackerConfig {
     pub refresh_interval: Duration,
+    pub max_branch_len: usize,
 }
 
 impl Default for ChainTrackerConfig {
     fn default() -> ChainTrackerConfig {
         ChainTrackerConfig {
             refresh_interval: DEFAULT_REFRESH_INTERVAL,
+            max_branch_len: MAX_DATA_BRANCH_LEN,
         }
     }
 }
@@ -208,6 +210,7 @@ where
             &*self.client,
             best_block_in_session.clone(),
             finalized_block,
+            self.config.max_branch_len,
         ) {
             *self.data_to_propose.lock().await = proposal;
         }
@@ -311,6 +314,8 @@ impl<B: BlockT> aleph_bft::DataProvider<AlephData<B>> for DataProvider<B> {
         let data = (*self.data_to_propose.lock().await).clone();
 
         if let Some(m) = &self.metrics {
+            // AlephBFT asks for data exactly once per unit it creates.
+            m.events().report_unit_created();
             if let AlephData::HeadProposal(proposal) = &data {
                 m.report_block(
                     *proposal.branch.last().unwrap(),
@@ -363,6 +368,7 @@ mod tests {
 
         let config = ChainTrackerConfig {
             refresh_interval: REFRESH_INTERVAL,
+            max_branch_len: MAX_DATA_BRANCH_LEN,
         };
 
         let (chain_tracker, data_provider) =
@@ -25,9 +25,13 @@ use crate::{
         chain_info::{CachedChainInfoProvider, ChainInfoProvider},
         proposal::{AlephProposal, ProposalStatus},
         status_provider::get_proposal_status,
-        AlephNetworkMessage,
+        AlephNetworkMessage, MAX_DATA_BRANCH_LEN,
+    },
+    metrics::EventMetrics,
+    network::{
+        ComponentNetwork, DataNetwork, ReceiverComponent, RequestBlocks, SenderComponent,
+        SimpleNetwork,
     },
-    network::{ComponentNetwork, DataNetwork, ReceiverComponent, RequestBlocks, SimpleNetwork},
     BlockHashNum, SessionBoundaries,
 };
 
@@ -103,6 +107,7 @@ impl<B: BlockT, M: AlephNetworkMessage<B>> PendingMessageInfo<B, M> {
     }
 }
 
+#[derive(Clone, Debug)]
 pub struct DataStoreConfig {
     pub max_triggers_pending: usize,
     pub max_proposals_pending: usize,
@@ -112,6 +117,8 @@ pub struct DataStoreConfig {
     // Specifies how much time must pass from receiving a given proposal for the first time, till we
     // perform a request for either a block or a justification required to let this proposal through.
     pub request_block_after: Duration,
+    // Maximum number of blocks above the last finalized allowed in a proposal.
+    pub max_branch_len: usize,
 }
 
 impl Default for DataStoreConfig {
@@ -123,6 +130,7 @@ impl Default for DataStoreConfig {
             available_proposals_cache_capacity: 8000,
             periodic_maintenance_interval: Duration::from_secs(25),
             request_block_after: Duration::from_secs(20),
+            max_branch_len: MAX_DATA_BRANCH_LEN,
         }
     }
 }
@@ -193,6 +201,8 @@ where
     config: DataStoreConfig,
     messages_from_network: Arc<Mutex<R>>,
     messages_for_aleph: UnboundedSender<Message>,
+    report_rejected: Box<dyn Fn(Message) + Send + Sync>,
+    metrics: Option<EventMetrics>,
 }
 
 impl<B, C, RB, Message, R> DataStore<B, C, RB, Message, R>
@@ -204,16 +214,22 @@ where
         AlephNetworkMessage<B> + std::fmt::Debug + Send + Sync + Clone + codec::Codec + 'static,
     R: ReceiverComponent<Message>,
 {
-    /// Returns a struct to be run and a network that outputs messages filtered as appropriate
+    /// Returns a struct to be run and a network that outputs messages filtered as appropriate.
+    /// Messages rejected as invalid are reported to the component network.
     pub fn new<N: ComponentNetwork<Message, R = R>>(
         session_boundaries: SessionBoundaries<B>,
         client: Arc<C>,
         block_requester: RB,
         config: DataStoreConfig,
         component_network: N,
-    ) -> (Self, impl DataNetwork<Message>) {
+        metrics: Option<EventMetrics>,
+    ) -> (Self, impl DataNetwork<Message>)
+    where
+        N::S: 'static,
+    {
         let (messages_for_aleph, messages_from_data_store) = mpsc::unbounded();
         let messages_to_network = component_network.sender().clone();
+        let rejects_for_network = component_network.sender().clone();
         let messages_from_network = component_network.receiver();
         let status = client.info();
         let chain_info_provider = CachedChainInfoProvider::new(client.clone(), Default::default());
@@ -235,6 +251,10 @@ where
                 config,
                 messages_from_network,
                 messages_for_aleph,
+                report_rejected: Box::new(move |message| {
+                    rejects_for_network.report_rejected(message)
+                }),
+                metrics,
             },
             SimpleNetwork::new(messages_from_data_store, messages_to_network),
         )
@@ -606,6 +626,7 @@ where
     // Checks if we have exceeded the maximum number of pending messages or proposals.
     // If so, we prune messages until the limits are satisfied again.
     fn prune_pending_messages(&mut self) {
+        let mut dropped = 0;
         while self.pending_messages.len() > self.config.max_messages_pending
             || self.pending_proposals.len() > self.config.max_proposals_pending
         {
@@ -613,6 +634,14 @@ where
                 warn!(target: "aleph-data-store", "Message pruning in DataStore failed. Moving on.");
                 break;
             }
+            dropped += 1;
+        }
+        if let Some(metrics) = &self.metrics {
+            metrics.report_data_store_dropped_messages(dropped);
+            metrics.report_data_store_pending(
+                self.pending_proposals.len(),
+                self.pending_messages.len(),
+            );
         }
     }
 
@@ -636,11 +665,14 @@ where
             match data {
                 Empty => {}
                 HeadProposal(unvalidated_proposal) => {
-                    match unvalidated_proposal.validate_bounds(&self.session_boundaries) {
+                    match unvalidated_proposal
+                        .validate_bounds(&self.session_boundaries, self.config.max_branch_len)
+                    {
                         Ok(proposal) => proposals.push(proposal),
                         Err(error) => {
                             warn!(target: "aleph-data-store", "Message {:?} dropped as it contains \
                             proposal {:?} not within bounds ({:?}).", message, unvalidated_proposal, error);
+                            (self.report_rejected)(message);
                             return;
                         }
                     }
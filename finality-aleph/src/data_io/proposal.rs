@@ -9,7 +9,7 @@ use sp_runtime::{
     SaturatedConversion,
 };
 
-use crate::{data_io::MAX_DATA_BRANCH_LEN, BlockHashNum, SessionBoundaries};
+use crate::{BlockHashNum, SessionBoundaries};
 
 /// Represents a proposal we obtain from another node. Note that since the proposal might come from
 /// a malicious node there is no guarantee that the block hashes in the proposal correspond to real blocks
@@ -78,10 +78,11 @@ impl<B: BlockT> UnvalidatedAlephProposal<B> {
     pub(crate) fn validate_bounds(
         &self,
         session_boundaries: &SessionBoundaries<B>,
+        max_branch_len: usize,
     ) -> Result<AlephProposal<B>, ValidationError<B>> {
         use ValidationError::*;
 
-        if self.branch.len() > MAX_DATA_BRANCH_LEN as usize {
+        if self.branch.len() > max_branch_len {
             return Err(BranchTooLong {
                 branch_size: self.branch.len(),
             });
@@ -236,7 +237,7 @@ mod tests {
         let branch = vec![];
         let proposal = UnvalidatedAlephProposal::new(branch, session_boundaries.first_block());
         assert_eq!(
-            proposal.validate_bounds(&session_boundaries),
+            proposal.validate_bounds(&session_boundaries, MAX_DATA_BRANCH_LEN),
             Err(BranchEmpty)
         );
     }
@@ -249,7 +250,7 @@ mod tests {
         let branch_size = branch.len();
         let proposal = UnvalidatedAlephProposal::new(branch, session_end);
         assert_eq!(
-            proposal.validate_bounds(&session_boundaries),
+            proposal.validate_bounds(&session_boundaries, MAX_DATA_BRANCH_LEN),
             Err(BranchTooLong { branch_size })
         );
     }
@@ -263,7 +264,7 @@ mod tests {
 
         let proposal = UnvalidatedAlephProposal::new(branch.clone(), session_start);
         assert_eq!(
-            proposal.validate_bounds(&session_boundaries),
+            proposal.validate_bounds(&session_boundaries, MAX_DATA_BRANCH_LEN),
             Err(BlockOutsideSessionBoundaries {
                 session_start,
                 session_end,
@@ -274,7 +275,7 @@ mod tests {
 
         let proposal = UnvalidatedAlephProposal::new(branch, session_end + 1);
         assert_eq!(
-            proposal.validate_bounds(&session_boundaries),
+            proposal.validate_bounds(&session_boundaries, MAX_DATA_BRANCH_LEN),
             Err(BlockOutsideSessionBoundaries {
                 session_start,
                 session_end,
@@ -291,7 +292,7 @@ mod tests {
 
         let proposal = UnvalidatedAlephProposal::new(branch, 1);
         assert_eq!(
-            proposal.validate_bounds(&session_boundaries),
+            proposal.validate_bounds(&session_boundaries, MAX_DATA_BRANCH_LEN),
             Err(BlockNumberOutOfBounds {
                 branch_size: 2,
                 block_number: 1
@@ -305,10 +306,14 @@ mod tests {
 
         let branch = vec![H256::default(); MAX_DATA_BRANCH_LEN];
         let proposal = UnvalidatedAlephProposal::new(branch, (MAX_DATA_BRANCH_LEN + 1) as u64);
-        assert!(proposal.validate_bounds(&session_boundaries).is_ok());
+        assert!(proposal
+            .validate_bounds(&session_boundaries, MAX_DATA_BRANCH_LEN)
+            .is_ok());
 
         let branch = vec![H256::default(); 1];
         let proposal = UnvalidatedAlephProposal::new(branch, (MAX_DATA_BRANCH_LEN + 1) as u64);
-        assert!(proposal.validate_bounds(&session_boundaries).is_ok());
+        assert!(proposal
+            .validate_bounds(&session_boundaries, MAX_DATA_BRANCH_LEN)
+            .is_ok());
     }
 }
@@ -174,7 +174,9 @@ mod tests {
         let unvalidated = unvalidated_proposal_from_headers(headers);
         let session_boundaries =
             SessionBoundaries::new(SessionId(0), SessionPeriod(DUMMY_SESSION_LEN));
-        unvalidated.validate_bounds(&session_boundaries).unwrap()
+        unvalidated
+            .validate_bounds(&session_boundaries, MAX_DATA_BRANCH_LEN)
+            .unwrap()
     }
 
     fn proposal_from_blocks(blocks: Vec<Block>) -> AlephProposal<Block> {
@@ -0,0 +1,82 @@
+use std::time::Duration;
+
+use aleph_primitives::{AlephSessionApi, FinalityParameters, FINALITY_PARAMS_API_VERSION};
+use log::warn;
+use sc_client_api::Backend;
+use sp_api::ApiError;
+use sp_runtime::{
+    generic::BlockId,
+    traits::{Block, NumberFor},
+    SaturatedConversion,
+};
+use tokio::time::sleep;
+
+use crate::{last_block_of_session, ClientForAleph, SessionId, SessionPeriod};
+
+/// How many times we try to read the finality parameters before running a session as
+/// non-authority.
+const READ_ATTEMPTS: u32 = 5;
+/// How long we wait between consecutive attempts to read the finality parameters.
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// The block whose state governs the session: the last block of the previous session, or the
+/// genesis for the first one.
+pub(crate) fn governing_block<B: Block>(
+    session_id: SessionId,
+    session_period: SessionPeriod,
+) -> NumberFor<B> {
+    match session_id {
+        SessionId(0) => <NumberFor<B>>::saturated_from(0u32),
+        SessionId(id) => last_block_of_session::<B>(SessionId(id - 1), session_period),
+    }
+}
+
+/// Returns the finality parameters governed on chain for the session, read from the state of the
+/// last block of the previous session. `None` if they were never set or the runtime predates them,
+/// in which case the local configuration should be used. Failing to read them is an error, as
+/// falling back to the local configuration could make us disagree with the rest of the committee.
+pub(crate) fn finality_params_for_session<B, C, BE>(
+    client: &C,
+    session_id: SessionId,
+    session_period: SessionPeriod,
+) -> Result<Option<FinalityParameters>, ApiError>
+where
+    B: Block,
+    C: ClientForAleph<B, BE>,
+    C::Api: AlephSessionApi<B>,
+    BE: Backend<B>,
+{
+    let block = BlockId::Number(governing_block::<B>(session_id, session_period));
+    let runtime_api = client.runtime_api();
+    match runtime_api.has_api_with::<dyn AlephSessionApi<B>, _>(&block, |version| {
+        version >= FINALITY_PARAMS_API_VERSION
+    })? {
+        true => runtime_api.next_session_finality_params(&block),
+        false => Ok(None),
+    }
+}
+
+/// Like `finality_params_for_session`, but retries failed reads a few times before giving up.
+pub(crate) async fn read_finality_params_for_session<B, C, BE>(
+    client: &C,
+    session_id: SessionId,
+    session_period: SessionPeriod,
+) -> Result<Option<FinalityParameters>, ApiError>
+where
+    B: Block,
+    C: ClientForAleph<B, BE>,
+    C::Api: AlephSessionApi<B>,
+    BE: Backend<B>,
+{
+    let mut attempt = 1;
+    loop {
+        match finality_params_for_session(client, session_id, session_period) {
+            Err(e) if attempt < READ_ATTEMPTS => {
+                warn!(target: "aleph-party", "Could not read finality parameters of session {:?} (attempt {}/{}), retrying: {:?}", session_id, attempt, READ_ATTEMPTS, e);
+                attempt += 1;
+                sleep(RETRY_DELAY).await;
+            }
+            result => return result,
+        }
+    }
+}
@@ -0,0 +1,665 @@
+use std::{
+    fmt::{Display, Error as FmtError, Formatter},
+    sync::Arc,
+};
+
+use aleph_primitives::{
+    storage_keys, AlephSessionApi, AuthorityId, BlsKey, SessionAuthorityData, ALEPH_ENGINE_ID,
+};
+use codec::{Decode, Encode};
+use sc_client_api::{BlockBackend, HeaderBackend, ProofProvider};
+use sp_api::{BlockId, NumberFor, ProvideRuntimeApi};
+use sp_runtime::traits::{Block, Header, Zero};
+use sp_state_machine::read_proof_check;
+use sp_trie::StorageProof;
+
+use crate::{
+    first_block_of_session,
+    justification::{
+        backwards_compatible_decode, versioned_encode, AlephJustification, DecodeError,
+        EncodeError, Verifier,
+    },
+    last_block_of_session,
+    nodes::JustificationVerifier,
+    session_id_from_block_num,
+    session_map::{get_authority_data_for_session, AuthorityProviderImpl},
+    SessionId, SessionPeriod,
+};
+
+#[derive(Debug)]
+pub enum FinalityProofError {
+    Client(sp_blockchain::Error),
+    Runtime(sp_api::ApiError),
+    NotFinalized,
+    UnknownBlock,
+    MissingJustification,
+    MissingAuthorityData(SessionId),
+    StateUnavailable(SessionId),
+    BadJustification(DecodeError),
+    Encode(EncodeError),
+    InvalidJustification,
+    NotLastBlockOfSession,
+    NotConsecutive(SessionId, SessionId),
+    BadStorageProof(String),
+    MissingNextAuthorities,
+    BadStorageValue(codec::Error),
+}
+
+impl Display for FinalityProofError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        use FinalityProofError::*;
+        match self {
+            Client(e) => write!(f, "client error: {}", e),
+            Runtime(e) => write!(f, "runtime api error: {}", e),
+            NotFinalized => write!(f, "the block is not finalized"),
+            UnknownBlock => write!(f, "the block is not known"),
+            MissingJustification => write!(
+                f,
+                "no justification is stored for the block, try the last block of its session"
+            ),
+            MissingAuthorityData(session) => {
+                write!(f, "no authority data for session {}", session.0)
+            }
+            StateUnavailable(session) => write!(
+                f,
+                "the state needed for session {} is unavailable, proofs can only be exported by nodes keeping the state of all blocks",
+                session.0
+            ),
+            BadJustification(e) => write!(f, "bad justification: {}", e),
+            Encode(e) => write!(f, "failed to encode justification: {}", e),
+            InvalidJustification => write!(
+                f,
+                "the justification does not prove finality of the block for the given authorities"
+            ),
+            NotLastBlockOfSession => write!(f, "the block is not the last block of the session"),
+            NotConsecutive(expected, got) => write!(
+                f,
+                "expected a handover from session {}, got one from session {}",
+                expected.0, got.0
+            ),
+            BadStorageProof(e) => write!(f, "bad storage proof: {}", e),
+            MissingNextAuthorities => write!(
+                f,
+                "the state of the block does not contain the authorities of the next session"
+            ),
+            BadStorageValue(e) => write!(f, "failed to decode a proven storage value: {}", e),
+        }
+    }
+}
+
+impl From<sp_blockchain::Error> for FinalityProofError {
+    fn from(e: sp_blockchain::Error) -> Self {
+        FinalityProofError::Client(e)
+    }
+}
+
+/// A self-contained proof of finality of a block. Contains everything needed to check it offline,
+/// apart from a way of trusting the authority data of the session, which has to be compared with
+/// some trusted source by whoever checks the proof.
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct FinalityProof<B: Block> {
+    header: B::Header,
+    /// The justification in its versioned encoding.
+    justification: Vec<u8>,
+    authority_data: SessionAuthorityData,
+}
+
+impl<B: Block> FinalityProof<B> {
+    pub fn header(&self) -> &B::Header {
+        &self.header
+    }
+
+    pub fn authority_data(&self) -> &SessionAuthorityData {
+        &self.authority_data
+    }
+
+    /// Checks that the justification proves finality of the header for the authority data
+    /// contained in the proof, and returns the justification.
+    pub fn verify(&self) -> Result<AlephJustification, FinalityProofError> {
+        verify_justification::<B>(&self.justification, &self.authority_data, &self.header)
+    }
+}
+
+fn verify_justification<B: Block>(
+    justification: &[u8],
+    authority_data: &SessionAuthorityData,
+    header: &B::Header,
+) -> Result<AlephJustification, FinalityProofError> {
+    let justification = backwards_compatible_decode(justification.to_vec())
+        .map_err(FinalityProofError::BadJustification)?;
+    let verifier: JustificationVerifier = authority_data.clone().into();
+    match Verifier::<B>::verify(&verifier, &justification, header.hash()) {
+        Ok(()) => Ok(justification),
+        Err(_) => Err(FinalityProofError::InvalidJustification),
+    }
+}
+
+/// A proof that the authority data of session `session + 1` is the one contained in the state of
+/// the justified last block of `session`. Starting from the authorities of session 0, a chain of
+/// handovers lets a light client follow the authority set without any state access.
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct AuthorityHandover<B: Block> {
+    session: SessionId,
+    header: B::Header,
+    /// The justification in its versioned encoding.
+    justification: Vec<u8>,
+    /// Proof of the next authorities and the queued emergency finalizer in the aleph pallet.
+    storage_proof: StorageProof,
+}
+
+impl<B: Block> AuthorityHandover<B> {
+    pub fn session(&self) -> SessionId {
+        self.session
+    }
+
+    pub fn header(&self) -> &B::Header {
+        &self.header
+    }
+
+    /// Checks the handover against the authority data of its session, and returns the authority
+    /// data of the next session.
+    pub fn verify(
+        &self,
+        authority_data: &SessionAuthorityData,
+        session_period: SessionPeriod,
+    ) -> Result<SessionAuthorityData, FinalityProofError> {
+        if *self.header.number() != last_block_of_session::<B>(self.session, session_period) {
+            return Err(FinalityProofError::NotLastBlockOfSession);
+        }
+        verify_justification::<B>(&self.justification, authority_data, &self.header)?;
+
+        let next_authorities_key = storage_keys::next_authorities();
+        let emergency_finalizer_key = storage_keys::queued_emergency_finalizer();
+        let bls_keys_key = storage_keys::next_session_bls_keys();
+        let mut values = read_proof_check::<<B::Header as Header>::Hashing, _>(
+            *self.header.state_root(),
+            self.storage_proof.clone(),
+            [
+                &next_authorities_key,
+                &emergency_finalizer_key,
+                &bls_keys_key,
+            ],
+        )
+        .map_err(|e| FinalityProofError::BadStorageProof(e.to_string()))?;
+        let next_authorities = values
+            .remove(&next_authorities_key)
+            .flatten()
+            .ok_or(FinalityProofError::MissingNextAuthorities)?;
+        let next_authorities = Vec::<AuthorityId>::decode(&mut next_authorities.as_slice())
+            .map_err(FinalityProofError::BadStorageValue)?;
+        let emergency_finalizer = values
+            .remove(&emergency_finalizer_key)
+            .flatten()
+            .map(|value| AuthorityId::decode(&mut value.as_slice()))
+            .transpose()
+            .map_err(FinalityProofError::BadStorageValue)?;
+        let bls_keys = values
+            .remove(&bls_keys_key)
+            .flatten()
+            .map(|value| Vec::<BlsKey>::decode(&mut value.as_slice()))
+            .transpose()
+            .map_err(FinalityProofError::BadStorageValue)?;
+        Ok(
+            SessionAuthorityData::new(next_authorities, emergency_finalizer)
+                .with_bls_keys(bls_keys),
+        )
+    }
+}
+
+/// Checks a chain of handovers for consecutive sessions, starting with the session the given
+/// authority data belongs to. Returns the authority data of the session after the last handover.
+pub fn verify_authority_handovers<B: Block>(
+    first_session: SessionId,
+    authority_data: SessionAuthorityData,
+    handovers: &[AuthorityHandover<B>],
+    session_period: SessionPeriod,
+) -> Result<SessionAuthorityData, FinalityProofError> {
+    handovers.iter().zip(first_session.0..).try_fold(
+        authority_data,
+        |authority_data, (handover, session)| {
+            if handover.session != SessionId(session) {
+                return Err(FinalityProofError::NotConsecutive(
+                    SessionId(session),
+                    handover.session,
+                ));
+            }
+            handover.verify(&authority_data, session_period)
+        },
+    )
+}
+
+/// Returns the header of the finalized block with the given number, together with its stored
+/// justification in the current versioned encoding.
+fn justified_header<B, C>(
+    client: &C,
+    number: NumberFor<B>,
+) -> Result<(B::Header, Vec<u8>), FinalityProofError>
+where
+    B: Block,
+    C: HeaderBackend<B> + BlockBackend<B>,
+{
+    if number > client.info().finalized_number {
+        return Err(FinalityProofError::NotFinalized);
+    }
+    let hash = client
+        .hash(number)?
+        .ok_or(FinalityProofError::UnknownBlock)?;
+    let header = client
+        .header(BlockId::Hash(hash))?
+        .ok_or(FinalityProofError::UnknownBlock)?;
+    let justification = client
+        .justifications(&BlockId::Hash(hash))?
+        .and_then(|justifications| justifications.get(ALEPH_ENGINE_ID).cloned())
+        .ok_or(FinalityProofError::MissingJustification)?;
+    // Normalize the encoding, in case the justification was stored by ancient code.
+    let justification =
+        backwards_compatible_decode(justification).map_err(FinalityProofError::BadJustification)?;
+    let justification = versioned_encode(justification).map_err(FinalityProofError::Encode)?;
+    Ok((header, justification))
+}
+
+fn session_period<B, C>(client: &C) -> Result<SessionPeriod, FinalityProofError>
+where
+    B: Block,
+    C: ProvideRuntimeApi<B>,
+    C::Api: AlephSessionApi<B>,
+{
+    Ok(SessionPeriod(
+        client
+            .runtime_api()
+            .session_period(&BlockId::Number(Zero::zero()))
+            .map_err(FinalityProofError::Runtime)?,
+    ))
+}
+
+/// Whether we have the state of the block, as nodes that did not sync the full chain might not.
+fn has_state<B, C>(client: &C, block: &BlockId<B>) -> bool
+where
+    B: Block,
+    C: ProvideRuntimeApi<B>,
+    C::Api: AlephSessionApi<B>,
+{
+    client.runtime_api().session_period(block).is_ok()
+}
+
+/// Creates a proof of finality of the finalized block with the given number, using the stored
+/// justification of the block and the authority data of its session.
+///
+/// The authority data is read from the state at the beginning of the previous session, so the
+/// node has to keep historical state.
+
+pub fn finality_proof<B, C>(
+    client: Arc<C>,
+    number: NumberFor<B>,
+) -> Result<FinalityProof<B>, FinalityProofError>
+where
+    B: Block,
+    C: HeaderBackend<B> + BlockBackend<B> + ProvideRuntimeApi<B> + Send + Sync + 'static,
+    C::Api: AlephSessionApi<B>,
+{
+    let (header, justification) = justified_header(client.as_ref(), number)?;
+    let session_period = session_period(client.as_ref())?;
+    let session_id = session_id_from_block_num::<B>(*header.number(), session_period);
+    let first_block = match session_id {
+        SessionId(0) => Zero::zero(),
+        SessionId(id) => first_block_of_session::<B>(SessionId(id - 1), session_period),
+    };
+    let authority_data = get_authority_data_for_session::<_, B>(
+        &AuthorityProviderImpl::new(client.clone()),
+        session_id,
+        first_block,
+    )
+    .ok_or_else(
+        || match has_state(client.as_ref(), &BlockId::Number(first_block)) {
+            true => FinalityProofError::MissingAuthorityData(session_id),
+            false => FinalityProofError::StateUnavailable(session_id),
+        },
+    )?;
+
+    Ok(FinalityProof {
+        header,
+        justification,
+        authority_data,
+    })
+}
+
+/// Creates handovers for `count` consecutive sessions starting with `first_session`, i.e. proofs
+/// of the authority data of sessions `first_session + 1` to `first_session + count`.
+///
+/// The proofs are made from the state at the end of every session, so the node has to keep
+/// historical state.
+pub fn authority_handovers<B, C>(
+    client: Arc<C>,
+    first_session: SessionId,
+    count: u32,
+) -> Result<Vec<AuthorityHandover<B>>, FinalityProofError>
+where
+    B: Block,
+    C: HeaderBackend<B> + BlockBackend<B> + ProofProvider<B> + ProvideRuntimeApi<B>,
+    C::Api: AlephSessionApi<B>,
+{
+    let session_period = session_period(client.as_ref())?;
+    let keys = [
+        storage_keys::next_authorities(),
+        storage_keys::queued_emergency_finalizer(),
+        storage_keys::next_session_bls_keys(),
+    ];
+    (first_session.0..first_session.0.saturating_add(count))
+        .map(|session| {
+            let session = SessionId(session);
+            let (header, justification) = justified_header(
+                client.as_ref(),
+                last_block_of_session::<B>(session, session_period),
+            )?;
+            let block = BlockId::Hash(header.hash());
+            let storage_proof = client
+                .read_proof(&block, &mut keys.iter().map(|key| key.as_slice()))
+                .map_err(|e| match has_state(client.as_ref(), &block) {
+                    true => FinalityProofError::Client(e),
+                    false => FinalityProofError::StateUnavailable(session),
+                })?;
+            Ok(AuthorityHandover {
+                session,
+                header,
+                justification,
+                storage_proof,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use aleph_bft::{PartialMultisignature, SignatureSet};
+    use aleph_primitives::{storage_keys, AuthorityId, AuthorityPair, SessionAuthorityData};
+    use codec::{Decode, Encode};
+    use sp_core::{storage::StateVersion, Pair};
+    use sp_runtime::traits::{BlakeTwo256, Header};
+    use sp_state_machine::{prove_read, InMemoryBackend};
+
+    use super::{verify_authority_handovers, AuthorityHandover, FinalityProof, FinalityProofError};
+    use crate::{
+        crypto::{generate_bls_pens, AggregatedSignature, Signature},
+        justification::{versioned_encode, AlephJustification},
+        testing::mocks::{TBlock, THash, THeader},
+        SessionId, SessionPeriod,
+    };
+
+    const SESSION_PERIOD: SessionPeriod = SessionPeriod(5);
+
+    fn header(number: u64) -> THeader {
+        header_with_state(number, Default::default())
+    }
+
+    fn header_with_state(number: u64, state_root: THash) -> THeader {
+        THeader::new(
+            number,
+            Default::default(),
+            state_root,
+            Default::default(),
+            Default::default(),
+        )
+    }
+
+    fn committee_justification(
+        pairs: &[AuthorityPair],
+        signers: usize,
+        header: &THeader,
+    ) -> Vec<u8> {
+        let hash = header.hash().encode();
+        let signature_set = pairs.iter().enumerate().take(signers).fold(
+            SignatureSet::<Signature>::with_size(pairs.len().into()),
+            |signature_set, (i, pair)| {
+                signature_set.add_signature(&pair.sign(&hash).into(), i.into())
+            },
+        );
+        versioned_encode(AlephJustification::CommitteeMultisignature(signature_set))
+            .expect("encoding should succeed")
+    }
+
+    fn authority_data(
+        pairs: &[AuthorityPair],
+        emergency_finalizer: Option<AuthorityId>,
+    ) -> SessionAuthorityData {
+        SessionAuthorityData::new(
+            pairs.iter().map(|pair| pair.public()).collect(),
+            emergency_finalizer,
+        )
+    }
+
+    fn committee_proof(pairs: &[AuthorityPair], signers: usize) -> FinalityProof<TBlock> {
+        let header = header(17);
+        FinalityProof {
+            justification: committee_justification(pairs, signers, &header),
+            header,
+            authority_data: authority_data(pairs, None),
+        }
+    }
+
+    /// A handover from `session`, signed by `pairs`, to the given authority data.
+    fn handover(
+        session: u32,
+        pairs: &[AuthorityPair],
+        next_authority_data: &SessionAuthorityData,
+    ) -> AuthorityHandover<TBlock> {
+        let mut storage = vec![(
+            storage_keys::next_authorities(),
+            Some(next_authority_data.authorities().encode()),
+        )];
+        if let Some(emergency_finalizer) = next_authority_data.emergency_finalizer() {
+            storage.push((
+                storage_keys::queued_emergency_finalizer(),
+                Some(emergency_finalizer.encode()),
+            ));
+        }
+        if let Some(bls_keys) = next_authority_data.bls_keys() {
+            storage.push((
+                storage_keys::next_session_bls_keys(),
+                Some(bls_keys.encode()),
+            ));
+        }
+        let backend =
+            InMemoryBackend::<BlakeTwo256>::from((vec![(None, storage)], StateVersion::V1));
+        let state_root = *backend.root();
+        let storage_proof = prove_read(
+            backend,
+            [
+                storage_keys::next_authorities(),
+                storage_keys::queued_emergency_finalizer(),
+                storage_keys::next_session_bls_keys(),
+            ],
+        )
+        .expect("proving should succeed");
+        let header = header_with_state(((session + 1) * SESSION_PERIOD.0 - 1).into(), state_root);
+        AuthorityHandover {
+            session: SessionId(session),
+            justification: committee_justification(pairs, pairs.len(), &header),
+            header,
+            storage_proof,
+        }
+    }
+
+    fn pairs(count: usize) -> Vec<AuthorityPair> {
+        (0..count).map(|_| AuthorityPair::generate().0).collect()
+    }
+
+    #[test]
+    fn verifies_committee_proof() {
+        let proof = committee_proof(&pairs(4), 3);
+        assert!(matches!(
+            proof.verify(),
+            Ok(AlephJustification::CommitteeMultisignature(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_proof_with_too_few_signatures() {
+        let proof = committee_proof(&pairs(4), 2);
+        assert!(matches!(
+            proof.verify(),
+            Err(FinalityProofError::InvalidJustification)
+        ));
+    }
+
+    #[test]
+    fn rejects_proof_for_different_header() {
+        let mut proof = committee_proof(&pairs(4), 4);
+        proof.header = header(18);
+        assert!(matches!(
+            proof.verify(),
+            Err(FinalityProofError::InvalidJustification)
+        ));
+    }
+
+    #[test]
+    fn rejects_proof_with_different_authorities() {
+        let mut proof = committee_proof(&pairs(4), 4);
+        proof.authority_data = committee_proof(&pairs(4), 4).authority_data;
+        assert!(matches!(
+            proof.verify(),
+            Err(FinalityProofError::InvalidJustification)
+        ));
+    }
+
+    #[test]
+    fn verifies_emergency_proof() {
+        let emergency_finalizer = AuthorityPair::generate().0;
+        let header = header(17);
+        let proof = FinalityProof::<TBlock> {
+            justification: versioned_encode(AlephJustification::EmergencySignature(
+                emergency_finalizer.sign(&header.hash().encode()),
+            ))
+            .expect("encoding should succeed"),
+            header,
+            authority_data: SessionAuthorityData::new(
+                Vec::new(),
+                Some(emergency_finalizer.public()),
+            ),
+        };
+        assert!(matches!(
+            proof.verify(),
+            Ok(AlephJustification::EmergencySignature(_))
+        ));
+    }
+
+    #[test]
+    fn survives_encoding() {
+        let proof = committee_proof(&pairs(4), 3);
+        let decoded = FinalityProof::<TBlock>::decode(&mut proof.encode().as_slice())
+            .expect("the proof should decode");
+        assert_eq!(decoded, proof);
+        assert!(decoded.verify().is_ok());
+    }
+
+    #[test]
+    fn verifies_handover_chain() {
+        let committees: Vec<_> = (0..3).map(|_| pairs(4)).collect();
+        let emergency_finalizer = AuthorityPair::generate().0.public();
+        let authority_data = vec![
+            authority_data(&committees[0], None),
+            authority_data(&committees[1], Some(emergency_finalizer)),
+            authority_data(&committees[2], None),
+        ];
+        let handovers = vec![
+            handover(0, &committees[0], &authority_data[1]),
+            handover(1, &committees[1], &authority_data[2]),
+        ];
+        assert_eq!(
+            handovers[0]
+                .verify(&authority_data[0], SESSION_PERIOD)
+                .expect("the handover should be valid"),
+            authority_data[1]
+        );
+        assert_eq!(
+            verify_authority_handovers(
+                SessionId(0),
+                authority_data[0].clone(),
+                &handovers,
+                SESSION_PERIOD
+            )
+            .expect("the chain should be valid"),
+            authority_data[2]
+        );
+    }
+
+    #[tokio::test]
+    async fn hands_over_bls_keys_for_aggregated_proofs() {
+        let committees: Vec<_> = (0..2).map(|_| pairs(4)).collect();
+        let (pens, _) = generate_bls_pens(4).await;
+        let keys: Vec<BlsKey> = pens.iter().map(|pen| pen.public().into()).collect();
+        let next_authority_data = authority_data(&committees[1], None).with_bls_keys(Some(keys));
+        let handed_over = handover(0, &committees[0], &next_authority_data)
+            .verify(&authority_data(&committees[0], None), SESSION_PERIOD)
+            .expect("the handover should be valid");
+        assert_eq!(handed_over, next_authority_data);
+
+        let header = header(17);
+        let hash = header.hash().encode();
+        let signature = pens.iter().enumerate().skip(1).fold(
+            AggregatedSignature::new(&pens[0].sign(&hash), 0.into()),
+            |signature, (i, pen)| signature.add_signature(&pen.sign(&hash), i.into()),
+        );
+        let proof = FinalityProof::<TBlock> {
+            justification: versioned_encode(signature.into()).expect("encoding should succeed"),
+            header,
+            authority_data: handed_over,
+        };
+        assert!(matches!(
+            proof.verify(),
+            Ok(AlephJustification::AggregatedMultisignature(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_handover_signed_by_other_committee() {
+        let committee = pairs(4);
+        let handover = handover(0, &pairs(4), &authority_data(&pairs(4), None));
+        assert!(matches!(
+            handover.verify(&authority_data(&committee, None), SESSION_PERIOD),
+            Err(FinalityProofError::InvalidJustification)
+        ));
+    }
+
+    #[test]
+    fn rejects_handover_with_proof_of_other_state() {
+        let committee = pairs(4);
+        let mut tampered = handover(0, &committee, &authority_data(&pairs(4), None));
+        tampered.storage_proof =
+            handover(0, &committee, &authority_data(&pairs(4), None)).storage_proof;
+        assert!(matches!(
+            tampered.verify(&authority_data(&committee, None), SESSION_PERIOD),
+            Err(FinalityProofError::BadStorageProof(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_handover_not_at_end_of_session() {
+        let committee = pairs(4);
+        let handover = handover(0, &committee, &authority_data(&pairs(4), None));
+        assert!(matches!(
+            handover.verify(&authority_data(&committee, None), SessionPeriod(6)),
+            Err(FinalityProofError::NotLastBlockOfSession)
+        ));
+    }
+
+    #[test]
+    fn rejects_non_consecutive_handovers() {
+        let committees: Vec<_> = (0..3).map(|_| pairs(4)).collect();
+        let handovers = vec![
+            handover(0, &committees[0], &authority_data(&committees[1], None)),
+            handover(2, &committees[1], &authority_data(&committees[2], None)),
+        ];
+        assert!(matches!(
+            verify_authority_handovers(
+                SessionId(0),
+                authority_data(&committees[0], None),
+                &handovers,
+                SESSION_PERIOD
+            ),
+            Err(FinalityProofError::NotConsecutive(
+                SessionId(1),
+                SessionId(2)
+            ))
+        ));
+    }
+}
@@ -5,7 +5,14 @@ use log::{debug, warn};
 use sc_client_api::{Backend, Finalizer, HeaderBackend, LockImportRun};
 use sp_api::{BlockId, NumberFor};
 use sp_blockchain::Error;
-use sp_runtime::{traits::Block, Justification};
+use sp_runtime::{
+    traits::{Block, One},
+    Justification,
+};
+
+use crate::{
+    first_block_of_session, last_block_of_session, session_id_from_block_num, SessionPeriod,
+};
 
 pub trait BlockFinalizer<B: Block> {
     fn finalize_block(
@@ -16,6 +23,13 @@ pub trait BlockFinalizer<B: Block> {
     ) -> Result<(), Error>;
 }
 
+/// Removes stored justifications of finalized blocks. The client can only add justifications, so
+/// this has to be done directly in the database.
+pub trait JustificationPruner<B: Block>: Send + Sync {
+    /// Removes the justifications of the given blocks, if they have any.
+    fn remove_justifications(&self, blocks: Vec<(NumberFor<B>, B::Hash)>) -> Result<(), Error>;
+}
+
 pub struct AlephFinalizer<B, BE, C>
 where
     B: Block,
@@ -23,6 +37,7 @@ where
     C: HeaderBackend<B> + LockImportRun<B, BE> + Finalizer<B, BE>,
 {
     client: Arc<C>,
+    justification_pruning: Option<(SessionPeriod, Arc<dyn JustificationPruner<B>>)>,
     phantom: PhantomData<(B, BE)>,
 }
 
@@ -35,9 +50,60 @@ where
     pub(crate) fn new(client: Arc<C>) -> Self {
         AlephFinalizer {
             client,
+            justification_pruning: None,
             phantom: PhantomData,
         }
     }
+
+    /// A finalizer that eventually keeps only the justifications of the last blocks of sessions.
+    ///
+    /// Justifications of the remaining blocks are stored as usual until the last block of their
+    /// session is finalized, as until then they are the only proof of finality of these blocks.
+    /// Afterwards they are removed, as the justification of the last block of the session finalizes
+    /// all of them anyway. This is enough to serve justification sync, and significantly reduces
+    /// the size of the database.
+    pub(crate) fn with_justification_pruning(
+        client: Arc<C>,
+        session_period: SessionPeriod,
+        pruner: Arc<dyn JustificationPruner<B>>,
+    ) -> Self {
+        AlephFinalizer {
+            client,
+            justification_pruning: Some((session_period, pruner)),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Removes the justifications of the other blocks of the session, if the block ends it.
+    fn prune_session_justifications(&self, block_number: NumberFor<B>) {
+        let (session_period, pruner) = match &self.justification_pruning {
+            Some(pruning) => pruning,
+            None => return,
+        };
+        let session_id = session_id_from_block_num::<B>(block_number, *session_period);
+        if block_number != last_block_of_session::<B>(session_id, *session_period) {
+            return;
+        }
+        let mut blocks = Vec::new();
+        let mut number = first_block_of_session::<B>(session_id, *session_period);
+        while number < block_number {
+            match self.client.hash(number) {
+                Ok(Some(hash)) => blocks.push((number, hash)),
+                _ => {
+                    warn!(target: "aleph-finality", "Missing hash of finalized block {:?}, its justification will not be pruned.", number)
+                }
+            }
+            number += One::one();
+        }
+        match pruner.remove_justifications(blocks) {
+            Ok(()) => {
+                debug!(target: "aleph-finality", "Pruned justifications of session {:?}.", session_id)
+            }
+            Err(e) => {
+                warn!(target: "aleph-finality", "Failed to prune justifications of session {:?}: {}", session_id, e)
+            }
+        }
+    }
 }
 
 impl<B, BE, C> BlockFinalizer<B> for AlephFinalizer<B, BE, C>
@@ -67,6 +133,9 @@ where
         });
         let status = self.client.info();
         debug!(target: "aleph-finality", "Attempted to finalize block with hash {:?}. Current best: #{:?}.", hash, status.finalized_number);
+        if update_res.is_ok() {
+            self.prune_session_justifications(block_number);
+        }
         update_res
     }
 }
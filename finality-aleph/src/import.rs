@@ -16,7 +16,7 @@ use sp_runtime::{
 
 use crate::{
     justification::{backwards_compatible_decode, DecodeError, JustificationNotification},
-    metrics::{Checkpoint, Metrics},
+    metrics::{Checkpoint, Metrics, VerificationFailure},
 };
 
 pub struct AlephBlockImport<Block, Be, I>
@@ -79,7 +79,13 @@ where
             )));
         }
         let justification_raw = justification.1;
-        let aleph_justification = backwards_compatible_decode(justification_raw)?;
+        let aleph_justification = backwards_compatible_decode(justification_raw).map_err(|e| {
+            if let Some(m) = &self.metrics {
+                m.events()
+                    .report_verification_failure(VerificationFailure::Undecodable);
+            }
+            e
+        })?;
 
         self.justification_tx
             .unbounded_send(JustificationNotification {
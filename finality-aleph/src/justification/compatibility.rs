@@ -1,9 +1,10 @@
 use std::{
+    cmp::min,
     fmt::{Display, Error as FmtError, Formatter},
-    mem::size_of,
 };
 
 use aleph_bft::{PartialMultisignature, SignatureSet};
+use aleph_primitives::AuthoritySignature;
 use codec::{Decode, DecodeAll, Encode, Error as CodecError, Input as CodecInput};
 
 use crate::{
@@ -13,6 +14,14 @@ use crate::{
 
 type Version = u16;
 type ByteCount = u16;
+type LongByteCount = u32;
+
+/// Put in place of the version to mark an envelope with a `LongByteCount`, followed by the actual
+/// version. Older code treats it as an unknown version, so it cannot be used as a version itself.
+const LONG_ENVELOPE_MARKER: Version = Version::MAX;
+
+/// How many bytes of a payload of unknown version we allocate at once when decoding.
+const PAYLOAD_READ_CHUNK: usize = 16 * 1024;
 
 /// Old format of justifications, needed for backwards compatibility.
 /// Used an old format of signature which unnecessarily contained the signer ID.
@@ -47,66 +56,120 @@ impl From<AlephJustificationV2> for AlephJustification {
     }
 }
 
+/// Old format of justifications, needed for backwards compatibility.
+/// Did not support aggregated signatures.
+#[derive(Clone, Encode, Decode, Debug, PartialEq, Eq)]
+enum AlephJustificationV3 {
+    CommitteeMultisignature(SignatureSet<Signature>),
+    EmergencySignature(AuthoritySignature),
+}
+
+impl From<AlephJustificationV3> for AlephJustification {
+    fn from(justification: AlephJustificationV3) -> AlephJustification {
+        use AlephJustificationV3::*;
+        match justification {
+            CommitteeMultisignature(signature) => {
+                AlephJustification::CommitteeMultisignature(signature)
+            }
+            EmergencySignature(signature) => AlephJustification::EmergencySignature(signature),
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 enum VersionedAlephJustification {
     // Most likely from the future.
     Other(Version, Vec<u8>),
     V1(AlephJustificationV1),
     V2(AlephJustificationV2),
-    V3(AlephJustification),
+    V3(AlephJustificationV3),
+    V4(AlephJustification),
 }
 
-fn encode_with_version(version: Version, mut payload: Vec<u8>) -> Vec<u8> {
-    let mut result = version.encode();
-    // This will produce rubbish if we ever try encodings that have more than u16::MAX bytes. We
-    // expect this won't happen, since we will switch to proper multisignatures before proofs get
-    // that big.
-    let num_bytes = payload.len() as ByteCount;
-    result.append(&mut num_bytes.encode());
-    result.append(&mut payload);
-    result
+fn long_byte_count(num_bytes: usize) -> Result<LongByteCount, EncodeError> {
+    LongByteCount::try_from(num_bytes).map_err(|_| EncodeError::PayloadTooLong(num_bytes))
 }
 
-impl Encode for VersionedAlephJustification {
-    fn size_hint(&self) -> usize {
-        use VersionedAlephJustification::*;
-        let version_size = size_of::<Version>();
-        let byte_count_size = size_of::<ByteCount>();
-        version_size
-            + byte_count_size
-            + match self {
-                Other(_, payload) => payload.len(),
-                V1(justification) => justification.size_hint(),
-                V2(justification) => justification.size_hint(),
-                V3(justification) => justification.size_hint(),
-            }
+/// Encodes the payload in an envelope with its version and byte count. Payloads that fit in the
+/// original envelope use it, so that older code can still decode them. Longer ones get an envelope
+/// with a `LongByteCount`.
+fn encode_with_version(version: Version, mut payload: Vec<u8>) -> Result<Vec<u8>, EncodeError> {
+    if version == LONG_ENVELOPE_MARKER {
+        return Err(EncodeError::ReservedVersion(version));
     }
+    let mut result = match ByteCount::try_from(payload.len()) {
+        Ok(num_bytes) => (version, num_bytes).encode(),
+        Err(_) => (
+            LONG_ENVELOPE_MARKER,
+            version,
+            long_byte_count(payload.len())?,
+        )
+            .encode(),
+    };
+    result.append(&mut payload);
+    Ok(result)
+}
 
-    fn encode(&self) -> Vec<u8> {
+impl VersionedAlephJustification {
+    fn try_encode(&self) -> Result<Vec<u8>, EncodeError> {
         use VersionedAlephJustification::*;
         match self {
             Other(version, payload) => encode_with_version(*version, payload.clone()),
             V1(justification) => encode_with_version(1, justification.encode()),
             V2(justification) => encode_with_version(2, justification.encode()),
             V3(justification) => encode_with_version(3, justification.encode()),
+            V4(justification) => encode_with_version(4, justification.encode()),
         }
     }
 }
 
+/// Reads the payload in chunks, so that a malicious byte count cannot make us allocate a lot of
+/// memory upfront.
+fn read_payload<I: CodecInput>(input: &mut I, num_bytes: usize) -> Result<Vec<u8>, CodecError> {
+    let mut payload = Vec::new();
+    while payload.len() < num_bytes {
+        let start = payload.len();
+        payload.resize(start + min(PAYLOAD_READ_CHUNK, num_bytes - start), 0);
+        input.read(&mut payload[start..])?;
+    }
+    Ok(payload)
+}
+
 impl Decode for VersionedAlephJustification {
     fn decode<I: CodecInput>(input: &mut I) -> Result<Self, CodecError> {
         use VersionedAlephJustification::*;
-        let version = Version::decode(input)?;
-        let num_bytes = ByteCount::decode(input)?;
+        let (version, num_bytes) = match Version::decode(input)? {
+            LONG_ENVELOPE_MARKER => (Version::decode(input)?, LongByteCount::decode(input)?),
+            version => (version, ByteCount::decode(input)?.into()),
+        };
         match version {
             1 => Ok(V1(AlephJustificationV1::decode(input)?)),
             2 => Ok(V2(AlephJustificationV2::decode(input)?)),
-            3 => Ok(V3(AlephJustification::decode(input)?)),
-            _ => {
-                let mut payload = vec![0; num_bytes.into()];
-                input.read(payload.as_mut_slice())?;
-                Ok(Other(version, payload))
+            3 => Ok(V3(AlephJustificationV3::decode(input)?)),
+            4 => Ok(V4(AlephJustification::decode(input)?)),
+            _ => Ok(Other(version, read_payload(input, num_bytes as usize)?)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EncodeError {
+    PayloadTooLong(usize),
+    ReservedVersion(Version),
+}
+
+impl Display for EncodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        use EncodeError::*;
+        match self {
+            PayloadTooLong(num_bytes) => {
+                write!(
+                    f,
+                    "justification of {} bytes is too long to encode",
+                    num_bytes
+                )
             }
+            ReservedVersion(version) => write!(f, "version {} is reserved", version),
         }
     }
 }
@@ -142,7 +205,8 @@ pub fn backwards_compatible_decode(
             match justification {
                 V1(justification) => Ok(justification.into()),
                 V2(justification) => Ok(justification.into()),
-                V3(justification) => Ok(justification),
+                V3(justification) => Ok(justification.into()),
+                V4(justification) => Ok(justification),
                 Other(version, _) => Err(UnknownVersion(version)),
             }
         }
@@ -164,8 +228,22 @@ pub fn backwards_compatible_decode(
 }
 
 /// Encodes the justification in a way that is forwards compatible with future versions.
-pub fn versioned_encode(justification: AlephJustification) -> Vec<u8> {
-    VersionedAlephJustification::V3(justification).encode()
+/// Justifications that older nodes understand are encoded as V3, so that they can still be
+/// exchanged with them.
+pub fn versioned_encode(justification: AlephJustification) -> Result<Vec<u8>, EncodeError> {
+    use AlephJustification::*;
+    match justification {
+        CommitteeMultisignature(signature) => VersionedAlephJustification::V3(
+            AlephJustificationV3::CommitteeMultisignature(signature),
+        ),
+        EmergencySignature(signature) => {
+            VersionedAlephJustification::V3(AlephJustificationV3::EmergencySignature(signature))
+        }
+        justification @ AggregatedMultisignature(_) => {
+            VersionedAlephJustification::V4(justification)
+        }
+    }
+    .try_encode()
 }
 
 #[cfg(test)]
@@ -173,14 +251,16 @@ mod test {
     use aleph_bft::{NodeCount, PartialMultisignature, SignatureSet};
     use aleph_primitives::{AuthorityPair, AuthoritySignature};
     use codec::{Decode, Encode};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
     use sp_core::Pair;
 
     use super::{
-        backwards_compatible_decode, versioned_encode, AlephJustificationV1, AlephJustificationV2,
-        VersionedAlephJustification,
+        backwards_compatible_decode, long_byte_count, versioned_encode, AlephJustificationV1,
+        AlephJustificationV2, ByteCount, EncodeError, LongByteCount, VersionedAlephJustification,
+        LONG_ENVELOPE_MARKER,
     };
     use crate::{
-        crypto::{Signature, SignatureV1},
+        crypto::{generate_bls_pens, AggregatedSignature, Signature, SignatureV1},
         justification::AlephJustification,
     };
 
@@ -238,19 +318,185 @@ mod test {
 
         let just_v3 = AlephJustification::CommitteeMultisignature(signature_set);
         // Here we use `versioned_encode` since we never sent plain v3 justifications.
-        let encoded_just = versioned_encode(just_v3.clone());
+        let encoded_just = versioned_encode(just_v3.clone()).expect("encoding should succeed");
+        assert_eq!(encoded_just[..2], 3u16.encode());
+        let decoded = backwards_compatible_decode(encoded_just);
+        assert_eq!(decoded, Ok(just_v3));
+    }
+
+    #[test]
+    fn correctly_decodes_v3_emergency() {
+        let just_v3 = AlephJustification::EmergencySignature(
+            AuthorityPair::generate()
+                .0
+                .sign(vec![0u8, 0u8, 0u8, 0u8].as_slice()),
+        );
+        let encoded_just = versioned_encode(just_v3.clone()).expect("encoding should succeed");
+        assert_eq!(encoded_just[..2], 3u16.encode());
         let decoded = backwards_compatible_decode(encoded_just);
         assert_eq!(decoded, Ok(just_v3));
     }
 
+    #[tokio::test]
+    async fn correctly_decodes_v4_aggregated() {
+        let (pens, _) = generate_bls_pens(4).await;
+        let msg = vec![0u8, 0u8, 0u8, 0u8];
+        let multisignature = pens.iter().enumerate().skip(1).fold(
+            AggregatedSignature::new(&pens[0].sign(&msg), 0.into()),
+            |multisignature, (i, pen)| multisignature.add_signature(&pen.sign(&msg), i.into()),
+        );
+
+        let just_v4 = AlephJustification::AggregatedMultisignature(multisignature);
+        let encoded_just = versioned_encode(just_v4.clone()).expect("encoding should succeed");
+        assert_eq!(encoded_just[..2], 4u16.encode());
+        let decoded = backwards_compatible_decode(encoded_just);
+        assert_eq!(decoded, Ok(just_v4));
+    }
+
     #[test]
     fn correctly_decodes_other() {
         let other = VersionedAlephJustification::Other(43, vec![21, 37]);
-        let encoded = other.encode();
+        let encoded = other.try_encode().expect("encoding should succeed");
         let decoded = VersionedAlephJustification::decode(&mut encoded.as_slice());
         assert_eq!(decoded, Ok(other));
     }
 
+    #[test]
+    fn correctly_decodes_other_in_old_envelope() {
+        let mut encoded = (43u16, 2u16).encode();
+        encoded.append(&mut vec![21, 37]);
+        let decoded = VersionedAlephJustification::decode(&mut encoded.as_slice());
+        assert_eq!(
+            decoded,
+            Ok(VersionedAlephJustification::Other(43, vec![21, 37]))
+        );
+    }
+
+    #[test]
+    fn uses_long_envelope_only_for_long_payloads() {
+        let short = VersionedAlephJustification::Other(43, vec![21; ByteCount::MAX as usize]);
+        let encoded = short.try_encode().expect("encoding should succeed");
+        assert_eq!(encoded[..2], 43u16.encode());
+        assert_eq!(
+            VersionedAlephJustification::decode(&mut encoded.as_slice()),
+            Ok(short)
+        );
+
+        let long = VersionedAlephJustification::Other(43, vec![21; ByteCount::MAX as usize + 1]);
+        let encoded = long.try_encode().expect("encoding should succeed");
+        assert_eq!(encoded[..2], LONG_ENVELOPE_MARKER.encode());
+        assert_eq!(
+            VersionedAlephJustification::decode(&mut encoded.as_slice()),
+            Ok(long)
+        );
+    }
+
+    #[test]
+    fn correctly_decodes_large_v3_committee() {
+        let node_count = 1500;
+        let authority_signature: AuthoritySignature = AuthorityPair::generate()
+            .0
+            .sign(vec![0u8, 0u8, 0u8, 0u8].as_slice());
+        let signature_set = (0..node_count).fold(
+            SignatureSet::with_size(node_count.into()),
+            |signature_set, i| {
+                signature_set.add_signature(&authority_signature.clone().into(), i.into())
+            },
+        );
+
+        let just_v3 = AlephJustification::CommitteeMultisignature(signature_set);
+        let encoded_just = versioned_encode(just_v3.clone()).expect("encoding should succeed");
+        assert!(encoded_just.len() > ByteCount::MAX as usize);
+        let decoded = backwards_compatible_decode(encoded_just);
+        assert_eq!(decoded, Ok(just_v3));
+    }
+
+    #[test]
+    fn refuses_to_encode_reserved_version() {
+        let other = VersionedAlephJustification::Other(LONG_ENVELOPE_MARKER, vec![21, 37]);
+        assert_eq!(
+            other.try_encode(),
+            Err(EncodeError::ReservedVersion(LONG_ENVELOPE_MARKER))
+        );
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn refuses_to_encode_too_long_payloads() {
+        let num_bytes = LongByteCount::MAX as usize + 1;
+        assert_eq!(
+            long_byte_count(num_bytes),
+            Err(EncodeError::PayloadTooLong(num_bytes))
+        );
+    }
+
+    /// A random number generator for the tests decoding random data. The seed is taken from the
+    /// `FUZZ_SEED` environment variable if set, and printed so that failures can be reproduced.
+    fn seeded_rng() -> StdRng {
+        let seed = std::env::var("FUZZ_SEED")
+            .ok()
+            .map(|seed| seed.parse().expect("FUZZ_SEED should be a number"))
+            .unwrap_or_else(rand::random);
+        println!("Using FUZZ_SEED={}", seed);
+        StdRng::seed_from_u64(seed)
+    }
+
+    #[test]
+    fn decodes_random_bytes_without_panicking() {
+        let mut rng = seeded_rng();
+        for _ in 0..1000 {
+            let len = rng.gen_range(0..200);
+            let raw: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+            let _ = backwards_compatible_decode(raw);
+        }
+    }
+
+    #[test]
+    fn decodes_corrupted_envelopes_without_panicking() {
+        let mut rng = seeded_rng();
+        for _ in 0..1000 {
+            let version = rng.gen_range(0..8);
+            let len = rng.gen_range(0..100);
+            let payload: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+            let other = VersionedAlephJustification::Other(version, payload);
+            let mut encoded = other.try_encode().expect("encoding should succeed");
+            if rng.gen() {
+                // Make it look like a long envelope, possibly with a huge byte count.
+                let mut long_prefix = LONG_ENVELOPE_MARKER.encode();
+                long_prefix.append(&mut version.encode());
+                long_prefix.append(&mut rng.gen::<LongByteCount>().encode());
+                encoded = [long_prefix, encoded[4..].to_vec()].concat();
+            }
+            if !encoded.is_empty() && rng.gen() {
+                let position = rng.gen_range(0..encoded.len());
+                encoded[position] ^= rng.gen::<u8>();
+            }
+            if !encoded.is_empty() && rng.gen() {
+                encoded.truncate(rng.gen_range(0..encoded.len()));
+            }
+            let _ = backwards_compatible_decode(encoded);
+        }
+    }
+
+    #[test]
+    fn round_trips_random_payloads() {
+        let mut rng = seeded_rng();
+        for _ in 0..100 {
+            let version = rng.gen_range(5..LONG_ENVELOPE_MARKER);
+            let len = match rng.gen() {
+                true => rng.gen_range(0..100),
+                false => rng.gen_range(ByteCount::MAX as usize..ByteCount::MAX as usize + 100),
+            };
+            let payload: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+            let other = VersionedAlephJustification::Other(version, payload);
+            let encoded = other.try_encode().expect("encoding should succeed");
+            assert_eq!(
+                VersionedAlephJustification::decode(&mut encoded.as_slice()),
+                Ok(other)
+            );
+        }
+    }
+
     #[test]
     fn correctly_decodes_legacy_v1_size4() {
         // This is a justification for 4 nodes generated by the version at commit `a426d7a`
@@ -275,6 +521,9 @@ mod test {
             Ok(AlephJustification::EmergencySignature(_)) => {
                 panic!("decoded V1 as emergency signature")
             }
+            Ok(AlephJustification::AggregatedMultisignature(_)) => {
+                panic!("decoded V1 as aggregated multisignature")
+            }
             Err(e) => panic!("decoding V1 failed: {}", e),
         }
     }
@@ -310,6 +559,9 @@ mod test {
             Ok(AlephJustification::EmergencySignature(_)) => {
                 panic!("decoded V1 as emergency signature")
             }
+            Ok(AlephJustification::AggregatedMultisignature(_)) => {
+                panic!("decoded V1 as aggregated multisignature")
+            }
             Err(e) => panic!("decoding V1 failed: {}", e),
         }
     }
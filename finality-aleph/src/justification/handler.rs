@@ -11,8 +11,9 @@ use tokio::time::timeout;
 use crate::{
     finalization::BlockFinalizer,
     justification::{
-        requester::BlockRequester, JustificationHandlerConfig, JustificationNotification,
-        JustificationRequestScheduler, SessionInfo, SessionInfoProvider, Verifier,
+        requester::BlockRequester, FinalizedBlockSender, JustificationHandlerConfig,
+        JustificationNotification, JustificationRequestScheduler, SessionInfo, SessionInfoProvider,
+        Verifier,
     },
     network, Metrics,
 };
@@ -43,6 +44,7 @@ where
     SI: SessionInfoProvider<B, V>,
     F: BlockFinalizer<B>,
 {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         session_info_provider: SI,
         block_requester: RB,
@@ -51,6 +53,7 @@ where
         justification_request_scheduler: S,
         metrics: Option<Metrics<<B::Header as Header>::Hash>>,
         justification_handler_config: JustificationHandlerConfig<B>,
+        finalized_block_sender: FinalizedBlockSender<B>,
     ) -> Self {
         Self {
             session_info_provider,
@@ -61,6 +64,7 @@ where
                 justification_request_scheduler,
                 metrics,
                 justification_handler_config.min_allowed_delay,
+                finalized_block_sender,
             ),
             verifier_timeout: justification_handler_config.verifier_timeout,
             notification_timeout: justification_handler_config.notification_timeout,
@@ -71,10 +75,15 @@ where
         mut self,
         authority_justification_rx: mpsc::UnboundedReceiver<JustificationNotification<B>>,
         import_justification_rx: mpsc::UnboundedReceiver<JustificationNotification<B>>,
+        sync_justification_rx: mpsc::UnboundedReceiver<JustificationNotification<B>>,
     ) {
         let import_stream = wrap_channel_with_logging(import_justification_rx, "import");
         let authority_stream = wrap_channel_with_logging(authority_justification_rx, "aggregator");
-        let mut notification_stream = futures::stream::select(import_stream, authority_stream);
+        let sync_stream = wrap_channel_with_logging(sync_justification_rx, "sync");
+        let mut notification_stream = futures::stream::select(
+            futures::stream::select(import_stream, authority_stream),
+            sync_stream,
+        );
 
         loop {
             let last_finalized_number = self.block_requester.finalized_number();
@@ -82,6 +91,7 @@ where
                 verifier,
                 last_block_height: stop_h,
                 current_session,
+                request_delay,
             } = self
                 .session_info_provider
                 .for_block_num(last_finalized_number + 1u32.into())
@@ -92,6 +102,7 @@ where
                 continue;
             }
             let verifier = verifier.expect("We loop until this is some.");
+            self.block_requester.set_request_delay(request_delay);
 
             match timeout(self.notification_timeout, notification_stream.next()).await {
                 Ok(Some(notification)) => {
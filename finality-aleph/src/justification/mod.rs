@@ -3,37 +3,70 @@ use std::time::Duration;
 use aleph_bft::SignatureSet;
 use aleph_primitives::AuthoritySignature;
 use codec::{Decode, Encode};
+use sc_utils::notification::{NotificationSender, NotificationStream, TracingKeyStr};
 use sp_api::{BlockT, NumberFor};
 
-use crate::{crypto::Signature, SessionId};
+use crate::{
+    crypto::{AggregatedSignature, Signature},
+    metrics::VerificationFailure,
+    SessionId,
+};
 
 mod compatibility;
 mod handler;
 mod requester;
 mod scheduler;
+mod sync;
 
-pub use compatibility::{backwards_compatible_decode, versioned_encode, Error as DecodeError};
+pub use compatibility::{
+    backwards_compatible_decode, versioned_encode, EncodeError, Error as DecodeError,
+};
 pub use handler::JustificationHandler;
 pub use scheduler::{
     JustificationRequestScheduler, JustificationRequestSchedulerImpl, SchedulerActions,
 };
+pub use sync::{
+    JustificationProvider, JustificationSyncConfig, RequestHandler as JustificationSyncHandler,
+    RequestJustifications, Requester as JustificationSyncRequester,
+};
 
-/// A proof of block finality, currently in the form of a sufficiently long list of signatures or a
-/// sudo signature of a block for emergency finalization.
+/// A proof of block finality, currently in the form of a sufficiently long list of signatures, a
+/// single aggregated signature of sufficiently many committee members, or a sudo signature of a
+/// block for emergency finalization.
 #[derive(Clone, Encode, Decode, Debug, PartialEq, Eq)]
 pub enum AlephJustification {
     CommitteeMultisignature(SignatureSet<Signature>),
     EmergencySignature(AuthoritySignature),
+    AggregatedMultisignature(AggregatedSignature),
+}
+
+impl From<SignatureSet<Signature>> for AlephJustification {
+    fn from(multisignature: SignatureSet<Signature>) -> Self {
+        AlephJustification::CommitteeMultisignature(multisignature)
+    }
+}
+
+impl From<AggregatedSignature> for AlephJustification {
+    fn from(multisignature: AggregatedSignature) -> Self {
+        AlephJustification::AggregatedMultisignature(multisignature)
+    }
 }
 
 pub trait Verifier<B: BlockT> {
-    fn verify(&self, justification: &AlephJustification, hash: B::Hash) -> bool;
+    /// Checks the justification of the block, returning why it is rejected if it is.
+    fn verify(
+        &self,
+        justification: &AlephJustification,
+        hash: B::Hash,
+    ) -> Result<(), VerificationFailure>;
 }
 
 pub struct SessionInfo<B: BlockT, V: Verifier<B>> {
     pub current_session: SessionId,
     pub last_block_height: NumberFor<B>,
     pub verifier: Option<V>,
+    /// Delay between justification requests governed on chain, if any.
+    pub request_delay: Option<Duration>,
 }
 
 /// Returns `SessionInfo` for the session regarding block with no. `number`.
@@ -53,6 +86,21 @@ pub struct JustificationNotification<Block: BlockT> {
     pub number: NumberFor<Block>,
 }
 
+#[derive(Clone)]
+pub struct FinalizedBlockTracingKey;
+
+impl TracingKeyStr for FinalizedBlockTracingKey {
+    const TRACING_KEY: &'static str = "mpsc_aleph_finalized_block_notification_stream";
+}
+
+/// Sends a notification with the justification of every block finalized by the gadget.
+pub type FinalizedBlockSender<B> = NotificationSender<JustificationNotification<B>>;
+
+/// A stream of notifications about blocks finalized by the gadget, which can be subscribed to
+/// e.g. by RPC handlers.
+pub type FinalizedBlockStream<B> =
+    NotificationStream<JustificationNotification<B>, FinalizedBlockTracingKey>;
+
 #[derive(Clone)]
 pub struct JustificationHandlerConfig<B: BlockT> {
     /// How long should we wait when the session verifier is not yet available.
@@ -1,4 +1,8 @@
-use std::{marker::PhantomData, sync::Arc, time::Instant};
+use std::{
+    marker::PhantomData,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use aleph_primitives::ALEPH_ENGINE_ID;
 use log::{debug, error, warn};
@@ -9,8 +13,8 @@ use sp_runtime::traits::Header;
 use crate::{
     finalization::BlockFinalizer,
     justification::{
-        scheduler::SchedulerActions, versioned_encode, JustificationNotification,
-        JustificationRequestScheduler, Verifier,
+        scheduler::SchedulerActions, versioned_encode, FinalizedBlockSender,
+        JustificationNotification, JustificationRequestScheduler, Verifier,
     },
     metrics::Checkpoint,
     network, Metrics,
@@ -31,6 +35,7 @@ where
     justification_request_scheduler: S,
     metrics: Option<Metrics<<B::Header as Header>::Hash>>,
     min_allowed_delay: NumberFor<B>,
+    finalized_block_sender: FinalizedBlockSender<B>,
     _phantom: PhantomData<V>,
 }
 
@@ -50,6 +55,7 @@ where
         justification_request_scheduler: S,
         metrics: Option<Metrics<<B::Header as Header>::Hash>>,
         min_allowed_delay: NumberFor<B>,
+        finalized_block_sender: FinalizedBlockSender<B>,
     ) -> Self {
         BlockRequester {
             block_requester,
@@ -58,6 +64,7 @@ where
             justification_request_scheduler,
             metrics,
             min_allowed_delay,
+            finalized_block_sender,
             _phantom: PhantomData,
         }
     }
@@ -80,16 +87,27 @@ where
             return;
         };
 
-        if !(verifier.verify(&justification, hash)) {
-            warn!(target: "aleph-justification", "Error when verifying justification for block {:?} {:?}", number, hash);
+        if let Err(failure) = verifier.verify(&justification, hash) {
+            warn!(target: "aleph-justification", "Error when verifying justification for block {:?} {:?}: {:?}", number, hash, failure);
+            if let Some(metrics) = &self.metrics {
+                metrics.events().report_verification_failure(failure);
+            }
             return;
         };
 
+        let encoded_justification = match versioned_encode(justification.clone()) {
+            Ok(justification) => justification,
+            Err(e) => {
+                error!(target: "aleph-justification", "Failed to encode justification for block {:?} {:?} -- {}", number, hash, e);
+                return;
+            }
+        };
+
         debug!(target: "aleph-justification", "Finalizing block {:?} {:?}", number, hash);
         let finalization_res = self.finalizer.finalize_block(
             hash,
             number,
-            Some((ALEPH_ENGINE_ID, versioned_encode(justification))),
+            Some((ALEPH_ENGINE_ID, encoded_justification)),
         );
         match finalization_res {
             Ok(()) => {
@@ -98,6 +116,13 @@ where
                 if let Some(metrics) = &self.metrics {
                     metrics.report_block(hash, Instant::now(), Checkpoint::Finalized);
                 }
+                let _ = self.finalized_block_sender.notify(|| {
+                    Ok::<_, ()>(JustificationNotification {
+                        justification,
+                        hash,
+                        number,
+                    })
+                });
             }
             Err(e) => {
                 error!(target: "aleph-justification", "Fail in finalization of {:?} {:?} -- {:?}", number, hash, e);
@@ -123,6 +148,9 @@ where
                     self.justification_request_scheduler.on_request_sent();
                     self.block_requester
                         .request_justification(&header.hash(), *header.number());
+                    if let Some(metrics) = &self.metrics {
+                        metrics.events().report_justification_request_sent();
+                    }
                 } else {
                     debug!(target: "aleph-justification", "Cancelling request, because we don't have block {:?}.", num);
                 }
@@ -135,6 +163,10 @@ where
         }
     }
 
+    pub fn set_request_delay(&mut self, delay: Option<Duration>) {
+        self.justification_request_scheduler.set_delay(delay);
+    }
+
     pub fn finalized_number(&self) -> NumberFor<B> {
         self.client.info().finalized_number
     }
@@ -19,12 +19,15 @@ pub trait JustificationRequestScheduler {
     fn on_block_finalized(&mut self);
     /// Notice request sending.
     fn on_request_sent(&mut self);
+    /// Overrides the delay between requests, or restores the default one if `None`.
+    fn set_delay(&mut self, delay: Option<Duration>);
 }
 
 pub struct JustificationRequestSchedulerImpl {
     last_request_time: Instant,
     last_finalization_time: Instant,
     delay: Duration,
+    default_delay: Duration,
     attempt: u32,
     max_attemps: u32,
 }
@@ -35,15 +38,17 @@ impl JustificationRequestSchedulerImpl {
         millisecs_per_block: &MillisecsPerBlock,
         max_attemps: u32,
     ) -> Self {
+        // Request justification during the session. Usually every two blocks,
+        // unless session period is peculiar small in which case we request it more often to ensure non-validators won't lag
+        let delay = Duration::from_millis(min(
+            millisecs_per_block.0 * 2,
+            millisecs_per_block.0 * session_period.0 as u64 / 10,
+        ));
         Self {
             last_request_time: Instant::now(),
             last_finalization_time: Instant::now(),
-            ///Request justification during the session. Usually every two blocks,
-            ///unless session period is peculiar small in which case we request it more often to ensure non-validators won't lag
-            delay: Duration::from_millis(min(
-                millisecs_per_block.0 * 2,
-                millisecs_per_block.0 * session_period.0 as u64 / 10,
-            )),
+            delay,
+            default_delay: delay,
             attempt: 0,
             max_attemps,
         }
@@ -83,4 +88,8 @@ impl JustificationRequestScheduler for JustificationRequestSchedulerImpl {
     fn on_request_sent(&mut self) {
         self.last_request_time = Instant::now();
     }
+
+    fn set_delay(&mut self, delay: Option<Duration>) {
+        self.delay = delay.unwrap_or(self.default_delay);
+    }
 }
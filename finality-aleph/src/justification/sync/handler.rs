@@ -0,0 +1,256 @@
+use std::{cmp::min, marker::PhantomData, sync::Arc};
+
+use aleph_primitives::ALEPH_ENGINE_ID;
+use codec::{Decode, Encode};
+use log::{debug, warn};
+use sc_client_api::{BlockBackend, HeaderBackend};
+use sp_api::{BlockId, BlockT, NumberFor};
+
+use crate::{
+    checked_last_block_of_session,
+    justification::{
+        backwards_compatible_decode,
+        sync::{
+            JustificationItem, JustificationProvider, JustificationRequest, JustificationResponse,
+            MAX_SESSIONS_PER_RESPONSE,
+        },
+        versioned_encode, AlephJustification,
+    },
+    metrics::EventMetrics,
+    session_id_from_block_num, SessionId, SessionPeriod,
+};
+
+impl<B, C> JustificationProvider<B> for Arc<C>
+where
+    B: BlockT,
+    C: HeaderBackend<B> + BlockBackend<B>,
+{
+    fn justification(&self, number: NumberFor<B>) -> Option<(B::Hash, AlephJustification)> {
+        let client = self.as_ref();
+        if number > client.info().finalized_number {
+            return None;
+        }
+        let hash = client.hash(number).ok()??;
+        let justifications = client.justifications(&BlockId::Hash(hash)).ok()??;
+        let raw_justification = justifications.get(ALEPH_ENGINE_ID)?;
+        match backwards_compatible_decode(raw_justification.clone()) {
+            Ok(justification) => Some((hash, justification)),
+            Err(e) => {
+                warn!(target: "aleph-justification", "Stored justification for block {:?} cannot be decoded: {}", number, e);
+                None
+            }
+        }
+    }
+
+    fn finalized_number(&self) -> NumberFor<B> {
+        self.as_ref().info().finalized_number
+    }
+}
+
+/// Answers justification sync requests of other nodes using the justifications we have stored.
+pub struct RequestHandler<B: BlockT, JP: JustificationProvider<B>> {
+    justification_provider: JP,
+    session_period: SessionPeriod,
+    metrics: Option<EventMetrics>,
+    _phantom: PhantomData<B>,
+}
+
+impl<B: BlockT, JP: JustificationProvider<B>> RequestHandler<B, JP> {
+    pub fn new(
+        justification_provider: JP,
+        session_period: SessionPeriod,
+        metrics: Option<EventMetrics>,
+    ) -> Self {
+        RequestHandler {
+            justification_provider,
+            session_period,
+            metrics,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Returns the justifications of the last blocks of the requested sessions, stopping at the
+    /// first session for which we do not have one. The request comes from an untrusted peer, so
+    /// we never look past the session we are currently finalizing.
+    pub fn handle_request(&self, request: JustificationRequest) -> JustificationResponse<B> {
+        let JustificationRequest {
+            first_session,
+            session_count,
+        } = request;
+        let session_count = min(session_count, MAX_SESSIONS_PER_RESPONSE);
+        let finalized_session = session_id_from_block_num::<B>(
+            self.justification_provider.finalized_number(),
+            self.session_period,
+        );
+        let mut response = Vec::new();
+        for session in (first_session.0..=finalized_session.0).take(session_count as usize) {
+            let number =
+                match checked_last_block_of_session::<B>(SessionId(session), self.session_period) {
+                    Some(number) => number,
+                    None => break,
+                };
+            let (hash, justification) = match self.justification_provider.justification(number) {
+                Some(justification) => justification,
+                None => break,
+            };
+            match versioned_encode(justification) {
+                Ok(justification) => response.push(JustificationItem {
+                    hash,
+                    number,
+                    justification,
+                }),
+                Err(e) => {
+                    warn!(target: "aleph-justification", "Failed to encode justification for block {:?}: {}", number, e);
+                    break;
+                }
+            }
+        }
+        debug!(target: "aleph-justification", "Responding with {} justifications starting from session {:?}", response.len(), first_session);
+        response
+    }
+
+    /// Decodes the request, handles it and encodes the response.
+    pub fn handle_encoded_request(&self, request: &[u8]) -> Result<Vec<u8>, codec::Error> {
+        let request = JustificationRequest::decode(&mut &request[..])?;
+        let response = self.handle_request(request).encode();
+        if let Some(metrics) = &self.metrics {
+            metrics.report_justification_request_answered();
+        }
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use aleph_bft::SignatureSet;
+    use codec::{Decode, Encode};
+
+    use super::RequestHandler;
+    use crate::{
+        justification::{
+            backwards_compatible_decode,
+            sync::{
+                JustificationProvider, JustificationRequest, JustificationResponse,
+                MAX_SESSIONS_PER_RESPONSE,
+            },
+            AlephJustification,
+        },
+        testing::mocks::{TBlock, THash, TNumber},
+        SessionId, SessionPeriod,
+    };
+
+    const SESSION_PERIOD: SessionPeriod = SessionPeriod(10);
+
+    struct MockProvider(HashMap<TNumber, THash>);
+
+    impl MockProvider {
+        fn with_sessions(sessions: u32) -> Self {
+            MockProvider(
+                (0..sessions)
+                    .map(|s| {
+                        let number = ((s + 1) * SESSION_PERIOD.0 - 1) as TNumber;
+                        (number, [s as u8; 32].into())
+                    })
+                    .collect(),
+            )
+        }
+    }
+
+    impl JustificationProvider<TBlock> for MockProvider {
+        fn justification(&self, number: TNumber) -> Option<(THash, AlephJustification)> {
+            self.0.get(&number).map(|hash| {
+                (
+                    *hash,
+                    AlephJustification::CommitteeMultisignature(SignatureSet::with_size(0.into())),
+                )
+            })
+        }
+
+        fn finalized_number(&self) -> TNumber {
+            self.0.keys().max().copied().unwrap_or(0)
+        }
+    }
+
+    fn handler(sessions: u32) -> RequestHandler<TBlock, MockProvider> {
+        RequestHandler::new(MockProvider::with_sessions(sessions), SESSION_PERIOD, None)
+    }
+
+    #[test]
+    fn responds_with_last_blocks_of_requested_sessions() {
+        let response = handler(10).handle_request(JustificationRequest {
+            first_session: SessionId(2),
+            session_count: 3,
+        });
+        let numbers: Vec<_> = response.iter().map(|item| item.number).collect();
+        assert_eq!(numbers, vec![29, 39, 49]);
+        for item in response {
+            assert!(backwards_compatible_decode(item.justification).is_ok());
+        }
+    }
+
+    #[test]
+    fn stops_at_first_missing_justification() {
+        let response = handler(4).handle_request(JustificationRequest {
+            first_session: SessionId(2),
+            session_count: 5,
+        });
+        assert_eq!(response.len(), 2);
+    }
+
+    #[test]
+    fn caps_response_size() {
+        let response =
+            handler(MAX_SESSIONS_PER_RESPONSE + 10).handle_request(JustificationRequest {
+                first_session: SessionId(0),
+                session_count: MAX_SESSIONS_PER_RESPONSE + 10,
+            });
+        assert_eq!(response.len(), MAX_SESSIONS_PER_RESPONSE as usize);
+    }
+
+    #[test]
+    fn ignores_sessions_we_have_not_finalized() {
+        let response = handler(4).handle_request(JustificationRequest {
+            first_session: SessionId(7),
+            session_count: 5,
+        });
+        assert!(response.is_empty());
+    }
+
+    #[test]
+    fn survives_hostile_requests() {
+        for first_session in [u32::MAX - 1, u32::MAX] {
+            let response = handler(4).handle_request(JustificationRequest {
+                first_session: SessionId(first_session),
+                session_count: u32::MAX,
+            });
+            assert!(response.is_empty());
+        }
+        let handler = RequestHandler::<TBlock, _>::new(
+            MockProvider(HashMap::from([(TNumber::MAX, [0; 32].into())])),
+            SESSION_PERIOD,
+            None,
+        );
+        let response = handler.handle_request(JustificationRequest {
+            first_session: SessionId(u32::MAX / SESSION_PERIOD.0),
+            session_count: u32::MAX,
+        });
+        assert!(response.is_empty());
+    }
+
+    #[test]
+    fn handles_encoded_requests() {
+        let request = JustificationRequest {
+            first_session: SessionId(0),
+            session_count: 2,
+        };
+        let response = handler(4)
+            .handle_encoded_request(&request.encode())
+            .expect("the request is correctly encoded");
+        let response = JustificationResponse::<TBlock>::decode(&mut &response[..])
+            .expect("the response is correctly encoded");
+        assert_eq!(response.len(), 2);
+        assert!(handler(4).handle_encoded_request(&[1, 2, 3]).is_err());
+    }
+}
@@ -0,0 +1,89 @@
+use std::{fmt::Debug, time::Duration};
+
+use async_trait::async_trait;
+use codec::{Decode, Encode};
+use sp_api::{BlockT, NumberFor};
+
+use crate::{justification::AlephJustification, SessionId};
+
+mod handler;
+mod requester;
+
+pub use handler::RequestHandler;
+pub use requester::Requester;
+
+/// The maximal number of sessions we will ever include in a single response.
+pub const MAX_SESSIONS_PER_RESPONSE: u32 = 64;
+
+/// A request for the justifications of the last blocks of a range of consecutive sessions.
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct JustificationRequest {
+    /// The first session we want the justification for.
+    pub first_session: SessionId,
+    /// How many consecutive sessions we want the justifications for.
+    pub session_count: u32,
+}
+
+/// A justification of the last block of a session, together with the block it finalizes.
+/// The justification is kept in its versioned encoding, so that nodes using different
+/// justification formats can still talk to each other.
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct JustificationItem<H: Encode + Decode, N: Encode + Decode> {
+    pub hash: H,
+    pub number: N,
+    pub justification: Vec<u8>,
+}
+
+/// A response to a `JustificationRequest`, containing the justifications in the order of sessions.
+/// Might contain fewer justifications than requested, but never skips a session.
+pub type JustificationResponse<B> = Vec<JustificationItem<<B as BlockT>::Hash, NumberFor<B>>>;
+
+/// Abstraction over sending justification sync requests to some peer and awaiting the response.
+#[async_trait]
+pub trait RequestJustifications: Clone + Send + Sync + 'static {
+    type Error: Debug + Send;
+
+    /// Sends the encoded request to a peer and returns the encoded response.
+    async fn request_justifications(&self, request: Vec<u8>) -> Result<Vec<u8>, Self::Error>;
+}
+
+/// Abstraction over reading justifications of finalized blocks from the database.
+pub trait JustificationProvider<B: BlockT> {
+    /// Returns the hash and the justification of the finalized block with the given number, if
+    /// we have them.
+    fn justification(&self, number: NumberFor<B>) -> Option<(B::Hash, AlephJustification)>;
+
+    /// Returns the number of the last block we finalized.
+    fn finalized_number(&self) -> NumberFor<B>;
+}
+
+#[derive(Clone)]
+pub struct JustificationSyncConfig {
+    /// How often should we check whether we are lagging behind and need to sync.
+    request_interval: Duration,
+    /// How long should we wait before requesting the same range again.
+    retry_delay: Duration,
+    /// How many sessions should we request at once.
+    batch_size: u32,
+}
+
+impl Default for JustificationSyncConfig {
+    fn default() -> Self {
+        Self {
+            request_interval: Duration::from_millis(1000),
+            retry_delay: Duration::from_millis(10000),
+            batch_size: 16,
+        }
+    }
+}
+
+#[cfg(test)]
+impl JustificationSyncConfig {
+    pub fn new(request_interval: Duration, retry_delay: Duration, batch_size: u32) -> Self {
+        Self {
+            request_interval,
+            retry_delay,
+            batch_size,
+        }
+    }
+}
@@ -0,0 +1,384 @@
+use std::{marker::PhantomData, sync::Arc, time::Instant};
+
+use codec::{Decode, Encode};
+use futures::channel::mpsc;
+use futures_timer::Delay;
+use log::{debug, warn};
+use sc_client_api::HeaderBackend;
+use sp_api::BlockT;
+
+use crate::{
+    justification::{
+        backwards_compatible_decode,
+        sync::{
+            JustificationRequest, JustificationResponse, JustificationSyncConfig,
+            RequestJustifications,
+        },
+        JustificationNotification,
+    },
+    last_block_of_session,
+    metrics::{EventMetrics, VerificationFailure},
+    session_id_from_block_num, SessionId, SessionPeriod,
+};
+
+/// Requests justifications of whole sessions at once when we are lagging behind, and passes them
+/// on to the justification handler, which verifies them and finalizes the blocks in order.
+pub struct Requester<B, RJ, C>
+where
+    B: BlockT,
+    RJ: RequestJustifications,
+    C: HeaderBackend<B> + Send + Sync + 'static,
+{
+    network: RJ,
+    client: Arc<C>,
+    session_period: SessionPeriod,
+    config: JustificationSyncConfig,
+    justifications_for_handler: mpsc::UnboundedSender<JustificationNotification<B>>,
+    last_request: Option<(SessionId, Instant)>,
+    metrics: Option<EventMetrics>,
+    _phantom: PhantomData<B>,
+}
+
+impl<B, RJ, C> Requester<B, RJ, C>
+where
+    B: BlockT,
+    RJ: RequestJustifications,
+    C: HeaderBackend<B> + Send + Sync + 'static,
+{
+    pub fn new(
+        network: RJ,
+        client: Arc<C>,
+        session_period: SessionPeriod,
+        config: JustificationSyncConfig,
+        justifications_for_handler: mpsc::UnboundedSender<JustificationNotification<B>>,
+        metrics: Option<EventMetrics>,
+    ) -> Self {
+        Requester {
+            network,
+            client,
+            session_period,
+            config,
+            justifications_for_handler,
+            last_request: None,
+            metrics,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Returns a request for the justifications of all the sessions we have the last blocks of,
+    /// but did not finalize yet, if there are any.
+    fn next_request(&self) -> Option<JustificationRequest> {
+        let info = self.client.info();
+        let first_session = session_id_from_block_num::<B>(
+            info.finalized_number + 1u32.into(),
+            self.session_period,
+        );
+        let session_count = (first_session.0..)
+            .take(self.config.batch_size as usize)
+            .take_while(|session| {
+                last_block_of_session::<B>(SessionId(*session), self.session_period)
+                    <= info.best_number
+            })
+            .count() as u32;
+        match session_count {
+            0 => None,
+            session_count => Some(JustificationRequest {
+                first_session,
+                session_count,
+            }),
+        }
+    }
+
+    fn recently_requested(&self, request: &JustificationRequest) -> bool {
+        match self.last_request {
+            Some((session, time)) => {
+                session == request.first_session && time.elapsed() < self.config.retry_delay
+            }
+            None => false,
+        }
+    }
+
+    /// Decodes the response and forwards the justifications to the handler. Returns the number of
+    /// justifications forwarded.
+    fn handle_response(&mut self, request: &JustificationRequest, response: Vec<u8>) -> usize {
+        let response = match JustificationResponse::<B>::decode(&mut &response[..]) {
+            Ok(response) => response,
+            Err(e) => {
+                warn!(target: "aleph-justification", "Failed to decode justification sync response: {}", e);
+                self.report_undecodable();
+                return 0;
+            }
+        };
+        let mut forwarded = 0;
+        for (session, item) in (request.first_session.0..).zip(response.into_iter()) {
+            if forwarded == request.session_count as usize {
+                warn!(target: "aleph-justification", "Received more justifications than requested, ignoring the rest.");
+                break;
+            }
+            let expected_number =
+                last_block_of_session::<B>(SessionId(session), self.session_period);
+            if item.number != expected_number {
+                warn!(target: "aleph-justification", "Received justification for block {:?}, expected one for block {:?}.", item.number, expected_number);
+                break;
+            }
+            let justification = match backwards_compatible_decode(item.justification) {
+                Ok(justification) => justification,
+                Err(e) => {
+                    warn!(target: "aleph-justification", "Failed to decode synced justification for block {:?}: {}", item.number, e);
+                    self.report_undecodable();
+                    break;
+                }
+            };
+            if self
+                .justifications_for_handler
+                .unbounded_send(JustificationNotification {
+                    justification,
+                    hash: item.hash,
+                    number: item.number,
+                })
+                .is_err()
+            {
+                warn!(target: "aleph-justification", "Failed to forward synced justification, the handler is gone.");
+                break;
+            }
+            forwarded += 1;
+        }
+        forwarded
+    }
+
+    fn report_undecodable(&self) {
+        if let Some(metrics) = &self.metrics {
+            metrics.report_verification_failure(VerificationFailure::Undecodable);
+        }
+    }
+
+    async fn sync(&mut self) {
+        let request = match self.next_request() {
+            Some(request) => request,
+            None => return,
+        };
+        if self.recently_requested(&request) {
+            return;
+        }
+        debug!(target: "aleph-justification", "Requesting justifications for {} sessions starting from {:?}", request.session_count, request.first_session);
+        let start = Instant::now();
+        self.last_request = Some((request.first_session, start));
+        if let Some(metrics) = &self.metrics {
+            metrics.report_justification_request_sent();
+        }
+        match self.network.request_justifications(request.encode()).await {
+            Ok(response) => {
+                if let Some(metrics) = &self.metrics {
+                    metrics.report_justification_response_time(start.elapsed());
+                }
+                let forwarded = self.handle_response(&request, response);
+                debug!(target: "aleph-justification", "Forwarded {} synced justifications starting from session {:?}", forwarded, request.first_session);
+                if forwarded == 0 {
+                    // Allow asking someone else right away.
+                    self.last_request = None;
+                }
+            }
+            Err(e) => {
+                debug!(target: "aleph-justification", "Justification sync request failed: {:?}", e);
+                self.last_request = None;
+            }
+        }
+    }
+
+    pub async fn run(mut self) {
+        loop {
+            Delay::new(self.config.request_interval).await;
+            self.sync().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use aleph_bft::SignatureSet;
+    use async_trait::async_trait;
+    use codec::{Decode, Encode};
+    use futures::{
+        channel::mpsc::{unbounded, UnboundedReceiver},
+        StreamExt,
+    };
+    use sp_api::BlockId;
+    use sp_blockchain::{BlockStatus, HeaderBackend, Info};
+
+    use super::Requester;
+    use crate::{
+        justification::{
+            sync::{
+                JustificationItem, JustificationRequest, JustificationResponse,
+                JustificationSyncConfig, RequestJustifications,
+            },
+            versioned_encode, AlephJustification, JustificationNotification,
+        },
+        testing::mocks::{TBlock, THash, THeader, TNumber},
+        SessionId, SessionPeriod,
+    };
+
+    const SESSION_PERIOD: SessionPeriod = SessionPeriod(5);
+
+    /// A chain which is only aware of its best and finalized block numbers.
+    struct LaggingChain {
+        finalized_number: TNumber,
+        best_number: TNumber,
+    }
+
+    impl HeaderBackend<TBlock> for LaggingChain {
+        fn header(&self, _id: BlockId<TBlock>) -> sp_blockchain::Result<Option<THeader>> {
+            Ok(None)
+        }
+
+        fn info(&self) -> Info<TBlock> {
+            Info {
+                best_hash: Default::default(),
+                best_number: self.best_number,
+                finalized_hash: Default::default(),
+                finalized_number: self.finalized_number,
+                genesis_hash: Default::default(),
+                number_leaves: Default::default(),
+                finalized_state: None,
+                block_gap: None,
+            }
+        }
+
+        fn status(&self, _id: BlockId<TBlock>) -> sp_blockchain::Result<BlockStatus> {
+            Ok(BlockStatus::Unknown)
+        }
+
+        fn number(&self, _hash: THash) -> sp_blockchain::Result<Option<TNumber>> {
+            Ok(None)
+        }
+
+        fn hash(&self, _number: TNumber) -> sp_blockchain::Result<Option<THash>> {
+            Ok(None)
+        }
+    }
+
+    #[derive(Clone)]
+    struct MockNetwork {
+        requests: Arc<Mutex<Vec<JustificationRequest>>>,
+        respond: bool,
+    }
+
+    #[async_trait]
+    impl RequestJustifications for MockNetwork {
+        type Error = ();
+
+        async fn request_justifications(&self, request: Vec<u8>) -> Result<Vec<u8>, ()> {
+            let request = JustificationRequest::decode(&mut &request[..]).map_err(|_| ())?;
+            self.requests.lock().unwrap().push(request.clone());
+            if !self.respond {
+                return Err(());
+            }
+            let response: JustificationResponse<TBlock> = (request.first_session.0..)
+                .take(request.session_count as usize)
+                .map(|session| JustificationItem {
+                    hash: [session as u8; 32].into(),
+                    number: ((session + 1) * SESSION_PERIOD.0 - 1) as TNumber,
+                    justification: versioned_encode(AlephJustification::CommitteeMultisignature(
+                        SignatureSet::with_size(0.into()),
+                    ))
+                    .expect("encoding should succeed"),
+                })
+                .collect();
+            Ok(response.encode())
+        }
+    }
+
+    fn prepare(
+        finalized_number: TNumber,
+        best_number: TNumber,
+        respond: bool,
+    ) -> (
+        Requester<TBlock, MockNetwork, LaggingChain>,
+        MockNetwork,
+        UnboundedReceiver<JustificationNotification<TBlock>>,
+    ) {
+        let network = MockNetwork {
+            requests: Arc::new(Mutex::new(Vec::new())),
+            respond,
+        };
+        let (tx, rx) = unbounded();
+        let requester = Requester::new(
+            network.clone(),
+            Arc::new(LaggingChain {
+                finalized_number,
+                best_number,
+            }),
+            SESSION_PERIOD,
+            JustificationSyncConfig::new(
+                Duration::from_millis(10),
+                Duration::from_millis(10000),
+                2,
+            ),
+            tx,
+            None,
+        );
+        (requester, network, rx)
+    }
+
+    #[tokio::test]
+    async fn does_not_request_when_no_session_end_is_available() {
+        let (mut requester, network, _rx) = prepare(5, 8, true);
+        requester.sync().await;
+        assert!(network.requests.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn requests_and_forwards_justifications_in_order() {
+        let (mut requester, network, mut rx) = prepare(5, 20, true);
+        requester.sync().await;
+        assert_eq!(
+            network.requests.lock().unwrap().clone(),
+            vec![JustificationRequest {
+                first_session: SessionId(1),
+                session_count: 2,
+            }]
+        );
+        let first = rx.next().await.expect("justification should be forwarded");
+        let second = rx.next().await.expect("justification should be forwarded");
+        assert_eq!(first.number, 9);
+        assert_eq!(second.number, 14);
+    }
+
+    #[tokio::test]
+    async fn does_not_repeat_request_too_early() {
+        let (mut requester, network, _rx) = prepare(5, 20, true);
+        requester.sync().await;
+        requester.sync().await;
+        assert_eq!(network.requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn retries_immediately_after_failure() {
+        let (mut requester, network, _rx) = prepare(5, 20, false);
+        requester.sync().await;
+        requester.sync().await;
+        assert_eq!(network.requests.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn rejects_unexpected_blocks() {
+        let (mut requester, _, mut rx) = prepare(5, 20, true);
+        let request = JustificationRequest {
+            first_session: SessionId(1),
+            session_count: 2,
+        };
+        let response: JustificationResponse<TBlock> = vec![JustificationItem {
+            hash: Default::default(),
+            number: 7,
+            justification: Vec::new(),
+        }];
+        assert_eq!(requester.handle_response(&request, response.encode()), 0);
+        drop(requester);
+        assert!(rx.next().await.is_none());
+    }
+}
@@ -1,4 +1,4 @@
-use std::{fmt::Debug, path::PathBuf, sync::Arc};
+use std::{fmt::Debug, sync::Arc, time::Duration};
 
 use aleph_bft::{NodeIndex, TaskHandle};
 use codec::{Decode, Encode};
@@ -6,9 +6,14 @@ use futures::{
     channel::{mpsc, oneshot},
     Future, TryFutureExt,
 };
-use sc_client_api::{backend::Backend, BlockchainEvents, Finalizer, LockImportRun, TransactionFor};
+use sc_client_api::{
+    backend::Backend, BlockBackend, BlockchainEvents, Finalizer, LockImportRun, TransactionFor,
+};
 use sc_consensus::BlockImport;
-use sc_network::{ExHashT, NetworkService};
+use sc_network::{
+    config::{IncomingRequest, RequestResponseConfig},
+    ExHashT, NetworkService,
+};
 use sc_service::SpawnTaskHandle;
 use sp_api::{NumberFor, ProvideRuntimeApi};
 use sp_blockchain::{HeaderBackend, HeaderMetadata};
@@ -18,16 +23,16 @@ use sp_runtime::traits::{BlakeTwo256, Block, Header};
 use crate::{
     aggregation::RmcNetworkData,
     network::{AlephNetworkData, Split},
-    session::{
-        first_block_of_session, last_block_of_session, session_id_from_block_num,
-        SessionBoundaries, SessionId,
-    },
-    substrate_network::protocol_name,
+    session::{checked_last_block_of_session, first_block_of_session, last_block_of_session},
+    substrate_network::{justification_sync_protocol_name, protocol_name},
 };
 
+mod address_registry;
 mod aggregation;
 mod crypto;
 mod data_io;
+mod finality_params;
+mod finality_proof;
 mod finalization;
 mod hash;
 mod import;
@@ -44,11 +49,28 @@ pub mod testing;
 
 pub use aleph_bft::default_config as default_aleph_config;
 pub use aleph_primitives::{AuthorityId, AuthorityPair, AuthoritySignature};
+pub use crypto::{AggregatingKeychain, BlsKeystore, BlsPen, BlsPublicKey, BlsVerifier};
+pub use data_io::{ChainTrackerConfig, DataStoreConfig};
+pub use finality_proof::{
+    authority_handovers, finality_proof, verify_authority_handovers, AuthorityHandover,
+    FinalityProof, FinalityProofError,
+};
+pub use finalization::JustificationPruner;
 pub use import::AlephBlockImport;
-pub use justification::{AlephJustification, JustificationNotification};
-pub use network::Protocol;
+pub use justification::{
+    backwards_compatible_decode, AlephJustification, FinalizedBlockSender, FinalizedBlockStream,
+    JustificationNotification,
+};
+pub use network::{
+    Protocol, ValidatorConnections, ValidatorNetworkConfig, DEFAULT_COMPRESSION_THRESHOLD,
+};
 pub use nodes::{run_nonvalidator_node, run_validator_node};
-pub use session::SessionPeriod;
+pub use party::{
+    verify_backups, AppendOnlyBackupStore, BackupFileState, BackupStore, DirectoryBackupStore,
+    InMemoryBackupStore, NoBackupStore, SessionBackupState, UnitCreationDelayBounds,
+};
+pub use session::{session_id_from_block_num, SessionBoundaries, SessionId, SessionPeriod};
+pub use session_map::{ReadOnlySessionMap, SharedSessionMap};
 
 pub use crate::metrics::Metrics;
 
@@ -67,6 +89,8 @@ pub fn peers_set_config(protocol: Protocol) -> sc_network::config::NonDefaultSet
         // Max size of alert is UNIT_SIZE * MAX_UNITS_IN_ALERT ~ 100 * 5000 = 50000 bytes
         // Max size of parents response UNIT_SIZE * N_MEMBERS ~ 100 * N_MEMBERS
         // When adding other (large) message types we need to make sure this limit is fine.
+        // Session data above a threshold is compressed for peers accepting it, which keeps large
+        // committees well under the limit.
         1024 * 1024,
     );
 
@@ -83,6 +107,26 @@ pub fn peers_set_config(protocol: Protocol) -> sc_network::config::NonDefaultSet
     config
 }
 
+/// How many justification sync requests can wait for being handled.
+const JUSTIFICATION_SYNC_QUEUE_SIZE: usize = 32;
+
+/// Returns a RequestResponseConfig for the justification sync protocol, together with the stream
+/// of incoming requests, which should be passed to the finality gadget in `AlephConfig`.
+pub fn justification_sync_config() -> (RequestResponseConfig, mpsc::Receiver<IncomingRequest>) {
+    let (inbound_queue, requests) = mpsc::channel(JUSTIFICATION_SYNC_QUEUE_SIZE);
+    let config = RequestResponseConfig {
+        name: justification_sync_protocol_name(),
+        fallback_names: Vec::new(),
+        max_request_size: 1024,
+        // A single justification grows with the committee size, roughly 100 bytes per member,
+        // and we send at most `MAX_SESSIONS_PER_RESPONSE` of them.
+        max_response_size: 16 * 1024 * 1024,
+        request_timeout: Duration::from_secs(20),
+        inbound_queue: Some(inbound_queue),
+    };
+    (config, requests)
+}
+
 #[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash, Ord, PartialOrd, Encode, Decode)]
 pub struct MillisecsPerBlock(pub u64);
 
@@ -99,6 +143,7 @@ pub trait ClientForAleph<B, BE>:
     + HeaderBackend<B>
     + HeaderMetadata<B, Error = sp_blockchain::Error>
     + BlockchainEvents<B>
+    + BlockBackend<B>
 where
     BE: Backend<B>,
     B: Block,
@@ -115,6 +160,7 @@ where
         + HeaderBackend<B>
         + HeaderMetadata<B, Error = sp_blockchain::Error>
         + BlockchainEvents<B>
+        + BlockBackend<B>
         + BlockImport<B, Transaction = TransactionFor<BE, B>, Error = sp_consensus::Error>,
 {
 }
@@ -176,10 +222,43 @@ pub struct AlephConfig<B: Block, H: ExHashT, C, SC> {
     pub select_chain: SC,
     pub spawn_handle: SpawnTaskHandle,
     pub keystore: Arc<dyn CryptoStore>,
+    /// The BLS keys used for aggregating signatures of blocks. Only needed by validators.
+    pub bls_keystore: Arc<BlsKeystore>,
     pub justification_rx: mpsc::UnboundedReceiver<JustificationNotification<B>>,
     pub metrics: Option<Metrics<<B::Header as Header>::Hash>>,
     pub session_period: SessionPeriod,
     pub millisecs_per_block: MillisecsPerBlock,
     pub unit_creation_delay: UnitCreationDelay,
-    pub backup_saving_path: Option<PathBuf>,
+    /// If set, the unit creation delay is adapted every session within these bounds, starting
+    /// from `unit_creation_delay`.
+    pub unit_creation_delay_bounds: Option<UnitCreationDelayBounds>,
+    /// Where AlephBFT backups are kept, so that the node can recover after a crash.
+    pub backup_store: Arc<dyn BackupStore>,
+    /// How many sessions before the one of the last finalized block keep their backups.
+    pub backup_retention: u32,
+    /// Limits and intervals of the data store of every session we are a committee member of.
+    /// The limits can be overridden by the finality parameters governed on chain.
+    pub data_store_config: DataStoreConfig,
+    pub chain_tracker_config: ChainTrackerConfig,
+    /// Session data encoded to at least this many bytes is compressed for peers that accept it.
+    /// Never compressed if `None`.
+    pub compression_threshold: Option<usize>,
+    /// Whether to connect to validators using the addresses registered on chain, and register
+    /// our own addresses there.
+    pub use_address_registry: bool,
+    /// If set, validators connect to each other directly over TCP, authenticating with their
+    /// authority keys, instead of using the validator protocol of the p2p network.
+    pub validator_network: Option<ValidatorNetworkConfig>,
+    pub justification_sync_requests: mpsc::Receiver<IncomingRequest>,
+    /// If set, only the justifications of the last blocks of sessions are kept, the remaining
+    /// ones are removed with it once their session is finalized.
+    pub justification_pruner: Option<Arc<dyn JustificationPruner<B>>>,
+    /// The session map to keep up to date, its read-only views can be handed out beforehand,
+    /// e.g. to RPC handlers.
+    pub session_map: SharedSessionMap,
+    /// Notified about every block finalized by the gadget.
+    pub finalized_block_sender: FinalizedBlockSender<B>,
+    /// Kept up to date with the number of validators we are connected to, its clones can be
+    /// handed out beforehand, e.g. to RPC handlers.
+    pub validator_connections: ValidatorConnections,
 }
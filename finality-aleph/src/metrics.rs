@@ -8,7 +8,10 @@ use std::{
 use log::{trace, warn};
 use lru::LruCache;
 use parking_lot::Mutex;
-use prometheus_endpoint::{register, Gauge, PrometheusError, Registry, U64};
+use prometheus_endpoint::{
+    exponential_buckets, register, Counter, CounterVec, Gauge, GaugeVec, Histogram, HistogramOpts,
+    Opts, PrometheusError, Registry, U64,
+};
 use sc_service::Arc;
 
 // How many entries (block hash + timestamp) we keep in memory per one checkpoint type.
@@ -23,6 +26,8 @@ impl<T: Hash + Eq + Debug + Copy> Key for T {}
 struct Inner<H: Key> {
     prev: HashMap<Checkpoint, Checkpoint>,
     gauges: HashMap<Checkpoint, Gauge<U64>>,
+    histograms: HashMap<Checkpoint, Histogram>,
+    import_to_finalize: Histogram,
     starts: HashMap<Checkpoint, LruCache<H, Instant>>,
 }
 
@@ -34,29 +39,55 @@ impl<H: Key> Inner<H> {
             starts.put(hash, checkpoint_time);
         });
 
-        if let Some(prev_checkpoint_type) = self.prev.get(&checkpoint_type) {
-            if let Some(start) = self
-                .starts
-                .get_mut(prev_checkpoint_type)
-                .expect("All checkpoint types were initialized")
-                .get(&hash)
+        if checkpoint_type == Checkpoint::Finalized {
+            if let Some(duration) = self.duration_since(
+                Checkpoint::Importing,
+                hash,
+                checkpoint_time,
+                checkpoint_type,
+            ) {
+                self.import_to_finalize.observe(duration.as_secs_f64());
+            }
+        }
+
+        if let Some(prev_checkpoint_type) = self.prev.get(&checkpoint_type).cloned() {
+            if let Some(duration) =
+                self.duration_since(prev_checkpoint_type, hash, checkpoint_time, checkpoint_type)
             {
-                let duration = match checkpoint_time.checked_duration_since(*start) {
-                    Some(duration) => duration,
-                    None => {
-                        warn!(target: "aleph-metrics", "Earlier metrics time {:?} is later that current one \
-                        {:?}. Checkpoint type {:?}, block: {:?}",
-                            *start, checkpoint_time, checkpoint_type, hash);
-                        Duration::new(0, 0)
-                    }
-                };
                 self.gauges
                     .get(&checkpoint_type)
                     .expect("All checkpoint types were initialized")
                     .set(duration.as_millis() as u64);
+                self.histograms
+                    .get(&checkpoint_type)
+                    .expect("All checkpoint types were initialized")
+                    .observe(duration.as_secs_f64());
             }
         }
     }
+
+    fn duration_since(
+        &mut self,
+        start_checkpoint_type: Checkpoint,
+        hash: H,
+        checkpoint_time: Instant,
+        checkpoint_type: Checkpoint,
+    ) -> Option<Duration> {
+        let start = *self
+            .starts
+            .get_mut(&start_checkpoint_type)
+            .expect("All checkpoint types were initialized")
+            .get(&hash)?;
+        Some(match checkpoint_time.checked_duration_since(start) {
+            Some(duration) => duration,
+            None => {
+                warn!(target: "aleph-metrics", "Earlier metrics time {:?} is later that current one \
+                {:?}. Checkpoint type {:?}, block: {:?}",
+                    start, checkpoint_time, checkpoint_type, hash);
+                Duration::new(0, 0)
+            }
+        })
+    }
 }
 
 #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
@@ -69,9 +100,253 @@ pub(crate) enum Checkpoint {
     Finalized,
 }
 
+/// Reasons for rejecting a justification, used as the `reason` label of
+/// `aleph_justification_verification_failures`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VerificationFailure {
+    /// The justification could not be decoded.
+    Undecodable,
+    /// The committee multisignature was incomplete or contained invalid signatures.
+    BadMultisignature,
+    /// The aggregated multisignature did not verify against the BLS keys of the committee.
+    BadAggregatedMultisignature,
+    /// An aggregated multisignature arrived, but we know no valid BLS keys of the committee.
+    UnknownBlsKeys,
+    /// The emergency signature was invalid.
+    BadEmergencySignature,
+    /// An emergency signature arrived, but the session has no emergency finalizer.
+    NoEmergencyFinalizer,
+}
+
+impl VerificationFailure {
+    fn label(&self) -> &'static str {
+        use VerificationFailure::*;
+        match self {
+            Undecodable => "undecodable",
+            BadMultisignature => "bad_multisignature",
+            BadAggregatedMultisignature => "bad_aggregated_multisignature",
+            UnknownBlsKeys => "unknown_bls_keys",
+            BadEmergencySignature => "bad_emergency_signature",
+            NoEmergencyFinalizer => "no_emergency_finalizer",
+        }
+    }
+}
+
+/// Counters and histograms of events in the gadget, independent of the block hash type.
+#[derive(Clone)]
+pub struct EventMetrics {
+    justification_requests_sent: Counter<U64>,
+    justification_requests_answered: Counter<U64>,
+    justification_verification_failures: CounterVec<U64>,
+    justification_response_time: Histogram,
+    data_store_pending_proposals: Gauge<U64>,
+    data_store_pending_messages: Gauge<U64>,
+    data_store_dropped_messages: Counter<U64>,
+    connected_validators: GaugeVec<U64>,
+    units_created: Counter<U64>,
+    multisignatures_completed: Counter<U64>,
+    multisignature_time: Histogram,
+    backup_bytes_written: Counter<U64>,
+    unit_creation_delay: Gauge<U64>,
+    compressed_bytes_saved: Counter<U64>,
+    compression_ratio: Histogram,
+}
+
+fn register_counter(
+    registry: &Registry,
+    name: &str,
+    help: &str,
+) -> Result<Counter<U64>, PrometheusError> {
+    register(Counter::new(name, help)?, registry)
+}
+
+fn register_gauge(
+    registry: &Registry,
+    name: &str,
+    help: &str,
+) -> Result<Gauge<U64>, PrometheusError> {
+    register(Gauge::new(name, help)?, registry)
+}
+
+fn register_histogram(
+    registry: &Registry,
+    name: &str,
+    help: &str,
+) -> Result<Histogram, PrometheusError> {
+    // From 10ms up to ~160s.
+    let buckets = exponential_buckets(0.01, 2.0, 15)?;
+    register(
+        Histogram::with_opts(HistogramOpts::new(name, help).buckets(buckets))?,
+        registry,
+    )
+}
+
+impl EventMetrics {
+    pub fn register(registry: &Registry) -> Result<Self, PrometheusError> {
+        Ok(EventMetrics {
+            justification_requests_sent: register_counter(
+                registry,
+                "aleph_justification_requests_sent",
+                "Number of justification requests sent to other nodes",
+            )?,
+            justification_requests_answered: register_counter(
+                registry,
+                "aleph_justification_requests_answered",
+                "Number of justification requests from other nodes that we responded to",
+            )?,
+            justification_verification_failures: register(
+                CounterVec::new(
+                    Opts::new(
+                        "aleph_justification_verification_failures",
+                        "Number of rejected justifications",
+                    ),
+                    &["reason"],
+                )?,
+                registry,
+            )?,
+            justification_response_time: register_histogram(
+                registry,
+                "aleph_justification_response_time",
+                "Time in seconds between sending a justification request and receiving a response",
+            )?,
+            data_store_pending_proposals: register_gauge(
+                registry,
+                "aleph_data_store_pending_proposals",
+                "Number of proposals the data store is waiting on",
+            )?,
+            data_store_pending_messages: register_gauge(
+                registry,
+                "aleph_data_store_pending_messages",
+                "Number of messages the data store is holding back",
+            )?,
+            data_store_dropped_messages: register_counter(
+                registry,
+                "aleph_data_store_dropped_messages",
+                "Number of pending messages the data store gave up on",
+            )?,
+            connected_validators: register(
+                GaugeVec::new(
+                    Opts::new(
+                        "aleph_connected_validators",
+                        "Number of other committee members with an open validator connection, per session",
+                    ),
+                    &["session"],
+                )?,
+                registry,
+            )?,
+            units_created: register_counter(
+                registry,
+                "aleph_units_created",
+                "Number of AlephBFT units created by this node",
+            )?,
+            multisignatures_completed: register_counter(
+                registry,
+                "aleph_multisignatures_completed",
+                "Number of blocks for which the RMC gathered a multisignature",
+            )?,
+            multisignature_time: register_histogram(
+                registry,
+                "aleph_multisignature_time",
+                "Time in seconds between starting the RMC for a block and gathering its multisignature",
+            )?,
+            backup_bytes_written: register_counter(
+                registry,
+                "aleph_backup_bytes_written",
+                "Number of bytes written to AlephBFT backups",
+            )?,
+            unit_creation_delay: register_gauge(
+                registry,
+                "aleph_unit_creation_delay",
+                "Unit creation delay in milliseconds used in the current session",
+            )?,
+            compressed_bytes_saved: register_counter(
+                registry,
+                "aleph_compressed_bytes_saved",
+                "Number of bytes saved by compressing session data sent to the network",
+            )?,
+            compression_ratio: register(
+                Histogram::with_opts(
+                    HistogramOpts::new(
+                        "aleph_compression_ratio",
+                        "Size of compressed session data relative to its uncompressed size",
+                    )
+                    .buckets((1..=10).map(|tenths| tenths as f64 / 10.0).collect()),
+                )?,
+                registry,
+            )?,
+        })
+    }
+
+    pub(crate) fn report_justification_request_sent(&self) {
+        self.justification_requests_sent.inc();
+    }
+
+    pub(crate) fn report_justification_request_answered(&self) {
+        self.justification_requests_answered.inc();
+    }
+
+    pub(crate) fn report_verification_failure(&self, reason: VerificationFailure) {
+        self.justification_verification_failures
+            .with_label_values(&[reason.label()])
+            .inc();
+    }
+
+    pub(crate) fn report_justification_response_time(&self, duration: Duration) {
+        self.justification_response_time
+            .observe(duration.as_secs_f64());
+    }
+
+    pub(crate) fn report_data_store_pending(&self, proposals: usize, messages: usize) {
+        self.data_store_pending_proposals.set(proposals as u64);
+        self.data_store_pending_messages.set(messages as u64);
+    }
+
+    pub(crate) fn report_data_store_dropped_messages(&self, count: usize) {
+        self.data_store_dropped_messages.inc_by(count as u64);
+    }
+
+    pub(crate) fn report_connected_validators(&self, session: u32, count: usize) {
+        self.connected_validators
+            .with_label_values(&[&session.to_string()])
+            .set(count as u64);
+    }
+
+    pub(crate) fn remove_session(&self, session: u32) {
+        // The label is missing if we never reported anything for this session.
+        let _ = self
+            .connected_validators
+            .remove_label_values(&[&session.to_string()]);
+    }
+
+    pub(crate) fn report_unit_created(&self) {
+        self.units_created.inc();
+    }
+
+    pub(crate) fn report_multisignature(&self, duration: Duration) {
+        self.multisignatures_completed.inc();
+        self.multisignature_time.observe(duration.as_secs_f64());
+    }
+
+    pub(crate) fn report_backup_bytes(&self, bytes: usize) {
+        self.backup_bytes_written.inc_by(bytes as u64);
+    }
+
+    pub(crate) fn report_unit_creation_delay(&self, millis: u64) {
+        self.unit_creation_delay.set(millis);
+    }
+
+    pub(crate) fn report_compression(&self, original_len: usize, compressed_len: usize) {
+        self.compressed_bytes_saved
+            .inc_by(original_len.saturating_sub(compressed_len) as u64);
+        self.compression_ratio
+            .observe(compressed_len as f64 / original_len as f64);
+    }
+}
+
 #[derive(Clone)]
 pub struct Metrics<H: Key> {
     inner: Arc<Mutex<Inner<H>>>,
+    events: EventMetrics,
 }
 
 impl<H: Key> Metrics<H> {
@@ -92,23 +367,53 @@ impl<H: Key> Metrics<H> {
             .collect();
 
         let mut gauges = HashMap::new();
+        let mut histograms = HashMap::new();
         for key in keys.iter() {
             gauges.insert(
                 *key,
                 register(Gauge::new(format!("aleph_{:?}", key), "no help")?, registry)?,
             );
         }
+        // The first checkpoint has no predecessor, so there is nothing to measure for it.
+        for (key, prev_key) in prev.iter() {
+            histograms.insert(
+                *key,
+                register_histogram(
+                    registry,
+                    &format!("aleph_{:?}_duration", key),
+                    &format!(
+                        "Time in seconds between the {:?} and {:?} checkpoints of a block",
+                        prev_key, key
+                    ),
+                )?,
+            );
+        }
+
+        let import_to_finalize = register_histogram(
+            registry,
+            "aleph_import_to_finalize_duration",
+            "Time in seconds between starting to import a block and finalizing it",
+        )?;
 
         let inner = Arc::new(Mutex::new(Inner {
             prev,
             gauges,
+            histograms,
+            import_to_finalize,
             starts: keys
                 .iter()
                 .map(|k| (*k, LruCache::new(MAX_BLOCKS_PER_CHECKPOINT)))
                 .collect(),
         }));
 
-        Ok(Self { inner })
+        Ok(Self {
+            inner,
+            events: EventMetrics::register(registry)?,
+        })
+    }
+
+    pub fn events(&self) -> &EventMetrics {
+        &self.events
     }
 
     pub(crate) fn report_block(
@@ -164,4 +469,82 @@ mod tests {
         metrics.report_block(0, later_timestamp, Checkpoint::Ordering);
         metrics.report_block(0, earlier_timestamp, Checkpoint::Ordered);
     }
+
+    #[test]
+    fn should_observe_every_checkpoint_transition() {
+        let metrics = Metrics::<usize>::register(&Registry::new()).unwrap();
+        let start = Instant::now();
+        for block in 0..3 {
+            metrics.report_block(block, start, Checkpoint::Importing);
+            metrics.report_block(
+                block,
+                start + Duration::from_millis(20),
+                Checkpoint::Imported,
+            );
+        }
+        // No predecessor was reported for this block.
+        metrics.report_block(3, start, Checkpoint::Imported);
+
+        let inner = metrics.inner.lock();
+        let imported = inner.histograms.get(&Checkpoint::Imported).unwrap();
+        assert_eq!(imported.get_sample_count(), 3);
+        assert!((imported.get_sample_sum() - 0.06).abs() < 1e-9);
+        assert!(!inner.histograms.contains_key(&Checkpoint::Importing));
+    }
+
+    #[test]
+    fn should_observe_time_from_import_to_finalization() {
+        let metrics = Metrics::<usize>::register(&Registry::new()).unwrap();
+        let start = Instant::now();
+        metrics.report_block(0, start, Checkpoint::Importing);
+        metrics.report_block(0, start + Duration::from_millis(10), Checkpoint::Imported);
+        metrics.report_block(0, start + Duration::from_millis(500), Checkpoint::Finalized);
+        // Finalized without seeing the import, e.g. after a restart.
+        metrics.report_block(1, start, Checkpoint::Finalized);
+
+        let inner = metrics.inner.lock();
+        assert_eq!(inner.import_to_finalize.get_sample_count(), 1);
+        assert!((inner.import_to_finalize.get_sample_sum() - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn should_forget_connected_validators_of_finished_sessions() {
+        let registry = Registry::new();
+        let metrics = EventMetrics::register(&registry).unwrap();
+        metrics.report_connected_validators(3, 5);
+        metrics.report_connected_validators(4, 2);
+        metrics.remove_session(3);
+        metrics.remove_session(7);
+
+        assert_eq!(
+            metrics.connected_validators.with_label_values(&["4"]).get(),
+            2
+        );
+        let families = registry.gather();
+        let family = families
+            .iter()
+            .find(|family| family.get_name() == "aleph_connected_validators")
+            .unwrap();
+        assert_eq!(family.get_metric().len(), 1);
+    }
+
+    #[test]
+    fn should_count_verification_failures_by_reason() {
+        let metrics = EventMetrics::register(&Registry::new()).unwrap();
+        metrics.report_verification_failure(VerificationFailure::BadMultisignature);
+        metrics.report_verification_failure(VerificationFailure::BadMultisignature);
+        metrics.report_verification_failure(VerificationFailure::UnknownBlsKeys);
+        metrics.report_verification_failure(VerificationFailure::Undecodable);
+
+        let failures = &metrics.justification_verification_failures;
+        assert_eq!(failures.with_label_values(&["bad_multisignature"]).get(), 2);
+        assert_eq!(failures.with_label_values(&["unknown_bls_keys"]).get(), 1);
+        assert_eq!(failures.with_label_values(&["undecodable"]).get(), 1);
+        assert_eq!(
+            failures
+                .with_label_values(&["bad_emergency_signature"])
+                .get(),
+            0
+        );
+    }
 }
@@ -9,6 +9,10 @@ use crate::network::{Data, DataNetwork, SendError};
 /// For sending arbitrary messages.
 pub trait Sender<D: Data>: Sync + Send + Clone {
     fn send(&self, data: D, recipient: Recipient) -> Result<(), SendError>;
+
+    /// Reports that data received from the network was rejected as invalid, so that its sender
+    /// can be penalized. Does nothing unless the network knows who sent the data.
+    fn report_rejected(&self, _data: D) {}
 }
 
 /// For receiving arbitrary messages.
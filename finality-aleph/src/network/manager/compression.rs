@@ -0,0 +1,90 @@
+//! Compression of large session data.
+//!
+//! Notifications are capped at 1 MiB, while some AlephBFT messages, e.g. alerts or responses to
+//! parents requests, grow with the size of the committee. Session data encoded to more bytes than
+//! a threshold is compressed with snappy, but only when sent to peers that announced in their
+//! discovery messages that they accept compressed data in the session, so nodes that do not
+//! support it can still take part.
+use codec::{Decode, Encode};
+use snap::raw::{decompress_len, Decoder, Encoder};
+
+/// By default data encoded to fewer bytes than this is sent uncompressed.
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 16 * 1024;
+
+/// Compressed data claiming to decompress to more bytes than this is rejected, so a malicious peer
+/// cannot make us allocate arbitrary amounts of memory.
+const MAX_DECOMPRESSED_SIZE: usize = 16 * 1024 * 1024;
+
+#[derive(Debug)]
+pub enum DecompressionError {
+    TooLarge(usize),
+    Snappy(snap::Error),
+    Decode(codec::Error),
+}
+
+/// Returns the encoded data compressed, together with the length of the encoding, if the encoding
+/// is at least `threshold` bytes long and compressing it actually saves space.
+pub fn compress<D: Encode>(data: &D, threshold: usize) -> Option<(Vec<u8>, usize)> {
+    if data.encoded_size() < threshold {
+        return None;
+    }
+    let encoded = data.encode();
+    match Encoder::new().compress_vec(&encoded) {
+        Ok(compressed) if compressed.len() < encoded.len() => Some((compressed, encoded.len())),
+        _ => None,
+    }
+}
+
+/// Decompresses and decodes data compressed with `compress`.
+pub fn decompress<D: Decode>(compressed: &[u8]) -> Result<D, DecompressionError> {
+    let len = decompress_len(compressed).map_err(DecompressionError::Snappy)?;
+    if len > MAX_DECOMPRESSED_SIZE {
+        return Err(DecompressionError::TooLarge(len));
+    }
+    let encoded = Decoder::new()
+        .decompress_vec(compressed)
+        .map_err(DecompressionError::Snappy)?;
+    D::decode(&mut &encoded[..]).map_err(DecompressionError::Decode)
+}
+
+#[cfg(test)]
+mod tests {
+    use codec::Encode;
+
+    use super::{compress, decompress, DecompressionError};
+
+    #[test]
+    fn compresses_large_data() {
+        let data = vec![7u8; 100_000];
+        let (compressed, original_len) = compress(&data, 1024).expect("the data is compressible");
+        assert_eq!(original_len, data.encode().len());
+        assert!(compressed.len() < original_len / 10);
+        assert_eq!(decompress::<Vec<u8>>(&compressed).unwrap(), data);
+    }
+
+    #[test]
+    fn does_not_compress_below_threshold() {
+        assert!(compress(&vec![7u8; 1000], 1024).is_none());
+    }
+
+    #[test]
+    fn does_not_compress_when_it_does_not_pay_off() {
+        let data: Vec<u8> = (0..10_000).map(|_| rand::random()).collect();
+        assert!(compress(&data, 1024).is_none());
+    }
+
+    #[test]
+    fn rejects_data_decompressing_to_too_much() {
+        // A snappy header claiming 2^30 bytes of decompressed data.
+        let compressed = vec![0x80, 0x80, 0x80, 0x80, 0x04];
+        assert!(matches!(
+            decompress::<Vec<u8>>(&compressed),
+            Err(DecompressionError::TooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        assert!(decompress::<Vec<u8>>(&[1, 2, 3, 4, 5]).is_err());
+    }
+}
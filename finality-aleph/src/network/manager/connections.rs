@@ -1,11 +1,97 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
+
+use parking_lot::Mutex;
+use tokio::time::Instant;
 
 use crate::{network::PeerId, SessionId};
 
+/// How long we let the network connect to a peer on its own, before we start reconnecting to it.
+pub const CONNECT_GRACE: Duration = Duration::from_secs(60);
+/// How long we wait before the second attempt at something that failed once.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(10);
+/// We never wait longer than this between consecutive attempts.
+const MAX_BACKOFF: Duration = Duration::from_secs(600);
+
+/// Exponential backoff between repeated attempts at something that keeps failing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    failed_attempts: u32,
+    next_attempt: Instant,
+}
+
+impl Backoff {
+    /// Creates a backoff without any failures, allowing the first attempt at the given time.
+    pub fn new(first_attempt: Instant) -> Self {
+        Backoff {
+            failed_attempts: 0,
+            next_attempt: first_attempt,
+        }
+    }
+
+    /// Whether the next attempt should be made by now.
+    pub fn is_due(&self, now: Instant) -> bool {
+        now >= self.next_attempt
+    }
+
+    /// Records a failed attempt, doubling the time until the next one up to a limit.
+    pub fn fail(&mut self, now: Instant) {
+        let delay = INITIAL_BACKOFF
+            .checked_mul(1 << self.failed_attempts.min(16))
+            .map_or(MAX_BACKOFF, |delay| delay.min(MAX_BACKOFF));
+        self.failed_attempts = self.failed_attempts.saturating_add(1);
+        self.next_attempt = now + delay;
+    }
+
+    pub fn failed_attempts(&self) -> u32 {
+        self.failed_attempts
+    }
+}
+
+/// What we know about the connection with a peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerHealth {
+    /// Since when the network has had an open Validator protocol stream with the peer, if it has
+    /// one.
+    pub connected_since: Option<Instant>,
+    /// When we last received a message from the peer.
+    pub last_message: Option<Instant>,
+    reconnect: Backoff,
+    /// Whether we tried reconnecting to the peer and it has not connected since.
+    reconnecting: bool,
+}
+
+impl PeerHealth {
+    fn new(now: Instant, connected: bool) -> Self {
+        let connected_since = match connected {
+            true => Some(now),
+            false => None,
+        };
+        PeerHealth {
+            connected_since,
+            last_message: None,
+            reconnect: Backoff::new(now + CONNECT_GRACE),
+            reconnecting: false,
+        }
+    }
+
+    /// The number of reconnection attempts the peer did not connect after, since it was last
+    /// connected.
+    pub fn failed_attempts(&self) -> u32 {
+        self.reconnect.failed_attempts()
+    }
+}
+
 /// Keeps track of connections we should maintain taking into account data from many sessions.
 pub struct Connections<PID: PeerId> {
     associated_sessions: HashMap<PID, HashSet<SessionId>>,
     peers_by_session: HashMap<SessionId, HashSet<PID>>,
+    health: HashMap<PID, PeerHealth>,
+    /// Peers with an open Validator protocol stream, as reported by the network.
+    connected: HashSet<PID>,
 }
 
 impl<PID: PeerId> Connections<PID> {
@@ -14,6 +100,8 @@ impl<PID: PeerId> Connections<PID> {
         Connections {
             associated_sessions: HashMap::new(),
             peers_by_session: HashMap::new(),
+            health: HashMap::new(),
+            connected: HashSet::new(),
         }
     }
 
@@ -28,7 +116,76 @@ impl<PID: PeerId> Connections<PID> {
                 .entry(session_id)
                 .or_default()
                 .insert(peer);
+            // Give new peers some time to connect before trying to reconnect to them.
+            let connected = self.connected.contains(&peer);
+            self.health
+                .entry(peer)
+                .or_insert_with(|| PeerHealth::new(Instant::now(), connected));
+        }
+    }
+
+    /// Records a message received from the peer, if it is one we should be connected to.
+    pub fn on_message(&mut self, peer: &PID, now: Instant) {
+        if let Some(health) = self.health.get_mut(peer) {
+            health.last_message = Some(now);
+        }
+    }
+
+    /// Records that the network opened a Validator protocol stream with the peer, which ends any
+    /// reconnection attempts.
+    pub fn on_connected(&mut self, peer: PID, now: Instant) {
+        if !self.connected.insert(peer) {
+            return;
+        }
+        if let Some(health) = self.health.get_mut(&peer) {
+            health.connected_since = Some(now);
+            health.reconnect = Backoff::new(now);
+            health.reconnecting = false;
+        }
+    }
+
+    /// Records that the network closed the Validator protocol stream with the peer. The network
+    /// gets some time to connect to it again before we start reconnecting.
+    pub fn on_disconnected(&mut self, peer: &PID, now: Instant) {
+        if !self.connected.remove(peer) {
+            return;
+        }
+        if let Some(health) = self.health.get_mut(peer) {
+            health.connected_since = None;
+            health.reconnect = Backoff::new(now + CONNECT_GRACE);
+            health.reconnecting = false;
+        }
+    }
+
+    /// Whether the network has an open Validator protocol stream with the peer.
+    pub fn is_connected(&self, peer: &PID) -> bool {
+        self.connected.contains(peer)
+    }
+
+    /// Returns the disconnected peers we should try reconnecting to now. An attempt counts as
+    /// failed if the peer does not connect before the next one is due, the attempts are backed
+    /// off exponentially.
+    pub fn to_reconnect(&mut self, now: Instant) -> HashSet<PID> {
+        let mut result = HashSet::new();
+        for (peer, health) in self.health.iter_mut() {
+            if self.connected.contains(peer) || !health.reconnect.is_due(now) {
+                continue;
+            }
+            match health.reconnecting {
+                true => health.reconnect.fail(now),
+                false => {
+                    health.reconnect = Backoff::new(now + INITIAL_BACKOFF);
+                    health.reconnecting = true;
+                }
+            }
+            result.insert(*peer);
         }
+        result
+    }
+
+    /// What we know about the connection with the peer, if it is one we should be connected to.
+    pub fn health(&self, peer: &PID) -> Option<PeerHealth> {
+        self.health.get(peer).copied()
     }
 
     /// Assume we no longer need to be connected to peers from the given session.
@@ -42,6 +199,7 @@ impl<PID: PeerId> Connections<PID> {
                     if !sessions.is_empty() {
                         self.associated_sessions.insert(peer, sessions);
                     } else {
+                        self.health.remove(&peer);
                         result.insert(peer);
                     }
                 }
@@ -51,11 +209,39 @@ impl<PID: PeerId> Connections<PID> {
     }
 }
 
+/// The number of other committee members we have an open Validator protocol stream with, in each
+/// running validator session. Clones share the counts, only the connection manager updates them.
+#[derive(Clone, Default)]
+pub struct ValidatorConnections {
+    counts: Arc<Mutex<HashMap<SessionId, usize>>>,
+}
+
+impl ValidatorConnections {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn set(&self, session_id: SessionId, count: usize) {
+        self.counts.lock().insert(session_id, count);
+    }
+
+    pub(crate) fn remove(&self, session_id: SessionId) {
+        self.counts.lock().remove(&session_id);
+    }
+
+    /// Returns the number of connected validators in the session, if we are a validator in it.
+    pub fn get(&self, session_id: SessionId) -> Option<usize> {
+        self.counts.lock().get(&session_id).copied()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashSet;
 
-    use super::Connections;
+    use tokio::time::{Duration, Instant};
+
+    use super::{Backoff, Connections, ValidatorConnections, CONNECT_GRACE, MAX_BACKOFF};
     use crate::{network::mock::MockPeerId, SessionId};
 
     fn random_peer_ids(num: usize) -> HashSet<MockPeerId> {
@@ -99,4 +285,125 @@ mod tests {
         let to_remove = connections.remove_session(SessionId(end));
         assert_eq!(to_remove, peer_ids);
     }
+
+    #[test]
+    fn backoff_doubles_up_to_limit() {
+        let now = Instant::now();
+        let mut backoff = Backoff::new(now);
+        assert!(backoff.is_due(now));
+        backoff.fail(now);
+        assert!(!backoff.is_due(now + Duration::from_secs(9)));
+        assert!(backoff.is_due(now + Duration::from_secs(10)));
+        backoff.fail(now);
+        assert!(!backoff.is_due(now + Duration::from_secs(19)));
+        assert!(backoff.is_due(now + Duration::from_secs(20)));
+        for _ in 0..100 {
+            backoff.fail(now);
+        }
+        assert_eq!(backoff.failed_attempts(), 102);
+        assert!(backoff.is_due(now + MAX_BACKOFF));
+    }
+
+    #[test]
+    fn tracks_health_of_peers() {
+        let session_id = SessionId(43);
+        let peer_ids = random_peer_ids(2);
+        let mut connections = Connections::new();
+        connections.add_peers(session_id, peer_ids.clone());
+        let now = Instant::now();
+        assert!(connections.to_reconnect(now).is_empty());
+        let peer = *peer_ids.iter().next().unwrap();
+        connections.on_connected(peer, now);
+        let health = connections.health(&peer).unwrap();
+        assert_eq!(health.connected_since, Some(now));
+        assert_eq!(health.last_message, None);
+        let later = now + Duration::from_secs(30);
+        connections.on_message(&peer, later);
+        connections.on_connected(peer, later);
+        let health = connections.health(&peer).unwrap();
+        assert_eq!(health.connected_since, Some(now));
+        assert_eq!(health.last_message, Some(later));
+        // The peer that never connected is the only one to reconnect to.
+        let disconnected: HashSet<_> = peer_ids
+            .difference(&HashSet::from([peer]))
+            .copied()
+            .collect();
+        assert_eq!(connections.to_reconnect(now + CONNECT_GRACE), disconnected);
+    }
+
+    #[test]
+    fn backs_off_reconnecting_to_disconnected_peers() {
+        let session_id = SessionId(43);
+        let peer_ids = random_peer_ids(1);
+        let peer = *peer_ids.iter().next().unwrap();
+        let mut connections = Connections::new();
+        connections.add_peers(session_id, peer_ids.clone());
+        let now = Instant::now();
+        connections.on_connected(peer, now);
+        // Messages do not make up for the lack of a connection and silence does not break it.
+        assert!(connections.to_reconnect(now + CONNECT_GRACE).is_empty());
+        connections.on_disconnected(&peer, now);
+        connections.on_message(&peer, now);
+        assert_eq!(connections.health(&peer).unwrap().connected_since, None);
+        assert!(connections
+            .to_reconnect(now + Duration::from_secs(5))
+            .is_empty());
+        let now = now + CONNECT_GRACE;
+        assert_eq!(connections.to_reconnect(now), peer_ids);
+        assert_eq!(connections.health(&peer).unwrap().failed_attempts(), 0);
+        assert!(connections
+            .to_reconnect(now + Duration::from_secs(5))
+            .is_empty());
+        let now = now + Duration::from_secs(10);
+        assert_eq!(connections.to_reconnect(now), peer_ids);
+        assert_eq!(connections.health(&peer).unwrap().failed_attempts(), 1);
+        let now = now + Duration::from_secs(10);
+        assert_eq!(connections.to_reconnect(now), peer_ids);
+        assert_eq!(connections.health(&peer).unwrap().failed_attempts(), 2);
+        assert!(connections
+            .to_reconnect(now + Duration::from_secs(19))
+            .is_empty());
+        // Connecting resets the backoff.
+        let now = now + Duration::from_secs(5);
+        connections.on_connected(peer, now);
+        let health = connections.health(&peer).unwrap();
+        assert_eq!(health.connected_since, Some(now));
+        assert_eq!(health.failed_attempts(), 0);
+        assert!(connections.to_reconnect(now).is_empty());
+        // After losing the connection the network gets some time to restore it again.
+        connections.on_disconnected(&peer, now);
+        assert!(connections.to_reconnect(now).is_empty());
+        assert_eq!(connections.to_reconnect(now + CONNECT_GRACE), peer_ids);
+    }
+
+    #[test]
+    fn tracks_connected_peers() {
+        let peer = MockPeerId::random();
+        let mut connections = Connections::new();
+        let now = Instant::now();
+        assert!(!connections.is_connected(&peer));
+        connections.on_connected(peer, now);
+        assert!(connections.is_connected(&peer));
+        // The connection does not depend on the sessions we need the peer in.
+        connections.add_peers(SessionId(43), [peer]);
+        assert!(connections.health(&peer).unwrap().connected_since.is_some());
+        connections.remove_session(SessionId(43));
+        assert!(connections.is_connected(&peer));
+        connections.on_disconnected(&peer, now);
+        assert!(!connections.is_connected(&peer));
+    }
+
+    #[test]
+    fn forgets_health_of_removed_peers() {
+        let session_id = SessionId(43);
+        let peer_ids = random_peer_ids(1);
+        let peer = *peer_ids.iter().next().unwrap();
+        let mut connections = Connections::new();
+        connections.add_peers(session_id, peer_ids);
+        connections.on_message(&peer, Instant::now());
+        connections.remove_session(session_id);
+        assert!(connections.health(&peer).is_none());
+        connections.on_message(&peer, Instant::now());
+        assert!(connections.health(&peer).is_none());
+    }
 }
@@ -31,6 +31,15 @@ impl<M: Multiaddress> DiscoveryMessage<M> {
             }
         }
     }
+
+    pub fn authentication(&self) -> &Authentication<M> {
+        use DiscoveryMessage::*;
+        match self {
+            AuthenticationBroadcast(authentication) | Authentication(authentication) => {
+                authentication
+            }
+        }
+    }
 }
 
 /// Handles creating and responding to discovery messages.
@@ -1,4 +1,4 @@
-use codec::{Decode, Encode};
+use codec::{Decode, Encode, Input};
 
 use crate::{
     crypto::Signature,
@@ -6,13 +6,18 @@ use crate::{
     NodeIndex, SessionId,
 };
 
+mod compression;
 mod connections;
 mod discovery;
+mod registry;
 mod service;
 mod session;
 
-use connections::Connections;
+pub use compression::DEFAULT_COMPRESSION_THRESHOLD;
+pub use connections::ValidatorConnections;
+use connections::{Backoff, Connections, PeerHealth};
 pub use discovery::{Discovery, DiscoveryMessage};
+pub use registry::{AddressRegistry, RegisteredAddresses};
 pub use service::{
     Config as ConnectionManagerConfig, Service as ConnectionManager, SessionCommand,
     IO as ConnectionIO,
@@ -45,9 +50,32 @@ impl<M: Multiaddress> AuthData<M> {
 /// A full authentication, consisting of a signed AuthData.
 pub type Authentication<M> = (AuthData<M>, Signature);
 
+/// Features supported by the sender of a discovery message. They are encoded at the very end of
+/// the message, where nodes not knowing about them stop decoding, so announcing them does not
+/// break communication with such nodes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Encode)]
+pub struct Capabilities {
+    /// Whether the sender accepts compressed data in the session of the message.
+    pub accepts_compression: bool,
+}
+
+impl Decode for Capabilities {
+    fn decode<I: Input>(input: &mut I) -> Result<Self, codec::Error> {
+        // Messages from nodes not announcing any capabilities end before them.
+        match input.remaining_len()? {
+            Some(0) => Ok(Capabilities::default()),
+            _ => Ok(Capabilities {
+                accepts_compression: bool::decode(input)?,
+            }),
+        }
+    }
+}
+
 /// The data that should be sent to the network service.
 #[derive(Clone, Debug, PartialEq, Encode, Decode)]
 pub enum NetworkData<D: Data, M: Multiaddress> {
-    Meta(DiscoveryMessage<M>),
+    Meta(DiscoveryMessage<M>, Capabilities),
     Data(D, SessionId),
+    /// Encoded data of a session, compressed, sent only to peers that accept compression.
+    CompressedData(Vec<u8>, SessionId),
 }
@@ -0,0 +1,152 @@
+//! Validator addresses registered on chain.
+//!
+//! Discovery through gossip can take a long time for a validator with few peers, so validators also
+//! register their addresses in `pallet_aleph`. Session handlers use the registered addresses of the
+//! nodes they have no authentication of yet, so we can connect to the committee right away.
+use std::collections::HashMap;
+
+use aleph_primitives::{
+    AuthorityId, AuthoritySignature, SignedValidatorAddresses, ValidatorAddresses,
+    MAX_VALIDATOR_ADDRESSES, MAX_VALIDATOR_ADDRESS_LEN,
+};
+use codec::Encode;
+
+use crate::{
+    crypto::{AuthorityPen, AuthorityVerifier, Signature},
+    network::Multiaddress,
+    NodeIndex, SessionId,
+};
+
+/// Addresses of a validator as registered on chain.
+pub type RegisteredAddresses = SignedValidatorAddresses<AuthoritySignature>;
+
+/// Access to the validator addresses registered on chain.
+pub trait AddressRegistry: Send + Sync {
+    /// The addresses registered by the authorities of the current and next session.
+    fn registered(&self) -> HashMap<AuthorityId, RegisteredAddresses>;
+
+    /// Submits the addresses of the authority for registration, they are registered once included
+    /// in a block.
+    fn register(&self, authority: AuthorityId, addresses: RegisteredAddresses);
+}
+
+fn encode_addresses<M: Multiaddress>(addresses: &[M]) -> Vec<Vec<u8>> {
+    addresses
+        .iter()
+        .map(Encode::encode)
+        .filter(|address| address.len() <= MAX_VALIDATOR_ADDRESS_LEN)
+        .take(MAX_VALIDATOR_ADDRESSES)
+        .collect()
+}
+
+/// Returns the addresses registered by the node, if they are signed by it and all of them decode.
+pub fn verified_addresses<M: Multiaddress>(
+    registered: &RegisteredAddresses,
+    node_id: NodeIndex,
+    verifier: &AuthorityVerifier,
+) -> Option<Vec<M>> {
+    let signature = Signature::from(registered.signature.clone());
+    if !verifier.verify(&registered.addresses.signing_payload(), &signature, node_id) {
+        return None;
+    }
+    registered
+        .addresses
+        .addresses
+        .iter()
+        .map(|address| M::decode(&mut &address[..]).ok())
+        .collect()
+}
+
+/// Whether the registered addresses differ from the given ones, so they should be registered anew.
+pub fn is_outdated<M: Multiaddress>(registered: &RegisteredAddresses, addresses: &[M]) -> bool {
+    registered.addresses.addresses != encode_addresses(addresses)
+}
+
+/// The session and nonce for a registration made in the session, so that it replaces the given
+/// registered addresses. Registrations for a later session are replaced by ones with a higher
+/// nonce in that session, as the registry does not accept going back.
+pub fn replacement_of(
+    registered: Option<&RegisteredAddresses>,
+    session_id: SessionId,
+) -> (SessionId, u32) {
+    match registered {
+        Some(registered) if registered.addresses.session >= session_id.0 => (
+            SessionId(registered.addresses.session),
+            registered.addresses.nonce.saturating_add(1),
+        ),
+        _ => (session_id, 0),
+    }
+}
+
+/// Signs the addresses for registration in the session, with the given nonce.
+pub async fn sign_addresses<M: Multiaddress>(
+    addresses: &[M],
+    session_id: SessionId,
+    nonce: u32,
+    pen: &AuthorityPen,
+) -> RegisteredAddresses {
+    let addresses = ValidatorAddresses {
+        addresses: encode_addresses(addresses),
+        session: session_id.0,
+        nonce,
+    };
+    let signature = pen.sign(&addresses.signing_payload()).await.into();
+    SignedValidatorAddresses {
+        addresses,
+        signature,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_outdated, replacement_of, sign_addresses, verified_addresses};
+    use crate::{
+        network::{
+            mock::{crypto_basics, MockMultiaddress, MockNetworkIdentity},
+            NetworkIdentity,
+        },
+        NodeIndex, SessionId,
+    };
+
+    #[tokio::test]
+    async fn verifies_signed_addresses() {
+        let (validator_data, verifier) = crypto_basics(3).await;
+        let (node_id, pen) = &validator_data[1];
+        let addresses = MockNetworkIdentity::new().identity().0;
+        let registered = sign_addresses(&addresses, SessionId(3), 0, pen).await;
+        assert_eq!(
+            verified_addresses(&registered, *node_id, &verifier),
+            Some(addresses.clone())
+        );
+        assert!(
+            verified_addresses::<MockMultiaddress>(&registered, NodeIndex(0), &verifier).is_none()
+        );
+        assert!(!is_outdated(&registered, &addresses));
+        assert!(is_outdated(
+            &registered,
+            &MockNetworkIdentity::new().identity().0
+        ));
+    }
+
+    #[tokio::test]
+    async fn replaces_registrations_of_the_same_or_later_session() {
+        let (validator_data, _) = crypto_basics(3).await;
+        let (_, pen) = &validator_data[1];
+        let addresses = MockNetworkIdentity::new().identity().0;
+        assert_eq!(replacement_of(None, SessionId(3)), (SessionId(3), 0));
+
+        let registered = sign_addresses(&addresses, SessionId(3), 0, pen).await;
+        assert_eq!(
+            replacement_of(Some(&registered), SessionId(4)),
+            (SessionId(4), 0)
+        );
+        assert_eq!(
+            replacement_of(Some(&registered), SessionId(3)),
+            (SessionId(3), 1)
+        );
+        assert_eq!(
+            replacement_of(Some(&registered), SessionId(2)),
+            (SessionId(3), 1)
+        );
+    }
+}
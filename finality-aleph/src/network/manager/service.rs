@@ -1,25 +1,33 @@
 use std::{
     cmp,
-    collections::{HashMap, HashSet},
+    collections::{hash_map::Entry, HashMap, HashSet},
+    sync::Arc,
     time::Duration,
 };
 
 use aleph_bft::Recipient;
+use aleph_primitives::AuthorityId;
+use codec::Encode;
 use futures::{
     channel::{mpsc, oneshot},
     StreamExt,
 };
 use log::{debug, trace, warn};
+use lru::LruCache;
+use sp_core::hashing::blake2_256;
 use tokio::time::{interval_at, Instant};
 
 use crate::{
     crypto::{AuthorityPen, AuthorityVerifier},
+    metrics::EventMetrics,
     network::{
         manager::{
-            Connections, Discovery, DiscoveryMessage, NetworkData, SessionHandler,
-            SessionHandlerError,
+            compression, registry, AddressRegistry, Backoff, Capabilities, Connections, Discovery,
+            DiscoveryMessage, NetworkData, PeerHealth, RegisteredAddresses, SessionHandler,
+            SessionHandlerError, ValidatorConnections, DEFAULT_COMPRESSION_THRESHOLD,
         },
-        ConnectionCommand, Data, DataCommand, Multiaddress, NetworkIdentity, Protocol,
+        ConnectionCommand, Data, DataCommand, Misbehavior, Multiaddress, NetworkIdentity,
+        PeerEvent, Protocol,
     },
     MillisecsPerBlock, NodeIndex, SessionId, SessionPeriod,
 };
@@ -42,8 +50,16 @@ struct Session<D: Data, M: Multiaddress> {
     handler: SessionHandler<M>,
     discovery: Discovery<M>,
     data_for_user: Option<mpsc::UnboundedSender<D>>,
+    /// Peers that announced they accept compressed data in this session.
+    compression_peers: HashSet<M::PeerId>,
+    /// Senders of the data recently passed to the user, by the hash of the data, so that they can
+    /// be reported if the user rejects it.
+    recent_senders: LruCache<[u8; 32], M::PeerId>,
 }
 
+/// How many senders of recently received data we remember per session.
+const RECENT_SENDERS_CAPACITY: usize = 2000;
+
 #[derive(Clone)]
 struct PreValidatorSession {
     session_id: SessionId,
@@ -74,11 +90,14 @@ impl PreSession {
 }
 
 /// Configuration for the session manager service. Controls how often the maintenance and
-/// rebroadcasts are triggerred. Also controls when maintenance starts.
+/// rebroadcasts are triggerred. Also controls when maintenance starts, which session data gets
+/// compressed and whether validator addresses registered on chain are used.
 pub struct Config {
     discovery_cooldown: Duration,
     maintenance_period: Duration,
     initial_delay: Duration,
+    compression_threshold: Option<usize>,
+    address_registry: Option<Arc<dyn AddressRegistry>>,
 }
 
 impl Config {
@@ -91,6 +110,26 @@ impl Config {
             discovery_cooldown,
             maintenance_period,
             initial_delay,
+            compression_threshold: Some(DEFAULT_COMPRESSION_THRESHOLD),
+            address_registry: None,
+        }
+    }
+
+    /// Connect to validators using the addresses they registered in the registry, before their
+    /// authentications arrive, and register our own addresses there.
+    pub fn with_address_registry(self, address_registry: Arc<dyn AddressRegistry>) -> Self {
+        Config {
+            address_registry: Some(address_registry),
+            ..self
+        }
+    }
+
+    /// Compress session data encoded to at least this many bytes, or never compress it when
+    /// `None`. Compressed data from other nodes is accepted regardless.
+    pub fn with_compression_threshold(self, compression_threshold: Option<usize>) -> Self {
+        Config {
+            compression_threshold,
+            ..self
         }
     }
 
@@ -134,7 +173,8 @@ impl<D: Data, M: Multiaddress> ServiceActions<D, M> {
 /// 3. Handling network messages:
 ///    1. In-session messages are forwarded to the user.
 ///    2. Authentication messages forwarded to session handlers.
-/// 4. Running periodic maintenance, mostly related to node discovery.
+/// 4. Running periodic maintenance, mostly related to node discovery and reconnecting to peers the
+///    network has not been connected to for a while.
 pub struct Service<NI: NetworkIdentity, D: Data> {
     network_identity: NI,
     connections: Connections<<NI::Multiaddress as Multiaddress>::PeerId>,
@@ -143,27 +183,61 @@ pub struct Service<NI: NetworkIdentity, D: Data> {
         PreSession,
         Option<oneshot::Sender<mpsc::UnboundedReceiver<D>>>,
     )>,
+    retry_backoff: HashMap<SessionId, Backoff>,
     discovery_cooldown: Duration,
     maintenance_period: Duration,
     initial_delay: Duration,
+    compression_threshold: Option<usize>,
+    address_registry: Option<Arc<dyn AddressRegistry>>,
+    validator_connections: ValidatorConnections,
+    metrics: Option<EventMetrics>,
 }
 
 impl<NI: NetworkIdentity, D: Data> Service<NI, D> {
     /// Create a new connection manager service.
-    pub fn new(network_identity: NI, config: Config) -> Self {
+    pub fn new(
+        network_identity: NI,
+        config: Config,
+        validator_connections: ValidatorConnections,
+        metrics: Option<EventMetrics>,
+    ) -> Self {
         let Config {
             discovery_cooldown,
             maintenance_period,
             initial_delay,
+            compression_threshold,
+            address_registry,
         } = config;
         Service {
             network_identity,
             connections: Connections::new(),
             sessions: HashMap::new(),
             to_retry: Vec::new(),
+            retry_backoff: HashMap::new(),
             discovery_cooldown,
             maintenance_period,
             initial_delay,
+            compression_threshold,
+            address_registry,
+            validator_connections,
+            metrics,
+        }
+    }
+
+    fn report_connected_validators(&self, session_id: &SessionId) {
+        let handler = match self.sessions.get(session_id) {
+            Some(Session { handler, .. }) if handler.is_validator() => handler,
+            _ => return,
+        };
+        let count = (0..handler.node_count().0)
+            .map(NodeIndex)
+            .filter(|node_id| Some(*node_id) != handler.index())
+            .filter_map(|node_id| handler.peer_id(&node_id))
+            .filter(|peer_id| self.connections.is_connected(peer_id))
+            .count();
+        self.validator_connections.set(*session_id, count);
+        if let Some(metrics) = &self.metrics {
+            metrics.report_connected_validators(session_id.0, count);
         }
     }
 
@@ -176,20 +250,116 @@ impl<NI: NetworkIdentity, D: Data> Service<NI, D> {
         }
     }
 
+    fn add_reserved(
+        to_add: HashSet<NI::Multiaddress>,
+    ) -> Option<ConnectionCommand<NI::Multiaddress>> {
+        match to_add.is_empty() {
+            true => None,
+            false => Some(ConnectionCommand::AddReserved(to_add)),
+        }
+    }
+
+    fn registered_addresses(&self) -> HashMap<AuthorityId, RegisteredAddresses> {
+        match &self.address_registry {
+            Some(address_registry) => address_registry.registered(),
+            None => HashMap::new(),
+        }
+    }
+
+    /// Passes the addresses registered on chain to the handlers of validator sessions and returns
+    /// the addresses we should connect to because of them.
+    fn use_registered_addresses(
+        &mut self,
+        registered: &HashMap<AuthorityId, RegisteredAddresses>,
+    ) -> HashSet<NI::Multiaddress> {
+        let mut to_add = HashSet::new();
+        if self.address_registry.is_none() {
+            return to_add;
+        }
+        for (session_id, Session { handler, .. }) in self.sessions.iter_mut() {
+            if !handler.is_validator() {
+                continue;
+            }
+            let addresses = handler.handle_registered_addresses(registered.clone());
+            if !addresses.is_empty() {
+                debug!(target: "aleph-network", "Adding registered addresses for session {:?} to reserved: {:?}", session_id, addresses);
+            }
+            self.connections.add_peers(
+                *session_id,
+                addresses.iter().flat_map(|address| address.get_peer_id()),
+            );
+            to_add.extend(addresses);
+        }
+        to_add
+    }
+
+    /// Connects to the validators of all sessions using the addresses they registered on chain,
+    /// unless we already know their addresses from authentications. Does not read the registry
+    /// at all if we have authentications of all the validators.
+    pub fn refresh_registered_addresses(&mut self) -> Option<ConnectionCommand<NI::Multiaddress>> {
+        if !self.sessions.values().any(|Session { handler, .. }| {
+            handler.is_validator() && handler.uses_registered_addresses()
+        }) {
+            return None;
+        }
+        let registered = self.registered_addresses();
+        Self::add_reserved(self.use_registered_addresses(&registered))
+    }
+
+    /// Registers our addresses on chain, replacing the registered ones unless they are the same.
+    async fn register_addresses(
+        &self,
+        registered: &HashMap<AuthorityId, RegisteredAddresses>,
+        session_id: SessionId,
+        pen: &AuthorityPen,
+        addresses: &[NI::Multiaddress],
+    ) {
+        let address_registry = match &self.address_registry {
+            Some(address_registry) => address_registry,
+            None => return,
+        };
+        let authority = pen.authority_id();
+        let registered = registered.get(&authority);
+        if let Some(registered) = registered {
+            if !registry::is_outdated(registered, addresses) {
+                return;
+            }
+        }
+        let (session_id, nonce) = registry::replacement_of(registered, session_id);
+        debug!(target: "aleph-network", "Registering our addresses in session {:?} with nonce {}: {:?}", session_id, nonce, addresses);
+        address_registry.register(
+            authority,
+            registry::sign_addresses(addresses, session_id, nonce, pen).await,
+        );
+    }
+
     fn finish_session(
         &mut self,
         session_id: SessionId,
     ) -> Option<ConnectionCommand<NI::Multiaddress>> {
+        self.network_identity.session_ended(session_id);
         self.sessions.remove(&session_id);
+        self.validator_connections.remove(session_id);
+        if let Some(metrics) = &self.metrics {
+            metrics.remove_session(session_id.0);
+        }
         self.to_retry
             .retain(|(pre_session, _)| pre_session.session_id() != session_id);
+        self.retry_backoff.remove(&session_id);
         Self::delete_reserved(self.connections.remove_session(session_id))
     }
 
+    /// Wraps discovery messages, announcing whether we accept compressed data in their session,
+    /// which we do only in sessions we are a validator in.
     fn network_message(
-        (message, command): (DiscoveryMessage<NI::Multiaddress>, DataCommand<NI::PeerId>),
+        accepts_compression: bool,
+    ) -> impl Fn(
+        (DiscoveryMessage<NI::Multiaddress>, DataCommand<NI::PeerId>),
     ) -> MessageForNetwork<D, NI::Multiaddress> {
-        (NetworkData::Meta(message), command)
+        let capabilities = Capabilities {
+            accepts_compression,
+        };
+        move |(message, command)| (NetworkData::Meta(message, capabilities), command)
     }
 
     fn discover_authorities(
@@ -197,13 +367,16 @@ impl<NI: NetworkIdentity, D: Data> Service<NI, D> {
         session_id: &SessionId,
     ) -> Vec<MessageForNetwork<D, NI::Multiaddress>> {
         if let Some(Session {
-            handler, discovery, ..
+            handler,
+            discovery,
+            data_for_user,
+            ..
         }) = self.sessions.get_mut(session_id)
         {
             discovery
                 .discover_authorities(handler)
                 .into_iter()
-                .map(Self::network_message)
+                .map(Self::network_message(data_for_user.is_some()))
                 .collect()
         } else {
             Vec::new()
@@ -215,6 +388,7 @@ impl<NI: NetworkIdentity, D: Data> Service<NI, D> {
         let mut result = Vec::new();
         let sessions: Vec<_> = self.sessions.keys().cloned().collect();
         for session_id in sessions {
+            self.report_connected_validators(&session_id);
             result.append(&mut self.discover_authorities(&session_id));
         }
         result
@@ -235,7 +409,7 @@ impl<NI: NetworkIdentity, D: Data> Service<NI, D> {
         addresses: Vec<NI::Multiaddress>,
     ) -> Result<
         (
-            Vec<MessageForNetwork<D, NI::Multiaddress>>,
+            ServiceActions<D, NI::Multiaddress>,
             mpsc::UnboundedReceiver<D>,
         ),
         SessionHandlerError,
@@ -246,8 +420,18 @@ impl<NI: NetworkIdentity, D: Data> Service<NI, D> {
             node_id,
             pen,
         } = pre_session;
-        let handler =
-            SessionHandler::new(Some((node_id, pen)), verifier, session_id, addresses).await?;
+        let handler = SessionHandler::new(
+            Some((node_id, pen.clone())),
+            verifier,
+            session_id,
+            addresses,
+        )
+        .await?;
+        let registered = self.registered_addresses();
+        if let Some((auth_data, _)) = handler.authentication() {
+            self.register_addresses(&registered, session_id, &pen, &auth_data.addresses())
+                .await;
+        }
         let discovery = Discovery::new(self.discovery_cooldown);
         let (data_for_user, data_from_network) = mpsc::unbounded();
         let data_for_user = Some(data_for_user);
@@ -257,9 +441,18 @@ impl<NI: NetworkIdentity, D: Data> Service<NI, D> {
                 handler,
                 discovery,
                 data_for_user,
+                compression_peers: HashSet::new(),
+                recent_senders: LruCache::new(RECENT_SENDERS_CAPACITY),
             },
         );
-        Ok((self.discover_authorities(&session_id), data_from_network))
+        let maybe_command = Self::add_reserved(self.use_registered_addresses(&registered));
+        Ok((
+            ServiceActions {
+                maybe_command,
+                data: self.discover_authorities(&session_id),
+            },
+            data_from_network,
+        ))
     }
 
     async fn update_validator_session(
@@ -275,17 +468,7 @@ impl<NI: NetworkIdentity, D: Data> Service<NI, D> {
         let addresses = self.addresses();
         let session = match self.sessions.get_mut(&pre_session.session_id) {
             Some(session) => session,
-            None => {
-                let (data, data_from_network) =
-                    self.start_validator_session(pre_session, addresses).await?;
-                return Ok((
-                    ServiceActions {
-                        maybe_command: None,
-                        data,
-                    },
-                    data_from_network,
-                ));
-            }
+            None => return self.start_validator_session(pre_session, addresses).await,
         };
         let PreValidatorSession {
             session_id,
@@ -295,11 +478,15 @@ impl<NI: NetworkIdentity, D: Data> Service<NI, D> {
         } = pre_session;
         let peers_to_stay = session
             .handler
-            .update(Some((node_id, pen)), verifier, addresses)
+            .update(Some((node_id, pen.clone())), verifier, addresses)
             .await?
             .iter()
             .flat_map(|address| address.get_peer_id())
             .collect();
+        let own_addresses = session
+            .handler
+            .authentication()
+            .map(|(auth_data, _)| auth_data.addresses());
         let maybe_command = Self::delete_reserved(
             self.connections
                 .remove_session(session_id)
@@ -310,6 +497,12 @@ impl<NI: NetworkIdentity, D: Data> Service<NI, D> {
         let (data_for_user, data_from_network) = mpsc::unbounded();
         session.data_for_user = Some(data_for_user);
         self.connections.add_peers(session_id, peers_to_stay);
+        // Our addresses might have changed since we registered them.
+        if let Some(own_addresses) = own_addresses {
+            let registered = self.registered_addresses();
+            self.register_addresses(&registered, session_id, &pen, &own_addresses)
+                .await;
+        }
         Ok((
             ServiceActions {
                 maybe_command,
@@ -324,8 +517,11 @@ impl<NI: NetworkIdentity, D: Data> Service<NI, D> {
         pre_session: PreValidatorSession,
         result_for_user: Option<oneshot::Sender<mpsc::UnboundedReceiver<D>>>,
     ) -> Result<ServiceActions<D, NI::Multiaddress>, SessionHandlerError> {
+        let session_id = pre_session.session_id;
         match self.update_validator_session(pre_session.clone()).await {
             Ok((actions, data_from_network)) => {
+                self.retry_backoff.remove(&session_id);
+                self.report_connected_validators(&session_id);
                 if let Some(result_for_user) = result_for_user {
                     if result_for_user.send(data_from_network).is_err() {
                         warn!(target: "aleph-network", "Failed to send started session.")
@@ -334,6 +530,7 @@ impl<NI: NetworkIdentity, D: Data> Service<NI, D> {
                 Ok(actions)
             }
             Err(e) => {
+                self.retry_later(session_id);
                 self.to_retry
                     .push((PreSession::Validator(pre_session), result_for_user));
                 Err(e)
@@ -358,6 +555,8 @@ impl<NI: NetworkIdentity, D: Data> Service<NI, D> {
                 handler,
                 discovery,
                 data_for_user: None,
+                compression_peers: HashSet::new(),
+                recent_senders: LruCache::new(RECENT_SENDERS_CAPACITY),
             },
         );
         Ok(())
@@ -387,13 +586,31 @@ impl<NI: NetworkIdentity, D: Data> Service<NI, D> {
         &mut self,
         pre_session: PreNonvalidatorSession,
     ) -> Result<(), SessionHandlerError> {
-        self.update_nonvalidator_session(pre_session.clone())
-            .await
-            .map_err(|e| {
+        let session_id = pre_session.session_id;
+        match self.update_nonvalidator_session(pre_session.clone()).await {
+            Ok(()) => {
+                self.retry_backoff.remove(&session_id);
+                Ok(())
+            }
+            Err(e) => {
+                self.retry_later(session_id);
                 self.to_retry
                     .push((PreSession::Nonvalidator(pre_session), None));
-                e
-            })
+                Err(e)
+            }
+        }
+    }
+
+    /// Records a failed attempt at starting the session. The first retry happens right away,
+    /// the following ones are backed off.
+    fn retry_later(&mut self, session_id: SessionId) {
+        let now = Instant::now();
+        match self.retry_backoff.entry(session_id) {
+            Entry::Occupied(mut backoff) => backoff.get_mut().fail(now),
+            Entry::Vacant(entry) => {
+                entry.insert(Backoff::new(now));
+            }
+        }
     }
 
     /// Handle a session command.
@@ -406,6 +623,8 @@ impl<NI: NetworkIdentity, D: Data> Service<NI, D> {
         use SessionCommand::*;
         match command {
             StartValidator(session_id, verifier, node_id, pen, result_for_user) => {
+                self.network_identity
+                    .session_started(session_id, &verifier, Some(&pen));
                 let pre_session = PreValidatorSession {
                     session_id,
                     verifier,
@@ -416,6 +635,8 @@ impl<NI: NetworkIdentity, D: Data> Service<NI, D> {
                     .await
             }
             StartNonvalidator(session_id, verifier) => {
+                self.network_identity
+                    .session_started(session_id, &verifier, None);
                 let pre_session = PreNonvalidatorSession {
                     session_id,
                     verifier,
@@ -430,59 +651,93 @@ impl<NI: NetworkIdentity, D: Data> Service<NI, D> {
         }
     }
 
+    fn compress(
+        &self,
+        message: &D,
+        session_id: SessionId,
+    ) -> Option<NetworkData<D, NI::Multiaddress>> {
+        let (compressed, original_len) =
+            compression::compress(message, self.compression_threshold?)?;
+        if let Some(metrics) = &self.metrics {
+            metrics.report_compression(original_len, compressed.len());
+        }
+        Some(NetworkData::CompressedData(compressed, session_id))
+    }
+
     /// Handle a user request for sending data.
-    /// Returns a list of data to be sent over the network.
+    /// Returns a list of data to be sent over the network, compressed for peers accepting it.
     pub fn on_user_message(
         &self,
         message: D,
         session_id: SessionId,
         recipient: Recipient,
     ) -> Vec<MessageForNetwork<D, NI::Multiaddress>> {
-        if let Some(handler) = self
-            .sessions
-            .get(&session_id)
-            .map(|session| &session.handler)
+        if let Some(Session {
+            handler,
+            compression_peers,
+            ..
+        }) = self.sessions.get(&session_id)
         {
-            let to_send = NetworkData::Data(message, session_id);
-            match recipient {
+            let peers: Vec<_> = match recipient {
                 Recipient::Everyone => (0..handler.node_count().0)
                     .map(NodeIndex)
                     .flat_map(|node_id| handler.peer_id(&node_id))
-                    .map(|peer_id| {
-                        (
-                            to_send.clone(),
-                            DataCommand::SendTo(peer_id, Protocol::Validator),
-                        )
-                    })
-                    .collect(),
-                Recipient::Node(node_id) => handler
-                    .peer_id(&node_id)
-                    .into_iter()
-                    .map(|peer_id| {
-                        (
-                            to_send.clone(),
-                            DataCommand::SendTo(peer_id, Protocol::Validator),
-                        )
-                    })
                     .collect(),
-            }
+                Recipient::Node(node_id) => handler.peer_id(&node_id).into_iter().collect(),
+            };
+            let compressed = match peers.iter().any(|peer| compression_peers.contains(peer)) {
+                true => self.compress(&message, session_id),
+                false => None,
+            };
+            let to_send = NetworkData::Data(message, session_id);
+            peers
+                .into_iter()
+                .map(|peer_id| {
+                    let data = match (&compressed, compression_peers.contains(&peer_id)) {
+                        (Some(compressed), true) => compressed.clone(),
+                        _ => to_send.clone(),
+                    };
+                    (data, DataCommand::SendTo(peer_id, Protocol::Validator))
+                })
+                .collect()
         } else {
             Vec::new()
         }
     }
 
-    /// Handle a discovery message.
-    /// Returns a command possibly changing what we should stay connected to and a list of data to
-    /// be sent over the network.
+    /// Handle a discovery message received from the given peer, together with the capabilities
+    /// the peer announced with it.
+    /// Returns a command possibly changing what we should stay connected to, or reporting the
+    /// sender if the message was invalid, and a list of data to be sent over the network.
     pub fn on_discovery_message(
         &mut self,
         message: DiscoveryMessage<NI::Multiaddress>,
+        capabilities: Capabilities,
+        sender: NI::PeerId,
     ) -> ServiceActions<D, NI::Multiaddress> {
         let session_id = message.session_id();
         match self.sessions.get_mut(&session_id) {
             Some(Session {
-                handler, discovery, ..
+                handler,
+                discovery,
+                data_for_user,
+                compression_peers,
+                ..
             }) => {
+                if handler.is_invalid(message.authentication()) {
+                    debug!(target: "aleph-network", "Received invalid authentication from peer {:?}: {:?}", sender, message);
+                    return ServiceActions {
+                        maybe_command: Some(ConnectionCommand::Report(
+                            sender,
+                            Misbehavior::InvalidAuthentication,
+                        )),
+                        data: Vec::new(),
+                    };
+                }
+                let accepts_compression = data_for_user.is_some();
+                if capabilities.accepts_compression && accepts_compression {
+                    compression_peers.insert(sender);
+                }
                 let (addresses, responses) = discovery.handle_message(message, handler);
                 let maybe_command = match !addresses.is_empty() && handler.is_validator() {
                     true => {
@@ -497,40 +752,131 @@ impl<NI: NetworkIdentity, D: Data> Service<NI, D> {
                     }
                     false => None,
                 };
+                self.report_connected_validators(&session_id);
                 ServiceActions {
                     maybe_command,
-                    data: responses.into_iter().map(Self::network_message).collect(),
+                    data: responses
+                        .into_iter()
+                        .map(Self::network_message(accepts_compression))
+                        .collect(),
                 }
             }
             None => {
                 debug!(target: "aleph-network", "Received message from unknown session: {:?}", message);
-                ServiceActions::noop()
+                let maybe_command = match self.is_unexpected_session(&session_id) {
+                    true => Some(ConnectionCommand::Report(
+                        sender,
+                        Misbehavior::UnknownSession,
+                    )),
+                    false => None,
+                };
+                ServiceActions {
+                    maybe_command,
+                    data: Vec::new(),
+                }
+            }
+        }
+    }
+
+    /// Decompresses the data and sends it to the identified session. The sender evidently accepts
+    /// compressed data itself.
+    pub fn send_compressed_session_data(
+        &mut self,
+        session_id: &SessionId,
+        compressed: Vec<u8>,
+        sender: NI::PeerId,
+    ) -> Result<(), Error> {
+        let data = compression::decompress(&compressed).map_err(|e| {
+            debug!(target: "aleph-network", "Failed to decompress data from peer {:?} in session {:?}: {:?}", sender, session_id, e);
+            Error::Undecodable
+        })?;
+        self.accept_compression(session_id, sender)?;
+        self.send_session_data(session_id, data, sender)
+    }
+
+    /// Notes that the peer accepts compressed data in the identified session.
+    fn accept_compression(
+        &mut self,
+        session_id: &SessionId,
+        peer: NI::PeerId,
+    ) -> Result<(), Error> {
+        match self.sessions.get_mut(session_id) {
+            Some(Session {
+                data_for_user: Some(_),
+                compression_peers,
+                ..
+            }) => {
+                compression_peers.insert(peer);
+                Ok(())
             }
+            _ => Err(Error::NoSession),
         }
     }
 
     /// Sends the data to the identified session.
-    pub fn send_session_data(&self, session_id: &SessionId, data: D) -> Result<(), Error> {
-        match self
-            .sessions
-            .get(session_id)
-            .and_then(|session| session.data_for_user.as_ref())
-        {
-            Some(data_for_user) => data_for_user
-                .unbounded_send(data)
-                .map_err(|_| Error::UserSend),
-            None => Err(Error::NoSession),
+    pub fn send_session_data(
+        &mut self,
+        session_id: &SessionId,
+        data: D,
+        sender: NI::PeerId,
+    ) -> Result<(), Error> {
+        match self.sessions.get_mut(session_id) {
+            Some(Session {
+                data_for_user: Some(data_for_user),
+                recent_senders,
+                ..
+            }) => {
+                recent_senders.put(blake2_256(&data.encode()), sender);
+                data_for_user
+                    .unbounded_send(data)
+                    .map_err(|_| Error::UserSend)
+            }
+            _ => Err(Error::NoSession),
         }
     }
 
+    /// Returns a command reporting the peer that sent us the data the user rejected as invalid,
+    /// if we still remember who it was.
+    pub fn on_rejected_data(
+        &mut self,
+        session_id: &SessionId,
+        data: D,
+    ) -> Option<ConnectionCommand<NI::Multiaddress>> {
+        let sender = self
+            .sessions
+            .get_mut(session_id)?
+            .recent_senders
+            .pop(&blake2_256(&data.encode()))?;
+        debug!(target: "aleph-network", "Reporting peer {:?} for sending data rejected in session {:?}.", sender, session_id);
+        Some(ConnectionCommand::Report(sender, Misbehavior::InvalidData))
+    }
+
+    /// Whether a message for the session means the sender misbehaves: we neither run the session
+    /// nor one adjacent to it. Messages for adjacent sessions are expected around session
+    /// boundaries, as nodes do not switch sessions at exactly the same time.
+    fn is_unexpected_session(&self, session_id: &SessionId) -> bool {
+        !self.sessions.is_empty()
+            && self
+                .sessions
+                .keys()
+                .all(|id| id.0.abs_diff(session_id.0) > 1)
+    }
+
     /// Retries starting a validator session the user requested, but which failed to start
     /// initially. Mostly useful when the network was not yet aware of its own address at time of
-    /// the request.
+    /// the request. Sessions that keep failing to start are retried less and less often.
     pub async fn retry_session_start(
         &mut self,
     ) -> Result<ServiceActions<D, NI::Multiaddress>, SessionHandlerError> {
-        let (pre_session, result_for_user) = match self.to_retry.pop() {
-            Some(to_retry) => to_retry,
+        let now = Instant::now();
+        let retry_backoff = &self.retry_backoff;
+        let position = self.to_retry.iter().rposition(|(pre_session, _)| {
+            retry_backoff
+                .get(&pre_session.session_id())
+                .map_or(true, |backoff| backoff.is_due(now))
+        });
+        let (pre_session, result_for_user) = match position {
+            Some(position) => self.to_retry.remove(position),
             None => return Ok(ServiceActions::noop()),
         };
         match pre_session {
@@ -544,6 +890,112 @@ impl<NI: NetworkIdentity, D: Data> Service<NI, D> {
             }
         }
     }
+
+    /// Updates the connection state of the peer and the counts of connected validators.
+    pub fn on_peer_event(&mut self, event: PeerEvent<NI::PeerId>) {
+        let now = Instant::now();
+        match event {
+            PeerEvent::Connected(peer) => self.connections.on_connected(peer, now),
+            PeerEvent::Disconnected(peer) => self.connections.on_disconnected(&peer, now),
+        }
+        let sessions: Vec<_> = self.sessions.keys().cloned().collect();
+        for session_id in sessions {
+            self.report_connected_validators(&session_id);
+        }
+    }
+
+    /// Records when we last received a message from the peer.
+    pub fn on_peer_message(&mut self, peer: &NI::PeerId, now: Instant) {
+        self.connections.on_message(peer, now);
+    }
+
+    /// Tries reconnecting to the committee members the network has not been connected to for a
+    /// while, backing off the attempts for members that do not connect. The members are removed
+    /// from the reserved peers and added back, so that the network dials them again.
+    pub fn reconnect(&mut self, now: Instant) -> Vec<ConnectionCommand<NI::Multiaddress>> {
+        let to_reconnect = self.connections.to_reconnect(now);
+        if to_reconnect.is_empty() {
+            return Vec::new();
+        }
+        let mut to_add = HashSet::new();
+        for Session { handler, .. } in self.sessions.values() {
+            to_add.extend(
+                (0..handler.node_count().0)
+                    .map(NodeIndex)
+                    .filter(|node_id| {
+                        handler
+                            .peer_id(node_id)
+                            .map_or(false, |peer_id| to_reconnect.contains(&peer_id))
+                    })
+                    .flat_map(|node_id| handler.peer_addresses(&node_id)),
+            );
+        }
+        if !to_add.is_empty() {
+            debug!(target: "aleph-network", "Reconnecting to disconnected peers: {:?}", to_add);
+        }
+        let to_remove = to_add
+            .iter()
+            .filter_map(|address| address.get_peer_id())
+            .collect();
+        Self::delete_reserved(to_remove)
+            .into_iter()
+            .chain(Self::add_reserved(to_add))
+            .collect()
+    }
+
+    /// Returns the other committee members of the validator session the network is not connected
+    /// to, together with what we know about the connection with them. Members we do not
+    /// know the address of have no connection to speak of.
+    pub fn unreachable_nodes(
+        &self,
+        session_id: &SessionId,
+    ) -> Vec<(NodeIndex, Option<PeerHealth>)> {
+        let handler = match self.sessions.get(session_id) {
+            Some(Session { handler, .. }) if handler.is_validator() => handler,
+            _ => return Vec::new(),
+        };
+        (0..handler.node_count().0)
+            .map(NodeIndex)
+            .filter(|node_id| Some(*node_id) != handler.index())
+            .filter_map(|node_id| match handler.peer_id(&node_id) {
+                Some(peer_id) if self.connections.is_connected(&peer_id) => None,
+                Some(peer_id) => Some((node_id, self.connections.health(&peer_id))),
+                None => Some((node_id, None)),
+            })
+            .collect()
+    }
+
+    /// Logs the committee members we cannot reach in every validator session.
+    pub fn report_unreachable(&self, now: Instant) {
+        for session_id in self.sessions.keys() {
+            let unreachable = self.unreachable_nodes(session_id);
+            if unreachable.is_empty() {
+                continue;
+            }
+            let description: Vec<_> = unreachable
+                .into_iter()
+                .map(|(node_id, health)| match health {
+                    None => format!("{}: address unknown", node_id.0),
+                    Some(health) => {
+                        let silence = match health.last_message {
+                            Some(last_message) => format!(
+                                "silent for {}s",
+                                now.saturating_duration_since(last_message).as_secs()
+                            ),
+                            None => "never heard from".to_string(),
+                        };
+                        format!(
+                            "{}: {}, {} failed reconnection attempts",
+                            node_id.0,
+                            silence,
+                            health.failed_attempts()
+                        )
+                    }
+                })
+                .collect();
+            debug!(target: "aleph-network", "Unreachable committee members in session {:?}: {}", session_id, description.join("; "));
+        }
+    }
 }
 
 /// Input/output interface for the connectiona manager service.
@@ -552,7 +1004,9 @@ pub struct IO<D: Data, M: Multiaddress> {
     messages_for_network: mpsc::UnboundedSender<MessageForNetwork<D, M>>,
     commands_from_user: mpsc::UnboundedReceiver<SessionCommand<D>>,
     messages_from_user: mpsc::UnboundedReceiver<(D, SessionId, Recipient)>,
-    messages_from_network: mpsc::UnboundedReceiver<NetworkData<D, M>>,
+    messages_from_network: mpsc::UnboundedReceiver<(NetworkData<D, M>, M::PeerId)>,
+    peer_events_from_network: mpsc::UnboundedReceiver<PeerEvent<M::PeerId>>,
+    rejected_from_user: mpsc::UnboundedReceiver<(D, SessionId)>,
 }
 
 /// Errors that can happen during the network service operations.
@@ -564,9 +1018,13 @@ pub enum Error {
     UserSend,
     /// Should never be fatal.
     NoSession,
+    /// Should never be fatal.
+    Undecodable,
     CommandsChannel,
     MessageChannel,
     NetworkChannel,
+    PeerEventChannel,
+    RejectedChannel,
 }
 
 impl<D: Data, M: Multiaddress> IO<D, M> {
@@ -575,7 +1033,9 @@ impl<D: Data, M: Multiaddress> IO<D, M> {
         messages_for_network: mpsc::UnboundedSender<MessageForNetwork<D, M>>,
         commands_from_user: mpsc::UnboundedReceiver<SessionCommand<D>>,
         messages_from_user: mpsc::UnboundedReceiver<(D, SessionId, Recipient)>,
-        messages_from_network: mpsc::UnboundedReceiver<NetworkData<D, M>>,
+        messages_from_network: mpsc::UnboundedReceiver<(NetworkData<D, M>, M::PeerId)>,
+        peer_events_from_network: mpsc::UnboundedReceiver<PeerEvent<M::PeerId>>,
+        rejected_from_user: mpsc::UnboundedReceiver<(D, SessionId)>,
     ) -> IO<D, M> {
         IO {
             commands_for_network,
@@ -583,6 +1043,8 @@ impl<D: Data, M: Multiaddress> IO<D, M> {
             commands_from_user,
             messages_from_user,
             messages_from_network,
+            peer_events_from_network,
+            rejected_from_user,
         }
     }
 
@@ -618,12 +1080,33 @@ impl<D: Data, M: Multiaddress> IO<D, M> {
         &self,
         service: &mut Service<NI, D>,
         message: NetworkData<D, M>,
+        sender: M::PeerId,
     ) -> Result<(), Error> {
         use NetworkData::*;
-        match message {
-            Meta(message) => self.send(service.on_discovery_message(message)),
-            Data(data, session_id) => service.send_session_data(&session_id, data),
-        }
+        service.on_peer_message(&sender, Instant::now());
+        let (result, session_id) = match message {
+            Meta(message, capabilities) => {
+                return self.send(service.on_discovery_message(message, capabilities, sender))
+            }
+            Data(data, session_id) => (
+                service.send_session_data(&session_id, data, sender),
+                session_id,
+            ),
+            CompressedData(compressed, session_id) => (
+                service.send_compressed_session_data(&session_id, compressed, sender),
+                session_id,
+            ),
+        };
+        // Report the sender if handling the message failed because of the message itself.
+        let misbehavior = match result {
+            Err(Error::NoSession) if service.is_unexpected_session(&session_id) => {
+                Misbehavior::UnknownSession
+            }
+            Err(Error::Undecodable) => Misbehavior::Undecodable,
+            _ => return result,
+        };
+        self.send_command(ConnectionCommand::Report(sender, misbehavior))?;
+        result
     }
 
     /// Run the connection manager service with this IO.
@@ -662,22 +1145,47 @@ impl<D: Data, M: Multiaddress> IO<D, M> {
                 maybe_message = self.messages_from_network.next() => {
                     trace!(target: "aleph-network", "Manager received a message from network");
                     match maybe_message {
-                        Some(message) => if let Err(e) = self.on_network_message(&mut service, message) {
+                        Some((message, sender)) => if let Err(e) = self.on_network_message(&mut service, message, sender) {
                             match e {
                                 Error::UserSend => trace!(target: "aleph-network", "Failed to send to user in session."),
                                 Error::NoSession => trace!(target: "aleph-network", "Received message for unknown session."),
+                                Error::Undecodable => trace!(target: "aleph-network", "Received undecodable message."),
                                 _ => return Err(e),
                             }
                         },
                         None => return Err(Error::NetworkChannel),
                     }
                 },
+                maybe_event = self.peer_events_from_network.next() => {
+                    trace!(target: "aleph-network", "Manager received a peer event from network");
+                    match maybe_event {
+                        Some(event) => service.on_peer_event(event),
+                        None => return Err(Error::PeerEventChannel),
+                    }
+                },
+                maybe_rejected = self.rejected_from_user.next() => {
+                    trace!(target: "aleph-network", "Manager received rejected data from user");
+                    match maybe_rejected {
+                        Some((data, session_id)) => if let Some(command) = service.on_rejected_data(&session_id, data) {
+                            self.send_command(command)?;
+                        },
+                        None => return Err(Error::RejectedChannel),
+                    }
+                },
                 _ = maintenance.tick() => {
                     debug!(target: "aleph-network", "Manager starts maintenence");
                     match service.retry_session_start().await {
                         Ok(to_send) => self.send(to_send)?,
                         Err(e) => warn!(target: "aleph-network", "Retry failed to update handler: {:?}", e),
                     }
+                    let now = Instant::now();
+                    for command in service.reconnect(now) {
+                        self.send_command(command)?;
+                    }
+                    service.report_unreachable(now);
+                    if let Some(command) = service.refresh_registered_addresses() {
+                        self.send_command(command)?;
+                    }
                     for to_send in service.discovery() {
                         self.send_data(to_send)?;
                     }
@@ -689,19 +1197,32 @@ impl<D: Data, M: Multiaddress> IO<D, M> {
 
 #[cfg(test)]
 mod tests {
-    use std::time::Duration;
+    use std::{
+        collections::{HashMap, HashSet},
+        sync::Arc,
+        time::Duration,
+    };
 
     use aleph_bft::Recipient;
+    use aleph_primitives::AuthorityId;
+    use codec::{Decode, Encode};
     use futures::{channel::oneshot, StreamExt};
+    use parking_lot::Mutex;
+    use tokio::time::Instant;
 
     use super::{Config, Error, Service, ServiceActions, SessionCommand};
     use crate::{
         network::{
-            manager::{DiscoveryMessage, NetworkData},
-            mock::{crypto_basics, MockNetworkIdentity},
-            ConnectionCommand, DataCommand, Protocol,
+            manager::{
+                compression, connections::CONNECT_GRACE, registry::sign_addresses, AddressRegistry,
+                Capabilities, DiscoveryMessage, NetworkData, RegisteredAddresses,
+                ValidatorConnections,
+            },
+            mock::{crypto_basics, MockMultiaddress, MockNetworkIdentity, MockPeerId},
+            ConnectionCommand, Data, DataCommand, Misbehavior, Multiaddress, NetworkIdentity,
+            PeerEvent, Protocol,
         },
-        SessionId,
+        NodeIndex, SessionId,
     };
 
     const NUM_NODES: usize = 7;
@@ -710,9 +1231,17 @@ mod tests {
     const INITIAL_DELAY: Duration = Duration::from_secs(5);
 
     fn build() -> Service<MockNetworkIdentity, i32> {
+        build_with_connections(ValidatorConnections::new())
+    }
+
+    fn build_with_connections(
+        validator_connections: ValidatorConnections,
+    ) -> Service<MockNetworkIdentity, i32> {
         Service::new(
             MockNetworkIdentity::new(),
             Config::new(MAINTENANCE_PERIOD, DISCOVERY_PERIOD, INITIAL_DELAY),
+            validator_connections,
+            None,
         )
     }
 
@@ -731,7 +1260,7 @@ mod tests {
         assert!(maybe_command.is_none());
         assert!(data.is_empty());
         assert_eq!(
-            service.send_session_data(&session_id, -43),
+            service.send_session_data(&session_id, -43, MockPeerId::random()),
             Err(Error::NoSession)
         );
     }
@@ -762,7 +1291,10 @@ mod tests {
             .iter()
             .all(|(_, command)| command == &DataCommand::Broadcast));
         let _data_from_network = result_from_service.await.unwrap();
-        assert_eq!(service.send_session_data(&session_id, -43), Ok(()));
+        assert_eq!(
+            service.send_session_data(&session_id, -43, MockPeerId::random()),
+            Ok(())
+        );
     }
 
     #[tokio::test]
@@ -790,7 +1322,10 @@ mod tests {
         assert!(data
             .iter()
             .all(|(_, command)| command == &DataCommand::Broadcast));
-        assert_eq!(service.send_session_data(&session_id, -43), Ok(()));
+        assert_eq!(
+            service.send_session_data(&session_id, -43, MockPeerId::random()),
+            Ok(())
+        );
         let mut data_from_network = result_from_service.await.unwrap();
         assert_eq!(data_from_network.next().await, Some(-43));
         let ServiceActions {
@@ -803,7 +1338,7 @@ mod tests {
         assert!(maybe_command.is_none());
         assert!(data.is_empty());
         assert_eq!(
-            service.send_session_data(&session_id, -43),
+            service.send_session_data(&session_id, -43, MockPeerId::random()),
             Err(Error::NoSession)
         );
         assert!(data_from_network.next().await.is_none());
@@ -834,7 +1369,7 @@ mod tests {
             .await
             .unwrap();
         let broadcast = match data[0].clone() {
-            (NetworkData::Meta(broadcast), DataCommand::Broadcast) => broadcast,
+            (NetworkData::Meta(broadcast, _), DataCommand::Broadcast) => broadcast,
             _ => panic!("Expected discovery massage broadcast, got: {:?}", data[0]),
         };
         let addresses = match &broadcast {
@@ -844,7 +1379,7 @@ mod tests {
         let ServiceActions {
             maybe_command,
             data,
-        } = service.on_discovery_message(broadcast);
+        } = service.on_discovery_message(broadcast, Capabilities::default(), MockPeerId::random());
         assert_eq!(
             maybe_command,
             Some(ConnectionCommand::AddReserved(
@@ -860,6 +1395,154 @@ mod tests {
             .any(|(_, command)| matches!(command, &DataCommand::SendTo(_, _))));
     }
 
+    #[tokio::test]
+    /// Returns the discovery broadcast of another node starting the session.
+    async fn discovery_broadcast(session_id: SessionId) -> DiscoveryMessage<MockMultiaddress> {
+        let (validator_data, verifier) = crypto_basics(NUM_NODES).await;
+        let mut other_service = build();
+        let (node_id, pen) = validator_data[1].clone();
+        let ServiceActions { data, .. } = other_service
+            .on_command(SessionCommand::StartValidator(
+                session_id, verifier, node_id, pen, None,
+            ))
+            .await
+            .unwrap();
+        match data[0].clone() {
+            (NetworkData::Meta(broadcast, _), DataCommand::Broadcast) => broadcast,
+            _ => panic!("Expected discovery massage broadcast, got: {:?}", data[0]),
+        }
+    }
+
+    #[tokio::test]
+    async fn reports_sender_of_unknown_session_message() {
+        let mut service = build();
+        let (_, verifier) = crypto_basics(NUM_NODES).await;
+        service
+            .on_command(SessionCommand::StartNonvalidator(SessionId(40), verifier))
+            .await
+            .unwrap();
+        let sender = MockPeerId::random();
+        let ServiceActions {
+            maybe_command,
+            data,
+        } = service.on_discovery_message(
+            discovery_broadcast(SessionId(43)).await,
+            Capabilities::default(),
+            sender,
+        );
+        assert_eq!(
+            maybe_command,
+            Some(ConnectionCommand::Report(
+                sender,
+                Misbehavior::UnknownSession
+            ))
+        );
+        assert!(data.is_empty());
+    }
+
+    #[tokio::test]
+    async fn tolerates_messages_from_adjacent_sessions() {
+        let mut service = build();
+        let sender = MockPeerId::random();
+        // We cannot tell which sessions are unexpected before running any.
+        let ServiceActions { maybe_command, .. } = service.on_discovery_message(
+            discovery_broadcast(SessionId(43)).await,
+            Capabilities::default(),
+            sender,
+        );
+        assert!(maybe_command.is_none());
+        let (_, verifier) = crypto_basics(NUM_NODES).await;
+        service
+            .on_command(SessionCommand::StartNonvalidator(SessionId(42), verifier))
+            .await
+            .unwrap();
+        let ServiceActions { maybe_command, .. } = service.on_discovery_message(
+            discovery_broadcast(SessionId(43)).await,
+            Capabilities::default(),
+            sender,
+        );
+        assert!(maybe_command.is_none());
+        assert_eq!(
+            service.send_session_data(&SessionId(41), -43, sender),
+            Err(Error::NoSession)
+        );
+        assert!(!service.is_unexpected_session(&SessionId(41)));
+        assert!(service.is_unexpected_session(&SessionId(44)));
+    }
+
+    #[tokio::test]
+    async fn reports_sender_of_rejected_data() {
+        let mut service = build();
+        let session_id = SessionId(43);
+        let (peer_id, _data_from_network) = start_session_with_peer(&mut service, session_id).await;
+        let other_peer_id = MockPeerId::random();
+        assert_eq!(service.send_session_data(&session_id, 7, peer_id), Ok(()));
+        assert_eq!(
+            service.send_session_data(&session_id, 8, other_peer_id),
+            Ok(())
+        );
+        assert_eq!(
+            service.on_rejected_data(&session_id, 7),
+            Some(ConnectionCommand::Report(peer_id, Misbehavior::InvalidData))
+        );
+        // Reported only once.
+        assert!(service.on_rejected_data(&session_id, 7).is_none());
+        assert!(service.on_rejected_data(&session_id, 9).is_none());
+        assert!(service.on_rejected_data(&SessionId(44), 8).is_none());
+    }
+
+    #[tokio::test]
+    async fn reports_sender_of_invalid_authentication() {
+        let mut service = build();
+        let (validator_data, verifier) = crypto_basics(NUM_NODES).await;
+        let (node_id, pen) = validator_data[0].clone();
+        let session_id = SessionId(43);
+        service
+            .on_command(SessionCommand::StartValidator(
+                session_id,
+                verifier.clone(),
+                node_id,
+                pen,
+                None,
+            ))
+            .await
+            .unwrap();
+        let mut other_service = build();
+        let (node_id, pen) = validator_data[1].clone();
+        let ServiceActions { data, .. } = other_service
+            .on_command(SessionCommand::StartValidator(
+                session_id, verifier, node_id, pen, None,
+            ))
+            .await
+            .unwrap();
+        let (auth_data, _) = match data[0].clone() {
+            (
+                NetworkData::Meta(DiscoveryMessage::AuthenticationBroadcast(authentication), _),
+                DataCommand::Broadcast,
+            ) => authentication,
+            _ => panic!("Expected an authentication broadcast, got: {:?}", data[0]),
+        };
+        // Claim to be a different node, keeping the signature.
+        let (_, signature) = service.sessions[&session_id]
+            .handler
+            .authentication()
+            .unwrap();
+        let forged = DiscoveryMessage::AuthenticationBroadcast((auth_data, signature));
+        let sender = MockPeerId::random();
+        let ServiceActions {
+            maybe_command,
+            data,
+        } = service.on_discovery_message(forged, Capabilities::default(), sender);
+        assert_eq!(
+            maybe_command,
+            Some(ConnectionCommand::Report(
+                sender,
+                Misbehavior::InvalidAuthentication
+            ))
+        );
+        assert!(data.is_empty());
+    }
+
     #[tokio::test]
     async fn sends_user_data() {
         let mut service = build();
@@ -885,10 +1568,10 @@ mod tests {
             .await
             .unwrap();
         let broadcast = match data[0].clone() {
-            (NetworkData::Meta(broadcast), DataCommand::Broadcast) => broadcast,
+            (NetworkData::Meta(broadcast, _), DataCommand::Broadcast) => broadcast,
             _ => panic!("Expected discovery massage broadcast, got: {:?}", data[0]),
         };
-        service.on_discovery_message(broadcast);
+        service.on_discovery_message(broadcast, Capabilities::default(), MockPeerId::random());
         let messages = service.on_user_message(2137, session_id, Recipient::Everyone);
         assert_eq!(messages.len(), 1);
         let (network_data, data_command) = &messages[0];
@@ -898,4 +1581,454 @@ mod tests {
         ));
         assert_eq!(network_data, &NetworkData::Data(2137, session_id));
     }
+
+    /// Starts a validator session and makes the service learn the peer of another node in it.
+    async fn start_session_with_peer<D: Data>(
+        service: &mut Service<MockNetworkIdentity, D>,
+        session_id: SessionId,
+    ) -> (MockPeerId, futures::channel::mpsc::UnboundedReceiver<D>) {
+        let (validator_data, verifier) = crypto_basics(NUM_NODES).await;
+        let (node_id, pen) = validator_data[0].clone();
+        let (result_for_user, result_from_service) = oneshot::channel();
+        service
+            .on_command(SessionCommand::StartValidator(
+                session_id,
+                verifier.clone(),
+                node_id,
+                pen,
+                Some(result_for_user),
+            ))
+            .await
+            .unwrap();
+        let mut other_service = build();
+        let (node_id, pen) = validator_data[1].clone();
+        let ServiceActions { data, .. } = other_service
+            .on_command(SessionCommand::StartValidator(
+                session_id, verifier, node_id, pen, None,
+            ))
+            .await
+            .unwrap();
+        let broadcast = match data[0].clone() {
+            (NetworkData::Meta(broadcast, _), DataCommand::Broadcast) => broadcast,
+            _ => panic!("Expected discovery massage broadcast, got: {:?}", data[0]),
+        };
+        service.on_discovery_message(broadcast, Capabilities::default(), MockPeerId::random());
+        let peer_id = service.sessions[&session_id]
+            .handler
+            .peer_id(&NodeIndex(1))
+            .expect("the peer should be known");
+        (peer_id, result_from_service.await.unwrap())
+    }
+
+    fn build_for_bytes(
+        compression_threshold: Option<usize>,
+    ) -> Service<MockNetworkIdentity, Vec<u8>> {
+        Service::new(
+            MockNetworkIdentity::new(),
+            Config::new(MAINTENANCE_PERIOD, DISCOVERY_PERIOD, INITIAL_DELAY)
+                .with_compression_threshold(compression_threshold),
+            ValidatorConnections::new(),
+            None,
+        )
+    }
+
+    #[tokio::test]
+    async fn compresses_large_data_only_for_peers_accepting_it() {
+        let mut service = build_for_bytes(Some(1024));
+        let session_id = SessionId(43);
+        let (peer_id, _data_from_network) = start_session_with_peer(&mut service, session_id).await;
+        let message = vec![7u8; 10_000];
+
+        let messages = service.on_user_message(message.clone(), session_id, Recipient::Everyone);
+        assert_eq!(
+            messages,
+            vec![(
+                NetworkData::Data(message.clone(), session_id),
+                DataCommand::SendTo(peer_id, Protocol::Validator)
+            )]
+        );
+
+        assert_eq!(service.accept_compression(&session_id, peer_id), Ok(()));
+        let messages = service.on_user_message(message.clone(), session_id, Recipient::Everyone);
+        assert_eq!(messages.len(), 1);
+        match &messages[0] {
+            (
+                NetworkData::CompressedData(compressed, compressed_session_id),
+                DataCommand::SendTo(recipient, Protocol::Validator),
+            ) => {
+                assert!(compressed.len() < message.len());
+                assert_eq!(compressed_session_id, &session_id);
+                assert_eq!(recipient, &peer_id);
+                assert_eq!(
+                    compression::decompress::<Vec<u8>>(compressed).unwrap(),
+                    message
+                );
+            }
+            _ => panic!("Expected compressed data, got: {:?}", messages[0]),
+        }
+    }
+
+    #[tokio::test]
+    async fn does_not_compress_small_data_or_when_disabled() {
+        for (compression_threshold, message) in
+            [(Some(1024), vec![7u8; 100]), (None, vec![7u8; 10_000])]
+        {
+            let mut service = build_for_bytes(compression_threshold);
+            let session_id = SessionId(43);
+            let (peer_id, _data_from_network) =
+                start_session_with_peer(&mut service, session_id).await;
+            service.accept_compression(&session_id, peer_id).unwrap();
+            let messages =
+                service.on_user_message(message.clone(), session_id, Recipient::Everyone);
+            assert_eq!(
+                messages,
+                vec![(
+                    NetworkData::Data(message, session_id),
+                    DataCommand::SendTo(peer_id, Protocol::Validator)
+                )]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn delivers_compressed_data() {
+        let mut service = build_for_bytes(None);
+        let session_id = SessionId(43);
+        let (peer_id, mut data_from_network) =
+            start_session_with_peer(&mut service, session_id).await;
+        let message = vec![7u8; 10_000];
+        let (compressed, _) = compression::compress(&message, 0).unwrap();
+
+        assert_eq!(
+            service.send_compressed_session_data(&session_id, compressed.clone(), peer_id),
+            Ok(())
+        );
+        assert_eq!(data_from_network.next().await, Some(message));
+        // Sending compressed data implies accepting it.
+        assert!(service.sessions[&session_id]
+            .compression_peers
+            .contains(&peer_id));
+
+        assert_eq!(
+            service.send_compressed_session_data(&session_id, vec![1, 2, 3, 4, 5], peer_id),
+            Err(Error::Undecodable)
+        );
+        // Garbage does not count as accepting compression.
+        let other_peer_id = MockPeerId::random();
+        assert_eq!(
+            service.send_compressed_session_data(&session_id, vec![1, 2, 3, 4, 5], other_peer_id),
+            Err(Error::Undecodable)
+        );
+        assert!(!service.sessions[&session_id]
+            .compression_peers
+            .contains(&other_peer_id));
+        assert_eq!(
+            service.send_compressed_session_data(&SessionId(44), compressed, peer_id),
+            Err(Error::NoSession)
+        );
+    }
+
+    #[tokio::test]
+    async fn announces_and_notes_accepting_compression_in_discovery() {
+        let mut service = build();
+        let session_id = SessionId(43);
+        let (peer_id, _data_from_network) = start_session_with_peer(&mut service, session_id).await;
+
+        let messages = service.discovery();
+        assert!(!messages.is_empty());
+        for (data, _) in messages {
+            assert!(matches!(
+                data,
+                NetworkData::Meta(
+                    _,
+                    Capabilities {
+                        accepts_compression: true
+                    }
+                )
+            ));
+        }
+
+        let broadcast = discovery_broadcast(session_id).await;
+        service.on_discovery_message(broadcast.clone(), Capabilities::default(), peer_id);
+        assert!(!service.sessions[&session_id]
+            .compression_peers
+            .contains(&peer_id));
+        service.on_discovery_message(
+            broadcast,
+            Capabilities {
+                accepts_compression: true,
+            },
+            peer_id,
+        );
+        assert!(service.sessions[&session_id]
+            .compression_peers
+            .contains(&peer_id));
+    }
+
+    #[tokio::test]
+    async fn capabilities_are_invisible_to_nodes_not_knowing_them() {
+        type MockNetworkData = NetworkData<Vec<u8>, MockMultiaddress>;
+        let broadcast = discovery_broadcast(SessionId(43)).await;
+        let capabilities = Capabilities {
+            accepts_compression: true,
+        };
+        let encoded = NetworkData::<Vec<u8>, _>::Meta(broadcast.clone(), capabilities).encode();
+
+        // Nodes not knowing about capabilities stop decoding right before them.
+        let mut input = &encoded[..];
+        let (variant, message) =
+            <(u8, DiscoveryMessage<MockMultiaddress>)>::decode(&mut input).unwrap();
+        assert_eq!(variant, 0);
+        assert_eq!(message, broadcast);
+        assert_eq!(input, &capabilities.encode()[..]);
+
+        // Messages from such nodes carry no capabilities.
+        let without_capabilities = &encoded[..encoded.len() - input.len()];
+        assert_eq!(
+            MockNetworkData::decode(&mut &without_capabilities[..]).unwrap(),
+            NetworkData::Meta(broadcast.clone(), Capabilities::default())
+        );
+        assert_eq!(
+            MockNetworkData::decode(&mut &encoded[..]).unwrap(),
+            NetworkData::Meta(broadcast, capabilities)
+        );
+    }
+
+    #[tokio::test]
+    async fn tracks_validator_connections_until_session_stops() {
+        let validator_connections = ValidatorConnections::new();
+        let mut service = build_with_connections(validator_connections.clone());
+        let session_id = SessionId(43);
+        let (peer_id, _data_from_network) = start_session_with_peer(&mut service, session_id).await;
+        assert_eq!(validator_connections.get(session_id), Some(0));
+        service.on_peer_event(PeerEvent::Connected(peer_id));
+        assert_eq!(validator_connections.get(session_id), Some(1));
+        service.on_peer_event(PeerEvent::Connected(MockPeerId::random()));
+        assert_eq!(validator_connections.get(session_id), Some(1));
+        service.on_peer_event(PeerEvent::Disconnected(peer_id));
+        assert_eq!(validator_connections.get(session_id), Some(0));
+        service.on_peer_event(PeerEvent::Connected(peer_id));
+        service
+            .on_command(SessionCommand::Stop(session_id))
+            .await
+            .unwrap();
+        assert_eq!(validator_connections.get(session_id), None);
+    }
+
+    #[tokio::test]
+    async fn reconnects_to_disconnected_peers_with_backoff() {
+        let mut service = build();
+        let session_id = SessionId(43);
+        let (peer_id, _data_from_network) = start_session_with_peer(&mut service, session_id).await;
+        service.on_peer_event(PeerEvent::Connected(peer_id));
+        let now = Instant::now();
+        assert!(service.reconnect(now + CONNECT_GRACE).is_empty());
+        service.on_peer_event(PeerEvent::Disconnected(peer_id));
+        assert!(service.reconnect(Instant::now()).is_empty());
+        let now = Instant::now() + CONNECT_GRACE;
+        let (to_remove, addresses) = match &service.reconnect(now)[..] {
+            [ConnectionCommand::DelReserved(to_remove), ConnectionCommand::AddReserved(addresses)] => {
+                (to_remove.clone(), addresses.clone())
+            }
+            commands => panic!(
+                "Expected removing and adding back the peer, got: {:?}",
+                commands
+            ),
+        };
+        assert_eq!(to_remove, HashSet::from([peer_id]));
+        assert!(!addresses.is_empty());
+        assert!(addresses
+            .iter()
+            .all(|address| address.get_peer_id() == Some(peer_id)));
+        assert!(service.reconnect(now).is_empty());
+        assert_eq!(service.reconnect(now + Duration::from_secs(10)).len(), 2);
+        service.on_peer_event(PeerEvent::Connected(peer_id));
+        assert!(service.reconnect(now + CONNECT_GRACE).is_empty());
+    }
+
+    #[tokio::test]
+    async fn retries_starting_sessions_right_away_once() {
+        let mut service = build();
+        let session_id = SessionId(43);
+        let (validator_data, verifier) = crypto_basics(NUM_NODES).await;
+        let (node_id, pen) = validator_data[0].clone();
+        service
+            .on_command(SessionCommand::StartNonvalidator(
+                session_id,
+                verifier.clone(),
+            ))
+            .await
+            .unwrap();
+        assert!(service
+            .on_command(SessionCommand::StartValidator(
+                session_id, verifier, node_id, pen, None,
+            ))
+            .await
+            .is_err());
+        // The first retry fails just as well, the next one is backed off.
+        assert!(service.retry_session_start().await.is_err());
+        let ServiceActions {
+            maybe_command,
+            data,
+        } = service.retry_session_start().await.unwrap();
+        assert!(maybe_command.is_none());
+        assert!(data.is_empty());
+    }
+
+    #[tokio::test]
+    async fn lists_unreachable_committee_members() {
+        let mut service = build();
+        let session_id = SessionId(43);
+        let (peer_id, _data_from_network) = start_session_with_peer(&mut service, session_id).await;
+        let unreachable = service.unreachable_nodes(&session_id);
+        assert_eq!(unreachable.len(), NUM_NODES - 1);
+        let (node_id, health) = unreachable[0];
+        assert_eq!(node_id, NodeIndex(1));
+        assert_eq!(health.expect("the peer is known").connected_since, None);
+        assert!(unreachable[1..].iter().all(|(_, health)| health.is_none()));
+        service.on_peer_event(PeerEvent::Connected(peer_id));
+        let unreachable = service.unreachable_nodes(&session_id);
+        assert_eq!(unreachable.len(), NUM_NODES - 2);
+        assert!(unreachable
+            .iter()
+            .all(|(node_id, _)| *node_id != NodeIndex(1)));
+    }
+
+    #[derive(Default)]
+    struct MockRegistry {
+        registered: Mutex<HashMap<AuthorityId, RegisteredAddresses>>,
+    }
+
+    impl AddressRegistry for MockRegistry {
+        fn registered(&self) -> HashMap<AuthorityId, RegisteredAddresses> {
+            self.registered.lock().clone()
+        }
+
+        fn register(&self, authority: AuthorityId, addresses: RegisteredAddresses) {
+            self.registered.lock().insert(authority, addresses);
+        }
+    }
+
+    fn build_with_registry(registry: Arc<MockRegistry>) -> Service<MockNetworkIdentity, i32> {
+        Service::new(
+            MockNetworkIdentity::new(),
+            Config::new(MAINTENANCE_PERIOD, DISCOVERY_PERIOD, INITIAL_DELAY)
+                .with_address_registry(registry),
+            ValidatorConnections::new(),
+            None,
+        )
+    }
+
+    #[tokio::test]
+    async fn connects_to_registered_validators_and_registers_own_addresses() {
+        let registry = Arc::new(MockRegistry::default());
+        let mut service = build_with_registry(registry.clone());
+        let (validator_data, verifier) = crypto_basics(NUM_NODES).await;
+        let session_id = SessionId(43);
+        let addresses = MockNetworkIdentity::new().identity().0;
+        let (_, pen1) = &validator_data[1];
+        registry.register(
+            pen1.authority_id(),
+            sign_addresses(&addresses, session_id, 0, pen1).await,
+        );
+        let (node_id, pen) = validator_data[0].clone();
+        let ServiceActions { maybe_command, .. } = service
+            .on_command(SessionCommand::StartValidator(
+                session_id,
+                verifier,
+                node_id,
+                pen.clone(),
+                None,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(
+            maybe_command,
+            Some(ConnectionCommand::AddReserved(
+                addresses.iter().cloned().collect()
+            ))
+        );
+        assert!(registry.registered().contains_key(&pen.authority_id()));
+        let messages = service.on_user_message(2, session_id, Recipient::Node(NodeIndex(1)));
+        assert_eq!(messages.len(), 1);
+        assert_eq!(
+            messages[0].1,
+            DataCommand::SendTo(addresses[0].get_peer_id().unwrap(), Protocol::Validator)
+        );
+    }
+
+    #[tokio::test]
+    async fn connects_to_validators_registered_later() {
+        let registry = Arc::new(MockRegistry::default());
+        let mut service = build_with_registry(registry.clone());
+        let (validator_data, verifier) = crypto_basics(NUM_NODES).await;
+        let session_id = SessionId(43);
+        let (node_id, pen) = validator_data[0].clone();
+        let ServiceActions { maybe_command, .. } = service
+            .on_command(SessionCommand::StartValidator(
+                session_id, verifier, node_id, pen, None,
+            ))
+            .await
+            .unwrap();
+        assert!(maybe_command.is_none());
+        assert!(service.refresh_registered_addresses().is_none());
+        let addresses = MockNetworkIdentity::new().identity().0;
+        let (_, pen2) = &validator_data[2];
+        registry.register(
+            pen2.authority_id(),
+            sign_addresses(&addresses, session_id, 0, pen2).await,
+        );
+        assert_eq!(
+            service.refresh_registered_addresses(),
+            Some(ConnectionCommand::AddReserved(
+                addresses.into_iter().collect::<HashSet<_>>()
+            ))
+        );
+        assert!(service.refresh_registered_addresses().is_none());
+    }
+
+    #[tokio::test]
+    async fn registers_changed_addresses_when_updating_session() {
+        let registry = Arc::new(MockRegistry::default());
+        let mut service = build_with_registry(registry.clone());
+        let (validator_data, verifier) = crypto_basics(NUM_NODES).await;
+        let session_id = SessionId(43);
+        let (node_id, pen) = validator_data[0].clone();
+        service
+            .on_command(SessionCommand::StartValidator(
+                session_id,
+                verifier.clone(),
+                node_id,
+                pen.clone(),
+                None,
+            ))
+            .await
+            .unwrap();
+        let own_registration = registry.registered()[&pen.authority_id()].clone();
+        assert_eq!(own_registration.addresses.nonce, 0);
+
+        // Make the registered addresses differ from ours.
+        let other_addresses = MockNetworkIdentity::new().identity().0;
+        registry.register(
+            pen.authority_id(),
+            sign_addresses(&other_addresses, session_id, 0, &pen).await,
+        );
+        service
+            .on_command(SessionCommand::StartValidator(
+                session_id,
+                verifier,
+                node_id,
+                pen.clone(),
+                None,
+            ))
+            .await
+            .unwrap();
+        let replacement = registry.registered()[&pen.authority_id()].clone();
+        assert_eq!(replacement.addresses.session, session_id.0);
+        assert_eq!(replacement.addresses.nonce, 1);
+        assert_eq!(
+            replacement.addresses.addresses,
+            own_registration.addresses.addresses
+        );
+    }
 }
@@ -1,12 +1,13 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use aleph_bft::NodeCount;
+use aleph_primitives::AuthorityId;
 use codec::Encode;
 
 use crate::{
     crypto::{AuthorityPen, AuthorityVerifier},
     network::{
-        manager::{AuthData, Authentication},
+        manager::{registry, AuthData, Authentication, RegisteredAddresses},
         Multiaddress, PeerId,
     },
     NodeIndex, SessionId,
@@ -31,9 +32,12 @@ type PeerAuthentications<M> = (Authentication<M>, Option<Authentication<M>>);
 
 /// A struct for handling authentications for a given session and maintaining
 /// mappings between PeerIds and NodeIndexes within that session.
+/// Nodes we have no authentication of are mapped using the addresses they registered on chain.
 pub struct Handler<M: Multiaddress> {
     peers_by_node: HashMap<NodeIndex, M::PeerId>,
     authentications: HashMap<M::PeerId, PeerAuthentications<M>>,
+    registered: HashMap<AuthorityId, RegisteredAddresses>,
+    registered_nodes: HashSet<NodeIndex>,
     session_info: SessionInfo<M>,
     own_peer_id: M::PeerId,
     authority_index_and_pen: Option<(NodeIndex, AuthorityPen)>,
@@ -139,6 +143,8 @@ impl<M: Multiaddress> Handler<M> {
         Ok(Handler {
             peers_by_node: HashMap::new(),
             authentications: HashMap::new(),
+            registered: HashMap::new(),
+            registered_nodes: HashSet::new(),
             session_info,
             authority_index_and_pen,
             authority_verifier,
@@ -146,7 +152,7 @@ impl<M: Multiaddress> Handler<M> {
         })
     }
 
-    fn index(&self) -> Option<NodeIndex> {
+    pub fn index(&self) -> Option<NodeIndex> {
         match self.authority_index_and_pen {
             Some((index, _)) => Some(index),
             _ => None,
@@ -173,7 +179,8 @@ impl<M: Multiaddress> Handler<M> {
         }
     }
 
-    /// Returns a vector of indices of nodes for which the handler has no authentication.
+    /// Returns a vector of indices of nodes whose peer the handler does not know, neither from an
+    /// authentication nor from addresses registered on chain.
     pub fn missing_nodes(&self) -> Vec<NodeIndex> {
         let node_count = self.node_count().0;
         if self.peers_by_node.len() + 1 == node_count {
@@ -215,20 +222,109 @@ impl<M: Multiaddress> Handler<M> {
             return false;
         }
         self.peers_by_node.insert(auth_data.node_id, peer_id);
+        self.registered_nodes.remove(&auth_data.node_id);
         self.authentications.insert(peer_id, (authentication, None));
         true
     }
 
+    /// Whether there are nodes we have no authentication of, so we use or could use the addresses
+    /// they registered on chain.
+    pub fn uses_registered_addresses(&self) -> bool {
+        !self.registered_nodes.is_empty() || !self.missing_nodes().is_empty()
+    }
+
+    /// Uses the addresses registered on chain for the nodes we have no authentication of.
+    /// Returns the addresses we should connect to, i.e. the ones of nodes we did not know of or
+    /// that registered new addresses.
+    pub fn handle_registered_addresses(
+        &mut self,
+        registered: HashMap<AuthorityId, RegisteredAddresses>,
+    ) -> Vec<M> {
+        self.registered = registered;
+        self.use_registered_addresses()
+    }
+
+    fn use_registered_addresses(&mut self) -> Vec<M> {
+        let mut result = Vec::new();
+        for (authority, registered) in &self.registered {
+            let node_id = match self.authority_verifier.index_of(authority) {
+                Some(node_id) if Some(node_id) != self.index() => node_id,
+                _ => continue,
+            };
+            // Authentications take precedence, as they are more recent.
+            if self.peers_by_node.contains_key(&node_id)
+                && !self.registered_nodes.contains(&node_id)
+            {
+                continue;
+            }
+            let addresses: Vec<M> =
+                match registry::verified_addresses(registered, node_id, &self.authority_verifier) {
+                    Some(addresses) => addresses,
+                    None => continue,
+                };
+            let peer_id = match get_common_peer_id(&addresses) {
+                Some(peer_id) if peer_id != self.own_peer_id => peer_id,
+                _ => continue,
+            };
+            self.registered_nodes.insert(node_id);
+            if self.peers_by_node.insert(node_id, peer_id) != Some(peer_id) {
+                result.extend(addresses);
+            }
+        }
+        result
+    }
+
+    /// Checks whether the authentication is malformed or has a signature that is invalid, even
+    /// allowing for a key change we are not aware of yet. Honest nodes never send such
+    /// authentications.
+    pub fn is_invalid(&self, authentication: &Authentication<M>) -> bool {
+        let (auth_data, signature) = authentication;
+        let peer_id = match get_common_peer_id(&auth_data.addresses) {
+            Some(peer_id) => peer_id,
+            None => return true,
+        };
+        !self.authentications.contains_key(&peer_id)
+            && !self
+                .authority_verifier
+                .verify(&auth_data.encode(), signature, auth_data.node_id)
+    }
+
     /// Returns the PeerId of the node with the given NodeIndex, if known.
     pub fn peer_id(&self, node_id: &NodeIndex) -> Option<M::PeerId> {
         self.peers_by_node.get(node_id).copied()
     }
 
+    /// Returns the addresses of the node with the given NodeIndex, either from its authentication
+    /// or as registered on chain, empty if unknown.
+    pub fn peer_addresses(&self, node_id: &NodeIndex) -> Vec<M> {
+        let peer_id = match self.peers_by_node.get(node_id) {
+            Some(peer_id) => peer_id,
+            None => return Vec::new(),
+        };
+        match self.registered_nodes.contains(node_id) {
+            true => self
+                .registered
+                .iter()
+                .find(|(authority, _)| {
+                    self.authority_verifier.index_of(authority) == Some(*node_id)
+                })
+                .and_then(|(_, registered)| {
+                    registry::verified_addresses(registered, *node_id, &self.authority_verifier)
+                })
+                .unwrap_or_default(),
+            false => self
+                .authentications
+                .get(peer_id)
+                .map(|((auth_data, _), _)| auth_data.addresses.clone())
+                .unwrap_or_default(),
+        }
+    }
+
     /// Updates the handler with the given keychain and set of own addresses.
     /// Returns an error if the set of addresses is not valid.
     /// All authentications will be rechecked, invalid ones purged and cached ones that turn out to
     /// now be valid canonalized.
-    /// Own authentication will be regenerated.
+    /// Own authentication will be regenerated, and the registered addresses reverified.
     /// If successful returns a set of addresses that we should be connected to.
     pub async fn update(
         &mut self,
@@ -241,6 +337,7 @@ impl<M: Multiaddress> Handler<M> {
         }
 
         let authentications = self.authentications.clone();
+        let registered = self.registered.clone();
 
         *self = Handler::new(
             authority_index_and_pen,
@@ -256,19 +353,24 @@ impl<M: Multiaddress> Handler<M> {
                 self.handle_authentication(auth);
             }
         }
-        Ok(self
-            .authentications
-            .values()
-            .flat_map(|((auth_data, _), _)| auth_data.addresses.iter().cloned())
-            .collect())
+        let mut addresses = self.handle_registered_addresses(registered);
+        addresses.extend(
+            self.authentications
+                .values()
+                .flat_map(|((auth_data, _), _)| auth_data.addresses.iter().cloned()),
+        );
+        Ok(addresses)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
+
     use super::{get_common_peer_id, Handler, HandlerError};
     use crate::{
         network::{
+            manager::registry::sign_addresses,
             mock::{crypto_basics, MockMultiaddress, MockNetworkIdentity, MockPeerId},
             NetworkIdentity,
         },
@@ -547,6 +649,32 @@ mod tests {
         assert_eq!(missing_nodes, expected_missing);
     }
 
+    #[tokio::test]
+    async fn recognizes_invalid_authentication() {
+        let crypto_basics = crypto_basics(NUM_NODES).await;
+        let handler0 = Handler::new(
+            Some(crypto_basics.0[0].clone()),
+            crypto_basics.1.clone(),
+            SessionId(43),
+            MockNetworkIdentity::new().identity().0,
+        )
+        .await
+        .unwrap();
+        let handler1 = Handler::new(
+            Some(crypto_basics.0[1].clone()),
+            crypto_basics.1.clone(),
+            SessionId(43),
+            MockNetworkIdentity::new().identity().0,
+        )
+        .await
+        .unwrap();
+        let authentication = handler1.authentication().unwrap();
+        assert!(!handler0.is_invalid(&authentication));
+        let mut badly_signed = authentication.clone();
+        badly_signed.1 = handler0.authentication().unwrap().1;
+        assert!(handler0.is_invalid(&badly_signed));
+    }
+
     #[tokio::test]
     async fn ignores_wrong_session_authentication() {
         let crypto_basics = crypto_basics(NUM_NODES).await;
@@ -673,4 +801,157 @@ mod tests {
             get_common_peer_id(&addresses1)
         );
     }
+
+    #[tokio::test]
+    async fn uses_registered_addresses() {
+        let crypto_basics = crypto_basics(NUM_NODES).await;
+        let mut handler0 = Handler::new(
+            Some(crypto_basics.0[0].clone()),
+            crypto_basics.1.clone(),
+            SessionId(43),
+            MockNetworkIdentity::new().identity().0,
+        )
+        .await
+        .unwrap();
+        let addresses = MockNetworkIdentity::new().identity().0;
+        let (_, pen1) = &crypto_basics.0[1];
+        let registered = HashMap::from([(
+            pen1.authority_id(),
+            sign_addresses(&addresses, SessionId(42), 0, pen1).await,
+        )]);
+        assert_eq!(
+            handler0.handle_registered_addresses(registered.clone()),
+            addresses
+        );
+        assert_eq!(
+            handler0.peer_id(&NodeIndex(1)),
+            get_common_peer_id(&addresses)
+        );
+        let expected_missing: Vec<_> = (2..NUM_NODES).map(NodeIndex).collect();
+        assert_eq!(handler0.missing_nodes(), expected_missing);
+        assert!(handler0.handle_registered_addresses(registered).is_empty());
+    }
+
+    #[tokio::test]
+    async fn ignores_badly_signed_registered_addresses() {
+        let crypto_basics = crypto_basics(NUM_NODES).await;
+        let mut handler0 = Handler::new(
+            Some(crypto_basics.0[0].clone()),
+            crypto_basics.1.clone(),
+            SessionId(43),
+            MockNetworkIdentity::new().identity().0,
+        )
+        .await
+        .unwrap();
+        let addresses = MockNetworkIdentity::new().identity().0;
+        let registered = HashMap::from([(
+            crypto_basics.0[1].1.authority_id(),
+            sign_addresses(&addresses, SessionId(43), 0, &crypto_basics.0[2].1).await,
+        )]);
+        assert!(handler0.handle_registered_addresses(registered).is_empty());
+        assert!(handler0.peer_id(&NodeIndex(1)).is_none());
+    }
+
+    #[tokio::test]
+    async fn prefers_authentication_over_registered_addresses() {
+        let crypto_basics = crypto_basics(NUM_NODES).await;
+        let mut handler0 = Handler::new(
+            Some(crypto_basics.0[0].clone()),
+            crypto_basics.1.clone(),
+            SessionId(43),
+            MockNetworkIdentity::new().identity().0,
+        )
+        .await
+        .unwrap();
+        let registered_addresses = MockNetworkIdentity::new().identity().0;
+        let (_, pen1) = &crypto_basics.0[1];
+        let registered = HashMap::from([(
+            pen1.authority_id(),
+            sign_addresses(&registered_addresses, SessionId(43), 0, pen1).await,
+        )]);
+        handler0.handle_registered_addresses(registered.clone());
+        let addresses = MockNetworkIdentity::new().identity().0;
+        let handler1 = Handler::new(
+            Some(crypto_basics.0[1].clone()),
+            crypto_basics.1.clone(),
+            SessionId(43),
+            addresses.clone(),
+        )
+        .await
+        .unwrap();
+        assert!(handler0.handle_authentication(handler1.authentication().unwrap()));
+        assert!(handler0.handle_registered_addresses(registered).is_empty());
+        assert_eq!(
+            handler0.peer_id(&NodeIndex(1)),
+            get_common_peer_id(&addresses)
+        );
+    }
+
+    #[tokio::test]
+    async fn knows_addresses_of_peers() {
+        let crypto_basics = crypto_basics(NUM_NODES).await;
+        let mut handler0 = Handler::new(
+            Some(crypto_basics.0[0].clone()),
+            crypto_basics.1.clone(),
+            SessionId(43),
+            MockNetworkIdentity::new().identity().0,
+        )
+        .await
+        .unwrap();
+        let registered_addresses = MockNetworkIdentity::new().identity().0;
+        let (_, pen1) = &crypto_basics.0[1];
+        let registered = HashMap::from([(
+            pen1.authority_id(),
+            sign_addresses(&registered_addresses, SessionId(43), 0, pen1).await,
+        )]);
+        handler0.handle_registered_addresses(registered);
+        let addresses = MockNetworkIdentity::new().identity().0;
+        let handler2 = Handler::new(
+            Some(crypto_basics.0[2].clone()),
+            crypto_basics.1.clone(),
+            SessionId(43),
+            addresses.clone(),
+        )
+        .await
+        .unwrap();
+        assert!(handler0.handle_authentication(handler2.authentication().unwrap()));
+        assert_eq!(handler0.peer_addresses(&NodeIndex(1)), registered_addresses);
+        assert_eq!(handler0.peer_addresses(&NodeIndex(2)), addresses);
+        assert!(handler0.peer_addresses(&NodeIndex(3)).is_empty());
+    }
+
+    #[tokio::test]
+    async fn keeps_registered_addresses_after_update() {
+        let crypto_basics = crypto_basics(NUM_NODES).await;
+        let mut handler0 = Handler::new(
+            Some(crypto_basics.0[0].clone()),
+            crypto_basics.1.clone(),
+            SessionId(43),
+            MockNetworkIdentity::new().identity().0,
+        )
+        .await
+        .unwrap();
+        let addresses = MockNetworkIdentity::new().identity().0;
+        let (_, pen1) = &crypto_basics.0[1];
+        let registered = HashMap::from([(
+            pen1.authority_id(),
+            sign_addresses(&addresses, SessionId(43), 0, pen1).await,
+        )]);
+        handler0.handle_registered_addresses(registered);
+        assert_eq!(
+            handler0
+                .update(
+                    Some(crypto_basics.0[0].clone()),
+                    crypto_basics.1.clone(),
+                    MockNetworkIdentity::new().identity().0,
+                )
+                .await
+                .unwrap(),
+            addresses
+        );
+        assert_eq!(
+            handler0.peer_id(&NodeIndex(1)),
+            get_common_peer_id(&addresses)
+        );
+    }
 }
@@ -19,7 +19,7 @@ use crate::{
     crypto::{AuthorityPen, AuthorityVerifier},
     network::{
         ConnectionCommand, Data, DataCommand, Event, EventStream, Multiaddress, Network,
-        NetworkIdentity, NetworkSender, PeerId, Protocol, IO,
+        NetworkIdentity, NetworkSender, PeerEvent, PeerId, Protocol, IO,
     },
     AuthorityId, NodeIndex,
 };
@@ -133,8 +133,9 @@ pub type MockData = Vec<u8>;
 
 pub struct MockIO {
     pub messages_for_user: mpsc::UnboundedSender<(MockData, MockDataCommand)>,
-    pub messages_from_user: mpsc::UnboundedReceiver<MockData>,
+    pub messages_from_user: mpsc::UnboundedReceiver<(MockData, MockPeerId)>,
     pub commands_for_manager: mpsc::UnboundedSender<MockConnectionCommand>,
+    pub peer_events_from_network: mpsc::UnboundedReceiver<PeerEvent<MockPeerId>>,
 }
 
 impl MockIO {
@@ -142,13 +143,20 @@ impl MockIO {
         let (mock_messages_for_user, messages_from_user) = mpsc::unbounded();
         let (messages_for_user, mock_messages_from_user) = mpsc::unbounded();
         let (mock_commands_for_manager, commands_from_manager) = mpsc::unbounded();
+        let (peer_events_for_manager, mock_peer_events_from_network) = mpsc::unbounded();
         (
             MockIO {
                 messages_for_user: mock_messages_for_user,
                 messages_from_user: mock_messages_from_user,
                 commands_for_manager: mock_commands_for_manager,
+                peer_events_from_network: mock_peer_events_from_network,
             },
-            IO::new(messages_from_user, messages_for_user, commands_from_manager),
+            IO::new(
+                messages_from_user,
+                messages_for_user,
+                commands_from_manager,
+                peer_events_for_manager,
+            ),
         )
     }
 }
@@ -193,6 +201,7 @@ impl<D: Data> NetworkSender for MockNetworkSender<D> {
 pub struct MockNetwork<D: Data> {
     pub add_reserved: Channel<(HashSet<MockMultiaddress>, Protocol)>,
     pub remove_reserved: Channel<(HashSet<MockPeerId>, Protocol)>,
+    pub disconnect: Channel<(MockPeerId, Protocol)>,
     pub send_message: Channel<(D, MockPeerId, Protocol)>,
     pub event_sinks: Arc<Mutex<Vec<mpsc::UnboundedSender<MockEvent>>>>,
     event_stream_taken_oneshot: Arc<Mutex<Option<oneshot::Sender<()>>>>,
@@ -259,6 +268,10 @@ impl<D: Data> Network for MockNetwork<D> {
     fn remove_reserved(&self, peers: HashSet<Self::PeerId>, protocol: Protocol) {
         self.remove_reserved.send((peers, protocol));
     }
+
+    fn disconnect(&self, peer: Self::PeerId, protocol: Protocol) {
+        self.disconnect.send((peer, protocol));
+    }
 }
 
 impl<D: Data> MockNetwork<D> {
@@ -266,6 +279,7 @@ impl<D: Data> MockNetwork<D> {
         MockNetwork {
             add_reserved: Channel::new(),
             remove_reserved: Channel::new(),
+            disconnect: Channel::new(),
             send_message: Channel::new(),
             event_sinks: Arc::new(Mutex::new(vec![])),
             event_stream_taken_oneshot: Arc::new(Mutex::new(Some(oneshot_sender))),
@@ -285,6 +299,7 @@ impl<D: Data> MockNetwork<D> {
         self.event_sinks.lock().clear();
         assert!(self.add_reserved.close().await.is_none());
         assert!(self.remove_reserved.close().await.is_none());
+        assert!(self.disconnect.close().await.is_none());
         assert!(self.send_message.close().await.is_none());
     }
 }
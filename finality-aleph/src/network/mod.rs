@@ -7,14 +7,21 @@ use codec::Codec;
 use sp_api::NumberFor;
 use sp_runtime::traits::Block;
 
+use crate::{
+    crypto::{AuthorityPen, AuthorityVerifier},
+    SessionId,
+};
+
 mod aleph;
 mod component;
 mod manager;
 #[cfg(test)]
 pub mod mock;
+mod reputation;
 mod service;
 mod session;
 mod split;
+mod tcp;
 
 pub use aleph::{NetworkData as AlephNetworkData, NetworkWrapper};
 pub use component::{
@@ -22,14 +29,21 @@ pub use component::{
     SimpleNetwork,
 };
 use manager::SessionCommand;
-pub use manager::{ConnectionIO, ConnectionManager, ConnectionManagerConfig};
+pub use manager::{
+    AddressRegistry, ConnectionIO, ConnectionManager, ConnectionManagerConfig, RegisteredAddresses,
+    ValidatorConnections, DEFAULT_COMPRESSION_THRESHOLD,
+};
+pub use reputation::{Misbehavior, RateLimit, ReputationConfig};
 pub use service::{Service, IO};
 pub use session::{Manager as SessionManager, ManagerError, Network as SessionNetwork};
 pub use split::{split, Split};
+pub use tcp::{TcpNetwork, ValidatorNetworkConfig, DEFAULT_RECONNECT_INTERVAL};
 
 #[cfg(test)]
 pub mod testing {
-    pub use super::manager::{Authentication, DiscoveryMessage, NetworkData, SessionHandler};
+    pub use super::manager::{
+        Authentication, Capabilities, DiscoveryMessage, NetworkData, SessionHandler,
+    };
 }
 
 /// Represents the id of an arbitrary node.
@@ -73,7 +87,7 @@ pub enum Event<M: Multiaddress> {
     Disconnected(M::PeerId),
     StreamOpened(M::PeerId, Protocol),
     StreamClosed(M::PeerId, Protocol),
-    Messages(Vec<Bytes>),
+    Messages(M::PeerId, Vec<(Protocol, Bytes)>),
 }
 
 #[async_trait]
@@ -104,6 +118,9 @@ pub trait Network: Clone + Send + Sync + 'static {
 
     /// Remove peers from one of the reserved sets.
     fn remove_reserved(&self, peers: HashSet<Self::PeerId>, protocol: Protocol);
+
+    /// Close the stream with the peer using the given protocol, if there is one.
+    fn disconnect(&self, peer: Self::PeerId, protocol: Protocol);
 }
 
 /// Abstraction for requesting own network addresses and PeerId.
@@ -113,6 +130,20 @@ pub trait NetworkIdentity {
 
     /// The external identity of this node, consisting of addresses and the PeerId.
     fn identity(&self) -> (Vec<Self::Multiaddress>, Self::PeerId);
+
+    /// Called when a session we take part in starts, with the verifier of its committee and, if we
+    /// are a validator in it, the pen we sign with. Networks identifying nodes by their authority
+    /// keys use this to know which key to identify with and whom to talk to.
+    fn session_started(
+        &self,
+        _session_id: SessionId,
+        _verifier: &AuthorityVerifier,
+        _pen: Option<&AuthorityPen>,
+    ) {
+    }
+
+    /// Called when a session we took part in ends.
+    fn session_ended(&self, _session_id: SessionId) {}
 }
 
 /// Abstraction for requesting justifications for finalized blocks and stale blocks.
@@ -143,11 +174,22 @@ pub enum DataCommand<PID: PeerId> {
     SendTo(PID, Protocol),
 }
 
-/// Commands for manipulating the reserved peers set.
+/// Commands for manipulating the reserved peers set and reporting peers that misbehave.
 #[derive(Debug, PartialEq, Eq)]
 pub enum ConnectionCommand<M: Multiaddress> {
     AddReserved(HashSet<M>),
     DelReserved(HashSet<M::PeerId>),
+    Report(M::PeerId, Misbehavior),
+}
+
+/// Changes of the connections with peers, as reported by the network service to the connection
+/// manager. Only streams of the Validator protocol count, as only they carry session data.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum PeerEvent<PID: PeerId> {
+    /// A Validator protocol stream with the peer was opened.
+    Connected(PID),
+    /// The Validator protocol stream with the peer was closed.
+    Disconnected(PID),
 }
 
 /// Returned when something went wrong when sending data using a DataNetwork.
@@ -0,0 +1,316 @@
+//! Rate limiting of incoming messages and scoring of misbehaving peers.
+//!
+//! Every peer gets a token bucket per protocol, so a single peer cannot flood us with messages.
+//! Messages exceeding the limit and other kinds of misbehavior earn the peer penalty points, which
+//! are slowly forgiven. Peers that accumulate too many of them should be disconnected, and are
+//! banned from reconnecting for a while.
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use crate::network::{PeerId, Protocol};
+
+/// Ways in which a peer can misbehave.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Hash)]
+pub enum Misbehavior {
+    /// Sent a message exceeding the rate limit of a protocol.
+    Flooding,
+    /// Sent a message that could not be decoded.
+    Undecodable,
+    /// Sent an authentication that is malformed or has an invalid signature.
+    InvalidAuthentication,
+    /// Sent a message for a session we know nothing about.
+    UnknownSession,
+    /// Sent session data that the session rejected as invalid.
+    InvalidData,
+}
+
+impl Misbehavior {
+    fn penalty(&self) -> u32 {
+        use Misbehavior::*;
+        match self {
+            Flooding => 1,
+            Undecodable => 100,
+            InvalidAuthentication => 50,
+            UnknownSession => 1,
+            InvalidData => 20,
+        }
+    }
+}
+
+/// The number of messages a peer can send using a single protocol.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RateLimit {
+    /// How many messages per second are allowed in the long run.
+    pub messages_per_second: u32,
+    /// How many messages can be sent at once.
+    pub burst: u32,
+}
+
+/// Configuration of the rate limits and peer scoring.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReputationConfig {
+    /// Limit for the Generic protocol, used only for discovery.
+    pub generic_limit: RateLimit,
+    /// Limit for the Validator protocol, carrying all the consensus traffic.
+    pub validator_limit: RateLimit,
+    /// Peers with more penalty points than this should be disconnected.
+    pub disconnect_threshold: u32,
+    /// How many penalty points are forgiven every second.
+    pub forgiveness_per_second: u32,
+    /// How long disconnected peers are kept from connecting again.
+    pub ban_duration: Duration,
+}
+
+impl Default for ReputationConfig {
+    fn default() -> Self {
+        ReputationConfig {
+            generic_limit: RateLimit {
+                messages_per_second: 50,
+                burst: 500,
+            },
+            validator_limit: RateLimit {
+                messages_per_second: 1000,
+                burst: 10_000,
+            },
+            disconnect_threshold: 1000,
+            forgiveness_per_second: 10,
+            ban_duration: Duration::from_secs(600),
+        }
+    }
+}
+
+impl ReputationConfig {
+    fn limit(&self, protocol: Protocol) -> RateLimit {
+        match protocol {
+            Protocol::Generic => self.generic_limit,
+            Protocol::Validator => self.validator_limit,
+        }
+    }
+}
+
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(limit: RateLimit, now: Instant) -> Self {
+        TokenBucket {
+            tokens: limit.burst as f64,
+            last_refill: now,
+        }
+    }
+
+    fn try_take(&mut self, limit: RateLimit, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * limit.messages_per_second as f64)
+            .min(limit.burst as f64);
+        self.last_refill = now;
+        match self.tokens >= 1.0 {
+            true => {
+                self.tokens -= 1.0;
+                true
+            }
+            false => false,
+        }
+    }
+}
+
+struct Penalty {
+    points: f64,
+    last_update: Instant,
+}
+
+/// Keeps track of the rate limits and penalty points of all the peers.
+pub struct Reputation<PID: PeerId> {
+    config: ReputationConfig,
+    buckets: HashMap<(PID, Protocol), TokenBucket>,
+    penalties: HashMap<PID, Penalty>,
+    bans: HashMap<PID, Instant>,
+}
+
+impl<PID: PeerId> Reputation<PID> {
+    pub fn new(config: ReputationConfig) -> Self {
+        Reputation {
+            config,
+            buckets: HashMap::new(),
+            penalties: HashMap::new(),
+            bans: HashMap::new(),
+        }
+    }
+
+    /// Checks whether a message the peer sent using the protocol fits within the rate limit.
+    pub fn allow_message(&mut self, peer: PID, protocol: Protocol, now: Instant) -> bool {
+        let limit = self.config.limit(protocol);
+        self.buckets
+            .entry((peer, protocol))
+            .or_insert_with(|| TokenBucket::new(limit, now))
+            .try_take(limit, now)
+    }
+
+    /// Penalizes the peer for the misbehavior and returns whether it should be disconnected. Such a
+    /// peer is banned for `ban_duration`, after which it starts anew with a clear record.
+    pub fn report(&mut self, peer: PID, misbehavior: Misbehavior, now: Instant) -> bool {
+        let forgiveness_per_second = self.config.forgiveness_per_second as f64;
+        let penalty = self.penalties.entry(peer).or_insert(Penalty {
+            points: 0.0,
+            last_update: now,
+        });
+        let forgiven = now
+            .saturating_duration_since(penalty.last_update)
+            .as_secs_f64()
+            * forgiveness_per_second;
+        penalty.points = (penalty.points - forgiven).max(0.0) + misbehavior.penalty() as f64;
+        penalty.last_update = now;
+        match penalty.points > self.config.disconnect_threshold as f64 {
+            true => {
+                self.penalties.remove(&peer);
+                self.bans.insert(peer, now + self.config.ban_duration);
+                true
+            }
+            false => false,
+        }
+    }
+
+    /// Whether the peer was disconnected for misbehaving and should not be connected to yet.
+    pub fn is_banned(&self, peer: &PID, now: Instant) -> bool {
+        self.bans.get(peer).map_or(false, |until| *until > now)
+    }
+
+    /// Forgets the rate limit state of the peer and protocol, once the stream closes.
+    pub fn on_stream_closed(&mut self, peer: &PID, protocol: Protocol) {
+        self.buckets.remove(&(*peer, protocol));
+    }
+
+    /// Forgets the expired bans and the penalties that have been completely forgiven by now.
+    pub fn prune(&mut self, now: Instant) {
+        self.bans.retain(|_, until| *until > now);
+        let threshold = self.config.disconnect_threshold as f64;
+        let forgiveness_per_second = self.config.forgiveness_per_second as f64;
+        // No penalty exceeds the threshold, so this is long enough to forgive all of it.
+        let forgiveness_time = match forgiveness_per_second > 0.0 {
+            true => Duration::from_secs_f64(threshold / forgiveness_per_second),
+            false => return,
+        };
+        self.penalties.retain(|_, penalty| {
+            now.saturating_duration_since(penalty.last_update) < forgiveness_time
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{Misbehavior, RateLimit, Reputation, ReputationConfig};
+    use crate::network::{mock::MockPeerId, Protocol};
+
+    fn config() -> ReputationConfig {
+        ReputationConfig {
+            generic_limit: RateLimit {
+                messages_per_second: 1,
+                burst: 2,
+            },
+            validator_limit: RateLimit {
+                messages_per_second: 10,
+                burst: 20,
+            },
+            disconnect_threshold: 200,
+            forgiveness_per_second: 10,
+            ban_duration: Duration::from_secs(60),
+        }
+    }
+
+    #[test]
+    fn allows_bursts_up_to_the_limit() {
+        let mut reputation = Reputation::new(config());
+        let peer = MockPeerId::random();
+        let now = Instant::now();
+        assert!(reputation.allow_message(peer, Protocol::Generic, now));
+        assert!(reputation.allow_message(peer, Protocol::Generic, now));
+        assert!(!reputation.allow_message(peer, Protocol::Generic, now));
+    }
+
+    #[test]
+    fn refills_over_time() {
+        let mut reputation = Reputation::new(config());
+        let peer = MockPeerId::random();
+        let now = Instant::now();
+        for _ in 0..2 {
+            assert!(reputation.allow_message(peer, Protocol::Generic, now));
+        }
+        assert!(!reputation.allow_message(peer, Protocol::Generic, now));
+        let later = now + Duration::from_secs(1);
+        assert!(reputation.allow_message(peer, Protocol::Generic, later));
+        assert!(!reputation.allow_message(peer, Protocol::Generic, later));
+    }
+
+    #[test]
+    fn limits_peers_and_protocols_separately() {
+        let mut reputation = Reputation::new(config());
+        let peer = MockPeerId::random();
+        let other_peer = MockPeerId::random();
+        let now = Instant::now();
+        for _ in 0..2 {
+            assert!(reputation.allow_message(peer, Protocol::Generic, now));
+        }
+        assert!(!reputation.allow_message(peer, Protocol::Generic, now));
+        assert!(reputation.allow_message(peer, Protocol::Validator, now));
+        assert!(reputation.allow_message(other_peer, Protocol::Generic, now));
+    }
+
+    #[test]
+    fn disconnects_persistent_misbehavers() {
+        let mut reputation = Reputation::new(config());
+        let peer = MockPeerId::random();
+        let now = Instant::now();
+        assert!(!reputation.report(peer, Misbehavior::Undecodable, now));
+        assert!(!reputation.report(peer, Misbehavior::Undecodable, now));
+        assert!(reputation.report(peer, Misbehavior::Undecodable, now));
+        // The record is cleared after disconnecting.
+        assert!(!reputation.report(peer, Misbehavior::Undecodable, now));
+    }
+
+    #[test]
+    fn bans_disconnected_peers_for_a_while() {
+        let mut reputation = Reputation::new(config());
+        let peer = MockPeerId::random();
+        let other_peer = MockPeerId::random();
+        let now = Instant::now();
+        for _ in 0..3 {
+            reputation.report(peer, Misbehavior::Undecodable, now);
+        }
+        reputation.report(other_peer, Misbehavior::Undecodable, now);
+        assert!(reputation.is_banned(&peer, now));
+        assert!(!reputation.is_banned(&other_peer, now));
+        let later = now + Duration::from_secs(60);
+        assert!(!reputation.is_banned(&peer, later));
+        reputation.prune(later);
+        assert!(reputation.bans.is_empty());
+    }
+
+    #[test]
+    fn forgives_occasional_misbehavior() {
+        let mut reputation = Reputation::new(config());
+        let peer = MockPeerId::random();
+        let mut now = Instant::now();
+        for _ in 0..10 {
+            assert!(!reputation.report(peer, Misbehavior::Undecodable, now));
+            now += Duration::from_secs(10);
+        }
+    }
+
+    #[test]
+    fn prunes_forgiven_penalties() {
+        let mut reputation = Reputation::new(config());
+        let peer = MockPeerId::random();
+        let now = Instant::now();
+        reputation.report(peer, Misbehavior::Undecodable, now);
+        reputation.prune(now + Duration::from_secs(1));
+        assert_eq!(reputation.penalties.len(), 1);
+        reputation.prune(now + Duration::from_secs(20));
+        assert!(reputation.penalties.is_empty());
+    }
+}
@@ -2,6 +2,7 @@ use std::{
     collections::{HashMap, HashSet},
     future::Future,
     iter,
+    time::Instant,
 };
 
 use futures::{channel::mpsc, StreamExt};
@@ -10,22 +11,27 @@ use sc_service::SpawnTaskHandle;
 use sc_utils::mpsc::{tracing_unbounded, TracingUnboundedReceiver, TracingUnboundedSender};
 
 use crate::network::{
-    ConnectionCommand, Data, DataCommand, Event, EventStream, Multiaddress, Network, NetworkSender,
-    Protocol,
+    reputation::Reputation, ConnectionCommand, Data, DataCommand, Event, EventStream, Misbehavior,
+    Multiaddress, Network, NetworkSender, PeerEvent, Protocol, ReputationConfig,
 };
 
 /// A service managing all the direct interaction with the underlying network implementation. It
 /// handles:
 /// 1. Incoming network events
-///   1. Messages are forwarded to the user.
-///   2. Various forms of (dis)connecting, keeping track of all currently connected nodes.
-/// 2. Commands from the network manager, modifying the reserved peer set.
+///   1. Messages are forwarded to the user, as long as their senders stay within the rate limits.
+///   2. Various forms of (dis)connecting, keeping track of all currently connected nodes and
+///      reporting the Validator protocol connections to the network manager.
+/// 2. Commands from the network manager, modifying the reserved peer set and reporting
+///    misbehaving peers, which get disconnected and banned for a while once their reputation
+///    gets too low. Banned peers are not added to the reserved sets and their streams are closed.
 /// 3. Outgoing messages, sending them out, using 1.2. to broadcast.
 pub struct Service<N: Network, D: Data> {
     network: N,
     messages_from_user: mpsc::UnboundedReceiver<(D, DataCommand<N::PeerId>)>,
-    messages_for_user: mpsc::UnboundedSender<D>,
+    messages_for_user: mpsc::UnboundedSender<(D, N::PeerId)>,
     commands_from_manager: mpsc::UnboundedReceiver<ConnectionCommand<N::Multiaddress>>,
+    peer_events_for_manager: mpsc::UnboundedSender<PeerEvent<N::PeerId>>,
+    reputation: Reputation<N::PeerId>,
     generic_connected_peers: HashSet<N::PeerId>,
     validator_connected_peers: HashSet<N::PeerId>,
     generic_peer_senders: HashMap<N::PeerId, TracingUnboundedSender<D>>,
@@ -36,20 +42,23 @@ pub struct Service<N: Network, D: Data> {
 /// Input/output channels for the network service.
 pub struct IO<D: Data, M: Multiaddress> {
     messages_from_user: mpsc::UnboundedReceiver<(D, DataCommand<M::PeerId>)>,
-    messages_for_user: mpsc::UnboundedSender<D>,
+    messages_for_user: mpsc::UnboundedSender<(D, M::PeerId)>,
     commands_from_manager: mpsc::UnboundedReceiver<ConnectionCommand<M>>,
+    peer_events_for_manager: mpsc::UnboundedSender<PeerEvent<M::PeerId>>,
 }
 
 impl<D: Data, M: Multiaddress> IO<D, M> {
     pub fn new(
         messages_from_user: mpsc::UnboundedReceiver<(D, DataCommand<M::PeerId>)>,
-        messages_for_user: mpsc::UnboundedSender<D>,
+        messages_for_user: mpsc::UnboundedSender<(D, M::PeerId)>,
         commands_from_manager: mpsc::UnboundedReceiver<ConnectionCommand<M>>,
+        peer_events_for_manager: mpsc::UnboundedSender<PeerEvent<M::PeerId>>,
     ) -> IO<D, M> {
         IO {
             messages_from_user,
             messages_for_user,
             commands_from_manager,
+            peer_events_for_manager,
         }
     }
 }
@@ -65,17 +74,21 @@ impl<N: Network, D: Data> Service<N, D> {
         network: N,
         spawn_handle: SpawnTaskHandle,
         io: IO<D, N::Multiaddress>,
+        reputation_config: ReputationConfig,
     ) -> Service<N, D> {
         let IO {
             messages_from_user,
             messages_for_user,
             commands_from_manager,
+            peer_events_for_manager,
         } = io;
         Service {
             network,
             messages_from_user,
             messages_for_user,
             commands_from_manager,
+            peer_events_for_manager,
+            reputation: Reputation::new(reputation_config),
             spawn_handle,
             generic_connected_peers: HashSet::new(),
             validator_connected_peers: HashSet::new(),
@@ -163,14 +176,24 @@ impl<N: Network, D: Data> Service<N, D> {
         }
     }
 
+    fn report_peer_event(&self, event: PeerEvent<N::PeerId>) {
+        if self.peer_events_for_manager.unbounded_send(event).is_err() {
+            debug!(target: "aleph-network", "Failed to report {:?} to the network manager.", event);
+        }
+    }
+
     fn handle_network_event(
         &mut self,
         event: Event<N::Multiaddress>,
-    ) -> Result<(), mpsc::TrySendError<D>> {
+    ) -> Result<(), mpsc::TrySendError<(D, N::PeerId)>> {
         use Event::*;
         match event {
             Connected(multiaddress) => {
                 trace!(target: "aleph-network", "Connected event from address {:?}", multiaddress);
+                if self.is_banned_address(&multiaddress) {
+                    trace!(target: "aleph-network", "Not adding banned address {:?} to reserved.", multiaddress);
+                    return Ok(());
+                }
                 self.network
                     .add_reserved(iter::once(multiaddress).collect(), Protocol::Generic);
             }
@@ -178,9 +201,15 @@ impl<N: Network, D: Data> Service<N, D> {
                 trace!(target: "aleph-network", "Disconnected event for peer {:?}", peer);
                 self.network
                     .remove_reserved(iter::once(peer).collect(), Protocol::Generic);
+                self.reputation.prune(Instant::now());
             }
             StreamOpened(peer, protocol) => {
                 trace!(target: "aleph-network", "StreamOpened event for peer {:?} and the protocol {:?}.", peer, protocol);
+                if self.reputation.is_banned(&peer, Instant::now()) {
+                    debug!(target: "aleph-network", "Closing {:?} stream opened by banned peer {:?}.", protocol, peer);
+                    self.network.disconnect(peer, protocol);
+                    return Ok(());
+                }
                 let rx = match &protocol {
                     Protocol::Generic => {
                         let (tx, rx) = tracing_unbounded("mpsc_notification_stream_generic");
@@ -192,6 +221,7 @@ impl<N: Network, D: Data> Service<N, D> {
                         let (tx, rx) = tracing_unbounded("mpsc_notification_stream_validator");
                         self.validator_connected_peers.insert(peer);
                         self.validator_peer_senders.insert(peer, tx);
+                        self.report_peer_event(PeerEvent::Connected(peer));
                         rx
                     }
                 };
@@ -203,6 +233,7 @@ impl<N: Network, D: Data> Service<N, D> {
             }
             StreamClosed(peer, protocol) => {
                 trace!(target: "aleph-network", "StreamClosed event for peer {:?} and protocol {:?}", peer, protocol);
+                self.reputation.on_stream_closed(&peer, protocol);
                 match protocol {
                     Protocol::Generic => {
                         self.generic_connected_peers.remove(&peer);
@@ -211,14 +242,30 @@ impl<N: Network, D: Data> Service<N, D> {
                     Protocol::Validator => {
                         self.validator_connected_peers.remove(&peer);
                         self.validator_peer_senders.remove(&peer);
+                        self.report_peer_event(PeerEvent::Disconnected(peer));
                     }
                 }
             }
-            Messages(messages) => {
-                for data in messages.into_iter() {
+            Messages(peer, messages) => {
+                for (protocol, data) in messages.into_iter() {
+                    if self.reputation.is_banned(&peer, Instant::now()) {
+                        trace!(target: "aleph-network", "Dropping message from banned peer {:?}.", peer);
+                        continue;
+                    }
+                    if !self
+                        .reputation
+                        .allow_message(peer, protocol, Instant::now())
+                    {
+                        trace!(target: "aleph-network", "Dropping message from peer {:?} exceeding the rate limit of protocol {:?}.", peer, protocol);
+                        self.on_misbehavior(peer, Misbehavior::Flooding);
+                        continue;
+                    }
                     match D::decode(&mut &data[..]) {
-                        Ok(message) => self.messages_for_user.unbounded_send(message)?,
-                        Err(e) => warn!(target: "aleph-network", "Error decoding message: {}", e),
+                        Ok(message) => self.messages_for_user.unbounded_send((message, peer))?,
+                        Err(e) => {
+                            warn!(target: "aleph-network", "Error decoding message from peer {:?}: {}", peer, e);
+                            self.on_misbehavior(peer, Misbehavior::Undecodable);
+                        }
                     }
                 }
             }
@@ -226,13 +273,37 @@ impl<N: Network, D: Data> Service<N, D> {
         Ok(())
     }
 
-    fn on_manager_command(&self, command: ConnectionCommand<N::Multiaddress>) {
+    fn on_misbehavior(&mut self, peer: N::PeerId, misbehavior: Misbehavior) {
+        if self.reputation.report(peer, misbehavior, Instant::now()) {
+            warn!(target: "aleph-network", "Disconnecting and banning peer {:?} for persistent misbehavior, most recently {:?}.", peer, misbehavior);
+            let peers: HashSet<_> = iter::once(peer).collect();
+            for protocol in [Protocol::Validator, Protocol::Generic] {
+                self.network.remove_reserved(peers.clone(), protocol);
+                self.network.disconnect(peer, protocol);
+            }
+        }
+    }
+
+    fn is_banned_address(&self, address: &N::Multiaddress) -> bool {
+        address.get_peer_id().map_or(false, |peer| {
+            self.reputation.is_banned(&peer, Instant::now())
+        })
+    }
+
+    fn on_manager_command(&mut self, command: ConnectionCommand<N::Multiaddress>) {
         use ConnectionCommand::*;
         match command {
             AddReserved(addresses) => {
-                self.network.add_reserved(addresses, Protocol::Validator);
+                let addresses: HashSet<_> = addresses
+                    .into_iter()
+                    .filter(|address| !self.is_banned_address(address))
+                    .collect();
+                if !addresses.is_empty() {
+                    self.network.add_reserved(addresses, Protocol::Validator);
+                }
             }
             DelReserved(peers) => self.network.remove_reserved(peers, Protocol::Validator),
+            Report(peer, misbehavior) => self.on_misbehavior(peer, misbehavior),
         }
     }
 
@@ -283,7 +354,7 @@ impl<N: Network, D: Data> Service<N, D> {
 
 #[cfg(test)]
 mod tests {
-    use std::{collections::HashSet, iter, iter::FromIterator};
+    use std::{collections::HashSet, iter, iter::FromIterator, time::Duration};
 
     use codec::Encode;
     use futures::{channel::oneshot, StreamExt};
@@ -296,7 +367,7 @@ mod tests {
             MockData, MockEvent, MockIO, MockMultiaddress, MockNetwork, MockNetworkIdentity,
             MockPeerId, MockSenderError,
         },
-        NetworkIdentity, Protocol,
+        Misbehavior, NetworkIdentity, PeerEvent, Protocol, RateLimit, ReputationConfig,
     };
 
     pub struct TestData {
@@ -310,6 +381,10 @@ mod tests {
 
     impl TestData {
         async fn prepare() -> Self {
+            Self::prepare_with_reputation_config(ReputationConfig::default()).await
+        }
+
+        async fn prepare_with_reputation_config(reputation_config: ReputationConfig) -> Self {
             let task_manager = TaskManager::new(Handle::current(), None).unwrap();
 
             // Prepare communication with service
@@ -317,7 +392,12 @@ mod tests {
             // Prepare service
             let (event_stream_oneshot_tx, event_stream_oneshot_rx) = oneshot::channel();
             let network = MockNetwork::new(event_stream_oneshot_tx);
-            let service = Service::new(network.clone(), task_manager.spawn_handle(), io);
+            let service = Service::new(
+                network.clone(),
+                task_manager.spawn_handle(),
+                io,
+                reputation_config,
+            );
             let (exit_tx, exit_rx) = oneshot::channel();
             let task_handle = async move {
                 tokio::select! {
@@ -345,6 +425,27 @@ mod tests {
             self.network.close_channels().await;
         }
 
+        async fn expect_disconnected(&mut self, peer_id: MockPeerId) {
+            for protocol in [Protocol::Validator, Protocol::Generic] {
+                assert_eq!(
+                    self.network
+                        .remove_reserved
+                        .next()
+                        .await
+                        .expect("Should remove the peer from reserved"),
+                    (iter::once(peer_id).collect(), protocol)
+                );
+                assert_eq!(
+                    self.network
+                        .disconnect
+                        .next()
+                        .await
+                        .expect("Should disconnect the peer"),
+                    (peer_id, protocol)
+                );
+            }
+        }
+
         // We do this only to make sure that NotificationStreamOpened/NotificationStreamClosed events are handled
         async fn wait_for_events_handled(&mut self) {
             let address = MockMultiaddress::random_with_id(MockPeerId::random());
@@ -519,6 +620,35 @@ mod tests {
         test_data.cleanup().await
     }
 
+    #[tokio::test]
+    async fn test_reports_validator_connections_to_manager() {
+        let mut test_data = TestData::prepare().await;
+
+        let generic_peer_id = MockPeerId::random();
+        let peer_id = MockPeerId::random();
+
+        test_data
+            .network
+            .emit_event(MockEvent::StreamOpened(generic_peer_id, Protocol::Generic));
+        test_data
+            .network
+            .emit_event(MockEvent::StreamOpened(peer_id, Protocol::Validator));
+        test_data
+            .network
+            .emit_event(MockEvent::StreamClosed(peer_id, Protocol::Validator));
+
+        assert_eq!(
+            test_data.mock_io.peer_events_from_network.next().await,
+            Some(PeerEvent::Connected(peer_id))
+        );
+        assert_eq!(
+            test_data.mock_io.peer_events_from_network.next().await,
+            Some(PeerEvent::Disconnected(peer_id))
+        );
+
+        test_data.cleanup().await
+    }
+
     #[tokio::test]
     async fn test_validator_data_command_send_to() {
         let mut test_data = TestData::prepare().await;
@@ -1074,10 +1204,12 @@ mod tests {
         let mut test_data = TestData::prepare().await;
 
         let message: Vec<u8> = vec![1, 2, 3];
+        let peer_id = MockPeerId::random();
 
-        test_data
-            .network
-            .emit_event(MockEvent::Messages(vec![Vec::encode(&message).into()]));
+        test_data.network.emit_event(MockEvent::Messages(
+            peer_id,
+            vec![(Protocol::Validator, Vec::encode(&message).into())],
+        ));
 
         assert_eq!(
             test_data
@@ -1086,8 +1218,190 @@ mod tests {
                 .next()
                 .await
                 .expect("Should receive message"),
-            message
+            (message, peer_id)
+        );
+
+        test_data.cleanup().await
+    }
+
+    fn strict_reputation_config() -> ReputationConfig {
+        ReputationConfig {
+            generic_limit: RateLimit {
+                messages_per_second: 1,
+                burst: 5,
+            },
+            validator_limit: RateLimit {
+                messages_per_second: 1,
+                burst: 5,
+            },
+            disconnect_threshold: 10,
+            forgiveness_per_second: 1,
+            ban_duration: Duration::from_secs(600),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_drops_messages_exceeding_rate_limit() {
+        let mut test_data =
+            TestData::prepare_with_reputation_config(strict_reputation_config()).await;
+
+        let peer_id = MockPeerId::random();
+        let messages: Vec<_> = (0..10u8)
+            .map(|i| (Protocol::Validator, Vec::encode(&vec![i]).into()))
+            .collect();
+
+        test_data
+            .network
+            .emit_event(MockEvent::Messages(peer_id, messages));
+        test_data.wait_for_events_handled().await;
+
+        for i in 0..5u8 {
+            assert_eq!(
+                test_data.mock_io.messages_from_user.try_next().unwrap(),
+                Some((vec![i], peer_id))
+            );
+        }
+        assert!(test_data.mock_io.messages_from_user.try_next().is_err());
+        assert!(test_data.network.remove_reserved.try_next().await.is_none());
+
+        test_data.cleanup().await
+    }
+
+    #[tokio::test]
+    async fn test_disconnects_flooding_peer() {
+        let mut test_data =
+            TestData::prepare_with_reputation_config(strict_reputation_config()).await;
+
+        let peer_id = MockPeerId::random();
+        let messages: Vec<_> = (0..20u8)
+            .map(|i| (Protocol::Generic, Vec::encode(&vec![i]).into()))
+            .collect();
+
+        test_data
+            .network
+            .emit_event(MockEvent::Messages(peer_id, messages));
+
+        test_data.expect_disconnected(peer_id).await;
+        for i in 0..5u8 {
+            assert_eq!(
+                test_data.mock_io.messages_from_user.try_next().unwrap(),
+                Some((vec![i], peer_id))
+            );
+        }
+
+        test_data.cleanup().await
+    }
+
+    #[tokio::test]
+    async fn test_disconnects_peer_sending_undecodable_messages() {
+        let mut test_data =
+            TestData::prepare_with_reputation_config(ReputationConfig::default()).await;
+
+        let peer_id = MockPeerId::random();
+        // A length prefix promising more bytes than there are.
+        let messages: Vec<_> = (0..20)
+            .map(|_| (Protocol::Validator, vec![255u8].into()))
+            .collect();
+
+        test_data
+            .network
+            .emit_event(MockEvent::Messages(peer_id, messages));
+
+        test_data.expect_disconnected(peer_id).await;
+
+        test_data.cleanup().await
+    }
+
+    #[tokio::test]
+    async fn test_command_report_disconnects_misbehaving_peer() {
+        let mut test_data =
+            TestData::prepare_with_reputation_config(strict_reputation_config()).await;
+
+        let peer_id = MockPeerId::random();
+        let other_peer_id = MockPeerId::random();
+
+        test_data
+            .mock_io
+            .commands_for_manager
+            .unbounded_send(ConnectionCommand::Report(
+                other_peer_id,
+                Misbehavior::UnknownSession,
+            ))
+            .unwrap();
+        for _ in 0..11 {
+            test_data
+                .mock_io
+                .commands_for_manager
+                .unbounded_send(ConnectionCommand::Report(
+                    peer_id,
+                    Misbehavior::UnknownSession,
+                ))
+                .unwrap();
+        }
+
+        test_data.expect_disconnected(peer_id).await;
+
+        test_data.cleanup().await
+    }
+
+    #[tokio::test]
+    async fn test_does_not_reconnect_banned_peer() {
+        let mut test_data =
+            TestData::prepare_with_reputation_config(strict_reputation_config()).await;
+
+        let peer_id = MockPeerId::random();
+        for _ in 0..11 {
+            test_data
+                .mock_io
+                .commands_for_manager
+                .unbounded_send(ConnectionCommand::Report(
+                    peer_id,
+                    Misbehavior::UnknownSession,
+                ))
+                .unwrap();
+        }
+        test_data.expect_disconnected(peer_id).await;
+
+        let banned_address = MockMultiaddress::random_with_id(peer_id);
+        let (addresses, _) = MockNetworkIdentity::new().identity();
+        test_data
+            .mock_io
+            .commands_for_manager
+            .unbounded_send(ConnectionCommand::AddReserved(
+                addresses
+                    .iter()
+                    .cloned()
+                    .chain(iter::once(banned_address))
+                    .collect(),
+            ))
+            .unwrap();
+        assert_eq!(
+            test_data
+                .network
+                .add_reserved
+                .next()
+                .await
+                .expect("Should receive message"),
+            (addresses.into_iter().collect(), Protocol::Validator)
+        );
+
+        test_data
+            .network
+            .emit_event(MockEvent::StreamOpened(peer_id, Protocol::Validator));
+        assert_eq!(
+            test_data
+                .network
+                .disconnect
+                .next()
+                .await
+                .expect("Should disconnect the banned peer"),
+            (peer_id, Protocol::Validator)
         );
+        assert!(test_data
+            .mock_io
+            .peer_events_from_network
+            .try_next()
+            .is_err());
 
         test_data.cleanup().await
     }
@@ -15,6 +15,7 @@ use crate::{
 pub struct Sender<D: Data> {
     session_id: SessionId,
     messages_for_network: mpsc::UnboundedSender<(D, SessionId, Recipient)>,
+    rejected_for_network: mpsc::UnboundedSender<(D, SessionId)>,
 }
 
 impl<D: Data> SenderComponent<D> for Sender<D> {
@@ -23,6 +24,13 @@ impl<D: Data> SenderComponent<D> for Sender<D> {
             .unbounded_send((data, self.session_id, recipient))
             .map_err(|_| SendError::SendFailed)
     }
+
+    fn report_rejected(&self, data: D) {
+        // Reporting is best effort, the network might be shutting down.
+        let _ = self
+            .rejected_for_network
+            .unbounded_send((data, self.session_id));
+    }
 }
 
 /// Sends and receives data within a single session.
@@ -46,6 +54,7 @@ impl<D: Data> ComponentNetwork<D> for Network<D> {
 pub struct Manager<D: Data> {
     commands_for_service: mpsc::UnboundedSender<SessionCommand<D>>,
     messages_for_service: mpsc::UnboundedSender<(D, SessionId, Recipient)>,
+    rejected_for_service: mpsc::UnboundedSender<(D, SessionId)>,
 }
 
 /// What went wrong during a session management operation.
@@ -60,10 +69,12 @@ impl<D: Data> Manager<D> {
     pub fn new(
         commands_for_service: mpsc::UnboundedSender<SessionCommand<D>>,
         messages_for_service: mpsc::UnboundedSender<(D, SessionId, Recipient)>,
+        rejected_for_service: mpsc::UnboundedSender<(D, SessionId)>,
     ) -> Self {
         Manager {
             commands_for_service,
             messages_for_service,
+            rejected_for_service,
         }
     }
 
@@ -103,10 +114,12 @@ impl<D: Data> Manager<D> {
             .await
             .map_err(|_| ManagerError::NetworkReceiveFailed)?;
         let messages_for_network = self.messages_for_service.clone();
+        let rejected_for_network = self.rejected_for_service.clone();
         Ok(Network {
             sender: Sender {
                 session_id,
                 messages_for_network,
+                rejected_for_network,
             },
             receiver: Arc::new(Mutex::new(data_from_network)),
         })
@@ -30,6 +30,10 @@ impl<LeftData: Data, RightData: Data, S: SenderComponent<Split<LeftData, RightDa
     fn send(&self, data: LeftData, recipient: Recipient) -> Result<(), SendError> {
         self.sender.send(Split::Left(data), recipient)
     }
+
+    fn report_rejected(&self, data: LeftData) {
+        self.sender.report_rejected(Split::Left(data))
+    }
 }
 
 #[derive(Clone)]
@@ -45,6 +49,10 @@ impl<LeftData: Data, RightData: Data, S: SenderComponent<Split<LeftData, RightDa
     fn send(&self, data: RightData, recipient: Recipient) -> Result<(), SendError> {
         self.sender.send(Split::Right(data), recipient)
     }
+
+    fn report_rejected(&self, data: RightData) {
+        self.sender.report_rejected(Split::Right(data))
+    }
 }
 
 struct LeftReceiver<
@@ -0,0 +1,149 @@
+//! Encryption and integrity protection of the frames sent over an authenticated connection.
+//!
+//! Both ends generate an ephemeral x25519 key for every connection and send its public part in the
+//! handshake, which signs it together with the rest of the handshake. Each direction of the
+//! connection uses its own ChaCha20-Poly1305 key derived from the shared secret, and frames are
+//! numbered, so they cannot be read, modified, dropped, reordered or replayed unnoticed.
+use std::fmt;
+
+use chacha20poly1305::{
+    aead::{Aead, NewAead},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use codec::Encode;
+use sp_core::hashing::blake2_256;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+const KEY_CONTEXT: &[u8] = b"aleph-validator-network-key";
+
+/// The number of bytes encryption adds to every frame.
+pub const TAG_SIZE: usize = 16;
+
+#[derive(Debug)]
+pub struct DecryptionError;
+
+impl fmt::Display for DecryptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "frame failed to decrypt")
+    }
+}
+
+/// The key pair used to agree on the keys of a single connection.
+pub struct EphemeralKey {
+    secret: StaticSecret,
+    public: [u8; 32],
+}
+
+impl EphemeralKey {
+    pub fn generate() -> Self {
+        let secret = StaticSecret::from(rand::random::<[u8; 32]>());
+        let public = PublicKey::from(&secret).to_bytes();
+        EphemeralKey { secret, public }
+    }
+
+    pub fn public(&self) -> [u8; 32] {
+        self.public
+    }
+
+    /// Derives the ciphers of both directions of the connection with the owner of the other
+    /// ephemeral key, the one for sending and the one for receiving.
+    pub fn agree(self, peer_public: [u8; 32]) -> (Encryptor, Decryptor) {
+        let shared = self.secret.diffie_hellman(&PublicKey::from(peer_public));
+        let key = |sender: &[u8; 32], receiver: &[u8; 32]| {
+            blake2_256(&(KEY_CONTEXT, shared.as_bytes(), sender, receiver).encode())
+        };
+        (
+            Encryptor(Cipher::new(key(&self.public, &peer_public))),
+            Decryptor(Cipher::new(key(&peer_public, &self.public))),
+        )
+    }
+}
+
+struct Cipher {
+    cipher: ChaCha20Poly1305,
+    counter: u64,
+}
+
+impl Cipher {
+    fn new(key: [u8; 32]) -> Self {
+        Cipher {
+            cipher: ChaCha20Poly1305::new(Key::from_slice(&key)),
+            counter: 0,
+        }
+    }
+
+    /// The nonce of the next frame, every frame uses a new one.
+    fn next_nonce(&mut self) -> [u8; 12] {
+        let mut nonce = [0u8; 12];
+        nonce[4..].copy_from_slice(&self.counter.to_le_bytes());
+        self.counter += 1;
+        nonce
+    }
+}
+
+/// Encrypts the frames we send.
+pub struct Encryptor(Cipher);
+
+impl Encryptor {
+    pub fn encrypt(&mut self, frame: &[u8]) -> Vec<u8> {
+        let nonce = self.0.next_nonce();
+        self.0
+            .cipher
+            .encrypt(Nonce::from_slice(&nonce), frame)
+            .expect("frames are short enough to encrypt")
+    }
+}
+
+/// Decrypts the frames we receive, in the order they were sent.
+pub struct Decryptor(Cipher);
+
+impl Decryptor {
+    pub fn decrypt(&mut self, encrypted: &[u8]) -> Result<Vec<u8>, DecryptionError> {
+        let nonce = self.0.next_nonce();
+        self.0
+            .cipher
+            .decrypt(Nonce::from_slice(&nonce), encrypted)
+            .map_err(|_| DecryptionError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EphemeralKey, TAG_SIZE};
+
+    #[test]
+    fn decrypts_frames_in_order() {
+        let (alice, bob) = (EphemeralKey::generate(), EphemeralKey::generate());
+        let (alice_public, bob_public) = (alice.public(), bob.public());
+        let (mut alice_encryptor, mut alice_decryptor) = alice.agree(bob_public);
+        let (mut bob_encryptor, mut bob_decryptor) = bob.agree(alice_public);
+
+        for frame in [&b"first"[..], b"", b"third"] {
+            let encrypted = alice_encryptor.encrypt(frame);
+            assert_eq!(encrypted.len(), frame.len() + TAG_SIZE);
+            assert_eq!(bob_decryptor.decrypt(&encrypted).unwrap(), frame);
+        }
+        let encrypted = bob_encryptor.encrypt(b"reply");
+        assert_eq!(alice_decryptor.decrypt(&encrypted).unwrap(), b"reply");
+    }
+
+    #[test]
+    fn rejects_modified_replayed_and_reflected_frames() {
+        let (alice, bob) = (EphemeralKey::generate(), EphemeralKey::generate());
+        let (alice_public, bob_public) = (alice.public(), bob.public());
+        let (mut alice_encryptor, mut alice_decryptor) = alice.agree(bob_public);
+        let (_, mut bob_decryptor) = bob.agree(alice_public);
+
+        let mut modified = alice_encryptor.encrypt(b"hello");
+        modified[0] ^= 1;
+        assert!(bob_decryptor.decrypt(&modified).is_err());
+
+        let encrypted = alice_encryptor.encrypt(b"hello");
+        assert!(bob_decryptor.decrypt(&encrypted).is_ok());
+        assert!(bob_decryptor.decrypt(&encrypted).is_err());
+        // Frames sent to a peer cannot be passed off as coming from it.
+        assert!(alice_decryptor
+            .decrypt(&alice_encryptor.encrypt(b"hello"))
+            .is_err());
+    }
+}
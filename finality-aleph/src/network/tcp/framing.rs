@@ -0,0 +1,97 @@
+//! Length-prefixed frames sent over a TCP stream.
+//!
+//! Every frame consists of its length, encoded as four little endian bytes, followed by that many
+//! bytes of content.
+use std::fmt;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Frames longer than this are rejected, so a peer cannot make us allocate arbitrary amounts of
+/// memory. Large enough for any message accepted by the p2p network, plus some overhead.
+pub const MAX_FRAME_SIZE: usize = 1024 * 1024 + 1024;
+
+#[derive(Debug)]
+pub enum FrameError {
+    Io(std::io::Error),
+    TooLarge(usize),
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameError::Io(e) => write!(f, "i/o error: {}", e),
+            FrameError::TooLarge(len) => {
+                write!(
+                    f,
+                    "frame of {} bytes exceeds the limit of {}",
+                    len, MAX_FRAME_SIZE
+                )
+            }
+        }
+    }
+}
+
+impl From<std::io::Error> for FrameError {
+    fn from(e: std::io::Error) -> Self {
+        FrameError::Io(e)
+    }
+}
+
+/// Writes the content as a single frame.
+pub async fn write_frame<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    content: &[u8],
+) -> Result<(), FrameError> {
+    if content.len() > MAX_FRAME_SIZE {
+        return Err(FrameError::TooLarge(content.len()));
+    }
+    let mut frame = Vec::with_capacity(4 + content.len());
+    frame.extend_from_slice(&(content.len() as u32).to_le_bytes());
+    frame.extend_from_slice(content);
+    writer.write_all(&frame).await?;
+    Ok(())
+}
+
+/// Reads the content of a single frame.
+pub async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Vec<u8>, FrameError> {
+    let mut len = [0u8; 4];
+    reader.read_exact(&mut len).await?;
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_FRAME_SIZE {
+        return Err(FrameError::TooLarge(len));
+    }
+    let mut content = vec![0u8; len];
+    reader.read_exact(&mut content).await?;
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{duplex, AsyncWriteExt};
+
+    use super::{read_frame, write_frame, FrameError, MAX_FRAME_SIZE};
+
+    #[tokio::test]
+    async fn reads_written_frames() {
+        let (mut writer, mut reader) = duplex(4096);
+        write_frame(&mut writer, b"first").await.unwrap();
+        write_frame(&mut writer, b"").await.unwrap();
+        write_frame(&mut writer, b"third").await.unwrap();
+        assert_eq!(read_frame(&mut reader).await.unwrap(), b"first");
+        assert!(read_frame(&mut reader).await.unwrap().is_empty());
+        assert_eq!(read_frame(&mut reader).await.unwrap(), b"third");
+    }
+
+    #[tokio::test]
+    async fn rejects_too_large_frames() {
+        let (mut writer, mut reader) = duplex(4096);
+        writer
+            .write_all(&((MAX_FRAME_SIZE + 1) as u32).to_le_bytes())
+            .await
+            .unwrap();
+        assert!(matches!(
+            read_frame(&mut reader).await,
+            Err(FrameError::TooLarge(_))
+        ));
+    }
+}
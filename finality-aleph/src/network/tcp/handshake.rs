@@ -0,0 +1,317 @@
+//! Mutual authentication of the two ends of a fresh connection, and agreement on the keys
+//! encrypting it.
+//!
+//! Both sides send their peer id, i.e. their authority key, together with a random challenge and
+//! an ephemeral key, and then sign the challenge of the other side. The signed payload also
+//! contains both peer ids and both ephemeral keys, so a signature made for one connection cannot
+//! be replayed in another, and nobody in between can replace the keys with their own.
+use std::{fmt, time::Duration};
+
+use aleph_primitives::{AuthorityId, AuthoritySignature};
+use codec::{Decode, Encode};
+use sp_runtime::RuntimeAppPublic;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    time::timeout,
+};
+
+use crate::{
+    crypto::{AuthorityPen, Signature},
+    network::tcp::{
+        encryption::{Decryptor, Encryptor, EphemeralKey},
+        framing::{read_frame, write_frame, FrameError},
+        AuthorityPeerId,
+    },
+};
+
+const HANDSHAKE_CONTEXT: &[u8] = b"aleph-validator-network-handshake";
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Encode, Decode)]
+struct Hello {
+    peer_id: AuthorityPeerId,
+    challenge: [u8; 32],
+    ephemeral_key: [u8; 32],
+}
+
+#[derive(Debug)]
+pub enum HandshakeError {
+    Frame(FrameError),
+    Decode(codec::Error),
+    SelfConnection,
+    UnexpectedPeer(AuthorityPeerId),
+    NotAllowed(AuthorityPeerId),
+    BadSignature(AuthorityPeerId),
+    TimedOut,
+}
+
+impl fmt::Display for HandshakeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use HandshakeError::*;
+        match self {
+            Frame(e) => write!(f, "{}", e),
+            Decode(e) => write!(f, "malformed handshake message: {}", e),
+            SelfConnection => write!(f, "connected to ourselves"),
+            UnexpectedPeer(peer_id) => write!(f, "connected to unexpected peer {:?}", peer_id),
+            NotAllowed(peer_id) => {
+                write!(f, "peer {:?} is not a member of a known committee", peer_id)
+            }
+            BadSignature(peer_id) => write!(f, "bad signature of peer {:?}", peer_id),
+            TimedOut => write!(f, "handshake timed out"),
+        }
+    }
+}
+
+impl From<FrameError> for HandshakeError {
+    fn from(e: FrameError) -> Self {
+        HandshakeError::Frame(e)
+    }
+}
+
+impl From<codec::Error> for HandshakeError {
+    fn from(e: codec::Error) -> Self {
+        HandshakeError::Decode(e)
+    }
+}
+
+/// The payload the signer signs to prove its identity to the verifier. The ephemeral keys are
+/// given in the same order, the one of the signer first.
+fn signing_payload(
+    challenge: &[u8; 32],
+    signer: &AuthorityPeerId,
+    verifier: &AuthorityPeerId,
+    ephemeral_keys: (&[u8; 32], &[u8; 32]),
+) -> Vec<u8> {
+    (
+        HANDSHAKE_CONTEXT,
+        challenge,
+        signer,
+        verifier,
+        ephemeral_keys,
+    )
+        .encode()
+}
+
+async fn run_handshake<R, W, A>(
+    reader: &mut R,
+    writer: &mut W,
+    pen: &AuthorityPen,
+    expected_peer_id: Option<AuthorityPeerId>,
+    is_allowed: A,
+) -> Result<(AuthorityPeerId, Encryptor, Decryptor), HandshakeError>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+    A: Fn(&AuthorityPeerId) -> bool,
+{
+    let own_peer_id = AuthorityPeerId::from(pen.authority_id());
+    let challenge: [u8; 32] = rand::random();
+    let ephemeral_key = EphemeralKey::generate();
+    let own_ephemeral_key = ephemeral_key.public();
+    let hello = Hello {
+        peer_id: own_peer_id,
+        challenge,
+        ephemeral_key: own_ephemeral_key,
+    };
+    write_frame(writer, &hello.encode()).await?;
+    let Hello {
+        peer_id,
+        challenge: peer_challenge,
+        ephemeral_key: peer_ephemeral_key,
+    } = Hello::decode(&mut &read_frame(reader).await?[..])?;
+    if peer_id == own_peer_id {
+        return Err(HandshakeError::SelfConnection);
+    }
+    if let Some(expected_peer_id) = expected_peer_id {
+        if peer_id != expected_peer_id {
+            return Err(HandshakeError::UnexpectedPeer(peer_id));
+        }
+    }
+    if !is_allowed(&peer_id) {
+        return Err(HandshakeError::NotAllowed(peer_id));
+    }
+    let signature = pen
+        .sign(&signing_payload(
+            &peer_challenge,
+            &own_peer_id,
+            &peer_id,
+            (&own_ephemeral_key, &peer_ephemeral_key),
+        ))
+        .await;
+    write_frame(writer, &signature.encode()).await?;
+    let peer_signature = Signature::decode(&mut &read_frame(reader).await?[..])?;
+    if !AuthorityId::from(peer_id).verify(
+        &signing_payload(
+            &challenge,
+            &peer_id,
+            &own_peer_id,
+            (&peer_ephemeral_key, &own_ephemeral_key),
+        ),
+        &AuthoritySignature::from(peer_signature),
+    ) {
+        return Err(HandshakeError::BadSignature(peer_id));
+    }
+    let (encryptor, decryptor) = ephemeral_key.agree(peer_ephemeral_key);
+    Ok((peer_id, encryptor, decryptor))
+}
+
+/// Authenticates both ends of the connection, returning the peer id of the other one, together
+/// with the ciphers for sending to it and receiving from it. When we dialed a specific peer its id
+/// should be passed as the expected one, so that we do not talk to whoever happens to listen at
+/// its address. Peers that are not allowed are refused before we sign anything for them.
+pub async fn handshake<R, W, A>(
+    reader: &mut R,
+    writer: &mut W,
+    pen: &AuthorityPen,
+    expected_peer_id: Option<AuthorityPeerId>,
+    is_allowed: A,
+) -> Result<(AuthorityPeerId, Encryptor, Decryptor), HandshakeError>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+    A: Fn(&AuthorityPeerId) -> bool,
+{
+    timeout(
+        HANDSHAKE_TIMEOUT,
+        run_handshake(reader, writer, pen, expected_peer_id, is_allowed),
+    )
+    .await
+    .map_err(|_| HandshakeError::TimedOut)?
+}
+
+#[cfg(test)]
+mod tests {
+    use codec::{Decode, Encode};
+    use tokio::io::{duplex, split};
+
+    use super::{handshake, signing_payload, HandshakeError, Hello};
+    use crate::network::{
+        mock::crypto_basics,
+        tcp::{
+            framing::{read_frame, write_frame},
+            AuthorityPeerId,
+        },
+    };
+
+    #[tokio::test]
+    async fn authenticates_both_sides() {
+        let (validator_data, _) = crypto_basics(2).await;
+        let (dialer_pen, listener_pen) = (&validator_data[0].1, &validator_data[1].1);
+        let dialer_id = AuthorityPeerId::from(dialer_pen.authority_id());
+        let listener_id = AuthorityPeerId::from(listener_pen.authority_id());
+        let (dialer_stream, listener_stream) = duplex(4096);
+        let (mut dialer_reader, mut dialer_writer) = split(dialer_stream);
+        let (mut listener_reader, mut listener_writer) = split(listener_stream);
+        let (dialer_result, listener_result) = tokio::join!(
+            handshake(
+                &mut dialer_reader,
+                &mut dialer_writer,
+                dialer_pen,
+                Some(listener_id),
+                |_| true,
+            ),
+            handshake(
+                &mut listener_reader,
+                &mut listener_writer,
+                listener_pen,
+                None,
+                |_| true,
+            ),
+        );
+        let (peer_id, mut dialer_encryptor, _) = dialer_result.unwrap();
+        assert_eq!(peer_id, listener_id);
+        let (peer_id, _, mut listener_decryptor) = listener_result.unwrap();
+        assert_eq!(peer_id, dialer_id);
+        // Both sides agreed on the keys.
+        let encrypted = dialer_encryptor.encrypt(b"hello");
+        assert_eq!(listener_decryptor.decrypt(&encrypted).unwrap(), b"hello");
+    }
+
+    #[tokio::test]
+    async fn refuses_peers_that_are_not_allowed() {
+        let (validator_data, _) = crypto_basics(2).await;
+        let (dialer_pen, listener_pen) = (&validator_data[0].1, &validator_data[1].1);
+        let dialer_id = AuthorityPeerId::from(dialer_pen.authority_id());
+        let listener_id = AuthorityPeerId::from(listener_pen.authority_id());
+        let (dialer_stream, listener_stream) = duplex(4096);
+        let (mut dialer_reader, mut dialer_writer) = split(dialer_stream);
+        let (mut listener_reader, mut listener_writer) = split(listener_stream);
+        // The listener never signs, so only it can finish.
+        let listener_result = tokio::select! {
+            result = handshake(&mut listener_reader, &mut listener_writer, listener_pen, None, |peer_id| *peer_id != dialer_id) => result,
+            _ = handshake(&mut dialer_reader, &mut dialer_writer, dialer_pen, Some(listener_id), |_| true) => panic!("the dialer cannot finish the handshake"),
+        };
+        assert!(matches!(
+            listener_result,
+            Err(HandshakeError::NotAllowed(peer_id)) if peer_id == dialer_id
+        ));
+    }
+
+    #[tokio::test]
+    async fn rejects_unexpected_peer() {
+        let (validator_data, _) = crypto_basics(3).await;
+        let (dialer_pen, listener_pen) = (&validator_data[0].1, &validator_data[1].1);
+        let expected_id = AuthorityPeerId::from(validator_data[2].1.authority_id());
+        let (dialer_stream, listener_stream) = duplex(4096);
+        let (mut dialer_reader, mut dialer_writer) = split(dialer_stream);
+        let (mut listener_reader, mut listener_writer) = split(listener_stream);
+        // The listener never gets a signature, so only the dialer can finish.
+        let dialer_result = tokio::select! {
+            result = handshake(&mut dialer_reader, &mut dialer_writer, dialer_pen, Some(expected_id), |_| true) => result,
+            _ = handshake(&mut listener_reader, &mut listener_writer, listener_pen, None, |_| true) => panic!("the listener cannot finish the handshake"),
+        };
+        assert!(matches!(
+            dialer_result,
+            Err(HandshakeError::UnexpectedPeer(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn rejects_impostor() {
+        let (validator_data, _) = crypto_basics(3).await;
+        let (listener_pen, impostor_pen) = (&validator_data[0].1, &validator_data[1].1);
+        let listener_id = AuthorityPeerId::from(listener_pen.authority_id());
+        let victim_id = AuthorityPeerId::from(validator_data[2].1.authority_id());
+        let (impostor_stream, listener_stream) = duplex(4096);
+        let (mut impostor_reader, mut impostor_writer) = split(impostor_stream);
+        let (mut listener_reader, mut listener_writer) = split(listener_stream);
+        let impostor = async {
+            let ephemeral_key = [0; 32];
+            let hello = Hello {
+                peer_id: victim_id,
+                challenge: [0; 32],
+                ephemeral_key,
+            };
+            write_frame(&mut impostor_writer, &hello.encode())
+                .await
+                .unwrap();
+            let hello =
+                Hello::decode(&mut &read_frame(&mut impostor_reader).await.unwrap()[..]).unwrap();
+            let signature = impostor_pen
+                .sign(&signing_payload(
+                    &hello.challenge,
+                    &victim_id,
+                    &listener_id,
+                    (&ephemeral_key, &hello.ephemeral_key),
+                ))
+                .await;
+            write_frame(&mut impostor_writer, &signature.encode())
+                .await
+                .unwrap();
+        };
+        let (listener_result, _) = tokio::join!(
+            handshake(
+                &mut listener_reader,
+                &mut listener_writer,
+                listener_pen,
+                None,
+                |_| true,
+            ),
+            impostor,
+        );
+        assert!(matches!(
+            listener_result,
+            Err(HandshakeError::BadSignature(peer_id)) if peer_id == victim_id
+        ));
+    }
+}
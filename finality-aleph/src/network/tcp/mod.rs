@@ -0,0 +1,1039 @@
+//! Validator network over direct TCP connections, independent of sc-network.
+//!
+//! Nodes are identified by their authority keys, and both ends of every connection prove they hold
+//! the corresponding private key in a handshake, which also agrees on the keys encrypting and
+//! authenticating everything sent afterwards. We identify with the key we sign with in the latest
+//! session we are a validator in, and only talk to members of the committees of the sessions we
+//! take part in. A single connection to a peer carries the messages of both protocols. Peers added
+//! to the reserved set of the Validator protocol are dialed, and redialed periodically whenever the
+//! connection is lost, until they are removed from the set. Connections from committee members
+//! outside of the set are accepted as well, so that they can discover us.
+use std::{
+    cmp::min,
+    collections::{HashMap, HashSet},
+    fmt,
+    net::{IpAddr, SocketAddr},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use aleph_primitives::AuthorityId;
+use async_trait::async_trait;
+use bytes::Bytes;
+use codec::{Decode, Encode};
+use futures::{
+    channel::mpsc,
+    future::{AbortHandle, Abortable, BoxFuture},
+    stream::{FuturesUnordered, StreamExt},
+    FutureExt,
+};
+use log::{debug, info, trace, warn};
+use parking_lot::Mutex;
+use sp_core::ed25519;
+use tokio::{
+    net::{
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+        TcpListener, TcpStream,
+    },
+    sync::{
+        mpsc::{channel, error::TrySendError, Receiver, Sender},
+        Notify,
+    },
+    time::{interval, timeout},
+};
+
+use crate::{
+    crypto::{AuthorityPen, AuthorityVerifier},
+    network::{
+        tcp::{
+            encryption::{Decryptor, Encryptor, TAG_SIZE},
+            framing::{read_frame, write_frame, FrameError, MAX_FRAME_SIZE},
+            handshake::handshake,
+        },
+        Event, EventStream, Multiaddress, Network, NetworkIdentity, NetworkSender, PeerId,
+        Protocol,
+    },
+    SessionId,
+};
+
+mod encryption;
+mod framing;
+mod handshake;
+
+/// How often the peers in the reserved set we are not connected to are dialed by default.
+pub const DEFAULT_RECONNECT_INTERVAL: Duration = Duration::from_secs(5);
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Connections from peers outside of the reserved set are refused once we have this many.
+const MAX_CONNECTIONS: usize = 1024;
+
+/// Incoming connections are dropped right away while this many handshakes are in progress.
+const MAX_PENDING_HANDSHAKES: usize = 64;
+
+/// Incoming connections from an IP address are dropped right away while this many handshakes with
+/// it are in progress.
+const MAX_PENDING_HANDSHAKES_PER_IP: usize = 4;
+
+/// The number of messages waiting to be sent to a peer, above which further ones are dropped.
+const MAX_QUEUED_OUTGOING: usize = 1024;
+
+/// The number of bytes of received messages a subscriber has not taken yet, above which further
+/// ones are dropped for it.
+const MAX_QUEUED_INCOMING_BYTES: usize = 64 * 1024 * 1024;
+
+/// The longest message that fits in a frame, together with the protocol and the encryption tag.
+const MAX_MESSAGE_SIZE: usize = MAX_FRAME_SIZE - 1 - TAG_SIZE;
+
+/// Configuration of the validator network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidatorNetworkConfig {
+    /// The address to listen on for connections from other validators.
+    pub listen_address: SocketAddr,
+    /// The addresses other validators should dial to reach us, as `host:port`.
+    pub external_addresses: Vec<String>,
+}
+
+/// Identifies a node of the validator network by its authority key.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Debug, Hash, Encode, Decode)]
+pub struct AuthorityPeerId([u8; 32]);
+
+impl From<AuthorityId> for AuthorityPeerId {
+    fn from(authority_id: AuthorityId) -> Self {
+        AuthorityPeerId(ed25519::Public::from(authority_id).0)
+    }
+}
+
+impl From<AuthorityPeerId> for AuthorityId {
+    fn from(peer_id: AuthorityPeerId) -> Self {
+        ed25519::Public(peer_id.0).into()
+    }
+}
+
+impl PeerId for AuthorityPeerId {}
+
+/// The address of a node of the validator network, i.e. a host and a port, possibly together with
+/// the peer id of the node.
+#[derive(PartialEq, Eq, Clone, Debug, Hash, Encode, Decode)]
+pub struct TcpMultiaddress {
+    peer_id: Option<AuthorityPeerId>,
+    address: String,
+}
+
+impl Multiaddress for TcpMultiaddress {
+    type PeerId = AuthorityPeerId;
+
+    fn get_peer_id(&self) -> Option<Self::PeerId> {
+        self.peer_id
+    }
+
+    fn add_matching_peer_id(mut self, peer_id: Self::PeerId) -> Option<Self> {
+        match self.peer_id {
+            Some(old_peer_id) => match old_peer_id == peer_id {
+                true => Some(self),
+                false => None,
+            },
+            None => {
+                self.peer_id = Some(peer_id);
+                Some(self)
+            }
+        }
+    }
+}
+
+type TcpEvent = Event<TcpMultiaddress>;
+
+fn protocol_byte(protocol: Protocol) -> u8 {
+    match protocol {
+        Protocol::Generic => 0,
+        Protocol::Validator => 1,
+    }
+}
+
+fn to_protocol(byte: u8) -> Option<Protocol> {
+    match byte {
+        0 => Some(Protocol::Generic),
+        1 => Some(Protocol::Validator),
+        _ => None,
+    }
+}
+
+#[derive(Debug)]
+pub enum SenderError {
+    NotConnected(AuthorityPeerId),
+    LostConnectionToPeer(AuthorityPeerId),
+    MessageTooLarge(usize),
+    QueueFull(AuthorityPeerId),
+}
+
+impl fmt::Display for SenderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SenderError::NotConnected(peer_id) => {
+                write!(f, "Not connected to peer {:?}", peer_id)
+            }
+            SenderError::LostConnectionToPeer(peer_id) => {
+                write!(f, "Lost connection to peer {:?}", peer_id)
+            }
+            SenderError::MessageTooLarge(len) => {
+                write!(f, "Message of {} bytes is too large to send", len)
+            }
+            SenderError::QueueFull(peer_id) => {
+                write!(
+                    f,
+                    "Too many messages waiting to be sent to peer {:?}",
+                    peer_id
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for SenderError {}
+
+pub struct TcpNetworkSender {
+    peer_id: AuthorityPeerId,
+    protocol: Protocol,
+    outgoing: Sender<Vec<u8>>,
+}
+
+#[async_trait]
+impl NetworkSender for TcpNetworkSender {
+    type SenderError = SenderError;
+
+    async fn send<'a>(
+        &'a self,
+        data: impl Into<Vec<u8>> + Send + Sync + 'static,
+    ) -> Result<(), SenderError> {
+        let data = data.into();
+        if data.len() > MAX_MESSAGE_SIZE {
+            return Err(SenderError::MessageTooLarge(data.len()));
+        }
+        let mut frame = Vec::with_capacity(data.len() + 1);
+        frame.push(protocol_byte(self.protocol));
+        frame.extend(data);
+        self.outgoing.try_send(frame).map_err(|e| match e {
+            TrySendError::Full(_) => SenderError::QueueFull(self.peer_id),
+            TrySendError::Closed(_) => SenderError::LostConnectionToPeer(self.peer_id),
+        })
+    }
+}
+
+fn messages_size(event: &TcpEvent) -> usize {
+    match event {
+        Event::Messages(_, messages) => messages.iter().map(|(_, data)| data.len()).sum(),
+        _ => 0,
+    }
+}
+
+pub struct TcpEventStream {
+    events: mpsc::UnboundedReceiver<TcpEvent>,
+    queued_bytes: Arc<AtomicUsize>,
+}
+
+#[async_trait]
+impl EventStream<TcpMultiaddress> for TcpEventStream {
+    async fn next_event(&mut self) -> Option<TcpEvent> {
+        let event = self.events.next().await?;
+        self.queued_bytes
+            .fetch_sub(messages_size(&event), Ordering::Relaxed);
+        Some(event)
+    }
+}
+
+/// The sending end of an event stream. Connection events are always queued, as they are few and
+/// small, but messages are dropped once too many bytes of them wait for the subscriber.
+struct Subscriber {
+    events: mpsc::UnboundedSender<TcpEvent>,
+    queued_bytes: Arc<AtomicUsize>,
+}
+
+impl Subscriber {
+    fn new() -> (Self, TcpEventStream) {
+        let (events_for_stream, events) = mpsc::unbounded();
+        let queued_bytes = Arc::new(AtomicUsize::new(0));
+        (
+            Subscriber {
+                events: events_for_stream,
+                queued_bytes: queued_bytes.clone(),
+            },
+            TcpEventStream {
+                events,
+                queued_bytes,
+            },
+        )
+    }
+
+    /// Returns whether the subscriber is still there.
+    fn send(&self, event: TcpEvent) -> bool {
+        let size = messages_size(&event);
+        if size > 0 {
+            if self.queued_bytes.load(Ordering::Relaxed) + size > MAX_QUEUED_INCOMING_BYTES {
+                debug!(target: "aleph-network", "Dropping validator network messages, the subscriber is not keeping up.");
+                return true;
+            }
+            self.queued_bytes.fetch_add(size, Ordering::Relaxed);
+        }
+        self.events.unbounded_send(event).is_ok()
+    }
+}
+
+#[derive(Debug)]
+enum ConnectionError {
+    Frame(FrameError),
+    Decryption,
+    MalformedFrame,
+    Closed,
+}
+
+struct Connection {
+    id: u64,
+    dialer: AuthorityPeerId,
+    outgoing: Sender<Vec<u8>>,
+    abort_handle: AbortHandle,
+}
+
+struct State {
+    pen: AuthorityPen,
+    peer_id: AuthorityPeerId,
+    committees: HashMap<SessionId, AuthorityVerifier>,
+    connections: HashMap<AuthorityPeerId, Connection>,
+    reserved: HashMap<AuthorityPeerId, HashSet<TcpMultiaddress>>,
+    dialing: HashSet<AuthorityPeerId>,
+    pending_handshakes: HashMap<IpAddr, usize>,
+    subscribers: Vec<Subscriber>,
+    next_connection_id: u64,
+}
+
+impl State {
+    fn new(pen: AuthorityPen) -> Self {
+        State {
+            peer_id: AuthorityPeerId::from(pen.authority_id()),
+            pen,
+            committees: HashMap::new(),
+            connections: HashMap::new(),
+            reserved: HashMap::new(),
+            dialing: HashSet::new(),
+            pending_handshakes: HashMap::new(),
+            subscribers: Vec::new(),
+            next_connection_id: 0,
+        }
+    }
+
+    fn emit(&mut self, event: TcpEvent) {
+        self.subscribers
+            .retain(|subscriber| subscriber.send(event.clone()));
+    }
+
+    fn emit_opened(&mut self, peer_id: AuthorityPeerId) {
+        for protocol in [Protocol::Generic, Protocol::Validator] {
+            self.emit(Event::StreamOpened(peer_id, protocol));
+        }
+    }
+
+    fn emit_closed(&mut self, peer_id: AuthorityPeerId) {
+        for protocol in [Protocol::Generic, Protocol::Validator] {
+            self.emit(Event::StreamClosed(peer_id, protocol));
+        }
+        self.emit(Event::Disconnected(peer_id));
+    }
+
+    /// Whether the peer is a member of the committee of any session we take part in.
+    fn is_committee_member(&self, peer_id: &AuthorityPeerId) -> bool {
+        let authority_id = AuthorityId::from(*peer_id);
+        self.committees
+            .values()
+            .any(|verifier| verifier.index_of(&authority_id).is_some())
+    }
+
+    fn is_current(&self, peer_id: &AuthorityPeerId, id: u64) -> bool {
+        matches!(self.connections.get(peer_id), Some(connection) if connection.id == id)
+    }
+
+    /// Keeps track of an authenticated connection and returns its id, or `None` if the connection
+    /// should be dropped in favor of the one we already have.
+    fn add_connection(
+        &mut self,
+        own_peer_id: AuthorityPeerId,
+        peer_id: AuthorityPeerId,
+        dialer: AuthorityPeerId,
+        outgoing: Sender<Vec<u8>>,
+        abort_handle: AbortHandle,
+    ) -> Option<u64> {
+        // When two peers dial each other at the same time, both of them keep the connection dialed
+        // by the one with the lower peer id. Otherwise the newer connection replaces the older one.
+        let preferred_dialer = min(own_peer_id, peer_id);
+        let connected = match self.connections.get(&peer_id) {
+            Some(connection) => {
+                if connection.dialer == preferred_dialer && dialer != preferred_dialer {
+                    return None;
+                }
+                true
+            }
+            None => false,
+        };
+        if !connected {
+            if self.connections.len() >= MAX_CONNECTIONS && !self.reserved.contains_key(&peer_id) {
+                return None;
+            }
+            self.emit_opened(peer_id);
+        }
+        let id = self.next_connection_id;
+        self.next_connection_id += 1;
+        let replaced = self.connections.insert(
+            peer_id,
+            Connection {
+                id,
+                dialer,
+                outgoing,
+                abort_handle,
+            },
+        );
+        if let Some(replaced) = replaced {
+            replaced.abort_handle.abort();
+        }
+        Some(id)
+    }
+
+    fn remove_connection(&mut self, peer_id: AuthorityPeerId, id: u64) {
+        if self.is_current(&peer_id, id) {
+            self.connections.remove(&peer_id);
+            self.emit_closed(peer_id);
+        }
+    }
+
+    /// Closes the connection with the peer, if any. Its task stops at once, so no more messages
+    /// from it are emitted, even if someone still holds a sender for it.
+    fn close(&mut self, peer_id: AuthorityPeerId) {
+        if let Some(connection) = self.connections.remove(&peer_id) {
+            connection.abort_handle.abort();
+            self.emit_closed(peer_id);
+        }
+    }
+
+    /// Returns the reserved peers that should be dialed now, and marks them as being dialed.
+    fn to_dial(&mut self) -> Vec<(AuthorityPeerId, Vec<TcpMultiaddress>)> {
+        let to_dial: Vec<_> = self
+            .reserved
+            .iter()
+            .filter(|(peer_id, _)| {
+                !self.connections.contains_key(peer_id) && !self.dialing.contains(peer_id)
+            })
+            .map(|(peer_id, addresses)| (*peer_id, addresses.iter().cloned().collect()))
+            .collect();
+        self.dialing
+            .extend(to_dial.iter().map(|(peer_id, _)| *peer_id));
+        to_dial
+    }
+}
+
+struct Inner {
+    external_addresses: Vec<String>,
+    state: Mutex<State>,
+    dial_needed: Notify,
+}
+
+/// The validator network, it only does anything while `run` is being polled.
+#[derive(Clone)]
+pub struct TcpNetwork {
+    inner: Arc<Inner>,
+}
+
+/// A handshake with an incoming connection, counted towards the limits until dropped.
+struct PendingHandshake {
+    network: TcpNetwork,
+    ip: IpAddr,
+}
+
+impl PendingHandshake {
+    /// `None` if there are too many handshakes in progress already.
+    fn new(network: &TcpNetwork, ip: IpAddr) -> Option<Self> {
+        let mut state = network.inner.state.lock();
+        let pending: usize = state.pending_handshakes.values().sum();
+        let pending_with_ip = state.pending_handshakes.get(&ip).cloned().unwrap_or(0);
+        if pending >= MAX_PENDING_HANDSHAKES || pending_with_ip >= MAX_PENDING_HANDSHAKES_PER_IP {
+            return None;
+        }
+        *state.pending_handshakes.entry(ip).or_default() += 1;
+        Some(PendingHandshake {
+            network: network.clone(),
+            ip,
+        })
+    }
+}
+
+impl Drop for PendingHandshake {
+    fn drop(&mut self) {
+        let mut state = self.network.inner.state.lock();
+        if let Some(pending) = state.pending_handshakes.get_mut(&self.ip) {
+            *pending -= 1;
+            if *pending == 0 {
+                state.pending_handshakes.remove(&self.ip);
+            }
+        }
+    }
+}
+
+async fn send_frames(
+    mut writer: OwnedWriteHalf,
+    mut outgoing: Receiver<Vec<u8>>,
+    mut encryptor: Encryptor,
+) -> Result<(), FrameError> {
+    while let Some(frame) = outgoing.recv().await {
+        write_frame(&mut writer, &encryptor.encrypt(&frame)).await?;
+    }
+    Ok(())
+}
+
+impl TcpNetwork {
+    /// Creates a network identified by the authority key of the pen, until a session we are a
+    /// validator in gives us another one, reachable at the given external addresses.
+    pub fn new(pen: AuthorityPen, external_addresses: Vec<String>) -> Self {
+        TcpNetwork {
+            inner: Arc::new(Inner {
+                external_addresses,
+                state: Mutex::new(State::new(pen)),
+                dial_needed: Notify::new(),
+            }),
+        }
+    }
+
+    async fn receive_frames(
+        &self,
+        mut reader: OwnedReadHalf,
+        peer_id: AuthorityPeerId,
+        id: u64,
+        mut decryptor: Decryptor,
+    ) -> ConnectionError {
+        loop {
+            let frame = match read_frame(&mut reader).await {
+                Ok(frame) => frame,
+                Err(e) => return ConnectionError::Frame(e),
+            };
+            let frame = match decryptor.decrypt(&frame) {
+                Ok(frame) => Bytes::from(frame),
+                Err(_) => return ConnectionError::Decryption,
+            };
+            let protocol = match frame.first().cloned().and_then(to_protocol) {
+                Some(protocol) => protocol,
+                None => return ConnectionError::MalformedFrame,
+            };
+            if !self.emit_message(peer_id, id, protocol, frame.slice(1..)) {
+                return ConnectionError::Closed;
+            }
+        }
+    }
+
+    /// Emits the message received through the connection, unless the connection was closed in
+    /// the meantime. Returns whether it was not.
+    fn emit_message(
+        &self,
+        peer_id: AuthorityPeerId,
+        id: u64,
+        protocol: Protocol,
+        data: Bytes,
+    ) -> bool {
+        let mut state = self.inner.state.lock();
+        if !state.is_current(&peer_id, id) {
+            return false;
+        }
+        state.emit(Event::Messages(peer_id, vec![(protocol, data)]));
+        true
+    }
+
+    /// Authenticates the connection and handles it until it is closed. Returns whether the
+    /// handshake succeeded. The pending handshake of an incoming connection is released once the
+    /// handshake ends.
+    async fn handle_connection(
+        self,
+        stream: TcpStream,
+        expected_peer_id: Option<AuthorityPeerId>,
+        pending_handshake: Option<PendingHandshake>,
+    ) -> bool {
+        let (mut reader, mut writer) = stream.into_split();
+        let pen = self.inner.state.lock().pen.clone();
+        let own_peer_id = AuthorityPeerId::from(pen.authority_id());
+        let handshake_result = handshake(
+            &mut reader,
+            &mut writer,
+            &pen,
+            expected_peer_id,
+            |peer_id| self.inner.state.lock().is_committee_member(peer_id),
+        )
+        .await;
+        drop(pending_handshake);
+        let (peer_id, encryptor, decryptor) = match handshake_result {
+            Ok(result) => result,
+            Err(e) => {
+                debug!(target: "aleph-network", "Validator network handshake failed: {}", e);
+                return false;
+            }
+        };
+        let dialer = match expected_peer_id {
+            Some(_) => own_peer_id,
+            None => peer_id,
+        };
+        let (outgoing, outgoing_rx) = channel(MAX_QUEUED_OUTGOING);
+        let (abort_handle, abort_registration) = AbortHandle::new_pair();
+        let added = self.inner.state.lock().add_connection(
+            own_peer_id,
+            peer_id,
+            dialer,
+            outgoing,
+            abort_handle,
+        );
+        let id = match added {
+            Some(id) => id,
+            None => {
+                trace!(target: "aleph-network", "Dropping redundant validator network connection with {:?}.", peer_id);
+                return true;
+            }
+        };
+        debug!(target: "aleph-network", "Connected to validator network peer {:?}.", peer_id);
+        let connection = async {
+            tokio::select! {
+                result = send_frames(writer, outgoing_rx, encryptor) => {
+                    if let Err(e) = result {
+                        debug!(target: "aleph-network", "Failed sending to validator network peer {:?}: {}", peer_id, e);
+                    }
+                },
+                e = self.receive_frames(reader, peer_id, id, decryptor) => {
+                    debug!(target: "aleph-network", "Failed receiving from validator network peer {:?}: {:?}", peer_id, e);
+                },
+            }
+        };
+        if Abortable::new(connection, abort_registration)
+            .await
+            .is_err()
+        {
+            debug!(target: "aleph-network", "Closed connection to validator network peer {:?}.", peer_id);
+        }
+        self.inner.state.lock().remove_connection(peer_id, id);
+        true
+    }
+
+    /// Tries the addresses of the peer one by one, until a connection is established and then
+    /// closed.
+    async fn dial(self, peer_id: AuthorityPeerId, addresses: Vec<TcpMultiaddress>) {
+        for address in addresses {
+            match timeout(
+                CONNECT_TIMEOUT,
+                TcpStream::connect(address.address.as_str()),
+            )
+            .await
+            {
+                Ok(Ok(stream)) => {
+                    if self
+                        .clone()
+                        .handle_connection(stream, Some(peer_id), None)
+                        .await
+                    {
+                        break;
+                    }
+                }
+                Ok(Err(e)) => {
+                    debug!(target: "aleph-network", "Failed to connect to validator network peer {:?} at {:?}: {}", peer_id, address.address, e)
+                }
+                Err(_) => {
+                    debug!(target: "aleph-network", "Timed out connecting to validator network peer {:?} at {:?}.", peer_id, address.address)
+                }
+            }
+        }
+        self.inner.state.lock().dialing.remove(&peer_id);
+    }
+
+    fn dial_reserved(&self, connections: &mut FuturesUnordered<BoxFuture<'static, ()>>) {
+        let to_dial = self.inner.state.lock().to_dial();
+        for (peer_id, addresses) in to_dial {
+            connections.push(self.clone().dial(peer_id, addresses).boxed());
+        }
+    }
+
+    /// Accepts incoming connections and dials the reserved peers, redialing the ones we are not
+    /// connected to every `reconnect_interval`.
+    pub async fn run(self, listener: TcpListener, reconnect_interval: Duration) {
+        let mut connections: FuturesUnordered<BoxFuture<'static, ()>> = FuturesUnordered::new();
+        let mut reconnect = interval(reconnect_interval);
+        loop {
+            tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok((stream, address)) => match PendingHandshake::new(&self, address.ip()) {
+                        Some(pending_handshake) => {
+                            trace!(target: "aleph-network", "Accepted validator network connection from {}.", address);
+                            connections.push(self.clone().handle_connection(stream, None, Some(pending_handshake)).map(|_| ()).boxed());
+                        }
+                        None => {
+                            debug!(target: "aleph-network", "Dropping validator network connection from {}, too many handshakes in progress.", address);
+                        }
+                    },
+                    Err(e) => warn!(target: "aleph-network", "Failed to accept validator network connection: {}", e),
+                },
+                _ = reconnect.tick() => self.dial_reserved(&mut connections),
+                _ = self.inner.dial_needed.notified() => self.dial_reserved(&mut connections),
+                Some(_) = connections.next(), if !connections.is_empty() => {},
+            }
+        }
+    }
+}
+
+impl Network for TcpNetwork {
+    type SenderError = SenderError;
+    type NetworkSender = TcpNetworkSender;
+    type PeerId = AuthorityPeerId;
+    type Multiaddress = TcpMultiaddress;
+    type EventStream = TcpEventStream;
+
+    fn event_stream(&self) -> Self::EventStream {
+        let (subscriber, event_stream) = Subscriber::new();
+        let mut state = self.inner.state.lock();
+        // The new subscriber should know about the connections established before it subscribed.
+        for peer_id in state.connections.keys() {
+            for protocol in [Protocol::Generic, Protocol::Validator] {
+                subscriber.send(Event::StreamOpened(*peer_id, protocol));
+            }
+        }
+        state.subscribers.push(subscriber);
+        event_stream
+    }
+
+    fn sender(
+        &self,
+        peer_id: Self::PeerId,
+        protocol: Protocol,
+    ) -> Result<Self::NetworkSender, Self::SenderError> {
+        match self.inner.state.lock().connections.get(&peer_id) {
+            Some(connection) => Ok(TcpNetworkSender {
+                peer_id,
+                protocol,
+                outgoing: connection.outgoing.clone(),
+            }),
+            None => Err(SenderError::NotConnected(peer_id)),
+        }
+    }
+
+    fn add_reserved(&self, addresses: HashSet<Self::Multiaddress>, protocol: Protocol) {
+        // Every connection carries both protocols, so only the Validator reserved set matters.
+        if protocol != Protocol::Validator {
+            return;
+        }
+        let mut state = self.inner.state.lock();
+        for address in addresses {
+            match address.get_peer_id() {
+                Some(peer_id) if peer_id == state.peer_id => {}
+                Some(peer_id) => {
+                    state.reserved.entry(peer_id).or_default().insert(address);
+                }
+                None => {
+                    debug!(target: "aleph-network", "Ignoring validator network address {:?} without a peer id.", address)
+                }
+            }
+        }
+        self.inner.dial_needed.notify_one();
+    }
+
+    fn remove_reserved(&self, peers: HashSet<Self::PeerId>, protocol: Protocol) {
+        if protocol != Protocol::Validator {
+            return;
+        }
+        let mut state = self.inner.state.lock();
+        for peer_id in peers {
+            state.reserved.remove(&peer_id);
+            state.close(peer_id);
+        }
+    }
+
+    fn disconnect(&self, peer_id: Self::PeerId, protocol: Protocol) {
+        // Every connection carries both protocols, so closing the Validator one closes it.
+        if protocol != Protocol::Validator {
+            return;
+        }
+        self.inner.state.lock().close(peer_id);
+    }
+}
+
+impl NetworkIdentity for TcpNetwork {
+    type PeerId = AuthorityPeerId;
+    type Multiaddress = TcpMultiaddress;
+
+    fn identity(&self) -> (Vec<Self::Multiaddress>, Self::PeerId) {
+        let peer_id = self.inner.state.lock().peer_id;
+        let addresses = self
+            .inner
+            .external_addresses
+            .iter()
+            .map(|address| TcpMultiaddress {
+                peer_id: Some(peer_id),
+                address: address.clone(),
+            })
+            .collect();
+        (addresses, peer_id)
+    }
+
+    fn session_started(
+        &self,
+        session_id: SessionId,
+        verifier: &AuthorityVerifier,
+        pen: Option<&AuthorityPen>,
+    ) {
+        let mut state = self.inner.state.lock();
+        state.committees.insert(session_id, verifier.clone());
+        if let Some(pen) = pen {
+            let peer_id = AuthorityPeerId::from(pen.authority_id());
+            if peer_id != state.peer_id {
+                info!(target: "aleph-network", "Identifying with the authority key of session {:?} in the validator network.", session_id);
+                state.pen = pen.clone();
+                state.peer_id = peer_id;
+            }
+        }
+    }
+
+    fn session_ended(&self, session_id: SessionId) {
+        self.inner.state.lock().committees.remove(&session_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, iter, time::Duration};
+
+    use tokio::{
+        io::AsyncReadExt,
+        net::{TcpListener, TcpStream},
+        task::JoinHandle,
+        time::{sleep, timeout},
+    };
+
+    use super::{
+        AuthorityPeerId, TcpEvent, TcpEventStream, TcpNetwork, MAX_PENDING_HANDSHAKES_PER_IP,
+    };
+    use crate::{
+        crypto::{AuthorityPen, AuthorityVerifier},
+        network::{
+            mock::crypto_basics, Event, EventStream, Network, NetworkIdentity, NetworkSender,
+            Protocol,
+        },
+        SessionId,
+    };
+
+    async fn start(
+        pen: AuthorityPen,
+        verifier: &AuthorityVerifier,
+    ) -> (TcpNetwork, JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let network = TcpNetwork::new(pen.clone(), vec![address]);
+        network.session_started(SessionId(0), verifier, Some(&pen));
+        let handle = tokio::spawn(network.clone().run(listener, Duration::from_millis(100)));
+        (network, handle)
+    }
+
+    async fn next_event(events: &mut TcpEventStream) -> TcpEvent {
+        timeout(Duration::from_secs(5), events.next_event())
+            .await
+            .expect("the event should come in time")
+            .expect("the event stream should not end")
+    }
+
+    async fn expect_connected(events: &mut TcpEventStream, peer_id: AuthorityPeerId) {
+        for protocol in [Protocol::Generic, Protocol::Validator] {
+            assert!(matches!(
+                next_event(events).await,
+                Event::StreamOpened(peer, opened) if peer == peer_id && opened == protocol
+            ));
+        }
+    }
+
+    async fn expect_disconnected(events: &mut TcpEventStream, peer_id: AuthorityPeerId) {
+        for protocol in [Protocol::Generic, Protocol::Validator] {
+            assert!(matches!(
+                next_event(events).await,
+                Event::StreamClosed(peer, closed) if peer == peer_id && closed == protocol
+            ));
+        }
+        assert!(matches!(
+            next_event(events).await,
+            Event::Disconnected(peer) if peer == peer_id
+        ));
+    }
+
+    async fn expect_message(
+        events: &mut TcpEventStream,
+        peer_id: AuthorityPeerId,
+        protocol: Protocol,
+        data: &[u8],
+    ) {
+        match next_event(events).await {
+            Event::Messages(peer, messages) => {
+                assert_eq!(peer, peer_id);
+                assert_eq!(messages.len(), 1);
+                assert_eq!(messages[0].0, protocol);
+                assert_eq!(&messages[0].1[..], data);
+            }
+            _ => panic!("expected a message"),
+        }
+    }
+
+    fn reserve(network: &TcpNetwork, other: &TcpNetwork) {
+        network.add_reserved(
+            other.identity().0.into_iter().collect::<HashSet<_>>(),
+            Protocol::Validator,
+        );
+    }
+
+    #[tokio::test]
+    async fn exchanges_messages() {
+        let (validator_data, verifier) = crypto_basics(2).await;
+        let (alice, _alice_handle) = start(validator_data[0].1.clone(), &verifier).await;
+        let (bob, _bob_handle) = start(validator_data[1].1.clone(), &verifier).await;
+        let (alice_id, bob_id) = (alice.identity().1, bob.identity().1);
+        let mut alice_events = alice.event_stream();
+        let mut bob_events = bob.event_stream();
+
+        reserve(&alice, &bob);
+        expect_connected(&mut alice_events, bob_id).await;
+        expect_connected(&mut bob_events, alice_id).await;
+
+        alice
+            .sender(bob_id, Protocol::Validator)
+            .unwrap()
+            .send(b"hello bob".to_vec())
+            .await
+            .unwrap();
+        expect_message(&mut bob_events, alice_id, Protocol::Validator, b"hello bob").await;
+        bob.sender(alice_id, Protocol::Generic)
+            .unwrap()
+            .send(b"hello alice".to_vec())
+            .await
+            .unwrap();
+        expect_message(&mut alice_events, bob_id, Protocol::Generic, b"hello alice").await;
+    }
+
+    #[tokio::test]
+    async fn removing_reserved_peer_disconnects() {
+        let (validator_data, verifier) = crypto_basics(2).await;
+        let (alice, _alice_handle) = start(validator_data[0].1.clone(), &verifier).await;
+        let (bob, _bob_handle) = start(validator_data[1].1.clone(), &verifier).await;
+        let (alice_id, bob_id) = (alice.identity().1, bob.identity().1);
+        let mut alice_events = alice.event_stream();
+        let mut bob_events = bob.event_stream();
+
+        reserve(&alice, &bob);
+        expect_connected(&mut alice_events, bob_id).await;
+        expect_connected(&mut bob_events, alice_id).await;
+
+        alice.remove_reserved(iter::once(bob_id).collect(), Protocol::Validator);
+        expect_disconnected(&mut alice_events, bob_id).await;
+        expect_disconnected(&mut bob_events, alice_id).await;
+        assert!(alice.sender(bob_id, Protocol::Validator).is_err());
+    }
+
+    #[tokio::test]
+    async fn reconnects_to_reserved_peers() {
+        let (validator_data, verifier) = crypto_basics(2).await;
+        let (alice, _alice_handle) = start(validator_data[0].1.clone(), &verifier).await;
+        let (bob, _bob_handle) = start(validator_data[1].1.clone(), &verifier).await;
+        let (alice_id, bob_id) = (alice.identity().1, bob.identity().1);
+        let mut alice_events = alice.event_stream();
+
+        reserve(&alice, &bob);
+        expect_connected(&mut alice_events, bob_id).await;
+
+        // Bob drops the connection, but Alice still wants to be connected.
+        bob.remove_reserved(iter::once(alice_id).collect(), Protocol::Validator);
+        expect_disconnected(&mut alice_events, bob_id).await;
+        expect_connected(&mut alice_events, bob_id).await;
+    }
+
+    #[tokio::test]
+    async fn late_subscribers_learn_about_connections() {
+        let (validator_data, verifier) = crypto_basics(2).await;
+        let (alice, _alice_handle) = start(validator_data[0].1.clone(), &verifier).await;
+        let (bob, _bob_handle) = start(validator_data[1].1.clone(), &verifier).await;
+        let bob_id = bob.identity().1;
+        let mut alice_events = alice.event_stream();
+
+        reserve(&alice, &bob);
+        expect_connected(&mut alice_events, bob_id).await;
+        expect_connected(&mut alice.event_stream(), bob_id).await;
+    }
+
+    #[tokio::test]
+    async fn no_messages_after_removing_reserved_peer() {
+        let (validator_data, verifier) = crypto_basics(2).await;
+        let (alice, _alice_handle) = start(validator_data[0].1.clone(), &verifier).await;
+        let (bob, _bob_handle) = start(validator_data[1].1.clone(), &verifier).await;
+        let (alice_id, bob_id) = (alice.identity().1, bob.identity().1);
+        let mut alice_events = alice.event_stream();
+
+        reserve(&alice, &bob);
+        expect_connected(&mut alice_events, bob_id).await;
+        let alice_sender = alice.sender(bob_id, Protocol::Validator).unwrap();
+
+        alice.remove_reserved(iter::once(bob_id).collect(), Protocol::Validator);
+        expect_disconnected(&mut alice_events, bob_id).await;
+        // Bob keeps sending, but we do not hear from him anymore.
+        if let Ok(bob_sender) = bob.sender(alice_id, Protocol::Validator) {
+            let _ = bob_sender.send(b"still there?".to_vec()).await;
+        }
+        assert!(
+            timeout(Duration::from_millis(500), alice_events.next_event())
+                .await
+                .is_err()
+        );
+        // The connection is gone, even though we still hold a sender for it.
+        assert!(alice_sender.send(b"hello bob".to_vec()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn refuses_peers_outside_committees() {
+        let (validator_data, verifier) = crypto_basics(2).await;
+        let alice_pen = validator_data[0].1.clone();
+        let alice_committee = AuthorityVerifier::new(vec![alice_pen.authority_id()]);
+        let (alice, _alice_handle) = start(alice_pen, &alice_committee).await;
+        let (bob, _bob_handle) = start(validator_data[1].1.clone(), &verifier).await;
+        let mut alice_events = alice.event_stream();
+        let mut bob_events = bob.event_stream();
+
+        reserve(&bob, &alice);
+        assert!(
+            timeout(Duration::from_millis(500), alice_events.next_event())
+                .await
+                .is_err()
+        );
+        assert!(timeout(Duration::from_millis(500), bob_events.next_event())
+            .await
+            .is_err());
+
+        // Once Bob is in a committee Alice knows of, they connect.
+        alice.session_started(SessionId(1), &verifier, None);
+        expect_connected(&mut alice_events, bob.identity().1).await;
+    }
+
+    #[tokio::test]
+    async fn limits_pending_handshakes_per_address() {
+        let (validator_data, verifier) = crypto_basics(1).await;
+        let (alice, _alice_handle) = start(validator_data[0].1.clone(), &verifier).await;
+        let address = alice.identity().0[0].address.clone();
+
+        let mut pending = Vec::new();
+        for _ in 0..MAX_PENDING_HANDSHAKES_PER_IP {
+            pending.push(TcpStream::connect(address.as_str()).await.unwrap());
+        }
+        // Give Alice a moment to accept the connections above.
+        sleep(Duration::from_millis(100)).await;
+        let mut refused = TcpStream::connect(address.as_str()).await.unwrap();
+        let mut buffer = [0u8; 1];
+        assert_eq!(
+            timeout(Duration::from_secs(5), refused.read(&mut buffer))
+                .await
+                .expect("the connection should be dropped right away")
+                .unwrap(),
+            0
+        );
+        // The accepted ones get a handshake.
+        assert_eq!(pending[0].read(&mut buffer).await.unwrap(), 1);
+    }
+}
@@ -1,14 +1,15 @@
 mod nonvalidator_node;
 mod validator_node;
 
-use std::{future::Future, sync::Arc};
+use std::{future::Future, marker::PhantomData, sync::Arc, time::Duration};
 
-use aleph_primitives::{AuthorityId, SessionAuthorityData};
+use aleph_primitives::{AuthorityId, FinalityParameters, SessionAuthorityData};
 use codec::Encode;
 use log::warn;
 pub use nonvalidator_node::run_nonvalidator_node;
+use parking_lot::Mutex;
 use sc_client_api::Backend;
-use sc_network::{ExHashT, NetworkService};
+use sc_network::{config::IncomingRequest, ExHashT, NetworkService};
 use sp_runtime::{
     traits::{Block, Header, NumberFor},
     RuntimeAppPublic,
@@ -16,38 +17,56 @@ use sp_runtime::{
 pub use validator_node::run_validator_node;
 
 use crate::{
-    crypto::AuthorityVerifier,
-    finalization::AlephFinalizer,
+    crypto::{AuthorityVerifier, BlsVerifier},
+    finality_params::finality_params_for_session,
+    finalization::{AlephFinalizer, JustificationPruner},
     justification::{
-        AlephJustification, JustificationHandler, JustificationRequestSchedulerImpl, SessionInfo,
-        SessionInfoProvider, Verifier,
+        AlephJustification, FinalizedBlockSender, JustificationHandler,
+        JustificationRequestSchedulerImpl, JustificationSyncHandler, JustificationSyncRequester,
+        SessionInfo, SessionInfoProvider, Verifier,
     },
-    last_block_of_session, mpsc,
+    last_block_of_session,
+    metrics::VerificationFailure,
+    mpsc,
     mpsc::UnboundedSender,
     session_id_from_block_num,
     session_map::ReadOnlySessionMap,
-    JustificationNotification, Metrics, MillisecsPerBlock, SessionPeriod,
+    substrate_network::{serve_justification_requests, JustificationSyncNetwork},
+    JustificationNotification, Metrics, MillisecsPerBlock, SessionId, SessionPeriod,
 };
 
 /// Max amount of tries we can not update a finalized block number before we will clear requests queue
 const MAX_ATTEMPTS: u32 = 5;
 
-struct JustificationVerifier {
+pub(crate) struct JustificationVerifier {
     authority_verifier: AuthorityVerifier,
+    aggregate_verifier: Option<BlsVerifier>,
     emergency_signer: Option<AuthorityId>,
 }
 
 impl From<SessionAuthorityData> for JustificationVerifier {
     fn from(authority_data: SessionAuthorityData) -> Self {
+        let aggregate_verifier = authority_data.bls_keys().as_ref().and_then(|keys| {
+            let verifier = BlsVerifier::from_keys(keys);
+            if verifier.is_none() {
+                warn!(target: "aleph-justification", "Invalid BLS keys in the authority data, not accepting aggregated multisignatures.");
+            }
+            verifier
+        });
         JustificationVerifier {
             authority_verifier: AuthorityVerifier::new(authority_data.authorities().to_vec()),
+            aggregate_verifier,
             emergency_signer: authority_data.emergency_finalizer().clone(),
         }
     }
 }
 
 impl<B: Block> Verifier<B> for JustificationVerifier {
-    fn verify(&self, justification: &AlephJustification, hash: B::Hash) -> bool {
+    fn verify(
+        &self,
+        justification: &AlephJustification,
+        hash: B::Hash,
+    ) -> Result<(), VerificationFailure> {
         use AlephJustification::*;
         let encoded_hash = hash.encode();
         match justification {
@@ -55,23 +74,38 @@ impl<B: Block> Verifier<B> for JustificationVerifier {
                 .authority_verifier
                 .is_complete(&encoded_hash, multisignature)
             {
-                true => true,
+                true => Ok(()),
                 false => {
                     warn!(target: "aleph-justification", "Bad multisignature for block hash #{:?} {:?}", hash, multisignature);
-                    false
+                    Err(VerificationFailure::BadMultisignature)
+                }
+            },
+            AggregatedMultisignature(multisignature) => match &self.aggregate_verifier {
+                Some(aggregate_verifier) => {
+                    match aggregate_verifier.is_complete(&encoded_hash, multisignature) {
+                        true => Ok(()),
+                        false => {
+                            warn!(target: "aleph-justification", "Bad aggregated multisignature for block hash #{:?} {:?}", hash, multisignature);
+                            Err(VerificationFailure::BadAggregatedMultisignature)
+                        }
+                    }
+                }
+                None => {
+                    warn!(target: "aleph-justification", "Received aggregated multisignature for block with hash #{:?}, but the BLS keys of the committee are unknown.", hash);
+                    Err(VerificationFailure::UnknownBlsKeys)
                 }
             },
             EmergencySignature(signature) => match &self.emergency_signer {
                 Some(emergency_signer) => match emergency_signer.verify(&encoded_hash, signature) {
-                    true => true,
+                    true => Ok(()),
                     false => {
                         warn!(target: "aleph-justification", "Bad emergency signature for block hash #{:?} {:?}", hash, signature);
-                        false
+                        Err(VerificationFailure::BadEmergencySignature)
                     }
                 },
                 None => {
                     warn!(target: "aleph-justification", "Received emergency signature for block with hash #{:?}, which has no emergency signer defined.", hash);
-                    false
+                    Err(VerificationFailure::NoEmergencyFinalizer)
                 }
             },
         }
@@ -86,24 +120,72 @@ struct JustificationParams<B: Block, H: ExHashT, C> {
     pub session_period: SessionPeriod,
     pub millisecs_per_block: MillisecsPerBlock,
     pub session_map: ReadOnlySessionMap,
+    pub justification_sync_requests: mpsc::Receiver<IncomingRequest>,
+    pub justification_pruner: Option<Arc<dyn JustificationPruner<B>>>,
+    pub finalized_block_sender: FinalizedBlockSender<B>,
 }
 
-struct SessionInfoProviderImpl {
+struct SessionInfoProviderImpl<C, BE> {
     session_authorities: ReadOnlySessionMap,
     session_period: SessionPeriod,
+    client: Arc<C>,
+    // The finality parameters of the last session we were asked about.
+    finality_params: Mutex<Option<(SessionId, Option<FinalityParameters>)>>,
+    _phantom: PhantomData<BE>,
 }
 
-impl SessionInfoProviderImpl {
-    fn new(session_authorities: ReadOnlySessionMap, session_period: SessionPeriod) -> Self {
+impl<C, BE> SessionInfoProviderImpl<C, BE> {
+    fn new(
+        session_authorities: ReadOnlySessionMap,
+        session_period: SessionPeriod,
+        client: Arc<C>,
+    ) -> Self {
         Self {
             session_authorities,
             session_period,
+            client,
+            finality_params: Mutex::new(None),
+            _phantom: PhantomData,
+        }
+    }
+
+    fn finality_params<B>(&self, session_id: SessionId) -> Option<FinalityParameters>
+    where
+        B: Block,
+        C: crate::ClientForAleph<B, BE>,
+        C::Api: aleph_primitives::AlephSessionApi<B>,
+        BE: Backend<B>,
+    {
+        let mut finality_params = self.finality_params.lock();
+        match &*finality_params {
+            Some((cached_session_id, params)) if *cached_session_id == session_id => params.clone(),
+            _ => match finality_params_for_session(
+                self.client.as_ref(),
+                session_id,
+                self.session_period,
+            ) {
+                Ok(params) => {
+                    *finality_params = Some((session_id, params.clone()));
+                    params
+                }
+                // Not cached, so that we try again the next time.
+                Err(e) => {
+                    warn!(target: "aleph-justification", "Could not read finality parameters of session {:?}, using the local configuration for now: {:?}", session_id, e);
+                    None
+                }
+            },
         }
     }
 }
 
 #[async_trait::async_trait]
-impl<B: Block> SessionInfoProvider<B, JustificationVerifier> for SessionInfoProviderImpl {
+impl<B, C, BE> SessionInfoProvider<B, JustificationVerifier> for SessionInfoProviderImpl<C, BE>
+where
+    B: Block,
+    C: crate::ClientForAleph<B, BE> + Send + Sync + 'static,
+    C::Api: aleph_primitives::AlephSessionApi<B>,
+    BE: Backend<B> + 'static,
+{
     async fn for_block_num(&self, number: NumberFor<B>) -> SessionInfo<B, JustificationVerifier> {
         let current_session = session_id_from_block_num::<B>(number, self.session_period);
         let last_block_height = last_block_of_session::<B>(current_session, self.session_period);
@@ -112,11 +194,15 @@ impl<B: Block> SessionInfoProvider<B, JustificationVerifier> for SessionInfoProv
             .get(current_session)
             .await
             .map(|authority_data| authority_data.into());
+        let request_delay = self
+            .finality_params::<B>(current_session)
+            .map(|params| Duration::from_millis(params.justification_request_delay));
 
         SessionInfo {
             current_session,
             last_block_height,
             verifier,
+            request_delay,
         }
     }
 }
@@ -126,6 +212,7 @@ fn setup_justification_handler<B, H, C, BE>(
 ) -> (
     UnboundedSender<JustificationNotification<B>>,
     impl Future<Output = ()>,
+    impl Future<Output = ()>,
 )
 where
     B: Block,
@@ -142,22 +229,124 @@ where
         session_period,
         millisecs_per_block,
         session_map,
+        justification_sync_requests,
+        justification_pruner,
+        finalized_block_sender,
     } = just_params;
 
+    let event_metrics = metrics.as_ref().map(|metrics| metrics.events().clone());
+    let finalizer = match justification_pruner {
+        Some(pruner) => {
+            AlephFinalizer::with_justification_pruning(client.clone(), session_period, pruner)
+        }
+        None => AlephFinalizer::new(client.clone()),
+    };
     let handler = JustificationHandler::new(
-        SessionInfoProviderImpl::new(session_map, session_period),
-        network,
+        SessionInfoProviderImpl::new(session_map, session_period, client.clone()),
+        network.clone(),
         client.clone(),
-        AlephFinalizer::new(client),
+        finalizer,
         JustificationRequestSchedulerImpl::new(&session_period, &millisecs_per_block, MAX_ATTEMPTS),
         metrics,
         Default::default(),
+        finalized_block_sender,
     );
 
+    let (sync_justification_tx, sync_justification_rx) = mpsc::unbounded();
+    let sync_network = JustificationSyncNetwork::new(network);
+    let sync_requester = JustificationSyncRequester::new(
+        sync_network.clone(),
+        client.clone(),
+        session_period,
+        Default::default(),
+        sync_justification_tx,
+        event_metrics.clone(),
+    );
+    let sync_handler = JustificationSyncHandler::<B, _>::new(client, session_period, event_metrics);
+
     let (authority_justification_tx, authority_justification_rx) = mpsc::unbounded();
-    (authority_justification_tx, async move {
-        handler
-            .run(authority_justification_rx, justification_rx)
-            .await;
-    })
+    (
+        authority_justification_tx,
+        async move {
+            handler
+                .run(
+                    authority_justification_rx,
+                    justification_rx,
+                    sync_justification_rx,
+                )
+                .await;
+        },
+        async move {
+            futures::join!(
+                sync_network.track_peers(),
+                sync_requester.run(),
+                serve_justification_requests(sync_handler, justification_sync_requests),
+            );
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use aleph_bft::{NodeIndex, PartialMultisignature};
+    use aleph_primitives::SessionAuthorityData;
+    use codec::Encode;
+
+    use super::JustificationVerifier;
+    use crate::{
+        crypto::{generate_bls_pens, AggregatedSignature, BlsPen},
+        justification::{AlephJustification, Verifier},
+        metrics::VerificationFailure,
+        testing::mocks::{TBlock, THash},
+    };
+
+    fn aggregated_justification(
+        pens: &[BlsPen],
+        signers: usize,
+        hash: THash,
+    ) -> AlephJustification {
+        let msg = hash.encode();
+        let multisignature = pens.iter().enumerate().take(signers).skip(1).fold(
+            AggregatedSignature::new(&pens[0].sign(&msg), NodeIndex(0)),
+            |multisignature, (i, pen)| multisignature.add_signature(&pen.sign(&msg), NodeIndex(i)),
+        );
+        AlephJustification::AggregatedMultisignature(multisignature)
+    }
+
+    #[tokio::test]
+    async fn verifies_aggregated_multisignatures() {
+        let (pens, _) = generate_bls_pens(4).await;
+        let keys = pens.iter().map(|pen| pen.public().into()).collect();
+        let verifier: JustificationVerifier = SessionAuthorityData::new(Vec::new(), None)
+            .with_bls_keys(Some(keys))
+            .into();
+        let hash = THash::from([1u8; 32]);
+        assert_eq!(
+            Verifier::<TBlock>::verify(&verifier, &aggregated_justification(&pens, 3, hash), hash),
+            Ok(())
+        );
+        assert_eq!(
+            Verifier::<TBlock>::verify(&verifier, &aggregated_justification(&pens, 2, hash), hash),
+            Err(VerificationFailure::BadAggregatedMultisignature)
+        );
+        assert_eq!(
+            Verifier::<TBlock>::verify(
+                &verifier,
+                &aggregated_justification(&pens, 3, hash),
+                THash::from([2u8; 32])
+            ),
+            Err(VerificationFailure::BadAggregatedMultisignature)
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_aggregated_multisignatures_without_keys() {
+        let (pens, _) = generate_bls_pens(4).await;
+        let verifier: JustificationVerifier = SessionAuthorityData::new(Vec::new(), None).into();
+        let hash = THash::from([1u8; 32]);
+        assert_eq!(
+            Verifier::<TBlock>::verify(&verifier, &aggregated_justification(&pens, 4, hash), hash),
+            Err(VerificationFailure::UnknownBlsKeys)
+        );
+    }
 }
@@ -27,18 +27,23 @@ where
         millisecs_per_block,
         justification_rx,
         spawn_handle,
+        justification_sync_requests,
+        justification_pruner,
+        session_map,
+        finalized_block_sender,
         ..
     } = aleph_config;
     let map_updater = SessionMapUpdater::<_, _, B>::new(
         AuthorityProviderImpl::new(client.clone()),
         FinalityNotificatorImpl::new(client.clone()),
+        session_map,
     );
     let session_authorities = map_updater.readonly_session_map();
     spawn_handle.spawn("aleph/updater", None, async move {
         debug!(target: "aleph-party", "SessionMapUpdater has started.");
         map_updater.run(session_period).await
     });
-    let (_, handler_task) = setup_justification_handler(JustificationParams {
+    let (_, handler_task, sync_task) = setup_justification_handler(JustificationParams {
         justification_rx,
         network,
         client,
@@ -46,8 +51,14 @@ where
         session_period,
         millisecs_per_block,
         session_map: session_authorities,
+        justification_sync_requests,
+        justification_pruner,
+        finalized_block_sender,
     });
 
+    spawn_handle.spawn("aleph/justification_sync", None, sync_task);
+    debug!(target: "aleph-party", "JustificationSync has started.");
+
     debug!(target: "aleph-party", "JustificationHandler has started.");
     handler_task.await;
     error!(target: "aleph-party", "JustificationHandler finished.");
@@ -1,21 +1,148 @@
-use log::{debug, error};
+use std::sync::Arc;
+
+use aleph_primitives::{AuthorityId, KEY_TYPE};
+use log::{debug, error, info, warn};
 use sc_client_api::Backend;
 use sc_network::ExHashT;
+use sc_service::SpawnTaskHandle;
 use sp_consensus::SelectChain;
+use sp_keystore::CryptoStore;
 use sp_runtime::traits::Block;
+use tokio::net::TcpListener;
 
 use crate::{
+    address_registry::ChainAddressRegistry,
+    crypto::AuthorityPen,
+    metrics::EventMetrics,
     mpsc,
     network::{
-        ConnectionIO, ConnectionManager, ConnectionManagerConfig, Service as NetworkService,
-        SessionManager, IO as NetworkIO,
+        ConnectionIO, ConnectionManager, ConnectionManagerConfig, Network, NetworkIdentity,
+        ReputationConfig, Service as NetworkService, SessionManager, TcpNetwork,
+        ValidatorConnections, ValidatorNetworkConfig, DEFAULT_RECONNECT_INTERVAL, IO as NetworkIO,
     },
     nodes::{setup_justification_handler, JustificationParams},
     party::{ConsensusParty, ConsensusPartyParams},
     session_map::{AuthorityProviderImpl, FinalityNotificatorImpl, SessionMapUpdater},
-    AlephConfig,
+    AlephConfig, SplitData,
 };
 
+/// Spawns the network manager and the network service on top of the given network, and returns
+/// the session manager used to communicate through them.
+fn spawn_network<B, N>(
+    network: N,
+    connection_manager_config: ConnectionManagerConfig,
+    validator_connections: ValidatorConnections,
+    metrics: Option<EventMetrics>,
+    spawn_handle: &SpawnTaskHandle,
+) -> SessionManager<SplitData<B>>
+where
+    B: Block,
+    N: Network
+        + NetworkIdentity<
+            PeerId = <N as Network>::PeerId,
+            Multiaddress = <N as Network>::Multiaddress,
+        >,
+    <N as Network>::PeerId: Sync,
+    <N as Network>::Multiaddress: Send + Sync,
+    N::EventStream: Send,
+{
+    let (commands_for_network, commands_from_io) = mpsc::unbounded();
+    let (messages_for_network, messages_from_user) = mpsc::unbounded();
+    let (commands_for_service, commands_from_user) = mpsc::unbounded();
+    let (messages_for_service, commands_from_manager) = mpsc::unbounded();
+    let (messages_for_user, messages_from_network) = mpsc::unbounded();
+    let (peer_events_for_manager, peer_events_from_network) = mpsc::unbounded();
+    let (rejected_for_service, rejected_from_user) = mpsc::unbounded();
+
+    let connection_io = ConnectionIO::new(
+        commands_for_network,
+        messages_for_network,
+        commands_from_user,
+        commands_from_manager,
+        messages_from_network,
+        peer_events_from_network,
+        rejected_from_user,
+    );
+    let connection_manager = ConnectionManager::new(
+        network.clone(),
+        connection_manager_config,
+        validator_connections,
+        metrics,
+    );
+    let session_manager = SessionManager::new(
+        commands_for_service,
+        messages_for_service,
+        rejected_for_service,
+    );
+    let network = NetworkService::new(
+        network,
+        spawn_handle.clone(),
+        NetworkIO::new(
+            messages_from_user,
+            messages_for_user,
+            commands_from_io,
+            peer_events_for_manager,
+        ),
+        ReputationConfig::default(),
+    );
+
+    let network_manager_task = async move {
+        connection_io
+            .run(connection_manager)
+            .await
+            .expect("Failed to run new network manager")
+    };
+
+    let network_task = async move { network.run().await };
+
+    spawn_handle.spawn("aleph/network_manager", None, network_manager_task);
+    spawn_handle.spawn("aleph/network", None, network_task);
+    session_manager
+}
+
+/// Starts listening for the connections of the standalone validator network. The network
+/// identifies with the key we sign with in the sessions we are a validator in, and with the first
+/// consensus key in our keystore before the first of them starts. Panics if that is impossible, as
+/// the node was explicitly asked to use the validator network.
+async fn start_tcp_network(
+    config: ValidatorNetworkConfig,
+    keystore: Arc<dyn CryptoStore>,
+    spawn_handle: &SpawnTaskHandle,
+) -> TcpNetwork {
+    let authority_id = match keystore.ed25519_public_keys(KEY_TYPE).await.first() {
+        Some(key) => AuthorityId::from(*key),
+        None => {
+            panic!("No consensus key in the keystore to identify with in the validator network.")
+        }
+    };
+    let pen = match AuthorityPen::new(authority_id, keystore).await {
+        Ok(pen) => pen,
+        Err(e) => panic!(
+            "Cannot sign with the consensus key for the validator network: {:?}",
+            e
+        ),
+    };
+    let listener = match TcpListener::bind(config.listen_address).await {
+        Ok(listener) => listener,
+        Err(e) => panic!(
+            "Cannot listen for validator network connections on {}: {}",
+            config.listen_address, e
+        ),
+    };
+    let external_addresses = match config.external_addresses.is_empty() {
+        true => vec![config.listen_address.to_string()],
+        false => config.external_addresses,
+    };
+    info!(target: "aleph-network", "Validator network listening on {}, reachable at {:?}.", config.listen_address, external_addresses);
+    let network = TcpNetwork::new(pen, external_addresses);
+    spawn_handle.spawn(
+        "aleph/validator_network",
+        None,
+        network.clone().run(listener, DEFAULT_RECONNECT_INTERVAL),
+    );
+    network
+}
+
 pub async fn run_validator_node<B, H, C, BE, SC>(aleph_config: AlephConfig<B, H, C, SC>)
 where
     B: Block,
@@ -31,19 +158,38 @@ where
         select_chain,
         spawn_handle,
         keystore,
+        bls_keystore,
         metrics,
         unit_creation_delay,
+        unit_creation_delay_bounds,
         session_period,
         millisecs_per_block,
         justification_rx,
-        backup_saving_path,
+        backup_store,
+        backup_retention,
+        data_store_config,
+        chain_tracker_config,
+        compression_threshold,
+        use_address_registry,
+        validator_network,
+        justification_sync_requests,
+        justification_pruner,
+        session_map,
+        finalized_block_sender,
+        validator_connections,
         ..
     } = aleph_config;
 
+    // Finality parameters governed on chain override some of these, the config in force is logged
+    // every session.
+    info!(target: "aleph-party", "Local data store config: {:?}", data_store_config);
+    info!(target: "aleph-party", "Local chain tracker config: {:?}", chain_tracker_config);
+
     let block_requester = network.clone();
     let map_updater = SessionMapUpdater::<_, _, B>::new(
         AuthorityProviderImpl::new(client.clone()),
         FinalityNotificatorImpl::new(client.clone()),
+        session_map,
     );
     let session_authorities = map_updater.readonly_session_map();
     spawn_handle.spawn("aleph/updater", None, async move {
@@ -51,7 +197,7 @@ where
         map_updater.run(session_period).await
     });
 
-    let (authority_justification_tx, handler_task) =
+    let (authority_justification_tx, handler_task, sync_task) =
         setup_justification_handler(JustificationParams {
             justification_rx,
             network: network.clone(),
@@ -60,48 +206,53 @@ where
             session_period,
             millisecs_per_block,
             session_map: session_authorities.clone(),
+            justification_sync_requests,
+            justification_pruner,
+            finalized_block_sender,
         });
 
     // Prepare and start the network
-    let (commands_for_network, commands_from_io) = mpsc::unbounded();
-    let (messages_for_network, messages_from_user) = mpsc::unbounded();
-    let (commands_for_service, commands_from_user) = mpsc::unbounded();
-    let (messages_for_service, commands_from_manager) = mpsc::unbounded();
-    let (messages_for_user, messages_from_network) = mpsc::unbounded();
-
-    let connection_io = ConnectionIO::new(
-        commands_for_network,
-        messages_for_network,
-        commands_from_user,
-        commands_from_manager,
-        messages_from_network,
-    );
-    let connection_manager = ConnectionManager::new(
-        network.clone(),
-        ConnectionManagerConfig::with_session_period(&session_period, &millisecs_per_block),
-    );
-    let session_manager = SessionManager::new(commands_for_service, messages_for_service);
-    let network = NetworkService::new(
-        network.clone(),
-        spawn_handle.clone(),
-        NetworkIO::new(messages_from_user, messages_for_user, commands_from_io),
-    );
-
-    let network_manager_task = async move {
-        connection_io
-            .run(connection_manager)
-            .await
-            .expect("Failed to run new network manager")
+    let connection_manager_config =
+        ConnectionManagerConfig::with_session_period(&session_period, &millisecs_per_block)
+            .with_compression_threshold(compression_threshold);
+    let connection_manager_config = match use_address_registry {
+        true => connection_manager_config
+            .with_address_registry(Arc::new(ChainAddressRegistry::new(client.clone()))),
+        false => connection_manager_config,
     };
-
-    let network_task = async move { network.run().await };
+    let event_metrics = metrics.as_ref().map(|metrics| metrics.events().clone());
+    let tcp_network = match validator_network {
+        Some(config) => {
+            if !use_address_registry {
+                warn!(target: "aleph-network", "The validator network relies on the address registry to find other validators, but it is disabled.");
+            }
+            Some(start_tcp_network(config, keystore.clone(), &spawn_handle).await)
+        }
+        None => None,
+    };
+    let session_manager = match tcp_network {
+        Some(tcp_network) => spawn_network(
+            tcp_network,
+            connection_manager_config,
+            validator_connections,
+            event_metrics,
+            &spawn_handle,
+        ),
+        None => spawn_network(
+            network,
+            connection_manager_config,
+            validator_connections,
+            event_metrics,
+            &spawn_handle,
+        ),
+    };
+    debug!(target: "aleph-party", "Network has started.");
 
     spawn_handle.spawn("aleph/justification_handler", None, handler_task);
     debug!(target: "aleph-party", "JustificationHandler has started.");
 
-    spawn_handle.spawn("aleph/network_manager", None, network_manager_task);
-    spawn_handle.spawn("aleph/network", None, network_task);
-    debug!(target: "aleph-party", "Network has started.");
+    spawn_handle.spawn("aleph/justification_sync", None, sync_task);
+    debug!(target: "aleph-party", "JustificationSync has started.");
 
     let party = ConsensusParty::new(ConsensusPartyParams {
         session_manager,
@@ -111,11 +262,16 @@ where
         client,
         select_chain,
         keystore,
+        bls_keystore,
         block_requester,
         metrics,
         authority_justification_tx,
         unit_creation_delay,
-        backup_saving_path,
+        unit_creation_delay_bounds,
+        backup_store,
+        backup_retention,
+        data_store_config,
+        chain_tracker_config,
     });
 
     debug!(target: "aleph-party", "Consensus party has started.");
@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use aleph_bft::{Keychain as BftKeychain, SignatureSet, SpawnHandle};
+use aleph_bft::{Keychain as BftKeychain, MultiKeychain, SpawnHandle};
 use aleph_bft_rmc::{DoublingDelayScheduler, ReliableMulticast};
 use futures::{
     channel::{mpsc, oneshot},
@@ -11,8 +11,9 @@ use sc_client_api::HeaderBackend;
 use sp_runtime::traits::{Block, Header};
 
 use crate::{
-    aggregation::{BlockSignatureAggregator, RmcNetworkData, SignableHash, IO as AggregatorIO},
-    crypto::{Keychain, Signature},
+    aggregation::{
+        BlockSignatureAggregator, RmcMessage, RmcNetworkData, SignableHash, IO as AggregatorIO,
+    },
     justification::{AlephJustification, JustificationNotification},
     metrics::Checkpoint,
     network::DataNetwork,
@@ -27,21 +28,25 @@ pub struct IO<B: Block> {
 }
 
 type SignableBlockHash<B> = SignableHash<<B as Block>::Hash>;
-type Rmc<'a, B> = ReliableMulticast<'a, SignableBlockHash<B>, Keychain>;
+type Rmc<'a, B, K> = ReliableMulticast<'a, SignableBlockHash<B>, K>;
 
-async fn process_new_block_data<B, N>(
+async fn process_new_block_data<B, K, N>(
     aggregator: &mut AggregatorIO<
         B::Hash,
         RmcNetworkData<B>,
+        RmcMessage<B, K>,
         N,
-        SignatureSet<Signature>,
-        Rmc<'_, B>,
+        <K as MultiKeychain>::PartialMultisignature,
+        Rmc<'_, B, K>,
     >,
     block: BlockHashNum<B>,
     session_boundaries: &SessionBoundaries<B>,
     metrics: &Option<Metrics<<B::Header as Header>::Hash>>,
 ) where
     B: Block,
+    K: MultiKeychain,
+    RmcMessage<B, K>: Into<RmcNetworkData<B>>,
+    RmcNetworkData<B>: TryInto<RmcMessage<B, K>>,
     N: DataNetwork<RmcNetworkData<B>>,
     <B as Block>::Hash: AsRef<[u8]>,
 {
@@ -56,19 +61,20 @@ async fn process_new_block_data<B, N>(
     }
 }
 
-fn process_hash<B, C>(
+fn process_hash<B, C, S>(
     hash: B::Hash,
-    multisignature: SignatureSet<Signature>,
+    multisignature: S,
     justifications_for_chain: &mpsc::UnboundedSender<JustificationNotification<B>>,
     client: &Arc<C>,
 ) where
     B: Block,
     C: HeaderBackend<B> + Send + Sync + 'static,
+    S: Into<AlephJustification>,
 {
     let number = client.number(hash).unwrap().unwrap();
     // The unwrap might actually fail if data availability is not implemented correctly.
     let notification = JustificationNotification {
-        justification: AlephJustification::CommitteeMultisignature(multisignature),
+        justification: multisignature.into(),
         hash,
         number,
     };
@@ -77,13 +83,14 @@ fn process_hash<B, C>(
     }
 }
 
-async fn run_aggregator<B, C, N>(
+async fn run_aggregator<B, C, K, N>(
     mut aggregator: AggregatorIO<
         B::Hash,
         RmcNetworkData<B>,
+        RmcMessage<B, K>,
         N,
-        SignatureSet<Signature>,
-        Rmc<'_, B>,
+        <K as MultiKeychain>::PartialMultisignature,
+        Rmc<'_, B, K>,
     >,
     io: IO<B>,
     client: Arc<C>,
@@ -93,6 +100,10 @@ async fn run_aggregator<B, C, N>(
 ) where
     B: Block,
     C: HeaderBackend<B> + Send + Sync + 'static,
+    K: MultiKeychain,
+    K::PartialMultisignature: Into<AlephJustification>,
+    RmcMessage<B, K>: Into<RmcNetworkData<B>>,
+    RmcNetworkData<B>: TryInto<RmcMessage<B, K>>,
     N: DataNetwork<RmcNetworkData<B>>,
     <B as Block>::Hash: AsRef<[u8]>,
 {
@@ -137,18 +148,22 @@ async fn run_aggregator<B, C, N>(
 }
 
 /// Runs the justification signature aggregator within a single session.
-pub fn task<B, C, N>(
+pub fn task<B, C, K, N>(
     subtask_common: AuthoritySubtaskCommon,
     client: Arc<C>,
     io: IO<B>,
     session_boundaries: SessionBoundaries<B>,
     metrics: Option<Metrics<<B::Header as Header>::Hash>>,
-    multikeychain: Keychain,
+    multikeychain: K,
     rmc_network: N,
 ) -> Task
 where
     B: Block,
     C: HeaderBackend<B> + Send + Sync + 'static,
+    K: MultiKeychain + 'static,
+    K::PartialMultisignature: Into<AlephJustification>,
+    RmcMessage<B, K>: Into<RmcNetworkData<B>>,
+    RmcNetworkData<B>: TryInto<RmcMessage<B, K>>,
     N: DataNetwork<RmcNetworkData<B>> + 'static,
 {
     let AuthoritySubtaskCommon {
@@ -1,19 +1,30 @@
 use std::{
+    collections::HashMap,
     fmt, fs,
-    fs::File,
+    fs::{File, OpenOptions},
     io,
     io::{Cursor, Read, Write},
     path::{Path, PathBuf},
     str::FromStr,
+    sync::Arc,
 };
 
-use log::{debug, warn};
+use log::{debug, info, warn};
+use parking_lot::Mutex;
+use sp_core::hashing::twox_64;
+
+use crate::metrics::EventMetrics;
 
 const BACKUP_FILE_EXTENSION: &str = ".abfts";
+/// Written at the start of every backup file consisting of records, older files have no header.
+const FILE_HEADER: &[u8] = b"ABFTREC1";
+/// Size of a record header: the length of the payload followed by its checksum.
+const RECORD_HEADER_SIZE: usize = 4 + 8;
 
 #[derive(Debug)]
 pub enum BackupLoadError {
     BackupIncomplete(Vec<usize>),
+    UnknownFormat(PathBuf),
     IOError(io::Error),
 }
 
@@ -27,6 +38,9 @@ impl fmt::Display for BackupLoadError {
                     backups
                 )
             }
+            BackupLoadError::UnknownFormat(path) => {
+                write!(f, "Backup file {:?} is not in a known format", path)
+            }
             BackupLoadError::IOError(err) => {
                 write!(f, "Backup could not be loaded because of IO error: {}", err)
             }
@@ -46,46 +60,283 @@ pub type Saver = Box<dyn Write + Send>;
 pub type Loader = Box<dyn Read + Send>;
 pub type ABFTBackup = (Saver, Loader);
 
-/// Find all `*.abfts` files at `session_path` and return their indexes sorted, if all are present.
-fn get_session_backup_idxs(session_path: &Path) -> Result<Vec<usize>, BackupLoadError> {
-    fs::create_dir_all(&session_path)?;
+/// Storage for AlephBFT backups, kept separately for every session.
+pub trait BackupStore: Send + Sync {
+    /// Loads the existing backup of the session and starts a new one for the current run.
+    ///
+    /// Returns a saver for the new run and a loader yielding the data saved in all the previous
+    /// runs, in order.
+    fn rotate(&self, session_id: u32) -> Result<ABFTBackup, BackupLoadError>;
+
+    /// Removes the backup of the session. Should be done after the end of the session.
+    ///
+    /// Any errors are logged and dropped.
+    fn remove(&self, session_id: u32);
+
+    /// Returns the sessions that have a backup in the store.
+    fn sessions(&self) -> io::Result<Vec<u32>>;
+}
+
+/// Removes the backups of all the sessions that are more than `retention` sessions older than
+/// the session of the last finalized block.
+pub fn prune(store: &dyn BackupStore, finalized_session: u32, retention: u32) {
+    let oldest_kept = finalized_session.saturating_sub(retention);
+    let sessions = match store.sessions() {
+        Ok(sessions) => sessions,
+        Err(err) => {
+            warn!(target: "aleph-party", "Error listing backups to prune: {}", err);
+            return;
+        }
+    };
+    for session_id in sessions.into_iter().filter(|s| *s < oldest_kept) {
+        info!(target: "aleph-party", "Pruning backup of session {}, the last finalized block is in session {}", session_id, finalized_session);
+        store.remove(session_id);
+    }
+}
+
+/// Doesn't store anything, at the cost of limiting crash recoverability.
+pub struct NoBackupStore;
+
+impl BackupStore for NoBackupStore {
+    fn rotate(&self, session_id: u32) -> Result<ABFTBackup, BackupLoadError> {
+        debug!(target: "aleph-party", "Passing empty backup for session {:?} as backups are turned off", session_id);
+        Ok((Box::new(io::sink()), Box::new(io::empty())))
+    }
+
+    fn remove(&self, _session_id: u32) {}
+
+    fn sessions(&self) -> io::Result<Vec<u32>> {
+        Ok(Vec::new())
+    }
+}
+
+/// Frames the data as a record: the length of the data and its checksum, followed by the data.
+fn encode_record(data: &[u8]) -> Vec<u8> {
+    let mut record = Vec::with_capacity(RECORD_HEADER_SIZE + data.len());
+    record.extend((data.len() as u32).to_le_bytes());
+    record.extend(twox_64(data));
+    record.extend(data);
+    record
+}
+
+/// The valid records at the start of some bytes.
+struct Records {
+    /// The concatenated data of the records.
+    data: Vec<u8>,
+    /// The number of records.
+    count: usize,
+    /// The number of bytes the records take.
+    len: usize,
+}
+
+/// Decodes consecutive records, stopping at the first truncated or corrupted one.
+fn decode_records(bytes: &[u8]) -> Records {
+    let mut data = Vec::new();
+    let mut count = 0;
+    let mut offset = 0;
+    while bytes.len() - offset >= RECORD_HEADER_SIZE {
+        let header = &bytes[offset..offset + RECORD_HEADER_SIZE];
+        let len = u32::from_le_bytes(header[..4].try_into().expect("slice has 4 bytes")) as usize;
+        let start = offset + RECORD_HEADER_SIZE;
+        let record = match bytes.get(start..start.saturating_add(len)) {
+            Some(record) if twox_64(record)[..] == header[4..] => record,
+            _ => break,
+        };
+        data.extend(record);
+        count += 1;
+        offset = start + len;
+    }
+    Records {
+        data,
+        count,
+        len: offset,
+    }
+}
+
+/// The state of a backup file, as seen when loading it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BackupFileState {
+    /// All the records are intact.
+    Intact { records: usize },
+    /// The file ends with a truncated or corrupted record, which is cut off together with
+    /// everything after it when the backup is loaded.
+    Damaged {
+        records: usize,
+        dropped_bytes: usize,
+    },
+    /// The file was written before records were introduced, so its contents cannot be checked.
+    Unframed,
+}
+
+/// The contents of a backup file.
+struct BackupFile {
+    /// The data to pass to AlephBFT.
+    data: Vec<u8>,
+    /// The length the file should be cut to, so that only the valid data remains.
+    valid_len: usize,
+    state: BackupFileState,
+}
+
+fn check_backup_file(bytes: &[u8]) -> BackupFile {
+    match bytes.strip_prefix(FILE_HEADER) {
+        Some(records) => {
+            let Records { data, count, len } = decode_records(records);
+            let dropped_bytes = records.len() - len;
+            BackupFile {
+                data,
+                valid_len: FILE_HEADER.len() + len,
+                state: match dropped_bytes {
+                    0 => BackupFileState::Intact { records: count },
+                    _ => BackupFileState::Damaged {
+                        records: count,
+                        dropped_bytes,
+                    },
+                },
+            }
+        }
+        // Empty or with the header cut short by a crash.
+        None if FILE_HEADER.starts_with(bytes) => BackupFile {
+            data: Vec::new(),
+            valid_len: 0,
+            state: match bytes.len() {
+                0 => BackupFileState::Intact { records: 0 },
+                dropped_bytes => BackupFileState::Damaged {
+                    records: 0,
+                    dropped_bytes,
+                },
+            },
+        },
+        None => BackupFile {
+            data: bytes.to_vec(),
+            valid_len: bytes.len(),
+            state: BackupFileState::Unframed,
+        },
+    }
+}
+
+/// Loads the data of a backup file, cutting off a damaged record at its end.
+fn load_backup_file(path: &Path, file: &mut File) -> Result<BackupFile, BackupLoadError> {
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+    let backup_file = check_backup_file(&bytes);
+    if let BackupFileState::Damaged { dropped_bytes, .. } = backup_file.state {
+        warn!(target: "aleph-party", "Cutting off {} bytes of a damaged record at the end of backup file {:?}", dropped_bytes, path);
+        file.set_len(backup_file.valid_len as u64)?;
+    }
+    Ok(backup_file)
+}
+
+/// Writes every chunk of data as a separate record.
+struct RecordWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> RecordWriter<W> {
+    /// Starts writing records to an empty file.
+    fn create(mut inner: W) -> io::Result<Self> {
+        inner.write_all(FILE_HEADER)?;
+        inner.flush()?;
+        Ok(RecordWriter { inner })
+    }
+}
+
+impl<W: Write> Write for RecordWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write_all(&encode_record(buf))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Find all `*.abfts` files at `session_path` and return their indexes sorted.
+fn find_session_backup_idxs(session_path: &Path) -> io::Result<Vec<usize>> {
     let mut session_backups: Vec<_> = fs::read_dir(&session_path)?
         .filter_map(|r| r.ok())
         .filter_map(|x| x.file_name().into_string().ok())
         .filter_map(|s| usize::from_str(s.strip_suffix(BACKUP_FILE_EXTENSION)?).ok())
         .collect();
     session_backups.sort_unstable();
-    if !session_backups.iter().cloned().eq(0..session_backups.len()) {
+    Ok(session_backups)
+}
+
+fn is_complete(session_idxs: &[usize]) -> bool {
+    session_idxs.iter().cloned().eq(0..session_idxs.len())
+}
+
+/// Find all `*.abfts` files at `session_path` and return their indexes sorted, if all are present.
+fn get_session_backup_idxs(session_path: &Path) -> Result<Vec<usize>, BackupLoadError> {
+    fs::create_dir_all(&session_path)?;
+    let session_backups = find_session_backup_idxs(session_path)?;
+    if !is_complete(&session_backups) {
         return Err(BackupLoadError::BackupIncomplete(session_backups));
     }
     Ok(session_backups)
 }
 
+fn run_path(session_path: &Path, index: usize) -> PathBuf {
+    session_path.join(format!("{}{}", index, BACKUP_FILE_EXTENSION))
+}
+
 /// Load session backup at path `session_path` from all `session_idxs`.
 fn load_backup(session_path: &Path, session_idxs: &[usize]) -> Result<Loader, BackupLoadError> {
     let mut buffer = Vec::new();
     for index in session_idxs.iter() {
-        let load_path = session_path.join(format!("{}{}", index, BACKUP_FILE_EXTENSION));
-        File::open(load_path)?.read_to_end(&mut buffer)?;
+        let load_path = run_path(session_path, *index);
+        let mut file = OpenOptions::new().read(true).write(true).open(&load_path)?;
+        buffer.extend(load_backup_file(&load_path, &mut file)?.data);
     }
     Ok(Box::new(Cursor::new(buffer)))
 }
 
 /// Get path of next backup file in session.
 fn get_next_path(session_path: &Path, session_idxs: &[usize]) -> PathBuf {
-    session_path.join(format!(
-        "{}{}",
-        session_idxs.last().map_or(0, |i| i + 1),
-        BACKUP_FILE_EXTENSION,
-    ))
+    run_path(session_path, session_idxs.last().map_or(0, |i| i + 1))
 }
 
-/// Loads the existing backups, and opens a new backup file to write to.
-///
-/// `backup_path` is the path to the backup directory (i.e. the argument to `--backup-saving-path`).
+fn remove_path(path: &Path, session_id: u32, remove: impl Fn(&Path) -> io::Result<()>) {
+    match remove(path) {
+        Ok(()) => {
+            debug!(target: "aleph-party", "Removed backup for session {}", session_id);
+        }
+        Err(err) => {
+            if err.kind() != io::ErrorKind::NotFound {
+                warn!(target: "aleph-party", "Error cleaning up backup for session {}: {}", session_id, err);
+            }
+        }
+    }
+}
+
+/// Lists the sessions in a backup directory, as given by the names of the entries of the right
+/// kind, either directories or files.
+fn list_sessions(path: &Path, directories: bool) -> io::Result<Vec<u32>> {
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    let mut sessions = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() != directories {
+            continue;
+        }
+        let name = entry.file_name();
+        let session_id = name.to_str().and_then(|name| match directories {
+            true => u32::from_str(name).ok(),
+            false => u32::from_str(name.strip_suffix(BACKUP_FILE_EXTENSION)?).ok(),
+        });
+        sessions.extend(session_id);
+    }
+    Ok(sessions)
+}
+
+/// Keeps every run of a session in a separate file of checksummed records.
 ///
-/// Returns the newly-created file (opened for writing), and the concatenation of the contents of
-/// all existing files.
+/// A damaged record at the end of a file, e.g. one cut short by a crash, is dropped when the
+/// backup is loaded.
 ///
 /// Current directory structure (this is an implementation detail, not part of the public API):
 ///   backup-stash/      - the main directory, backup_path/--backup-saving-path
@@ -94,52 +345,488 @@ fn get_next_path(session_path: &Path, session_idxs: &[usize]) -> PathBuf {
 ///       |-- 1.abfts    - each restart after a crash will cause another one to be created
 ///       |-- 2.abfts    - these numbers count up sequentially
 ///       `-- 3.abfts
-pub fn rotate(
-    backup_path: Option<PathBuf>,
-    session_id: u32,
-) -> Result<ABFTBackup, BackupLoadError> {
-    debug!(target: "aleph-party", "Loading AlephBFT backup for session {:?}", session_id);
-    let session_path = if let Some(path) = backup_path {
-        path.join(format!("{}", session_id))
-    } else {
-        debug!(target: "aleph-party", "Passing empty backup for session {:?} as no backup path was provided", session_id);
-        return Ok((Box::new(io::sink()), Box::new(io::empty())));
-    };
-    debug!(target: "aleph-party", "Loading backup for session {:?} at path {:?}", session_id, session_path);
+pub struct DirectoryBackupStore {
+    path: PathBuf,
+}
 
-    let session_backup_idxs = get_session_backup_idxs(&session_path)?;
+impl DirectoryBackupStore {
+    pub fn new(path: PathBuf) -> Self {
+        DirectoryBackupStore { path }
+    }
 
-    let backup_loader = load_backup(&session_path, &session_backup_idxs)?;
+    fn session_path(&self, session_id: u32) -> PathBuf {
+        self.path.join(session_id.to_string())
+    }
+}
 
-    let next_backup_path = get_next_path(&session_path, &session_backup_idxs);
-    debug!(target: "aleph-party", "Loaded backup for session {:?}. Creating new backup file at {:?}", session_id, next_backup_path);
-    let backup_saver = Box::new(File::create(next_backup_path)?);
+impl BackupStore for DirectoryBackupStore {
+    fn rotate(&self, session_id: u32) -> Result<ABFTBackup, BackupLoadError> {
+        let session_path = self.session_path(session_id);
+        debug!(target: "aleph-party", "Loading backup for session {:?} at path {:?}", session_id, session_path);
 
-    debug!(target: "aleph-party", "Backup rotation done for session {:?}", session_id);
-    Ok((backup_saver, backup_loader))
+        let session_backup_idxs = get_session_backup_idxs(&session_path)?;
+
+        let backup_loader = load_backup(&session_path, &session_backup_idxs)?;
+
+        let next_backup_path = get_next_path(&session_path, &session_backup_idxs);
+        debug!(target: "aleph-party", "Loaded backup for session {:?}. Creating new backup file at {:?}", session_id, next_backup_path);
+        let backup_saver = Box::new(RecordWriter::create(File::create(next_backup_path)?)?);
+
+        debug!(target: "aleph-party", "Backup rotation done for session {:?}", session_id);
+        Ok((backup_saver, backup_loader))
+    }
+
+    fn remove(&self, session_id: u32) {
+        remove_path(
+            &self.session_path(session_id),
+            session_id,
+            fs::remove_dir_all,
+        );
+    }
+
+    fn sessions(&self) -> io::Result<Vec<u32>> {
+        list_sessions(&self.path, true)
+    }
 }
 
-/// Removes the backup directory for a session.
-///
-/// `backup_path` is the path to the backup directory (i.e. the argument to `--backup-saving-path`).
-/// If it is `None`, nothing is done.
+/// Keeps all runs of a session in a single append-only file of checksummed records.
 ///
-/// Any filesystem errors are logged and dropped.
+/// A damaged record at the end of the file, e.g. one cut short by a crash, is dropped when the
+/// backup is loaded.
 ///
-/// This should be done after the end of the session.
-pub fn remove(path: Option<PathBuf>, session_id: u32) {
-    let path = match path {
-        Some(path) => path.join(session_id.to_string()),
-        None => return,
+/// Directory structure (this is an implementation detail, not part of the public API):
+///   backup-stash/      - the main directory, backup_path/--backup-saving-path
+///   |-- 18722.abfts    - records of all the runs of a session
+///   `-- 18723.abfts
+pub struct AppendOnlyBackupStore {
+    path: PathBuf,
+}
+
+impl AppendOnlyBackupStore {
+    pub fn new(path: PathBuf) -> Self {
+        AppendOnlyBackupStore { path }
+    }
+
+    fn session_path(&self, session_id: u32) -> PathBuf {
+        self.path
+            .join(format!("{}{}", session_id, BACKUP_FILE_EXTENSION))
+    }
+}
+
+impl BackupStore for AppendOnlyBackupStore {
+    fn rotate(&self, session_id: u32) -> Result<ABFTBackup, BackupLoadError> {
+        let session_path = self.session_path(session_id);
+        debug!(target: "aleph-party", "Loading backup for session {:?} at path {:?}", session_id, session_path);
+
+        fs::create_dir_all(&self.path)?;
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&session_path)?;
+        let BackupFile {
+            data,
+            valid_len,
+            state,
+        } = load_backup_file(&session_path, &mut file)?;
+        let backup_saver = match (state, valid_len) {
+            (BackupFileState::Unframed, _) => {
+                return Err(BackupLoadError::UnknownFormat(session_path))
+            }
+            (_, 0) => RecordWriter::create(file)?,
+            _ => RecordWriter { inner: file },
+        };
+
+        debug!(target: "aleph-party", "Backup rotation done for session {:?}", session_id);
+        Ok((Box::new(backup_saver), Box::new(Cursor::new(data))))
+    }
+
+    fn remove(&self, session_id: u32) {
+        remove_path(&self.session_path(session_id), session_id, fs::remove_file);
+    }
+
+    fn sessions(&self) -> io::Result<Vec<u32>> {
+        list_sessions(&self.path, false)
+    }
+}
+
+/// The state of the backup of a single session, as seen when loading it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionBackupState {
+    /// The backup files of all the runs, in order.
+    Runs(Vec<(PathBuf, BackupFileState)>),
+    /// Some runs are missing, so the backup cannot be loaded. Contains the present runs.
+    Incomplete(Vec<usize>),
+}
+
+/// Checks the backups of all the sessions under `backup_path`, in either layout, without
+/// modifying anything. Entries that are not backups are skipped.
+pub fn verify_backups(backup_path: &Path) -> io::Result<Vec<(u32, SessionBackupState)>> {
+    let check = |path: PathBuf| -> io::Result<(PathBuf, BackupFileState)> {
+        let state = check_backup_file(&fs::read(&path)?).state;
+        Ok((path, state))
     };
-    match fs::remove_dir_all(&path) {
-        Ok(()) => {
-            debug!(target: "aleph-party", "Removed backup for session {}", session_id);
+    let mut sessions = Vec::new();
+    for entry in fs::read_dir(backup_path)? {
+        let path = entry?.path();
+        let name = match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) => name,
+            None => continue,
+        };
+        if path.is_dir() {
+            let session_id = match u32::from_str(name) {
+                Ok(session_id) => session_id,
+                Err(_) => continue,
+            };
+            let session_idxs = find_session_backup_idxs(&path)?;
+            let state = match is_complete(&session_idxs) {
+                true => SessionBackupState::Runs(
+                    session_idxs
+                        .into_iter()
+                        .map(|index| check(run_path(&path, index)))
+                        .collect::<io::Result<_>>()?,
+                ),
+                false => SessionBackupState::Incomplete(session_idxs),
+            };
+            sessions.push((session_id, state));
+        } else if let Some(Ok(session_id)) =
+            name.strip_suffix(BACKUP_FILE_EXTENSION).map(u32::from_str)
+        {
+            sessions.push((session_id, SessionBackupState::Runs(vec![check(path)?])));
         }
-        Err(err) => {
-            if err.kind() != io::ErrorKind::NotFound {
-                warn!(target: "aleph-party", "Error cleaning up backup for session {}: {}", session_id, err);
-            }
+    }
+    sessions.sort_unstable_by_key(|(session_id, _)| *session_id);
+    Ok(sessions)
+}
+
+type SessionRuns = Arc<Mutex<Vec<Vec<u8>>>>;
+
+/// Keeps the backups in memory, for tests.
+#[derive(Clone, Default)]
+pub struct InMemoryBackupStore {
+    sessions: Arc<Mutex<HashMap<u32, SessionRuns>>>,
+}
+
+impl InMemoryBackupStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// Appends data to the last run of a session.
+struct InMemorySaver {
+    runs: SessionRuns,
+}
+
+impl Write for InMemorySaver {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.runs
+            .lock()
+            .last_mut()
+            .expect("a run is created on rotation")
+            .extend(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl BackupStore for InMemoryBackupStore {
+    fn rotate(&self, session_id: u32) -> Result<ABFTBackup, BackupLoadError> {
+        let runs = self.sessions.lock().entry(session_id).or_default().clone();
+        let data = {
+            let mut runs = runs.lock();
+            let data = runs.concat();
+            runs.push(Vec::new());
+            data
+        };
+        Ok((
+            Box::new(InMemorySaver { runs }),
+            Box::new(Cursor::new(data)),
+        ))
+    }
+
+    fn remove(&self, session_id: u32) {
+        self.sessions.lock().remove(&session_id);
+    }
+
+    fn sessions(&self) -> io::Result<Vec<u32>> {
+        Ok(self.sessions.lock().keys().cloned().collect())
+    }
+}
+
+/// Reports the number of bytes AlephBFT writes to its backup, not counting record framing.
+struct MeteredSaver {
+    inner: Saver,
+    metrics: EventMetrics,
+}
+
+impl Write for MeteredSaver {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.metrics.report_backup_bytes(written);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Makes the saver of the backup report the bytes written, if metrics are enabled.
+pub fn with_metrics(backup: ABFTBackup, metrics: Option<EventMetrics>) -> ABFTBackup {
+    match metrics {
+        Some(metrics) => {
+            let (saver, loader) = backup;
+            (
+                Box::new(MeteredSaver {
+                    inner: saver,
+                    metrics,
+                }),
+                loader,
+            )
+        }
+        None => backup,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        io::{Read, Write},
+    };
+
+    use super::{
+        check_backup_file, encode_record, prune, verify_backups, AppendOnlyBackupStore,
+        BackupFileState, BackupStore, DirectoryBackupStore, InMemoryBackupStore,
+        SessionBackupState, FILE_HEADER, RECORD_HEADER_SIZE,
+    };
+
+    fn load(store: &impl BackupStore, session_id: u32) -> Vec<u8> {
+        let (_, mut loader) = store.rotate(session_id).unwrap();
+        let mut data = Vec::new();
+        loader.read_to_end(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn in_memory_store_concatenates_runs_of_a_session() {
+        let store = InMemoryBackupStore::new();
+        for (session_id, chunk) in [(1, b"ab"), (2, b"ef"), (1, b"cd"), (2, b"gh")] {
+            let (mut saver, _) = store.rotate(session_id).unwrap();
+            saver.write_all(chunk).unwrap();
+        }
+
+        assert_eq!(load(&store, 1), b"abcd");
+        assert_eq!(load(&store, 2), b"efgh");
+    }
+
+    #[test]
+    fn in_memory_store_removes_session() {
+        let store = InMemoryBackupStore::new();
+        let (mut saver, _) = store.rotate(1).unwrap();
+        saver.write_all(b"ab").unwrap();
+
+        store.remove(1);
+
+        assert!(load(&store, 1).is_empty());
+    }
+
+    #[test]
+    fn prunes_sessions_older_than_retention() {
+        let store = InMemoryBackupStore::new();
+        for session_id in 3..10 {
+            store.rotate(session_id).unwrap();
+        }
+
+        prune(&store, 8, 2);
+
+        let mut sessions = store.sessions().unwrap();
+        sessions.sort_unstable();
+        assert_eq!(sessions, vec![6, 7, 8, 9]);
+    }
+
+    #[test]
+    fn prunes_nothing_early_in_the_chain() {
+        let store = InMemoryBackupStore::new();
+        for session_id in 0..3 {
+            store.rotate(session_id).unwrap();
+        }
+
+        prune(&store, 1, 2);
+
+        assert_eq!(store.sessions().unwrap().len(), 3);
+    }
+
+    /// Saves the chunks in consecutive runs of the sessions, then checks that every run loads
+    /// what the previous ones saved, and that removing a session leaves the others intact.
+    fn check_runs_are_reloaded(store: impl BackupStore) {
+        for (session_id, chunk) in [(1, b"ab"), (2, b"ef"), (1, b"cd"), (2, b"gh")] {
+            let (mut saver, _) = store.rotate(session_id).unwrap();
+            saver.write_all(chunk).unwrap();
+            saver.flush().unwrap();
+        }
+
+        let mut sessions = store.sessions().unwrap();
+        sessions.sort_unstable();
+        assert_eq!(sessions, vec![1, 2]);
+        assert_eq!(load(&store, 1), b"abcd");
+        assert_eq!(load(&store, 2), b"efgh");
+        // The runs of loading add nothing.
+        assert_eq!(load(&store, 1), b"abcd");
+
+        store.remove(1);
+
+        assert_eq!(store.sessions().unwrap(), vec![2]);
+        assert_eq!(load(&store, 2), b"efgh");
+        assert!(load(&store, 1).is_empty());
+    }
+
+    #[test]
+    fn directory_store_reloads_runs_of_a_session() {
+        let dir = tempfile::tempdir().unwrap();
+
+        check_runs_are_reloaded(DirectoryBackupStore::new(dir.path().to_path_buf()));
+    }
+
+    #[test]
+    fn append_only_store_reloads_runs_of_a_session() {
+        let dir = tempfile::tempdir().unwrap();
+
+        check_runs_are_reloaded(AppendOnlyBackupStore::new(dir.path().to_path_buf()));
+    }
+
+    #[test]
+    fn directory_store_refuses_incomplete_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = DirectoryBackupStore::new(dir.path().to_path_buf());
+        for _ in 0..3 {
+            store.rotate(1).unwrap();
         }
+
+        fs::remove_file(dir.path().join("1").join("1.abfts")).unwrap();
+
+        assert!(store.rotate(1).is_err());
+    }
+
+    #[test]
+    fn stores_list_no_sessions_before_first_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("backups");
+
+        assert!(DirectoryBackupStore::new(path.clone())
+            .sessions()
+            .unwrap()
+            .is_empty());
+        assert!(AppendOnlyBackupStore::new(path)
+            .sessions()
+            .unwrap()
+            .is_empty());
+    }
+
+    fn framed(records: &[&[u8]]) -> Vec<u8> {
+        let mut bytes = FILE_HEADER.to_vec();
+        for record in records {
+            bytes.extend(encode_record(record));
+        }
+        bytes
+    }
+
+    #[test]
+    fn decodes_encoded_records() {
+        let bytes = framed(&[b"abc", b"", b"de"]);
+
+        let backup_file = check_backup_file(&bytes);
+
+        assert_eq!(backup_file.data, b"abcde");
+        assert_eq!(backup_file.valid_len, bytes.len());
+        assert_eq!(backup_file.state, BackupFileState::Intact { records: 3 });
+    }
+
+    #[test]
+    fn drops_truncated_record() {
+        let first = framed(&[b"abc"]);
+        let bytes = framed(&[b"abc", b"def"]);
+
+        for len in first.len() + 1..bytes.len() {
+            let backup_file = check_backup_file(&bytes[..len]);
+
+            assert_eq!(backup_file.data, b"abc");
+            assert_eq!(backup_file.valid_len, first.len());
+            assert_eq!(
+                backup_file.state,
+                BackupFileState::Damaged {
+                    records: 1,
+                    dropped_bytes: len - first.len()
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn drops_corrupted_record_and_everything_after_it() {
+        let first = framed(&[b"abc"]);
+        let mut bytes = framed(&[b"abc", b"def", b"gh"]);
+        bytes[first.len() + RECORD_HEADER_SIZE + 1] ^= 1;
+
+        let backup_file = check_backup_file(&bytes);
+
+        assert_eq!(backup_file.data, b"abc");
+        assert_eq!(backup_file.valid_len, first.len());
+    }
+
+    #[test]
+    fn drops_truncated_header() {
+        let backup_file = check_backup_file(&FILE_HEADER[..3]);
+
+        assert!(backup_file.data.is_empty());
+        assert_eq!(backup_file.valid_len, 0);
+    }
+
+    #[test]
+    fn cuts_damaged_tail_of_backup_file_at_last_intact_record() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = AppendOnlyBackupStore::new(dir.path().to_path_buf());
+        let file_path = dir.path().join("1.abfts");
+        let (mut saver, _) = store.rotate(1).unwrap();
+        for chunk in [&b"abc"[..], b"def", b"gh"] {
+            saver.write_all(chunk).unwrap();
+        }
+        saver.flush().unwrap();
+        drop(saver);
+        let intact_len = framed(&[b"abc", b"def"]).len();
+        // Corrupt the last record and leave a partial one after it, as a crash could.
+        let mut bytes = fs::read(&file_path).unwrap();
+        bytes[intact_len + RECORD_HEADER_SIZE] ^= 1;
+        bytes.extend(&encode_record(b"ijk")[..5]);
+        fs::write(&file_path, &bytes).unwrap();
+
+        assert_eq!(
+            verify_backups(dir.path()).unwrap(),
+            vec![(
+                1,
+                SessionBackupState::Runs(vec![(
+                    file_path.clone(),
+                    BackupFileState::Damaged {
+                        records: 2,
+                        dropped_bytes: bytes.len() - intact_len,
+                    }
+                )])
+            )]
+        );
+
+        assert_eq!(load(&store, 1), b"abcdef");
+        assert_eq!(fs::read(&file_path).unwrap(), framed(&[b"abc", b"def"]));
+    }
+
+    #[test]
+    fn passes_unframed_data_through() {
+        let backup_file = check_backup_file(b"some older backup");
+
+        assert_eq!(backup_file.data, b"some older backup");
+        assert_eq!(backup_file.state, BackupFileState::Unframed);
     }
 }
@@ -0,0 +1,557 @@
+//! Detection and reporting of equivocating committee members.
+//!
+//! AlephBFT handles forks internally and does not expose the proofs it learns about, so we look
+//! for them in the fork alerts we receive. AlephBFT keeps its messages private, so the layout of
+//! fork alerts and units of AlephBFT 0.15 is mirrored below, and the proofs carry the units as
+//! `VersionedUnit::V1`. The tests check the layout against the messages of a running AlephBFT
+//! member, so they fail once AlephBFT changes it.
+use std::{collections::HashSet, marker::PhantomData, sync::Arc};
+
+use aleph_bft::{Network as AlephNetwork, NodeIndex, NodeSubset, Recipient};
+use aleph_primitives::{
+    AlephSessionApi, AuthoritySignature, EquivocationProof, SignedUnit, VersionedUnit,
+    ALEPH_BFT_SIGNING_CONTEXT_API_VERSION,
+};
+use codec::{Decode, Encode, Output};
+use futures::{channel::mpsc, StreamExt};
+use log::{debug, info, warn};
+use sc_client_api::HeaderBackend;
+use sp_api::{ApiExt, BlockId, ProvideRuntimeApi};
+use sp_runtime::traits::Block;
+
+use crate::{
+    crypto::{AuthorityVerifier, Signature},
+    data_io::AlephData,
+    finality_params::governing_block,
+    network::AlephNetworkData,
+    SessionId, SessionPeriod,
+};
+
+/// Index of the alert variant of an AlephBFT network message.
+const ALERT_MESSAGE: u8 = 1;
+/// Index of the fork alert variant of an AlephBFT alert message.
+const FORK_ALERT: u8 = 0;
+/// How many fork proofs may wait for the reporter before new ones are dropped.
+const MAX_PENDING_PROOFS: usize = 16;
+
+/// Whether the committee of the session signs AlephBFT messages with the AlephBFT signing
+/// context. Only such signatures can be reported, so that they cannot be confused with anything
+/// else signed with the authority keys. Read from the runtime governing the session, so that the
+/// whole committee switches at once.
+pub fn uses_aleph_bft_context<B, C>(
+    client: &C,
+    session_id: SessionId,
+    session_period: SessionPeriod,
+) -> bool
+where
+    B: Block,
+    C: ProvideRuntimeApi<B>,
+    C::Api: AlephSessionApi<B>,
+{
+    let block = governing_block::<B>(session_id, session_period);
+    match client
+        .runtime_api()
+        .has_api_with::<dyn AlephSessionApi<B>, _>(&BlockId::Number(block), |version| {
+            version >= ALEPH_BFT_SIGNING_CONTEXT_API_VERSION
+        }) {
+        Ok(uses_context) => uses_context,
+        Err(e) => {
+            warn!(target: "aleph-party", "Could not read the version of the session API at block #{:?}, assuming session {:?} signs without the AlephBFT context: {:?}.", block, session_id, e);
+            false
+        }
+    }
+}
+
+/// Mirrors the encoding of the control hash of an AlephBFT 0.15 unit.
+#[derive(Decode)]
+struct ControlHash<B: Block> {
+    _parents: NodeSubset,
+    _combined_hash: B::Hash,
+}
+
+/// Decodes a signed AlephBFT 0.15 unit: its creator, round, control hash, data and session,
+/// followed by the signature.
+fn decode_signed_unit<B: Block>(input: &mut &[u8]) -> Option<SignedUnit<AuthoritySignature>> {
+    let creator = NodeIndex::decode(input).ok()?;
+    let round = u16::decode(input).ok()?;
+    let body = *input;
+    ControlHash::<B>::decode(input).ok()?;
+    AlephData::<B>::decode(input).ok()?;
+    let control_hash_and_data = body[..body.len() - input.len()].to_vec();
+    let session = u64::decode(input).ok()?;
+    let signature = AuthoritySignature::decode(input).ok()?;
+    Some(SignedUnit {
+        unit: VersionedUnit::V1 {
+            creator: creator.0 as u64,
+            round,
+            control_hash_and_data,
+            session,
+        },
+        signature,
+    })
+}
+
+/// Extracts the fork proof from an encoded AlephBFT message, if it is a fork alert.
+fn fork_proof<B: Block>(encoded: &[u8]) -> Option<EquivocationProof<AuthoritySignature>> {
+    let input = &mut &encoded[..];
+    if u8::decode(input).ok()? != ALERT_MESSAGE || u8::decode(input).ok()? != FORK_ALERT {
+        return None;
+    }
+    // The sender of the alert, followed by the two forking units.
+    NodeIndex::decode(input).ok()?;
+    let first = decode_signed_unit::<B>(input)?;
+    let second = decode_signed_unit::<B>(input)?;
+    Some(EquivocationProof { first, second })
+}
+
+/// Keeps the variant indices an encoding starts with and drops the rest, so that recognizing
+/// fork alerts neither allocates nor copies the messages.
+#[derive(Default)]
+struct VariantPrefix {
+    bytes: [u8; 2],
+    len: usize,
+}
+
+impl Output for VariantPrefix {
+    fn write(&mut self, bytes: &[u8]) {
+        let missing = (self.bytes.len() - self.len).min(bytes.len());
+        self.bytes[self.len..self.len + missing].copy_from_slice(&bytes[..missing]);
+        self.len += missing;
+    }
+}
+
+fn is_fork_alert<B: Block>(message: &AlephNetworkData<B>) -> bool {
+    let mut prefix = VariantPrefix::default();
+    message.encode_to(&mut prefix);
+    prefix.len == prefix.bytes.len() && prefix.bytes == [ALERT_MESSAGE, FORK_ALERT]
+}
+
+/// Returns the offender if the proof shows an equivocation in the session.
+fn offender(
+    verifier: &AuthorityVerifier,
+    session_id: u64,
+    proof: &EquivocationProof<AuthoritySignature>,
+) -> Option<u64> {
+    let coord = proof.coord()?;
+    if coord.session != session_id {
+        return None;
+    }
+    let creator = NodeIndex(coord.creator as usize);
+    [&proof.first, &proof.second]
+        .iter()
+        .all(|signed_unit| {
+            verifier.verify(
+                &signed_unit.unit.signing_payload(),
+                &Signature::from(signed_unit.signature.clone()),
+                creator,
+            )
+        })
+        .then(|| coord.creator)
+}
+
+/// Reports the equivocations found by a `ReportingNetwork` of a single session, at most once per
+/// offender.
+pub struct EquivocationReporter<B, C>
+where
+    B: Block,
+    C: HeaderBackend<B> + ProvideRuntimeApi<B>,
+    C::Api: AlephSessionApi<B>,
+{
+    client: Arc<C>,
+    verifier: AuthorityVerifier,
+    session_id: u64,
+    reported: HashSet<u64>,
+    proofs: mpsc::Receiver<EquivocationProof<AuthoritySignature>>,
+    _phantom: PhantomData<B>,
+}
+
+impl<B, C> EquivocationReporter<B, C>
+where
+    B: Block,
+    C: HeaderBackend<B> + ProvideRuntimeApi<B>,
+    C::Api: AlephSessionApi<B>,
+{
+    fn report(&mut self, proof: EquivocationProof<AuthoritySignature>) {
+        let offender = match offender(&self.verifier, self.session_id, &proof) {
+            Some(offender) => offender,
+            None => {
+                debug!(target: "aleph-party", "Received an invalid fork proof in session {:?}.", self.session_id);
+                return;
+            }
+        };
+        if self.reported.contains(&offender) {
+            return;
+        }
+        info!(target: "aleph-party", "Reporting equivocation of member {:?} in session {:?}.", offender, self.session_id);
+        let best_hash = self.client.info().best_hash;
+        match self
+            .client
+            .runtime_api()
+            .submit_report_equivocation_unsigned_extrinsic(&BlockId::Hash(best_hash), proof)
+        {
+            Ok(Some(())) => {
+                self.reported.insert(offender);
+            }
+            Ok(None) => {
+                warn!(target: "aleph-party", "Equivocation report for member {:?} was not accepted by the transaction pool.", offender)
+            }
+            Err(e) => {
+                warn!(target: "aleph-party", "Failed to submit equivocation report for member {:?}: {:?}.", offender, e)
+            }
+        }
+    }
+
+    /// Reports the proofs until the network is gone.
+    pub async fn run(mut self) {
+        while let Some(proof) = self.proofs.next().await {
+            self.report(proof);
+        }
+    }
+}
+
+/// Passes every message received by AlephBFT on, handing the fork proofs over to the
+/// `EquivocationReporter`.
+pub struct ReportingNetwork<B: Block, N> {
+    inner: N,
+    proofs: Option<mpsc::Sender<EquivocationProof<AuthoritySignature>>>,
+    _phantom: PhantomData<B>,
+}
+
+impl<B: Block, N> ReportingNetwork<B, N> {
+    /// Creates the network together with the reporter of the proofs it finds.
+    pub fn new<C>(
+        inner: N,
+        client: Arc<C>,
+        verifier: AuthorityVerifier,
+        session_id: SessionId,
+    ) -> (Self, EquivocationReporter<B, C>)
+    where
+        C: HeaderBackend<B> + ProvideRuntimeApi<B>,
+        C::Api: AlephSessionApi<B>,
+    {
+        let (proofs_for_reporter, proofs) = mpsc::channel(MAX_PENDING_PROOFS);
+        let network = ReportingNetwork {
+            inner,
+            proofs: Some(proofs_for_reporter),
+            _phantom: PhantomData,
+        };
+        let reporter = EquivocationReporter {
+            client,
+            verifier,
+            session_id: session_id.0 as u64,
+            reported: HashSet::new(),
+            proofs,
+            _phantom: PhantomData,
+        };
+        (network, reporter)
+    }
+
+    /// Creates the network for a session whose equivocations cannot be reported.
+    pub fn not_reporting(inner: N) -> Self {
+        ReportingNetwork {
+            inner,
+            proofs: None,
+            _phantom: PhantomData,
+        }
+    }
+
+    fn inspect(&mut self, message: &AlephNetworkData<B>) {
+        let proofs = match &mut self.proofs {
+            Some(proofs) => proofs,
+            None => return,
+        };
+        if !is_fork_alert(message) {
+            return;
+        }
+        let proof = match message.using_encoded(fork_proof::<B>) {
+            Some(proof) => proof,
+            None => return,
+        };
+        if let Err(e) = proofs.try_send(proof) {
+            debug!(target: "aleph-party", "Dropping a fork proof, the reporter is not keeping up: {:?}.", e);
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<B, N> AlephNetwork<AlephNetworkData<B>> for ReportingNetwork<B, N>
+where
+    B: Block,
+    N: AlephNetwork<AlephNetworkData<B>>,
+{
+    fn send(&self, data: AlephNetworkData<B>, recipient: Recipient) {
+        self.inner.send(data, recipient)
+    }
+
+    async fn next_event(&mut self) -> Option<AlephNetworkData<B>> {
+        let data = self.inner.next_event().await;
+        if let Some(data) = &data {
+            self.inspect(data);
+        }
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{future::Future, io, sync::Arc, time::Duration};
+
+    use aleph_bft::{
+        DataProvider, FinalizationHandler, Hasher as _, Keychain as _, LocalIO,
+        Network as AlephNetwork, NodeCount, NodeIndex, Recipient, SpawnHandle, TaskHandle,
+    };
+    use aleph_primitives::{AuthoritySignature, SignedUnit, VersionedUnit};
+    use codec::{Decode, Encode};
+    use futures::{
+        channel::{mpsc, oneshot},
+        StreamExt, TryFutureExt,
+    };
+
+    use super::{
+        decode_signed_unit, fork_proof, is_fork_alert, offender, ReportingNetwork, ALERT_MESSAGE,
+        FORK_ALERT, MAX_PENDING_PROOFS,
+    };
+    use crate::{
+        crypto::{AuthorityVerifier, Keychain, Signature},
+        data_io::AlephData,
+        default_aleph_config,
+        network::{mock::crypto_basics, AlephNetworkData},
+        testing::mocks::TBlock,
+        Hasher,
+    };
+
+    const SESSION: u64 = 3;
+
+    #[derive(Clone)]
+    struct Spawner;
+
+    impl SpawnHandle for Spawner {
+        fn spawn(&self, _name: &'static str, task: impl Future<Output = ()> + Send + 'static) {
+            tokio::spawn(task);
+        }
+
+        fn spawn_essential(
+            &self,
+            _name: &'static str,
+            task: impl Future<Output = ()> + Send + 'static,
+        ) -> TaskHandle {
+            Box::pin(tokio::spawn(task).map_err(|_| ()))
+        }
+    }
+
+    struct EmptyData;
+
+    #[async_trait::async_trait]
+    impl DataProvider<AlephData<TBlock>> for EmptyData {
+        async fn get_data(&mut self) -> AlephData<TBlock> {
+            AlephData::Empty
+        }
+    }
+
+    struct IgnoreFinalized;
+
+    #[async_trait::async_trait]
+    impl FinalizationHandler<AlephData<TBlock>> for IgnoreFinalized {
+        async fn data_finalized(&mut self, _data: AlephData<TBlock>) {}
+    }
+
+    struct MockNetwork {
+        sent: mpsc::UnboundedSender<AlephNetworkData<TBlock>>,
+        received: mpsc::UnboundedReceiver<AlephNetworkData<TBlock>>,
+    }
+
+    #[async_trait::async_trait]
+    impl AlephNetwork<AlephNetworkData<TBlock>> for MockNetwork {
+        fn send(&self, data: AlephNetworkData<TBlock>, _recipient: Recipient) {
+            let _ = self.sent.unbounded_send(data);
+        }
+
+        async fn next_event(&mut self) -> Option<AlephNetworkData<TBlock>> {
+            self.received.next().await
+        }
+    }
+
+    /// Runs an AlephBFT member of a single member committee and returns the encoding of the
+    /// first unit it broadcasts, without the message variant indices.
+    async fn aleph_bft_unit(keychain: Keychain) -> Vec<u8> {
+        let (sent, mut messages) = mpsc::unbounded();
+        let (_messages_for_member, received) = mpsc::unbounded();
+        let (_exit_member, exit) = oneshot::channel();
+        let mut config = default_aleph_config(NodeCount(1), NodeIndex(0), SESSION);
+        config.delay_config.unit_creation_delay = Arc::new(|_| Duration::from_millis(10));
+        let local_io = LocalIO::new(EmptyData, IgnoreFinalized, io::sink(), io::empty());
+        tokio::spawn(aleph_bft::run_session(
+            config,
+            local_io,
+            MockNetwork { sent, received },
+            keychain,
+            Spawner,
+            exit,
+        ));
+        let find_unit = async {
+            while let Some(message) = messages.next().await {
+                let encoded = message.encode();
+                // The new unit variant of the unit variant of the message.
+                if encoded.starts_with(&[0, 0]) {
+                    return encoded[2..].to_vec();
+                }
+            }
+            panic!("the member should keep running");
+        };
+        tokio::time::timeout(Duration::from_secs(10), find_unit)
+            .await
+            .expect("the member should broadcast a unit")
+    }
+
+    async fn member_keychain() -> (Keychain, AuthorityVerifier) {
+        let (mut crypto_basics, verifier) = crypto_basics(1).await;
+        let (node_id, pen) = crypto_basics.remove(0);
+        (
+            Keychain::new(node_id, verifier.clone(), pen).with_aleph_bft_context(),
+            verifier,
+        )
+    }
+
+    /// Another unit for the slot of the given one, signed by its creator.
+    async fn forked(
+        signed_unit: &SignedUnit<AuthoritySignature>,
+        keychain: &Keychain,
+    ) -> SignedUnit<AuthoritySignature> {
+        let unit = match signed_unit.unit.clone() {
+            VersionedUnit::V1 {
+                creator,
+                round,
+                mut control_hash_and_data,
+                session,
+            } => {
+                // The data is `AlephData::Empty`, so this changes the last byte of the combined
+                // hash of the parents.
+                let last_hash_byte = control_hash_and_data.len() - 2;
+                control_hash_and_data[last_hash_byte] ^= 1;
+                VersionedUnit::V1 {
+                    creator,
+                    round,
+                    control_hash_and_data,
+                    session,
+                }
+            }
+        };
+        let signature = keychain
+            .sign(Hasher::hash(&unit.aleph_bft_encoding()).as_ref())
+            .await
+            .into();
+        SignedUnit { unit, signature }
+    }
+
+    fn encode_signed_unit(signed_unit: &SignedUnit<AuthoritySignature>) -> Vec<u8> {
+        let mut encoded = signed_unit.unit.aleph_bft_encoding();
+        signed_unit.signature.encode_to(&mut encoded);
+        encoded
+    }
+
+    async fn fork_alert(keychain: &Keychain) -> (Vec<u8>, SignedUnit<AuthoritySignature>) {
+        let encoded_unit = aleph_bft_unit(keychain.clone()).await;
+        let unit = decode_signed_unit::<TBlock>(&mut encoded_unit.as_slice())
+            .expect("the unit should decode");
+        let fork = forked(&unit, keychain).await;
+        let mut alert = vec![ALERT_MESSAGE, FORK_ALERT];
+        NodeIndex(0).encode_to(&mut alert);
+        alert.extend(encoded_unit);
+        alert.extend(encode_signed_unit(&fork));
+        // No legit units, and the signature of the alert.
+        Vec::<u8>::new().encode_to(&mut alert);
+        let alert_signature = keychain.sign(Hasher::hash(&alert[2..]).as_ref()).await;
+        alert_signature.encode_to(&mut alert);
+        (alert, fork)
+    }
+
+    #[tokio::test]
+    async fn decodes_signed_units_of_aleph_bft() {
+        let (keychain, verifier) = member_keychain().await;
+        let encoded = aleph_bft_unit(keychain).await;
+        let input = &mut encoded.as_slice();
+
+        let signed_unit = decode_signed_unit::<TBlock>(input).expect("the unit should decode");
+
+        assert!(input.is_empty());
+        let coord = signed_unit.unit.coord();
+        assert_eq!((coord.creator, coord.round, coord.session), (0, 0, SESSION));
+        let mut reencoded = signed_unit.unit.aleph_bft_encoding();
+        signed_unit.signature.encode_to(&mut reencoded);
+        assert_eq!(reencoded, encoded);
+        assert!(verifier.verify(
+            &signed_unit.unit.signing_payload(),
+            &Signature::from(signed_unit.signature),
+            NodeIndex(0)
+        ));
+    }
+
+    #[tokio::test]
+    async fn signatures_without_context_do_not_verify() {
+        let (mut crypto_basics, verifier) = crypto_basics(1).await;
+        let (node_id, pen) = crypto_basics.remove(0);
+        let encoded = aleph_bft_unit(Keychain::new(node_id, verifier.clone(), pen)).await;
+
+        let signed_unit =
+            decode_signed_unit::<TBlock>(&mut encoded.as_slice()).expect("the unit should decode");
+
+        assert!(!verifier.verify(
+            &signed_unit.unit.signing_payload(),
+            &Signature::from(signed_unit.signature),
+            NodeIndex(0)
+        ));
+    }
+
+    #[tokio::test]
+    async fn finds_fork_proof_in_fork_alert() {
+        let (keychain, verifier) = member_keychain().await;
+        let (alert, fork) = fork_alert(&keychain).await;
+        let message = AlephNetworkData::<TBlock>::decode(&mut alert.as_slice())
+            .expect("AlephBFT should decode the alert");
+        assert!(is_fork_alert(&message));
+
+        let proof = message
+            .using_encoded(fork_proof::<TBlock>)
+            .expect("the alert carries a fork proof");
+
+        assert_eq!(proof.second, fork);
+        assert_eq!(offender(&verifier, SESSION, &proof), Some(0));
+        assert_eq!(offender(&verifier, SESSION + 1, &proof), None);
+    }
+
+    #[tokio::test]
+    async fn passes_fork_proofs_to_reporter() {
+        let (keychain, verifier) = member_keychain().await;
+        let (alert, _) = fork_alert(&keychain).await;
+        let encoded_unit = aleph_bft_unit(keychain).await;
+        let mut new_unit = vec![0, 0];
+        new_unit.extend(encoded_unit);
+        let (sent, _) = mpsc::unbounded();
+        let (messages_for_network, received) = mpsc::unbounded();
+        let (proofs_for_reporter, mut proofs) = mpsc::channel(MAX_PENDING_PROOFS);
+        let mut network = ReportingNetwork {
+            inner: MockNetwork { sent, received },
+            proofs: Some(proofs_for_reporter),
+            _phantom: Default::default(),
+        };
+
+        for encoded in [new_unit, alert] {
+            let message = AlephNetworkData::<TBlock>::decode(&mut encoded.as_slice())
+                .expect("AlephBFT should decode the message");
+            messages_for_network
+                .unbounded_send(message.clone())
+                .expect("the network should be running");
+            assert_eq!(
+                network.next_event().await.map(|data| data.encode()),
+                Some(message.encode())
+            );
+        }
+
+        let proof = proofs.try_next().expect("the alert should carry a proof");
+        assert_eq!(
+            proof.and_then(|proof| offender(&verifier, SESSION, &proof)),
+            Some(0)
+        );
+        assert!(proofs.try_next().is_err());
+    }
+}
@@ -1,36 +1,51 @@
 use aleph_bft::{Config, LocalIO, SpawnHandle};
+use aleph_primitives::AlephSessionApi;
 use futures::channel::oneshot;
 use log::debug;
 use sc_client_api::HeaderBackend;
+use sp_api::ProvideRuntimeApi;
 use sp_runtime::traits::Block;
 
 use crate::{
     crypto::Keychain,
     data_io::{AlephData, OrderedDataInterpreter},
     network::{AlephNetworkData, DataNetwork, NetworkWrapper},
-    party::{backup::ABFTBackup, AuthoritySubtaskCommon, Task},
+    party::{
+        backup::ABFTBackup,
+        equivocation::{EquivocationReporter, ReportingNetwork},
+        AuthoritySubtaskCommon, Task,
+    },
 };
 
-/// Runs the member within a single session.
-pub fn task<
-    B: Block,
-    C: HeaderBackend<B> + Send + 'static,
-    ADN: DataNetwork<AlephNetworkData<B>> + 'static,
->(
+/// Runs the member within a single session, reporting the equivocations it learns about if there
+/// is a reporter.
+#[allow(clippy::too_many_arguments)]
+pub fn task<B, C, ADN>(
     subtask_common: AuthoritySubtaskCommon,
     multikeychain: Keychain,
     config: Config,
-    network: NetworkWrapper<AlephNetworkData<B>, ADN>,
+    network: ReportingNetwork<B, NetworkWrapper<AlephNetworkData<B>, ADN>>,
     data_provider: impl aleph_bft::DataProvider<AlephData<B>> + Send + 'static,
     ordered_data_interpreter: OrderedDataInterpreter<B, C>,
     backup: ABFTBackup,
-) -> Task {
+    equivocation_reporter: Option<EquivocationReporter<B, C>>,
+) -> Task
+where
+    B: Block,
+    C: HeaderBackend<B> + ProvideRuntimeApi<B> + Send + Sync + 'static,
+    C::Api: AlephSessionApi<B>,
+    ADN: DataNetwork<AlephNetworkData<B>> + 'static,
+{
     let AuthoritySubtaskCommon {
         spawn_handle,
         session_id,
     } = subtask_common;
     let (stop, exit) = oneshot::channel();
     let local_io = LocalIO::new(data_provider, ordered_data_interpreter, backup.0, backup.1);
+    if let Some(equivocation_reporter) = equivocation_reporter {
+        // Stops once the member drops the network.
+        spawn_handle.spawn("aleph/equivocation_reporter", equivocation_reporter.run());
+    }
 
     let task = {
         let spawn_handle = spawn_handle.clone();
@@ -1,23 +1,31 @@
-use std::{
-    collections::HashSet, default::Default, marker::PhantomData, path::PathBuf, sync::Arc,
-    time::Duration,
-};
+use std::{collections::HashSet, default::Default, marker::PhantomData, sync::Arc, time::Duration};
 
 use aleph_bft::{DelayConfig, SpawnHandle};
-use aleph_primitives::KEY_TYPE;
+use aleph_primitives::{
+    AlephSessionApi, AuthoritySignature, BlsKey, BlsKeyRegistration, FinalityParameters,
+    SignedBlsKeyRegistration, KEY_TYPE,
+};
 use futures::channel::mpsc;
 use futures_timer::Delay;
 use log::{debug, error, info, trace, warn};
 use sc_client_api::Backend;
+use sp_api::{BlockId, ProvideRuntimeApi};
 use sp_consensus::SelectChain;
 use sp_keystore::CryptoStore;
 use sp_runtime::traits::{Block, Header};
 use tokio::{task::spawn_blocking, time::sleep};
 
 use crate::{
-    crypto::{AuthorityPen, AuthorityVerifier, Keychain},
-    data_io::{ChainTracker, DataStore, OrderedDataInterpreter},
+    crypto::{
+        AggregatingKeychain, AuthorityPen, AuthorityVerifier, BlsKeystore, BlsPen, BlsVerifier,
+        Keychain,
+    },
+    data_io::{
+        ChainTracker, ChainTrackerConfig, DataStore, DataStoreConfig, OrderedDataInterpreter,
+        MAX_DATA_BRANCH_LEN,
+    },
     default_aleph_config,
+    finality_params::read_finality_params_for_session,
     justification::JustificationNotification,
     last_block_of_session,
     network::{split, RequestBlocks, SessionManager, SessionNetwork},
@@ -26,8 +34,10 @@ use crate::{
             SubtaskCommon as AuthoritySubtaskCommon, Subtasks as AuthoritySubtasks,
             Task as AuthorityTask,
         },
-        backup::ABFTBackup,
+        backup::{ABFTBackup, BackupStore},
+        equivocation::{uses_aleph_bft_context, ReportingNetwork},
         task::{Handle, Task},
+        unit_creation_delay::{ObservedDataProvider, UnitCreationDelayController},
     },
     session_id_from_block_num,
     session_map::ReadOnlySessionMap,
@@ -40,8 +50,20 @@ mod authority;
 mod backup;
 mod chain_tracker;
 mod data_store;
+mod equivocation;
 mod member;
 mod task;
+mod unit_creation_delay;
+
+pub use backup::{
+    verify_backups, AppendOnlyBackupStore, BackupFileState, BackupStore, DirectoryBackupStore,
+    InMemoryBackupStore, NoBackupStore, SessionBackupState,
+};
+pub use unit_creation_delay::UnitCreationDelayBounds;
+
+pub mod testing {
+    pub use super::unit_creation_delay::{ObservedDataProvider, UnitCreationDelayController};
+}
 
 async fn get_node_index(
     authorities: &[AuthorityId],
@@ -64,11 +86,16 @@ pub(crate) struct ConsensusPartyParams<B: Block, SC, C, RB> {
     pub client: Arc<C>,
     pub select_chain: SC,
     pub keystore: Arc<dyn CryptoStore>,
+    pub bls_keystore: Arc<BlsKeystore>,
     pub block_requester: RB,
     pub metrics: Option<Metrics<<B::Header as Header>::Hash>>,
     pub authority_justification_tx: mpsc::UnboundedSender<JustificationNotification<B>>,
     pub unit_creation_delay: UnitCreationDelay,
-    pub backup_saving_path: Option<PathBuf>,
+    pub unit_creation_delay_bounds: Option<UnitCreationDelayBounds>,
+    pub backup_store: Arc<dyn BackupStore>,
+    pub backup_retention: u32,
+    pub data_store_config: DataStoreConfig,
+    pub chain_tracker_config: ChainTrackerConfig,
 }
 
 pub(crate) struct ConsensusParty<B, C, BE, SC, RB>
@@ -87,12 +114,16 @@ where
     client: Arc<C>,
     select_chain: SC,
     keystore: Arc<dyn CryptoStore>,
+    bls_keystore: Arc<BlsKeystore>,
     block_requester: RB,
     phantom: PhantomData<BE>,
     metrics: Option<Metrics<<B::Header as Header>::Hash>>,
     authority_justification_tx: mpsc::UnboundedSender<JustificationNotification<B>>,
-    unit_creation_delay: UnitCreationDelay,
-    backup_saving_path: Option<PathBuf>,
+    unit_creation_delay: UnitCreationDelayController,
+    backup_store: Arc<dyn BackupStore>,
+    backup_retention: u32,
+    data_store_config: DataStoreConfig,
+    chain_tracker_config: ChainTrackerConfig,
 }
 
 const SESSION_STATUS_CHECK_PERIOD: Duration = Duration::from_millis(1000);
@@ -115,16 +146,27 @@ where
             client,
             select_chain,
             keystore,
+            bls_keystore,
             block_requester,
             metrics,
             authority_justification_tx,
             unit_creation_delay,
-            backup_saving_path,
+            unit_creation_delay_bounds,
+            backup_store,
+            backup_retention,
+            data_store_config,
+            chain_tracker_config,
         } = params;
+        let unit_creation_delay = UnitCreationDelayController::new(
+            unit_creation_delay,
+            unit_creation_delay_bounds,
+            metrics.as_ref().map(|metrics| metrics.events().clone()),
+        );
         Self {
             session_manager,
             client,
             keystore,
+            bls_keystore,
             select_chain,
             block_requester,
             metrics,
@@ -134,7 +176,10 @@ where
             spawn_handle,
             phantom: PhantomData,
             unit_creation_delay,
-            backup_saving_path,
+            backup_store,
+            backup_retention,
+            data_store_config,
+            chain_tracker_config,
         }
     }
 
@@ -143,28 +188,54 @@ where
         &self,
         node_id: NodeIndex,
         multikeychain: Keychain,
+        aggregating_keychain: Option<AggregatingKeychain>,
         data_network: SessionNetwork<SplitData<B>>,
         session_id: SessionId,
         authorities: Vec<AuthorityId>,
         backup: ABFTBackup,
+        finality_params: Option<FinalityParameters>,
         exit_rx: futures::channel::oneshot::Receiver<()>,
     ) -> AuthoritySubtasks {
         debug!(target: "aleph-party", "Authority task {:?}", session_id);
         let session_boundaries = SessionBoundaries::new(session_id, self.session_period);
+        let event_metrics = self
+            .metrics
+            .as_ref()
+            .map(|metrics| metrics.events().clone());
         let (blocks_for_aggregator, blocks_from_interpreter) = mpsc::unbounded();
+        let max_branch_len = finality_params
+            .as_ref()
+            .map(|params| params.max_data_branch_len as usize)
+            .unwrap_or(MAX_DATA_BRANCH_LEN);
+        let data_store_config = match &finality_params {
+            Some(params) => DataStoreConfig {
+                max_proposals_pending: params.max_proposals_pending as usize,
+                max_messages_pending: params.max_messages_pending as usize,
+                max_branch_len,
+                ..self.data_store_config.clone()
+            },
+            None => self.data_store_config.clone(),
+        };
+        let chain_tracker_config = ChainTrackerConfig {
+            max_branch_len,
+            ..self.chain_tracker_config.clone()
+        };
+        info!(target: "aleph-party", "Data store config in session {:?}: {:?}", session_id, data_store_config);
+        info!(target: "aleph-party", "Chain tracker config in session {:?}: {:?}", session_id, chain_tracker_config);
 
+        let member_verifier = AuthorityVerifier::new(authorities.clone());
         let consensus_config = create_aleph_config(
             authorities.len(),
             node_id,
             session_id,
-            self.unit_creation_delay,
+            self.unit_creation_delay.delay(),
         );
 
         let (chain_tracker, data_provider) = ChainTracker::new(
             self.select_chain.clone(),
             self.client.clone(),
             session_boundaries.clone(),
-            Default::default(),
+            chain_tracker_config,
             self.metrics.clone(),
         );
 
@@ -172,6 +243,7 @@ where
             blocks_for_aggregator,
             self.client.clone(),
             session_boundaries.clone(),
+            max_branch_len,
         );
 
         let subtask_common = AuthoritySubtaskCommon {
@@ -188,41 +260,180 @@ where
             session_boundaries.clone(),
             self.client.clone(),
             self.block_requester.clone(),
-            Default::default(),
+            data_store_config,
             unfiltered_aleph_network,
+            event_metrics.clone(),
         );
 
-        AuthoritySubtasks::new(
-            exit_rx,
-            member::task(
+        let aggregator = match aggregating_keychain {
+            Some(aggregating_keychain) => aggregator::task(
                 subtask_common.clone(),
-                multikeychain.clone(),
-                consensus_config,
-                aleph_network.into(),
-                data_provider,
-                ordered_data_interpreter,
-                backup,
+                self.client.clone(),
+                aggregator_io,
+                session_boundaries,
+                self.metrics.clone(),
+                aggregating_keychain,
+                rmc_network,
             ),
-            aggregator::task(
+            None => aggregator::task(
                 subtask_common.clone(),
                 self.client.clone(),
                 aggregator_io,
                 session_boundaries,
                 self.metrics.clone(),
-                multikeychain,
+                multikeychain.clone(),
                 rmc_network,
             ),
+        };
+
+        // Equivocations can only be reported in sessions whose members sign with the AlephBFT
+        // context.
+        let (member_keychain, aleph_network, equivocation_reporter) =
+            match uses_aleph_bft_context(&*self.client, session_id, self.session_period) {
+                true => {
+                    let (aleph_network, equivocation_reporter) = ReportingNetwork::new(
+                        aleph_network.into(),
+                        self.client.clone(),
+                        member_verifier,
+                        session_id,
+                    );
+                    (
+                        multikeychain.with_aleph_bft_context(),
+                        aleph_network,
+                        Some(equivocation_reporter),
+                    )
+                }
+                false => (
+                    multikeychain,
+                    ReportingNetwork::not_reporting(aleph_network.into()),
+                    None,
+                ),
+            };
+
+        // Units are only observed when adapting the delay.
+        let member = match self.unit_creation_delay.observer() {
+            Some(observer) => member::task(
+                subtask_common.clone(),
+                member_keychain,
+                consensus_config,
+                aleph_network,
+                ObservedDataProvider::new(data_provider, observer),
+                ordered_data_interpreter,
+                backup::with_metrics(backup, event_metrics),
+                equivocation_reporter,
+            ),
+            None => member::task(
+                subtask_common.clone(),
+                member_keychain,
+                consensus_config,
+                aleph_network,
+                data_provider,
+                ordered_data_interpreter,
+                backup::with_metrics(backup, event_metrics),
+                equivocation_reporter,
+            ),
+        };
+
+        AuthoritySubtasks::new(
+            exit_rx,
+            member,
+            aggregator,
             chain_tracker::task(subtask_common.clone(), chain_tracker),
             data_store::task(subtask_common, data_store),
         )
     }
 
+    /// The keychain aggregating BLS signatures in the session, if all its authorities have
+    /// registered BLS keys.
+    fn aggregating_keychain(
+        &self,
+        session_id: SessionId,
+        node_id: NodeIndex,
+        bls_keys: &Option<Vec<BlsKey>>,
+    ) -> Option<AggregatingKeychain> {
+        let bls_keys = bls_keys.as_ref()?;
+        let verifier = match BlsVerifier::from_keys(bls_keys) {
+            Some(verifier) => verifier,
+            None => {
+                warn!(target: "aleph-party", "Invalid BLS keys in session {:?}, not aggregating signatures.", session_id);
+                return None;
+            }
+        };
+        let pen = match bls_keys
+            .get(node_id.0)
+            .and_then(|key| self.bls_keystore.pen(key))
+        {
+            Some(pen) => pen,
+            None => {
+                // Our signatures will be rejected, but we can still gather the ones of others.
+                warn!(target: "aleph-party", "Missing our BLS key registered for session {:?}, our signatures will not count.", session_id);
+                BlsPen::generate()
+            }
+        };
+        Some(AggregatingKeychain::new(node_id, verifier, pen))
+    }
+
+    /// Registers our BLS key on chain, unless it is already registered for the authority.
+    async fn register_bls_key(&self, session_id: SessionId, authority_pen: &AuthorityPen) {
+        let pen = match self.bls_keystore.any_pen() {
+            Ok(pen) => pen,
+            Err(e) => {
+                error!(target: "aleph-party", "Failed to get a BLS key from the keystore: {:?}.", e);
+                return;
+            }
+        };
+        let key: BlsKey = pen.public().into();
+        let authority = authority_pen.authority_id();
+        let best_block = BlockId::Hash(self.client.info().best_hash);
+        match self
+            .client
+            .runtime_api()
+            .registered_bls_key(&best_block, authority.clone())
+        {
+            Ok(Some(registered)) if registered == key => return,
+            Ok(_) => (),
+            Err(e) => {
+                warn!(target: "aleph-party", "Failed to read the BLS key registered by {:?}: {:?}.", authority, e);
+                return;
+            }
+        }
+        let registration = BlsKeyRegistration {
+            key,
+            proof_of_possession: pen.prove_possession(),
+            session: session_id.0,
+        };
+        let signature: AuthoritySignature = authority_pen
+            .sign(&registration.signing_payload())
+            .await
+            .into();
+        match self.client.runtime_api().submit_bls_key_unsigned_extrinsic(
+            &best_block,
+            authority,
+            SignedBlsKeyRegistration {
+                registration,
+                signature,
+            },
+        ) {
+            Ok(Some(())) => {
+                info!(target: "aleph-party", "Registered our BLS key in session {:?}.", session_id)
+            }
+            Ok(None) => {
+                warn!(target: "aleph-party", "Registration of our BLS key in session {:?} was not accepted by the transaction pool.", session_id)
+            }
+            Err(e) => {
+                warn!(target: "aleph-party", "Failed to register our BLS key in session {:?}: {:?}.", session_id, e)
+            }
+        }
+    }
+
     async fn spawn_authority_task(
         &self,
         session_id: SessionId,
         node_id: NodeIndex,
         authorities: Vec<AuthorityId>,
+        bls_keys: &Option<Vec<BlsKey>>,
         backup: ABFTBackup,
+        finality_params: Option<FinalityParameters>,
     ) -> AuthorityTask {
         let authority_verifier = AuthorityVerifier::new(authorities.clone());
         let authority_pen =
@@ -231,6 +442,8 @@ where
                 .expect("The keys should sign successfully");
 
         let keychain = Keychain::new(node_id, authority_verifier.clone(), authority_pen.clone());
+        let aggregating_keychain = self.aggregating_keychain(session_id, node_id, bls_keys);
+        self.register_bls_key(session_id, &authority_pen).await;
 
         let data_network = self
             .session_manager
@@ -243,10 +456,12 @@ where
             .spawn_authority_subtasks(
                 node_id,
                 keychain,
+                aggregating_keychain,
                 data_network,
                 session_id,
                 authorities,
                 backup,
+                finality_params,
                 exit_rx,
             )
             .await;
@@ -262,12 +477,17 @@ where
         )
     }
 
+    fn start_nonvalidator_session(&self, session_id: SessionId, authorities: &[AuthorityId]) {
+        if let Err(e) = self
+            .session_manager
+            .start_nonvalidator_session(session_id, AuthorityVerifier::new(authorities.to_vec()))
+        {
+            warn!(target: "aleph-party", "Failed to start nonvalidator session{:?}:{:?}", session_id, e);
+        }
+    }
+
     async fn run_session(&mut self, session_id: SessionId) {
         let last_block = last_block_of_session::<B>(session_id, self.session_period);
-        if let Some(previous_session_id) = session_id.0.checked_sub(1) {
-            let backup_saving_path = self.backup_saving_path.clone();
-            spawn_blocking(move || backup::remove(backup_saving_path, previous_session_id));
-        }
 
         // Early skip attempt -- this will trigger during catching up (initial sync).
         if self.client.info().best_number >= last_block {
@@ -282,6 +502,7 @@ where
                 let last_finalized_number = self.client.info().finalized_number;
                 if last_finalized_number >= last_block {
                     debug!(target: "aleph-party", "Skipping session {:?} early because block {:?} is already finalized", session_id, last_finalized_number);
+                    self.prune_backups();
                     return;
                 }
             }
@@ -308,13 +529,50 @@ where
         let mut maybe_authority_task = if let Some(node_id) =
             get_node_index(authorities, self.keystore.clone()).await
         {
-            match backup::rotate(self.backup_saving_path.clone(), session_id.0) {
+            match self.backup_store.rotate(session_id.0) {
                 Ok(backup) => {
-                    debug!(target: "aleph-party", "Running session {:?} as authority id {:?}", session_id, node_id);
-                    Some(
-                        self.spawn_authority_task(session_id, node_id, authorities.clone(), backup)
-                            .await,
+                    match read_finality_params_for_session(
+                        self.client.as_ref(),
+                        session_id,
+                        self.session_period,
                     )
+                    .await
+                    {
+                        Ok(finality_params) => {
+                            debug!(target: "aleph-party", "Running session {:?} as authority id {:?}", session_id, node_id);
+                            if let Some(params) = &finality_params {
+                                info!(target: "aleph-party", "Using finality parameters governed on chain in session {:?}: {:?}", session_id, params);
+                            }
+                            self.unit_creation_delay.next_session(
+                                authorities.len(),
+                                finality_params
+                                    .as_ref()
+                                    .map(|params| UnitCreationDelay(params.unit_creation_delay)),
+                            );
+                            Some(
+                                self.spawn_authority_task(
+                                    session_id,
+                                    node_id,
+                                    authorities.clone(),
+                                    authority_data.bls_keys(),
+                                    backup,
+                                    finality_params,
+                                )
+                                .await,
+                            )
+                        }
+                        Err(e) => {
+                            // Guessing the parameters could make us disagree with the rest of the
+                            // committee, so we only follow the session until it ends. The network
+                            // session might have been started early as a validator one.
+                            error!(target: "aleph-party", "Could not read finality parameters of session {:?}, running it as non-authority: {:?}", session_id, e);
+                            if let Err(e) = self.session_manager.stop_session(session_id) {
+                                warn!(target: "aleph-party", "Session Manager failed to stop in session {:?}: {:?}", session_id, e)
+                            }
+                            self.start_nonvalidator_session(session_id, authorities);
+                            None
+                        }
+                    }
                 }
                 Err(err) => {
                     error!(
@@ -327,12 +585,7 @@ where
             }
         } else {
             debug!(target: "aleph-party", "Running session {:?} as non-authority", session_id);
-            if let Err(e) = self
-                .session_manager
-                .start_nonvalidator_session(session_id, AuthorityVerifier::new(authorities.clone()))
-            {
-                warn!(target: "aleph-party", "Failed to start nonvalidator session{:?}:{:?}", session_id, e);
-            }
+            self.start_nonvalidator_session(session_id, authorities);
             None
         };
         let mut check_session_status = Delay::new(SESSION_STATUS_CHECK_PERIOD);
@@ -378,6 +631,7 @@ where
                             )
                             .await
                             .expect("The keys should sign successfully");
+                            self.register_bls_key(next_session_id, &authority_pen).await;
 
                             if let Err(e) = self
                                 .session_manager
@@ -419,10 +673,25 @@ where
         if let Err(e) = self.session_manager.stop_session(session_id) {
             warn!(target: "aleph-party", "Session Manager failed to stop in session {:?}: {:?}", session_id, e)
         }
+        self.prune_backups();
+    }
+
+    /// Removes, in the background, the backups of the sessions that are too old to be needed.
+    fn prune_backups(&self) {
+        let finalized_session = session_id_from_block_num::<B>(
+            self.client.info().finalized_number,
+            self.session_period,
+        );
+        let backup_store = self.backup_store.clone();
+        let backup_retention = self.backup_retention;
+        spawn_blocking(move || {
+            backup::prune(backup_store.as_ref(), finalized_session.0, backup_retention)
+        });
     }
 
     pub async fn run(mut self) {
         let starting_session = self.catch_up().await;
+        self.prune_backups();
         for curr_id in starting_session.0.. {
             info!(target: "aleph-party", "Running session {:?}.", curr_id);
             self.run_session(SessionId(curr_id)).await;
@@ -0,0 +1,274 @@
+//! Choosing the unit creation delay of every session, either statically or adaptively.
+//!
+//! In the adaptive mode we measure how long it takes to create consecutive units during a session.
+//! AlephBFT never creates units faster than the delay, so an interval noticeably longer than the
+//! delay means it had to wait for the units of the previous round, and creating units sooner would
+//! not make the session any faster. Otherwise we lower the delay step by step, as long as the
+//! committee keeps up.
+use std::{
+    cmp::max,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use log::debug;
+use parking_lot::Mutex;
+
+use crate::{data_io::AlephData, metrics::EventMetrics, UnitCreationDelay};
+
+/// Intervals longer than the delay by this factor mean we waited for the previous round.
+const SLOW_ROUND_FACTOR: f64 = 1.25;
+/// How much the delay is lowered after a session in which the committee kept up.
+const SPEEDUP_FACTOR: f64 = 0.9;
+/// Every member of the committee adds this much to the lowest delay we are willing to use, as the
+/// number of messages per round grows with the size of the committee.
+const MILLIS_PER_MEMBER: u64 = 2;
+
+/// Bounds for the unit creation delay chosen in the adaptive mode.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct UnitCreationDelayBounds {
+    pub min: UnitCreationDelay,
+    pub max: UnitCreationDelay,
+}
+
+#[derive(Default)]
+struct Observations {
+    last_unit: Option<Instant>,
+    total: Duration,
+    intervals: u32,
+}
+
+/// Measures the intervals between consecutive units created by us within a single session.
+#[derive(Clone, Default)]
+pub struct RoundLatencyObserver {
+    observations: Arc<Mutex<Observations>>,
+}
+
+impl RoundLatencyObserver {
+    fn on_unit_created(&self, now: Instant) {
+        let mut observations = self.observations.lock();
+        if let Some(last_unit) = observations.last_unit {
+            observations.total += now - last_unit;
+            observations.intervals += 1;
+        }
+        observations.last_unit = Some(now);
+    }
+
+    /// The mean interval between consecutive units, if at least two units were created.
+    fn mean_interval(&self) -> Option<Duration> {
+        let observations = self.observations.lock();
+        match observations.intervals {
+            0 => None,
+            intervals => Some(observations.total / intervals),
+        }
+    }
+}
+
+/// Wraps a data provider, reporting every unit creation to the observer.
+pub struct ObservedDataProvider<DP> {
+    inner: DP,
+    observer: RoundLatencyObserver,
+}
+
+impl<DP> ObservedDataProvider<DP> {
+    pub fn new(inner: DP, observer: RoundLatencyObserver) -> Self {
+        ObservedDataProvider { inner, observer }
+    }
+}
+
+#[async_trait]
+impl<B, DP> aleph_bft::DataProvider<AlephData<B>> for ObservedDataProvider<DP>
+where
+    B: sp_runtime::traits::Block,
+    DP: aleph_bft::DataProvider<AlephData<B>>,
+{
+    async fn get_data(&mut self) -> AlephData<B> {
+        // AlephBFT asks for data exactly once per unit it creates.
+        self.observer.on_unit_created(Instant::now());
+        self.inner.get_data().await
+    }
+}
+
+/// Returns the delay to use in a session, given the delay and the mean interval between units of
+/// the previous one.
+fn adapt(
+    current: UnitCreationDelay,
+    mean_interval: Option<Duration>,
+    committee_size: usize,
+    bounds: UnitCreationDelayBounds,
+) -> UnitCreationDelay {
+    let current = current.0 as f64;
+    let target = match mean_interval {
+        Some(interval) if interval.as_millis() as f64 > current * SLOW_ROUND_FACTOR => {
+            interval.as_millis() as f64
+        }
+        Some(_) => current * SPEEDUP_FACTOR,
+        None => current,
+    };
+    let floor = max(bounds.min.0, committee_size as u64 * MILLIS_PER_MEMBER);
+    UnitCreationDelay((target as u64).clamp(floor.min(bounds.max.0), bounds.max.0))
+}
+
+/// Keeps track of the unit creation delay used in the sessions we are a committee member of.
+pub struct UnitCreationDelayController {
+    delay: UnitCreationDelay,
+    bounds: Option<UnitCreationDelayBounds>,
+    observer: Option<RoundLatencyObserver>,
+    metrics: Option<EventMetrics>,
+}
+
+impl UnitCreationDelayController {
+    /// Creates a controller using the given delay, or starting from it in the adaptive mode, when
+    /// bounds are provided.
+    pub fn new(
+        delay: UnitCreationDelay,
+        bounds: Option<UnitCreationDelayBounds>,
+        metrics: Option<EventMetrics>,
+    ) -> Self {
+        UnitCreationDelayController {
+            delay,
+            bounds,
+            observer: None,
+            metrics,
+        }
+    }
+
+    /// Chooses the delay for the next session we are a committee member of, and starts observing
+    /// the units created in it. A delay governed on chain takes precedence over the local choice.
+    pub fn next_session(&mut self, committee_size: usize, governed: Option<UnitCreationDelay>) {
+        if let Some(delay) = governed {
+            self.delay = delay;
+            self.observer = None;
+            debug!(target: "aleph-party", "Using unit creation delay of {:?}ms governed on chain.", delay.0);
+        } else if let Some(bounds) = self.bounds {
+            let mean_interval = self
+                .observer
+                .as_ref()
+                .and_then(|observer| observer.mean_interval());
+            self.delay = adapt(self.delay, mean_interval, committee_size, bounds);
+            self.observer = Some(RoundLatencyObserver::default());
+            debug!(target: "aleph-party", "Chose unit creation delay of {:?}ms based on mean unit interval {:?} and committee size {}.", self.delay.0, mean_interval, committee_size);
+        }
+        if let Some(metrics) = &self.metrics {
+            metrics.report_unit_creation_delay(self.delay.0);
+        }
+    }
+
+    /// The delay to use in the current session.
+    pub fn delay(&self) -> UnitCreationDelay {
+        self.delay
+    }
+
+    /// The observer of the current session, if running in the adaptive mode.
+    pub fn observer(&self) -> Option<RoundLatencyObserver> {
+        self.observer.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{
+        adapt, RoundLatencyObserver, UnitCreationDelayBounds, UnitCreationDelayController,
+    };
+    use crate::UnitCreationDelay;
+
+    const BOUNDS: UnitCreationDelayBounds = UnitCreationDelayBounds {
+        min: UnitCreationDelay(100),
+        max: UnitCreationDelay(1000),
+    };
+
+    fn interval(millis: u64) -> Option<Duration> {
+        Some(Duration::from_millis(millis))
+    }
+
+    #[test]
+    fn follows_slow_rounds() {
+        assert_eq!(
+            adapt(UnitCreationDelay(300), interval(600), 10, BOUNDS),
+            UnitCreationDelay(600)
+        );
+    }
+
+    #[test]
+    fn speeds_up_when_committee_keeps_up() {
+        assert_eq!(
+            adapt(UnitCreationDelay(300), interval(310), 10, BOUNDS),
+            UnitCreationDelay(270)
+        );
+    }
+
+    #[test]
+    fn keeps_delay_without_observations() {
+        assert_eq!(
+            adapt(UnitCreationDelay(300), None, 10, BOUNDS),
+            UnitCreationDelay(300)
+        );
+    }
+
+    #[test]
+    fn stays_within_bounds() {
+        assert_eq!(
+            adapt(UnitCreationDelay(900), interval(5000), 10, BOUNDS),
+            UnitCreationDelay(1000)
+        );
+        assert_eq!(
+            adapt(UnitCreationDelay(105), interval(105), 10, BOUNDS),
+            UnitCreationDelay(100)
+        );
+    }
+
+    #[test]
+    fn larger_committees_raise_the_lowest_delay() {
+        assert_eq!(
+            adapt(UnitCreationDelay(300), interval(300), 200, BOUNDS),
+            UnitCreationDelay(400)
+        );
+        assert_eq!(
+            adapt(UnitCreationDelay(300), interval(300), 1000, BOUNDS),
+            UnitCreationDelay(1000)
+        );
+    }
+
+    #[test]
+    fn adapts_to_observed_units() {
+        let mut controller =
+            UnitCreationDelayController::new(UnitCreationDelay(300), Some(BOUNDS), None);
+        controller.next_session(10, None);
+        assert_eq!(controller.delay(), UnitCreationDelay(300));
+        let observer = controller.observer().expect("the controller is adaptive");
+        let start = Instant::now();
+        for unit in 0..5 {
+            observer.on_unit_created(start + Duration::from_millis(500 * unit));
+        }
+        controller.next_session(10, None);
+        assert_eq!(controller.delay(), UnitCreationDelay(500));
+    }
+
+    #[test]
+    fn static_delay_is_not_observed() {
+        let mut controller = UnitCreationDelayController::new(UnitCreationDelay(300), None, None);
+        controller.next_session(10, None);
+        assert!(controller.observer().is_none());
+        assert_eq!(controller.delay(), UnitCreationDelay(300));
+    }
+
+    #[test]
+    fn governed_delay_takes_precedence() {
+        let mut controller =
+            UnitCreationDelayController::new(UnitCreationDelay(300), Some(BOUNDS), None);
+        controller.next_session(10, Some(UnitCreationDelay(2000)));
+        assert!(controller.observer().is_none());
+        assert_eq!(controller.delay(), UnitCreationDelay(2000));
+    }
+
+    #[test]
+    fn observer_needs_two_units() {
+        let observer = RoundLatencyObserver::default();
+        assert!(observer.mean_interval().is_none());
+        observer.on_unit_created(Instant::now());
+        assert!(observer.mean_interval().is_none());
+    }
+}
@@ -40,6 +40,20 @@ pub fn last_block_of_session<B: Block>(
     ((session_id.0 + 1) * period.0 - 1).into()
 }
 
+/// Like `last_block_of_session`, but returns `None` for sessions so far ahead that their last
+/// block number does not fit in 32 bits, e.g. ones requested by a malicious peer.
+pub fn checked_last_block_of_session<B: Block>(
+    session_id: SessionId,
+    period: SessionPeriod,
+) -> Option<NumberFor<B>> {
+    session_id
+        .0
+        .checked_add(1)?
+        .checked_mul(period.0)?
+        .checked_sub(1)
+        .map(Into::into)
+}
+
 pub fn session_id_from_block_num<B: Block>(num: NumberFor<B>, period: SessionPeriod) -> SessionId {
     SessionId(num.saturated_into::<u32>() / period.0)
 }
@@ -5,6 +5,7 @@ use futures::StreamExt;
 use log::{debug, error, trace};
 use sc_client_api::{Backend, FinalityNotification};
 use sc_utils::mpsc::TracingUnboundedReceiver;
+use sp_api::ProvideRuntimeApi;
 use sp_runtime::{
     generic::BlockId,
     traits::{Block, Header, NumberFor},
@@ -31,23 +32,21 @@ pub trait AuthorityProvider<B> {
 }
 
 /// Default implementation of authority provider trait.
-pub struct AuthorityProviderImpl<C, B, BE>
+pub struct AuthorityProviderImpl<C, B>
 where
-    C: ClientForAleph<B, BE> + Send + Sync + 'static,
+    C: ProvideRuntimeApi<B> + Send + Sync + 'static,
     C::Api: aleph_primitives::AlephSessionApi<B>,
     B: Block,
-    BE: Backend<B> + 'static,
 {
     client: Arc<C>,
-    _phantom: PhantomData<(B, BE)>,
+    _phantom: PhantomData<B>,
 }
 
-impl<C, B, BE> AuthorityProviderImpl<C, B, BE>
+impl<C, B> AuthorityProviderImpl<C, B>
 where
-    C: ClientForAleph<B, BE> + Send + Sync + 'static,
+    C: ProvideRuntimeApi<B> + Send + Sync + 'static,
     C::Api: aleph_primitives::AlephSessionApi<B>,
     B: Block,
-    BE: Backend<B> + 'static,
 {
     pub fn new(client: Arc<C>) -> Self {
         Self {
@@ -57,12 +56,11 @@ where
     }
 }
 
-impl<C, B, BE> AuthorityProvider<NumberFor<B>> for AuthorityProviderImpl<C, B, BE>
+impl<C, B> AuthorityProvider<NumberFor<B>> for AuthorityProviderImpl<C, B>
 where
-    C: ClientForAleph<B, BE> + Send + Sync + 'static,
+    C: ProvideRuntimeApi<B> + Send + Sync + 'static,
     C::Api: aleph_primitives::AlephSessionApi<B>,
     B: Block,
-    BE: Backend<B> + 'static,
 {
     fn authority_data(&self, num: NumberFor<B>) -> Option<SessionAuthorityData> {
         match self
@@ -154,7 +152,7 @@ where
 #[derive(Clone)]
 /// Wrapper around Mapping from sessionId to Vec of AuthorityIds allowing mutation
 /// and hiding locking details
-struct SharedSessionMap(Arc<RwLock<(SessionMap, SessionSubscribers)>>);
+pub struct SharedSessionMap(Arc<RwLock<(SessionMap, SessionSubscribers)>>);
 
 #[derive(Clone)]
 /// Wrapper around Mapping from sessionId to Vec of AuthorityIds allowing only reads
@@ -163,7 +161,7 @@ pub struct ReadOnlySessionMap {
 }
 
 impl SharedSessionMap {
-    fn new() -> Self {
+    pub fn new() -> Self {
         Self(Arc::new(RwLock::new((HashMap::new(), HashMap::new()))))
     }
 
@@ -193,13 +191,19 @@ impl SharedSessionMap {
         guard.1.retain(|&s, _| s >= id);
     }
 
-    fn read_only(&self) -> ReadOnlySessionMap {
+    pub fn read_only(&self) -> ReadOnlySessionMap {
         ReadOnlySessionMap {
             inner: self.0.clone(),
         }
     }
 }
 
+impl Default for SharedSessionMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ReadOnlySessionMap {
     pub async fn get(&self, id: SessionId) -> Option<SessionAuthorityData> {
         self.inner.read().await.0.get(&id).cloned()
@@ -228,26 +232,43 @@ impl ReadOnlySessionMap {
     }
 }
 
-fn get_authority_data_for_session<AP, B>(
+/// Returns the authority data of the session, read from the genesis block for the first session,
+/// and from the given first block of the previous session for the others. Reading it requires the
+/// state of that block.
+pub(crate) fn get_authority_data_for_session<AP, B>(
     authority_provider: &AP,
     session_id: SessionId,
     first_block: NumberFor<B>,
-) -> SessionAuthorityData
+) -> Option<SessionAuthorityData>
 where
     B: Block,
     AP: AuthorityProvider<NumberFor<B>>,
 {
     if session_id == SessionId(0) {
-        authority_provider
-            .authority_data(<NumberFor<B>>::saturated_from(0u32))
-            .expect("Authorities for the session 0 must be available from the beginning")
+        authority_provider.authority_data(<NumberFor<B>>::saturated_from(0u32))
     } else {
-        authority_provider.next_authority_data(first_block).unwrap_or_else(||
-            panic!("Authorities for next session {:?} must be available at first block #{:?} of current session", session_id.0, first_block)
-        )
+        authority_provider.next_authority_data(first_block)
     }
 }
 
+fn expect_authority_data_for_session<AP, B>(
+    authority_provider: &AP,
+    session_id: SessionId,
+    first_block: NumberFor<B>,
+) -> SessionAuthorityData
+where
+    B: Block,
+    AP: AuthorityProvider<NumberFor<B>>,
+{
+    get_authority_data_for_session::<_, B>(authority_provider, session_id, first_block)
+        .unwrap_or_else(|| match session_id {
+            SessionId(0) => {
+                panic!("Authorities for the session 0 must be available from the beginning")
+            }
+            _ => panic!("Authorities for next session {:?} must be available at first block #{:?} of current session", session_id.0, first_block),
+        })
+}
+
 /// Struct responsible for updating session map
 pub struct SessionMapUpdater<AP, FN, B>
 where
@@ -267,9 +288,13 @@ where
     FN: FinalityNotificator<FinalityNotification<B>, NumberFor<B>>,
     B: Block,
 {
-    pub fn new(authority_provider: AP, finality_notificator: FN) -> Self {
+    pub fn new(
+        authority_provider: AP,
+        finality_notificator: FN,
+        session_map: SharedSessionMap,
+    ) -> Self {
         Self {
-            session_map: SharedSessionMap::new(),
+            session_map,
             authority_provider,
             finality_notificator,
             _phantom: PhantomData,
@@ -289,7 +314,7 @@ where
         self.session_map
             .update(
                 next_session,
-                get_authority_data_for_session::<_, B>(authority_provider, next_session, num),
+                expect_authority_data_for_session::<_, B>(authority_provider, next_session, num),
             )
             .await;
 
@@ -299,7 +324,7 @@ where
             self.session_map
                 .update(
                     session_id,
-                    get_authority_data_for_session::<_, B>(authority_provider, session_id, num),
+                    expect_authority_data_for_session::<_, B>(authority_provider, session_id, num),
                 )
                 .await;
         }
@@ -476,7 +501,8 @@ mod tests {
             .next_session_map
             .insert(2, authority_data(12, 16));
 
-        let updater = SessionMapUpdater::new(mock_provider, mock_notificator);
+        let updater =
+            SessionMapUpdater::new(mock_provider, mock_notificator, SharedSessionMap::new());
         let session_map = updater.readonly_session_map();
 
         let blocks = n_new_blocks(&mut client, 2);
@@ -541,7 +567,8 @@ mod tests {
 
         mock_notificator.last_finalized = 2;
 
-        let updater = SessionMapUpdater::new(mock_provider, mock_notificator);
+        let updater =
+            SessionMapUpdater::new(mock_provider, mock_notificator, SharedSessionMap::new());
         let session_map = updater.readonly_session_map();
 
         let _handle = tokio::spawn(updater.run(SessionPeriod(1)));
@@ -584,7 +611,8 @@ mod tests {
         mock_notificator.last_finalized = 20;
 
         let asked = mock_provider.asked_for.clone();
-        let updater = SessionMapUpdater::new(mock_provider, mock_notificator);
+        let updater =
+            SessionMapUpdater::new(mock_provider, mock_notificator, SharedSessionMap::new());
         let session_map = updater.readonly_session_map();
 
         let _handle = tokio::spawn(updater.run(SessionPeriod(1)));
@@ -2,18 +2,28 @@ use std::{borrow::Cow, collections::HashSet, fmt, iter, pin::Pin, sync::Arc};
 
 use async_trait::async_trait;
 use codec::{Decode, Encode};
-use futures::stream::{Stream, StreamExt};
-use log::error;
+use futures::{
+    channel::mpsc,
+    stream::{Stream, StreamExt},
+};
+use log::{debug, error};
+use parking_lot::Mutex;
+use rand::{seq::IteratorRandom, thread_rng};
 use sc_network::{
-    multiaddr::Protocol as MultiaddressProtocol, Event as SubstrateEvent, ExHashT, Multiaddr,
-    NetworkService, NetworkStateInfo, NotificationSender, PeerId as SubstratePeerId,
+    config::{IncomingRequest, OutgoingResponse},
+    multiaddr::Protocol as MultiaddressProtocol,
+    Event as SubstrateEvent, ExHashT, IfDisconnected, Multiaddr, NetworkService, NetworkStateInfo,
+    NotificationSender, PeerId as SubstratePeerId, RequestFailure,
 };
 use sp_api::NumberFor;
 use sp_runtime::traits::Block;
 
-use crate::network::{
-    Event, EventStream, Multiaddress as MultiaddressT, Network, NetworkIdentity, NetworkSender,
-    PeerId as PeerIdT, Protocol, RequestBlocks,
+use crate::{
+    justification::{JustificationProvider, JustificationSyncHandler, RequestJustifications},
+    network::{
+        Event, EventStream, Multiaddress as MultiaddressT, Network, NetworkIdentity, NetworkSender,
+        PeerId as PeerIdT, Protocol, RequestBlocks,
+    },
 };
 
 impl<B: Block, H: ExHashT> RequestBlocks<B> for Arc<NetworkService<B, H>> {
@@ -182,6 +192,14 @@ const ALEPH_PROTOCOL_NAME: &str = "/cardinals/aleph/2";
 /// ALEPH_PROTOCOL_NAME, but only used by validators that authenticated to each other.
 const ALEPH_VALIDATOR_PROTOCOL_NAME: &str = "/cardinals/aleph_validator/1";
 
+/// Name of the request-response protocol used for syncing justifications of whole sessions.
+const ALEPH_JUSTIFICATION_SYNC_PROTOCOL_NAME: &str = "/cardinals/aleph_justification_sync/1";
+
+/// Returns the canonical name of the justification sync protocol.
+pub fn justification_sync_protocol_name() -> Cow<'static, str> {
+    Cow::Borrowed(ALEPH_JUSTIFICATION_SYNC_PROTOCOL_NAME)
+}
+
 /// Returns the canonical name of the protocol.
 pub fn protocol_name(protocol: &Protocol) -> Cow<'static, str> {
     use Protocol::*;
@@ -287,13 +305,14 @@ impl EventStream<Multiaddress> for NetworkEventStream {
                             Err(_) => continue,
                         }
                     }
-                    NotificationsReceived { messages, .. } => {
+                    NotificationsReceived { remote, messages } => {
                         return Some(Messages(
+                            remote.into(),
                             messages
                                 .into_iter()
                                 .filter_map(|(protocol, data)| {
                                     match to_protocol(protocol.as_ref()) {
-                                        Ok(_) => Some(data),
+                                        Ok(protocol) => Some((protocol, data)),
                                         // This might end with us returning an empty vec, but it's probably not
                                         // worth it to handle this situation here.
                                         Err(_) => None,
@@ -352,6 +371,10 @@ impl<B: Block, H: ExHashT> Network for Arc<NetworkService<B, H>> {
         let addresses = peers.into_iter().map(|peer_id| peer_id.0).collect();
         self.remove_peers_from_reserved_set(protocol_name(&protocol), addresses);
     }
+
+    fn disconnect(&self, peer: Self::PeerId, protocol: Protocol) {
+        self.disconnect_peer(peer.0, protocol_name(&protocol));
+    }
 }
 
 impl<B: Block, H: ExHashT> NetworkIdentity for Arc<NetworkService<B, H>> {
@@ -369,6 +392,109 @@ impl<B: Block, H: ExHashT> NetworkIdentity for Arc<NetworkService<B, H>> {
     }
 }
 
+#[derive(Debug)]
+pub enum JustificationSyncError {
+    NoPeers,
+    RequestFailed(RequestFailure),
+}
+
+impl fmt::Display for JustificationSyncError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JustificationSyncError::NoPeers => write!(f, "no peers to request justifications from"),
+            JustificationSyncError::RequestFailed(e) => {
+                write!(f, "justification sync request failed: {}", e)
+            }
+        }
+    }
+}
+
+/// Sends justification sync requests to random peers we are syncing with.
+#[derive(Clone)]
+pub struct JustificationSyncNetwork<B: Block, H: ExHashT> {
+    network: Arc<NetworkService<B, H>>,
+    peers: Arc<Mutex<HashSet<SubstratePeerId>>>,
+}
+
+impl<B: Block, H: ExHashT> JustificationSyncNetwork<B, H> {
+    pub fn new(network: Arc<NetworkService<B, H>>) -> Self {
+        JustificationSyncNetwork {
+            network,
+            peers: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    /// Keeps track of the peers we could send requests to, should be run in the background.
+    pub async fn track_peers(self) {
+        let mut events = self.network.event_stream("aleph-justification-sync");
+        while let Some(event) = events.next().await {
+            match event {
+                SubstrateEvent::SyncConnected { remote } => {
+                    self.peers.lock().insert(remote);
+                }
+                SubstrateEvent::SyncDisconnected { remote } => {
+                    self.peers.lock().remove(&remote);
+                }
+                _ => {}
+            }
+        }
+        error!(target: "aleph-network", "Network event stream ended, no longer tracking justification sync peers.");
+    }
+}
+
+#[async_trait]
+impl<B: Block, H: ExHashT> RequestJustifications for JustificationSyncNetwork<B, H> {
+    type Error = JustificationSyncError;
+
+    async fn request_justifications(&self, request: Vec<u8>) -> Result<Vec<u8>, Self::Error> {
+        let peer = self
+            .peers
+            .lock()
+            .iter()
+            .choose(&mut thread_rng())
+            .cloned()
+            .ok_or(JustificationSyncError::NoPeers)?;
+        self.network
+            .request(
+                peer,
+                justification_sync_protocol_name(),
+                request,
+                IfDisconnected::ImmediateError,
+            )
+            .await
+            .map_err(JustificationSyncError::RequestFailed)
+    }
+}
+
+/// Answers incoming justification sync requests until the request stream ends.
+pub async fn serve_justification_requests<B, JP>(
+    handler: JustificationSyncHandler<B, JP>,
+    mut requests: mpsc::Receiver<IncomingRequest>,
+) where
+    B: Block,
+    JP: JustificationProvider<B>,
+{
+    while let Some(IncomingRequest {
+        peer,
+        payload,
+        pending_response,
+    }) = requests.next().await
+    {
+        let result = handler.handle_encoded_request(&payload).map_err(|e| {
+            debug!(target: "aleph-network", "Malformed justification sync request from {}: {}", peer, e);
+        });
+        let response = OutgoingResponse {
+            result,
+            reputation_changes: Vec::new(),
+            sent_feedback: None,
+        };
+        if pending_response.send(response).is_err() {
+            debug!(target: "aleph-network", "Failed to respond to justification sync request from {}", peer);
+        }
+    }
+    error!(target: "aleph-network", "Justification sync request stream ended.");
+}
+
 #[cfg(test)]
 mod tests {
     use codec::{Decode, Encode};
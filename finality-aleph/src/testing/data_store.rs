@@ -1,5 +1,6 @@
 use std::{future::Future, sync::Arc, time::Duration};
 
+use aleph_bft::Recipient;
 use futures::{
     channel::{
         mpsc::{self, UnboundedReceiver, UnboundedSender},
@@ -18,7 +19,7 @@ use tokio::time::timeout;
 
 use crate::{
     data_io::{AlephData, AlephNetworkMessage, DataStore, DataStoreConfig, MAX_DATA_BRANCH_LEN},
-    network::{DataNetwork, RequestBlocks, SimpleNetwork},
+    network::{DataNetwork, RequestBlocks, SendError, SenderComponent, SimpleNetwork},
     session::{SessionBoundaries, SessionId, SessionPeriod},
     testing::{
         client_chain_builder::ClientChainBuilder,
@@ -80,10 +81,30 @@ impl AlephNetworkMessage<Block> for TestData {
     }
 }
 
+/// Sends messages nowhere, but keeps the ones reported as rejected.
+#[derive(Clone)]
+struct TestSender {
+    messages: UnboundedSender<(TestData, Recipient)>,
+    rejected: UnboundedSender<TestData>,
+}
+
+impl SenderComponent<TestData> for TestSender {
+    fn send(&self, data: TestData, recipient: Recipient) -> Result<(), SendError> {
+        self.messages
+            .unbounded_send((data, recipient))
+            .map_err(|_| SendError::SendFailed)
+    }
+
+    fn report_rejected(&self, data: TestData) {
+        self.rejected.unbounded_send(data).unwrap()
+    }
+}
+
 struct TestHandler {
     chain_builder: ClientChainBuilder,
     block_requests_rx: UnboundedReceiver<BlockHashNum<Block>>,
     justification_requests_rx: UnboundedReceiver<BlockHashNum<Block>>,
+    rejected_rx: UnboundedReceiver<TestData>,
     network_tx: UnboundedSender<TestData>,
     network: Box<dyn DataNetwork<TestData>>,
 }
@@ -143,6 +164,14 @@ impl TestHandler {
         self.justification_requests_rx.next().await.unwrap()
     }
 
+    /// Receive next message reported as rejected by Data Store
+    async fn next_rejected(&mut self) -> TestData {
+        timeout(TIMEOUT_SUCC, self.rejected_rx.next())
+            .await
+            .expect("Data Store should report the rejected message")
+            .unwrap()
+    }
+
     async fn assert_no_message_out(&mut self, err_message: &'static str) {
         let res = timeout(TIMEOUT_FAIL, self.network.next()).await;
         assert!(res.is_err(), "{} (message out: {:?})", err_message, res);
@@ -163,8 +192,15 @@ fn prepare_data_store(
 
     let (block_requester, block_requests_rx, justification_requests_rx) = TestBlockRequester::new();
     let (sender_tx, _sender_rx) = mpsc::unbounded();
+    let (rejected_tx, rejected_rx) = mpsc::unbounded();
     let (network_tx, network_rx) = mpsc::unbounded();
-    let test_network = SimpleNetwork::new(network_rx, sender_tx);
+    let test_network = SimpleNetwork::new(
+        network_rx,
+        TestSender {
+            messages: sender_tx,
+            rejected: rejected_tx,
+        },
+    );
     let data_store_config = DataStoreConfig {
         max_triggers_pending: 80_000,
         max_proposals_pending: 80_000,
@@ -172,6 +208,7 @@ fn prepare_data_store(
         available_proposals_cache_capacity: 8000,
         periodic_maintenance_interval: Duration::from_millis(20),
         request_block_after: Duration::from_millis(30),
+        max_branch_len: MAX_DATA_BRANCH_LEN,
     };
 
     let session_boundaries = if let Some(session_boundaries) = session_boundaries {
@@ -185,6 +222,7 @@ fn prepare_data_store(
         block_requester,
         data_store_config,
         test_network,
+        None,
     );
 
     let chain_builder = ClientChainBuilder::new(client, Arc::new(TestClientBuilder::new().build()));
@@ -199,6 +237,7 @@ fn prepare_data_store(
             chain_builder,
             block_requests_rx,
             justification_requests_rx,
+            rejected_rx,
             network_tx,
             network: Box::new(network),
         },
@@ -268,6 +307,7 @@ async fn too_long_branch_message_does_not_go_through() {
         test_handler
             .assert_no_message_out("Data Store let through a too long message")
             .await;
+        assert_eq!(test_handler.next_rejected().await, test_data);
     })
     .await;
 }
@@ -1,20 +1,30 @@
 use std::{cell::RefCell, collections::VecDeque, sync::Arc, time::Duration};
 
-use aleph_bft::SignatureSet;
+use aleph_bft::{Keychain, MultiKeychain, NodeIndex, PartialMultisignature, SignatureSet};
+use aleph_primitives::{AuthorityPair, BlsKey, BlsKeyRegistration, SessionAuthorityData};
+use codec::{Decode, Encode};
 use futures::{
     channel::mpsc::{unbounded, UnboundedSender},
-    Future,
+    Future, StreamExt,
 };
 use sp_api::BlockId;
+use sp_core::Pair;
 use sp_runtime::traits::Block;
 use tokio::{task::JoinHandle, time::timeout};
 use AcceptancePolicy::*;
 
 use crate::{
-    justification::{AlephJustification, JustificationHandler, JustificationHandlerConfig},
+    crypto::{AggregatingKeychain, BlsPen, BlsVerifier},
+    justification::{
+        backwards_compatible_decode, versioned_encode, AlephJustification, FinalizedBlockStream,
+        JustificationHandler, JustificationHandlerConfig, SessionInfo, SessionInfoProvider,
+    },
+    last_block_of_session,
+    nodes::JustificationVerifier,
+    session_id_from_block_num,
     testing::mocks::{
         create_block, AcceptancePolicy, Client, JustificationRequestSchedulerImpl,
-        MockedBlockFinalizer, MockedBlockRequester, SessionInfoProviderImpl, TBlock,
+        MockedBlockFinalizer, MockedBlockRequester, SessionInfoProviderImpl, TBlock, TNumber,
         VerifierWrapper,
     },
     JustificationNotification, SessionPeriod,
@@ -39,6 +49,7 @@ type Environment = (
     MockedBlockRequester,
     MockedBlockFinalizer,
     JustificationRequestSchedulerImpl,
+    FinalizedBlockStream<TBlock>,
 );
 
 fn create_justification_notification_for(block: TBlock) -> JustificationNotification<TBlock> {
@@ -56,9 +67,14 @@ fn run_justification_handler(
 ) -> (JoinHandle<()>, Sender, Sender) {
     let (auth_just_tx, auth_just_rx) = unbounded();
     let (imp_just_tx, imp_just_rx) = unbounded();
+    // Synced justifications go through the same path as imported ones, so we do not test them here.
+    let (_, sync_just_rx) = unbounded();
 
-    let handle =
-        tokio::spawn(async move { justification_handler.run(auth_just_rx, imp_just_rx).await });
+    let handle = tokio::spawn(async move {
+        justification_handler
+            .run(auth_just_rx, imp_just_rx, sync_just_rx)
+            .await
+    });
 
     (handle, auth_just_tx, imp_just_tx)
 }
@@ -74,6 +90,7 @@ fn prepare_env(
     let requester = MockedBlockRequester::new();
     let config = JustificationHandlerConfig::test();
     let justification_request_scheduler = JustificationRequestSchedulerImpl::new(request_policy);
+    let (finalized_block_sender, finalized_block_stream) = FinalizedBlockStream::channel();
 
     let justification_handler = JustificationHandler::new(
         info_provider,
@@ -83,6 +100,7 @@ fn prepare_env(
         justification_request_scheduler.clone(),
         None,
         config,
+        finalized_block_sender,
     );
 
     (
@@ -91,6 +109,7 @@ fn prepare_env(
         requester,
         finalizer,
         justification_request_scheduler,
+        finalized_block_stream,
     )
 }
 
@@ -132,7 +151,7 @@ where
         JustificationRequestSchedulerImpl,
     ) -> F,
 {
-    let (justification_handler, client, requester, finalizer, justification_request_scheduler) =
+    let (justification_handler, client, requester, finalizer, justification_request_scheduler, _) =
         env;
     let (handle_run, auth_just_tx, imp_just_tx) = run_justification_handler(justification_handler);
     scenario(
@@ -183,6 +202,28 @@ async fn expect_not_requested(
     assert!(!justification_request_scheduler.has_been_requested().await);
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn notifies_about_finalized_blocks() {
+    let env = prepare_env(FINALIZED_HEIGHT, AlwaysAccept, AlwaysReject);
+    let mut finalized_blocks = env.5.subscribe();
+    run_test(
+        env,
+        |_, imp_just_tx, client, _, finalizer, justification_request_scheduler| async move {
+            let block = client.next_block_to_finalize();
+            let message = create_justification_notification_for(block.clone());
+            imp_just_tx.unbounded_send(message).unwrap();
+            expect_finalized(&finalizer, &justification_request_scheduler, block.clone()).await;
+            let notification = timeout(Duration::from_millis(50), finalized_blocks.next())
+                .await
+                .expect("notification should arrive")
+                .expect("stream should not end");
+            assert_eq!(notification.hash, block.hash());
+            assert_eq!(notification.number, block.header.number);
+        },
+    )
+    .await;
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn leads_to_finalization_when_appropriate_justification_comes() {
     run_test(
@@ -358,3 +399,103 @@ async fn does_not_request_nor_finalize_when_verifier_is_not_available() {
     )
     .await;
 }
+
+/// Provides the verifier built from the same authority data for all sessions.
+struct AuthorityDataSessionInfo {
+    authority_data: SessionAuthorityData,
+}
+
+#[async_trait::async_trait]
+impl SessionInfoProvider<TBlock, JustificationVerifier> for AuthorityDataSessionInfo {
+    async fn for_block_num(&self, number: TNumber) -> SessionInfo<TBlock, JustificationVerifier> {
+        let current_session = session_id_from_block_num::<TBlock>(number, SESSION_PERIOD);
+        SessionInfo {
+            current_session,
+            last_block_height: last_block_of_session::<TBlock>(current_session, SESSION_PERIOD),
+            verifier: Some(self.authority_data.clone().into()),
+            request_delay: None,
+        }
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn finalizes_with_aggregated_justification_of_registered_keys() {
+    let pairs: Vec<_> = (0..4).map(|_| AuthorityPair::generate().0).collect();
+    let pens: Vec<_> = (0..4).map(|_| BlsPen::generate()).collect();
+    let keys: Vec<BlsKey> = pens.iter().map(|pen| pen.public().into()).collect();
+    for (key, pen) in keys.iter().zip(&pens) {
+        let registration = BlsKeyRegistration {
+            key: *key,
+            proof_of_possession: pen.prove_possession(),
+            session: 0,
+        };
+        assert!(registration.has_valid_proof());
+    }
+    let authority_data =
+        SessionAuthorityData::new(pairs.iter().map(|pair| pair.public()).collect(), None)
+            .with_bls_keys(Some(keys));
+    // The authority data reaches the nodes through the runtime API.
+    let authority_data = SessionAuthorityData::decode(&mut authority_data.encode().as_slice())
+        .expect("the authority data should decode");
+    let verifier = BlsVerifier::from_keys(
+        authority_data
+            .bls_keys()
+            .as_ref()
+            .expect("the keys should survive encoding"),
+    )
+    .expect("the keys should be valid");
+    let keychains: Vec<_> = pens
+        .into_iter()
+        .enumerate()
+        .map(|(index, pen)| AggregatingKeychain::new(NodeIndex(index), verifier.clone(), pen))
+        .collect();
+
+    let client = Client::new(FINALIZED_HEIGHT);
+    let block = client.next_block_to_finalize();
+    let msg = block.hash().encode();
+    let mut multisignature =
+        keychains[0].bootstrap_multi(&keychains[0].sign(&msg).await, NodeIndex(0));
+    for (index, keychain) in keychains.iter().enumerate().skip(1).take(2) {
+        multisignature = multisignature.add_signature(&keychain.sign(&msg).await, NodeIndex(index));
+    }
+    assert!(keychains[3].is_complete(&msg, &multisignature));
+    let encoded = versioned_encode(multisignature.into()).expect("encoding should succeed");
+    let justification = backwards_compatible_decode(encoded).expect("decoding should succeed");
+    assert!(matches!(
+        justification,
+        AlephJustification::AggregatedMultisignature(_)
+    ));
+
+    let finalizer = MockedBlockFinalizer::new();
+    let justification_request_scheduler = JustificationRequestSchedulerImpl::new(AlwaysReject);
+    let (finalized_block_sender, _) = FinalizedBlockStream::channel();
+    let justification_handler = JustificationHandler::new(
+        AuthorityDataSessionInfo { authority_data },
+        MockedBlockRequester::new(),
+        Arc::new(client),
+        finalizer.clone(),
+        justification_request_scheduler.clone(),
+        None,
+        JustificationHandlerConfig::test(),
+        finalized_block_sender,
+    );
+    let (auth_just_tx, auth_just_rx) = unbounded();
+    let (imp_just_tx, imp_just_rx) = unbounded();
+    let (_, sync_just_rx) = unbounded();
+    let handle = tokio::spawn(async move {
+        justification_handler
+            .run(auth_just_rx, imp_just_rx, sync_just_rx)
+            .await
+    });
+    imp_just_tx
+        .unbounded_send(JustificationNotification {
+            justification,
+            hash: block.hash(),
+            number: block.header.number,
+        })
+        .unwrap();
+    expect_finalized(&finalizer, &justification_request_scheduler, block).await;
+    auth_just_tx.close_channel();
+    imp_just_tx.close_channel();
+    let _ = timeout(Duration::from_millis(10), handle).await;
+}
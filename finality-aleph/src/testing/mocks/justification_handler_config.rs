@@ -53,6 +53,8 @@ impl JustificationRequestScheduler for JustificationRequestSchedulerImpl {
     fn on_request_sent(&mut self) {
         self.req_reporter.invoke_with(());
     }
+
+    fn set_delay(&mut self, _delay: Option<Duration>) {}
 }
 
 const DEFAULT_VERIFIER_TIMEOUT_MS: u64 = 10u64;
@@ -2,7 +2,9 @@ use std::sync::{Arc, Mutex};
 
 use crate::{
     justification::{AlephJustification, SessionInfo, SessionInfoProvider, Verifier},
-    last_block_of_session, session_id_from_block_num,
+    last_block_of_session,
+    metrics::VerificationFailure,
+    session_id_from_block_num,
     testing::mocks::{AcceptancePolicy, TBlock, THash, TNumber},
     SessionPeriod,
 };
@@ -12,8 +14,15 @@ pub(crate) struct VerifierWrapper {
 }
 
 impl Verifier<TBlock> for VerifierWrapper {
-    fn verify(&self, _justification: &AlephJustification, _hash: THash) -> bool {
-        self.acceptance_policy.lock().unwrap().accepts()
+    fn verify(
+        &self,
+        _justification: &AlephJustification,
+        _hash: THash,
+    ) -> Result<(), VerificationFailure> {
+        match self.acceptance_policy.lock().unwrap().accepts() {
+            true => Ok(()),
+            false => Err(VerificationFailure::BadMultisignature),
+        }
     }
 }
 
@@ -47,6 +56,7 @@ impl SessionInfoProvider<TBlock, VerifierWrapper> for SessionInfoProviderImpl {
                     acceptance_policy: self.acceptance_policy.clone(),
                 }),
             },
+            request_delay: None,
         }
     }
 }
@@ -17,9 +17,10 @@ use crate::{
             crypto_basics, MockData, MockEvent, MockMultiaddress, MockNetwork, MockNetworkIdentity,
             MockPeerId,
         },
-        testing::{Authentication, DiscoveryMessage, NetworkData, SessionHandler},
+        testing::{Authentication, Capabilities, DiscoveryMessage, NetworkData, SessionHandler},
         ConnectionIO, ConnectionManager, ConnectionManagerConfig, DataNetwork, NetworkIdentity,
-        Protocol, Service as NetworkService, SessionManager, SessionNetwork, IO as NetworkIO,
+        Protocol, ReputationConfig, Service as NetworkService, SessionManager, SessionNetwork,
+        ValidatorConnections, IO as NetworkIO,
     },
     MillisecsPerBlock, NodeIndex, SessionId, SessionPeriod,
 };
@@ -100,6 +101,8 @@ async fn prepare_one_session_test_data() -> TestData {
     let (commands_for_service, commands_from_user) = mpsc::unbounded();
     let (messages_for_service, commands_from_manager) = mpsc::unbounded();
     let (messages_for_user, messages_from_network) = mpsc::unbounded();
+    let (peer_events_for_manager, peer_events_from_network) = mpsc::unbounded();
+    let (rejected_for_service, rejected_from_user) = mpsc::unbounded();
 
     let connection_io = ConnectionIO::new(
         commands_for_network,
@@ -107,16 +110,30 @@ async fn prepare_one_session_test_data() -> TestData {
         commands_from_user,
         commands_from_manager,
         messages_from_network,
+        peer_events_from_network,
+        rejected_from_user,
     );
     let connection_manager = ConnectionManager::<Authority, MockData>::new(
         authorities[0].clone(),
         ConnectionManagerConfig::with_session_period(&SESSION_PERIOD, &MILLISECS_PER_BLOCK),
+        ValidatorConnections::new(),
+        None,
+    );
+    let session_manager = SessionManager::new(
+        commands_for_service,
+        messages_for_service,
+        rejected_for_service,
     );
-    let session_manager = SessionManager::new(commands_for_service, messages_for_service);
     let network_service = NetworkService::new(
         network.clone(),
         task_manager.spawn_handle(),
-        NetworkIO::new(messages_from_user, messages_for_user, commands_from_io),
+        NetworkIO::new(
+            messages_from_user,
+            messages_for_user,
+            commands_from_io,
+            peer_events_for_manager,
+        ),
+        ReputationConfig::default(),
     );
 
     let network_manager_task = async move {
@@ -226,7 +243,7 @@ impl TestData {
         let mut sent_auth = HashMap::new();
         while sent_auth.len() < NODES_N - 1 {
             if let Some((
-                MockNetworkData::Meta(DiscoveryMessage::Authentication(auth_data)),
+                MockNetworkData::Meta(DiscoveryMessage::Authentication(auth_data), _),
                 peer_id,
                 protocol,
             )) = timeout(DEFAULT_TIMEOUT, self.next_sent_authentication())
@@ -253,12 +270,20 @@ impl TestData {
             self.connect_identity_to_network(authority.peer_id(), Protocol::Generic);
             self.connect_identity_to_network(authority.peer_id(), Protocol::Validator);
 
-            self.network
-                .emit_event(MockEvent::Messages(vec![MockNetworkData::Meta(
-                    DiscoveryMessage::AuthenticationBroadcast(handler.authentication().unwrap()),
-                )
-                .encode()
-                .into()]));
+            self.network.emit_event(MockEvent::Messages(
+                authority.peer_id(),
+                vec![(
+                    Protocol::Generic,
+                    MockNetworkData::Meta(
+                        DiscoveryMessage::AuthenticationBroadcast(
+                            handler.authentication().unwrap(),
+                        ),
+                        Capabilities::default(),
+                    )
+                    .encode()
+                    .into(),
+                )],
+            ));
         }
     }
 
@@ -279,7 +304,11 @@ impl TestData {
 
     fn emit_notifications_received(&mut self, messages: Vec<MockNetworkData>) {
         self.network.emit_event(MockEvent::Messages(
-            messages.iter().map(|m| m.encode().into()).collect(),
+            self.authorities[1].peer_id(),
+            messages
+                .iter()
+                .map(|m| (Protocol::Validator, m.encode().into()))
+                .collect(),
         ));
     }
 
@@ -289,12 +318,18 @@ impl TestData {
         loop {
             match self.network.send_message.next().await {
                 Some((
-                    MockNetworkData::Meta(DiscoveryMessage::AuthenticationBroadcast(auth_data)),
+                    MockNetworkData::Meta(
+                        DiscoveryMessage::AuthenticationBroadcast(auth_data),
+                        capabilities,
+                    ),
                     peer_id,
                     protocol,
                 )) => {
                     return Some((
-                        MockNetworkData::Meta(DiscoveryMessage::AuthenticationBroadcast(auth_data)),
+                        MockNetworkData::Meta(
+                            DiscoveryMessage::AuthenticationBroadcast(auth_data),
+                            capabilities,
+                        ),
                         peer_id,
                         protocol,
                     ))
@@ -311,12 +346,18 @@ impl TestData {
         loop {
             match self.network.send_message.next().await {
                 Some((
-                    MockNetworkData::Meta(DiscoveryMessage::Authentication(auth_data)),
+                    MockNetworkData::Meta(
+                        DiscoveryMessage::Authentication(auth_data),
+                        capabilities,
+                    ),
                     peer_id,
                     protocol,
                 )) => {
                     return Some((
-                        MockNetworkData::Meta(DiscoveryMessage::Authentication(auth_data)),
+                        MockNetworkData::Meta(
+                            DiscoveryMessage::Authentication(auth_data),
+                            capabilities,
+                        ),
                         peer_id,
                         protocol,
                     ))
@@ -365,7 +406,7 @@ async fn test_sends_discovery_message() {
 
     for _ in 0..5 {
         if let Some((
-            MockNetworkData::Meta(DiscoveryMessage::AuthenticationBroadcast(auth_data)),
+            MockNetworkData::Meta(DiscoveryMessage::AuthenticationBroadcast(auth_data), _),
             peer_id,
             protocol,
         )) = timeout(DEFAULT_TIMEOUT, test_data.network.send_message.next())
@@ -398,15 +439,20 @@ async fn test_sends_authentication_on_receiving_broadcast() {
     let sending_peer = test_data.authorities[1].clone();
     test_data.connect_identity_to_network(sending_peer.peer_id(), Protocol::Generic);
 
-    test_data
-        .network
-        .emit_event(MockEvent::Messages(vec![MockNetworkData::Meta(
-            DiscoveryMessage::AuthenticationBroadcast(
-                sending_peer_handler.authentication().unwrap(),
-            ),
-        )
-        .encode()
-        .into()]));
+    test_data.network.emit_event(MockEvent::Messages(
+        sending_peer.peer_id(),
+        vec![(
+            Protocol::Generic,
+            MockNetworkData::Meta(
+                DiscoveryMessage::AuthenticationBroadcast(
+                    sending_peer_handler.authentication().unwrap(),
+                ),
+                Capabilities::default(),
+            )
+            .encode()
+            .into(),
+        )],
+    ));
 
     assert_eq!(
         timeout(DEFAULT_TIMEOUT, test_data.network.add_reserved.next())
@@ -421,7 +467,7 @@ async fn test_sends_authentication_on_receiving_broadcast() {
     );
 
     if let Some((
-        MockNetworkData::Meta(DiscoveryMessage::Authentication(auth_data)),
+        MockNetworkData::Meta(DiscoveryMessage::Authentication(auth_data), _),
         peer_id,
         protocol,
     )) = timeout(DEFAULT_TIMEOUT, test_data.next_sent_authentication())
@@ -453,15 +499,20 @@ async fn test_forwards_authentication_broadcast() {
         test_data.connect_identity_to_network(authority.peer_id(), Protocol::Generic);
     }
 
-    test_data
-        .network
-        .emit_event(MockEvent::Messages(vec![MockNetworkData::Meta(
-            DiscoveryMessage::AuthenticationBroadcast(
-                sending_peer_handler.authentication().unwrap(),
-            ),
-        )
-        .encode()
-        .into()]));
+    test_data.network.emit_event(MockEvent::Messages(
+        sending_peer.peer_id(),
+        vec![(
+            Protocol::Generic,
+            MockNetworkData::Meta(
+                DiscoveryMessage::AuthenticationBroadcast(
+                    sending_peer_handler.authentication().unwrap(),
+                ),
+                Capabilities::default(),
+            )
+            .encode()
+            .into(),
+        )],
+    ));
 
     assert_eq!(
         timeout(DEFAULT_TIMEOUT, test_data.network.add_reserved.next())
@@ -486,7 +537,7 @@ async fn test_forwards_authentication_broadcast() {
     let mut sent_authentication = HashMap::new();
     while sent_authentication.len() < NODES_N - 1 {
         if let Some((
-            MockNetworkData::Meta(DiscoveryMessage::AuthenticationBroadcast(auth_data)),
+            MockNetworkData::Meta(DiscoveryMessage::AuthenticationBroadcast(auth_data), _),
             peer_id,
             protocol,
         )) = timeout(
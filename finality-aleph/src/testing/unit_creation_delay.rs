@@ -0,0 +1,93 @@
+use std::time::Duration;
+
+use aleph_bft::DataProvider;
+use async_trait::async_trait;
+use substrate_test_runtime_client::runtime::Block;
+use tokio::time::sleep;
+
+use crate::{
+    data_io::AlephData,
+    party::{
+        testing::{ObservedDataProvider, UnitCreationDelayController},
+        UnitCreationDelayBounds,
+    },
+    UnitCreationDelay,
+};
+
+const COMMITTEE_SIZE: usize = 4;
+const UNITS_PER_SESSION: usize = 5;
+const BOUNDS: UnitCreationDelayBounds = UnitCreationDelayBounds {
+    min: UnitCreationDelay(10),
+    max: UnitCreationDelay(200),
+};
+
+struct EmptyDataProvider;
+
+#[async_trait]
+impl DataProvider<AlephData<Block>> for EmptyDataProvider {
+    async fn get_data(&mut self) -> AlephData<Block> {
+        AlephData::Empty
+    }
+}
+
+/// Runs a session in which the member asks for data for every unit, waiting `round_time` between
+/// consecutive units, as it would when waiting for the units of the previous round.
+async fn run_session(controller: &mut UnitCreationDelayController, round_time: Duration) {
+    controller.next_session(COMMITTEE_SIZE, None);
+    let observer = controller
+        .observer()
+        .expect("the controller should be adaptive");
+    let mut data_provider = ObservedDataProvider::new(EmptyDataProvider, observer);
+    for _ in 0..UNITS_PER_SESSION {
+        assert_eq!(data_provider.get_data().await, AlephData::Empty);
+        sleep(round_time).await;
+    }
+}
+
+#[tokio::test]
+async fn follows_slow_rounds_and_speeds_up_afterwards() {
+    let mut controller =
+        UnitCreationDelayController::new(UnitCreationDelay(20), Some(BOUNDS), None);
+
+    run_session(&mut controller, Duration::from_millis(100)).await;
+    controller.next_session(COMMITTEE_SIZE, None);
+    let after_slow_session = controller.delay();
+    assert!(after_slow_session >= UnitCreationDelay(100));
+    assert!(after_slow_session <= BOUNDS.max);
+
+    // The units of the session started above come without waiting.
+    let observer = controller
+        .observer()
+        .expect("the controller should be adaptive");
+    let mut data_provider = ObservedDataProvider::new(EmptyDataProvider, observer);
+    for _ in 0..UNITS_PER_SESSION {
+        data_provider.get_data().await;
+    }
+    controller.next_session(COMMITTEE_SIZE, None);
+    assert!(controller.delay() < after_slow_session);
+    assert!(controller.delay() >= BOUNDS.min);
+}
+
+#[tokio::test]
+async fn never_exceeds_the_highest_delay() {
+    let mut controller =
+        UnitCreationDelayController::new(UnitCreationDelay(20), Some(BOUNDS), None);
+    run_session(&mut controller, Duration::from_millis(300)).await;
+    controller.next_session(COMMITTEE_SIZE, None);
+    assert_eq!(controller.delay(), BOUNDS.max);
+}
+
+#[tokio::test]
+async fn governed_delay_stops_observing_units() {
+    let mut controller =
+        UnitCreationDelayController::new(UnitCreationDelay(20), Some(BOUNDS), None);
+    run_session(&mut controller, Duration::from_millis(100)).await;
+    controller.next_session(COMMITTEE_SIZE, Some(UnitCreationDelay(150)));
+    assert!(controller.observer().is_none());
+    assert_eq!(controller.delay(), UnitCreationDelay(150));
+
+    // Adapting resumes from the governed delay once it is no longer set.
+    controller.next_session(COMMITTEE_SIZE, None);
+    assert_eq!(controller.delay(), UnitCreationDelay(150));
+    assert!(controller.observer().is_some());
+}
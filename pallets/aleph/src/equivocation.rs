@@ -0,0 +1,110 @@
+//! Punishment of committee members that equivocated in AlephBFT.
+
+use codec::{Decode, Encode};
+use frame_support::{
+    log,
+    sp_runtime::{traits::Convert, Perbill, RuntimeAppPublic},
+};
+use pallet_session::historical::IdentificationTuple;
+use primitives::{BanHandler, SessionIndex};
+use scale_info::TypeInfo;
+use sp_staking::offence::{Kind, Offence, ReportOffence};
+use sp_std::{marker::PhantomData, prelude::*};
+
+use crate::{Config, HandleEquivocation};
+
+/// The slash of a lone equivocation, which is more likely a misconfigured node running twice
+/// than an attack.
+pub const MIN_EQUIVOCATION_SLASH: Perbill = Perbill::from_percent(1);
+
+/// An equivocation of an AlephBFT committee member, i.e. creating two different units for the
+/// same round.
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode, TypeInfo)]
+pub struct AlephEquivocationOffence<Offender> {
+    /// The session in which the equivocation happened.
+    pub session_index: SessionIndex,
+    /// The size of the committee in that session.
+    pub validator_set_count: u32,
+    /// The offender.
+    pub offender: Offender,
+}
+
+impl<Offender: Clone> Offence<Offender> for AlephEquivocationOffence<Offender> {
+    const ID: Kind = *b"aleph:equivocati";
+    type TimeSlot = SessionIndex;
+
+    fn offenders(&self) -> Vec<Offender> {
+        vec![self.offender.clone()]
+    }
+
+    fn session_index(&self) -> SessionIndex {
+        self.session_index
+    }
+
+    fn validator_set_count(&self) -> u32 {
+        self.validator_set_count
+    }
+
+    fn time_slot(&self) -> Self::TimeSlot {
+        self.session_index
+    }
+
+    /// A lone equivocation is most likely a misconfiguration, so it is only slashed with
+    /// `MIN_EQUIVOCATION_SLASH`. Every further offender in the session makes an attack more
+    /// likely, so the slash grows quadratically with their share in the committee, reaching 100%
+    /// once more than a third of it equivocated.
+    fn slash_fraction(offenders_count: u32, validator_set_count: u32) -> Perbill {
+        let fraction = Perbill::from_rational(
+            offenders_count.saturating_sub(1).saturating_mul(3),
+            validator_set_count,
+        );
+        fraction.square().max(MIN_EQUIVOCATION_SLASH)
+    }
+}
+
+/// Reports equivocations as offences to `R`, which applies the slashes through the staking
+/// pallet, and bans the offenders with `B` once their offence was accepted.
+pub struct EquivocationHandler<T, R, B>(PhantomData<(T, R, B)>);
+
+impl<T, R, B> HandleEquivocation<T::AuthorityId> for EquivocationHandler<T, R, B>
+where
+    T: Config + pallet_session::historical::Config,
+    R: ReportOffence<
+        T::AccountId,
+        IdentificationTuple<T>,
+        AlephEquivocationOffence<IdentificationTuple<T>>,
+    >,
+    B: BanHandler<AccountId = T::ValidatorId>,
+{
+    fn handle_equivocation(session: SessionIndex, offender: &T::AuthorityId, committee_size: u32) {
+        let validator = match pallet_session::Pallet::<T>::key_owner(
+            T::AuthorityId::ID,
+            &offender.to_raw_vec(),
+        ) {
+            Some(validator) => validator,
+            None => {
+                log::warn!(target: "pallet_aleph", "No validator owns the key of the equivocating member {:?}", offender);
+                return;
+            }
+        };
+        let identification = match T::FullIdentificationOf::convert(validator.clone()) {
+            Some(identification) => identification,
+            None => {
+                log::warn!(target: "pallet_aleph", "Validator {:?} cannot be identified, not punishing it", validator);
+                return;
+            }
+        };
+
+        let offence = AlephEquivocationOffence {
+            session_index: session,
+            validator_set_count: committee_size,
+            offender: (validator.clone(), identification),
+        };
+        match R::report_offence(vec![], offence) {
+            Ok(()) => B::ban_validator(&validator),
+            Err(e) => {
+                log::warn!(target: "pallet_aleph", "Failed to report equivocation of validator {:?}, not banning it: {:?}", validator, e)
+            }
+        }
+    }
+}
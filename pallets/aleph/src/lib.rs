@@ -1,7 +1,10 @@
 //! This pallet is a runtime companion of Aleph finality gadget.
 //!
-//! Currently, it only provides support for changing sessions but in the future
-//! it will allow reporting equivocation in AlephBFT.
+//! It keeps track of the authorities, the emergency finalizer and the finality parameters across
+//! sessions, and allows reporting equivocations (forks) of AlephBFT committee members, which get
+//! slashed and banned through the configured `EquivocationHandler`. Authorities can also register
+//! their network addresses, so that the committee finds them without waiting for gossip, and their
+//! BLS keys, so that the committee can aggregate its signatures of blocks.
 
 #![cfg_attr(not(feature = "std"), no_std)]
 
@@ -10,39 +13,104 @@ mod mock;
 #[cfg(test)]
 mod tests;
 
+mod equivocation;
 mod migrations;
+mod traits;
 
+pub use equivocation::{AlephEquivocationOffence, EquivocationHandler, MIN_EQUIVOCATION_SLASH};
 use frame_support::{
     log,
-    sp_runtime::BoundToRuntimeAppPublic,
+    sp_runtime::{BoundToRuntimeAppPublic, RuntimeAppPublic},
     traits::{OneSessionHandler, StorageVersion},
 };
 pub use pallet::*;
+use primitives::{BlsKey, FinalityParameters, SessionIndex};
 use sp_std::prelude::*;
+pub use traits::{HandleEquivocation, SessionInfoProvider};
 
 /// The current storage version.
-const STORAGE_VERSION: StorageVersion = StorageVersion::new(2);
+const STORAGE_VERSION: StorageVersion = StorageVersion::new(3);
+
+/// How many blocks an equivocation report stays valid in the transaction pool.
+const EQUIVOCATION_REPORT_LONGEVITY: u64 = 64;
+
+/// How many blocks an address registration stays valid in the transaction pool.
+const VALIDATOR_ADDRESSES_LONGEVITY: u64 = 64;
+
+/// How many blocks a BLS key registration stays valid in the transaction pool.
+const BLS_KEY_LONGEVITY: u64 = 64;
+
+/// Upper bound on the weight of verifying a single unit signature.
+const SIGNATURE_VERIFICATION_WEIGHT: u64 = 100_000_000;
+
+/// Upper bound on the weight of verifying a proof of possession of a BLS key, two pairings.
+const PROOF_OF_POSSESSION_VERIFICATION_WEIGHT: u64 = 2_000_000_000;
+
+pub type AuthoritySignatureOf<T> = <<T as Config>::AuthorityId as RuntimeAppPublic>::Signature;
+pub type EquivocationProofOf<T> = primitives::EquivocationProof<AuthoritySignatureOf<T>>;
+pub type SignedValidatorAddressesOf<T> =
+    primitives::SignedValidatorAddresses<AuthoritySignatureOf<T>>;
+pub type SignedBlsKeyRegistrationOf<T> =
+    primitives::SignedBlsKeyRegistration<AuthoritySignatureOf<T>>;
 
 #[frame_support::pallet]
 pub mod pallet {
-    use frame_support::{pallet_prelude::*, sp_runtime::RuntimeAppPublic};
+    use frame_support::pallet_prelude::*;
     use frame_system::{
-        ensure_root,
+        ensure_none, ensure_root,
+        offchain::{SendTransactionTypes, SubmitTransaction},
         pallet_prelude::{BlockNumberFor, OriginFor},
     };
 
     use super::*;
 
     #[pallet::config]
-    pub trait Config: frame_system::Config {
+    pub trait Config: frame_system::Config + SendTransactionTypes<Call<Self>> {
         type AuthorityId: Member + Parameter + RuntimeAppPublic + MaybeSerializeDeserialize;
         type Event: From<Event<Self>> + IsType<<Self as frame_system::Config>::Event>;
+        type SessionInfoProvider: SessionInfoProvider;
+        /// Something that punishes the members with proven equivocations.
+        type EquivocationHandler: HandleEquivocation<Self::AuthorityId>;
     }
 
     #[pallet::event]
     #[pallet::generate_deposit(pub(super) fn deposit_event)]
     pub enum Event<T: Config> {
         ChangeEmergencyFinalizer(T::AuthorityId),
+        /// A committee member equivocated in the given session.
+        EquivocationReported(SessionIndex, T::AuthorityId),
+        /// New finality parameters were set, they are used from the next session onwards.
+        ChangeFinalityParameters(FinalityParameters),
+        /// The authority registered new network addresses.
+        ValidatorAddressesRegistered(T::AuthorityId),
+        /// The authority registered a new BLS key.
+        BlsKeyRegistered(T::AuthorityId, BlsKey),
+    }
+
+    #[pallet::error]
+    pub enum Error<T> {
+        /// The proof is malformed, is not signed by the creator of the units or does not show
+        /// two different units for the same round.
+        InvalidEquivocationProof,
+        /// The proof concerns a session other than the current one.
+        OutdatedEquivocationProof,
+        /// The equivocation of this member in this session was already reported.
+        DuplicateEquivocationReport,
+        /// Some of the finality parameters are zero.
+        InvalidFinalityParameters,
+        /// Only authorities of the current and next session can register addresses.
+        NotAnAuthority,
+        /// The addresses are empty, too many, too long or not signed by the authority.
+        InvalidValidatorAddresses,
+        /// The addresses are registered for a session other than the current or next one, or the
+        /// authority already registered addresses in that session.
+        OutdatedValidatorAddresses,
+        /// The proof of possession of the BLS key is invalid, or the registration is not signed by
+        /// the authority.
+        InvalidBlsKey,
+        /// The BLS key is registered for a session other than the current or next one, the
+        /// authority registered a key in a later session, or it already registered this key.
+        OutdatedBlsKey,
     }
 
     #[pallet::pallet]
@@ -57,17 +125,22 @@ pub mod pallet {
             T::DbWeight::get().reads(1)
                 + match on_chain {
                     _ if on_chain == STORAGE_VERSION => 0,
+                    _ if on_chain == StorageVersion::new(2) => {
+                        migrations::v2_to_v3::migrate::<T, Self>()
+                    }
                     _ if on_chain == StorageVersion::new(1) => {
                         migrations::v1_to_v2::migrate::<T, Self>()
+                            + migrations::v2_to_v3::migrate::<T, Self>()
                     }
                     _ if on_chain == StorageVersion::new(0) => {
                         migrations::v0_to_v1::migrate::<T, Self>()
                             + migrations::v1_to_v2::migrate::<T, Self>()
+                            + migrations::v2_to_v3::migrate::<T, Self>()
                     }
                     _ => {
                         log::warn!(
                             target: "pallet_aleph",
-                            "On chain storage version of pallet aleph is {:?} but it should not be bigger than 2",
+                            "On chain storage version of pallet aleph is {:?} but it should not be bigger than 3",
                             on_chain
                         );
                         0
@@ -80,6 +153,12 @@ pub mod pallet {
     #[pallet::getter(fn authorities)]
     pub(super) type Authorities<T: Config> = StorageValue<_, Vec<T::AuthorityId>, ValueQuery>;
 
+    /// Authorities of the next session. Kept in storage, so that light clients can learn about
+    /// authority changes through storage proofs against the state of the last block of a session.
+    #[pallet::storage]
+    #[pallet::getter(fn next_authorities)]
+    pub(super) type NextAuthorities<T: Config> = StorageValue<_, Vec<T::AuthorityId>, ValueQuery>;
+
     #[pallet::storage]
     #[pallet::getter(fn emergency_finalizer)]
     pub(super) type EmergencyFinalizer<T: Config> = StorageValue<_, T::AuthorityId, OptionQuery>;
@@ -92,6 +171,62 @@ pub mod pallet {
     #[pallet::storage]
     type NextEmergencyFinalizer<T: Config> = StorageValue<_, T::AuthorityId, OptionQuery>;
 
+    /// Finality parameters in force in the current session.
+    #[pallet::storage]
+    #[pallet::getter(fn finality_params)]
+    pub(super) type FinalityParams<T: Config> = StorageValue<_, FinalityParameters, OptionQuery>;
+
+    /// Finality parameters that come into force in the next session.
+    #[pallet::storage]
+    #[pallet::getter(fn next_finality_params)]
+    pub(super) type NextFinalityParams<T: Config> =
+        StorageValue<_, FinalityParameters, OptionQuery>;
+
+    /// Committee members reported for equivocation, keyed by session and their index in the
+    /// committee.
+    #[pallet::storage]
+    #[pallet::getter(fn reported_equivocations)]
+    pub(super) type ReportedEquivocations<T: Config> = StorageDoubleMap<
+        _,
+        Twox64Concat,
+        SessionIndex,
+        Twox64Concat,
+        u32,
+        T::AuthorityId,
+        OptionQuery,
+    >;
+
+    /// Signed network addresses registered by the authorities of the current and next session.
+    #[pallet::storage]
+    #[pallet::getter(fn validator_addresses)]
+    pub(super) type ValidatorAddresses<T: Config> =
+        StorageMap<_, Blake2_128Concat, T::AuthorityId, SignedValidatorAddressesOf<T>, OptionQuery>;
+
+    /// BLS keys registered by the authorities, together with the registrations. Unlike addresses
+    /// they are kept when the authorities leave the committee, as the committees are planned using
+    /// keys registered earlier.
+    #[pallet::storage]
+    #[pallet::getter(fn bls_key_registrations)]
+    pub(super) type BlsKeys<T: Config> = StorageMap<
+        _,
+        Blake2_128Concat,
+        T::AuthorityId,
+        primitives::BlsKeyRegistration,
+        OptionQuery,
+    >;
+
+    /// BLS keys of the authorities of the current session, in the order of the authorities. Only
+    /// present if all of them had keys registered when the session was planned.
+    #[pallet::storage]
+    #[pallet::getter(fn session_bls_keys)]
+    pub(super) type SessionBlsKeys<T: Config> = StorageValue<_, Vec<BlsKey>, OptionQuery>;
+
+    /// BLS keys of the authorities of the next session, fixed when the session is planned so that
+    /// every node reads the same keys.
+    #[pallet::storage]
+    #[pallet::getter(fn next_session_bls_keys)]
+    pub(super) type NextSessionBlsKeys<T: Config> = StorageValue<_, Vec<BlsKey>, OptionQuery>;
+
     impl<T: Config> Pallet<T> {
         pub(crate) fn initialize_authorities(authorities: &[T::AuthorityId]) {
             if !authorities.is_empty() {
@@ -107,6 +242,10 @@ pub mod pallet {
             <Authorities<T>>::put(authorities);
         }
 
+        pub(crate) fn update_next_authorities(authorities: &[T::AuthorityId]) {
+            <NextAuthorities<T>>::put(authorities);
+        }
+
         pub(crate) fn update_emergency_finalizer() {
             match <QueuedEmergencyFinalizer<T>>::get() {
                 Some(emergency_finalizer) => <EmergencyFinalizer<T>>::put(emergency_finalizer),
@@ -123,6 +262,231 @@ pub mod pallet {
         pub(crate) fn set_next_emergency_finalizer(emergency_finalizer: T::AuthorityId) {
             <NextEmergencyFinalizer<T>>::put(emergency_finalizer);
         }
+
+        pub(crate) fn update_finality_params() {
+            if let Some(finality_params) = <NextFinalityParams<T>>::take() {
+                <FinalityParams<T>>::put(finality_params);
+            }
+        }
+
+        /// The finality parameters that will be in force in the next session.
+        pub fn next_session_finality_params() -> Option<FinalityParameters> {
+            <NextFinalityParams<T>>::get().or_else(<FinalityParams<T>>::get)
+        }
+
+        /// Forgets the addresses of validators that are no longer authorities.
+        /// Removes the addresses of the previous authorities that are neither authorities of the
+        /// current nor the next session. Only these can register addresses, so the map never
+        /// outgrows the two authority sets and we only visit the entries of the previous ones.
+        pub(crate) fn prune_validator_addresses(previous_authorities: &[T::AuthorityId]) {
+            let authorities = <Authorities<T>>::get();
+            let next_authorities = <NextAuthorities<T>>::get();
+            for authority in previous_authorities {
+                if !authorities.contains(authority) && !next_authorities.contains(authority) {
+                    <ValidatorAddresses<T>>::remove(authority);
+                }
+            }
+        }
+
+        /// Moves the BLS keys of the next session to the current one, and fixes the keys of the
+        /// session after it.
+        pub(crate) fn update_bls_keys(next_authorities: &[T::AuthorityId]) {
+            <SessionBlsKeys<T>>::set(<NextSessionBlsKeys<T>>::take());
+            <NextSessionBlsKeys<T>>::set(Self::bls_keys_of(next_authorities));
+        }
+
+        /// The keys of all the authorities, if all of them registered one.
+        fn bls_keys_of(authorities: &[T::AuthorityId]) -> Option<Vec<BlsKey>> {
+            if authorities.is_empty() {
+                return None;
+            }
+            authorities
+                .iter()
+                .map(|authority| <BlsKeys<T>>::get(authority).map(|registration| registration.key))
+                .collect()
+        }
+
+        /// The BLS key registered by the authority, if any.
+        pub fn registered_bls_key(authority: &T::AuthorityId) -> Option<BlsKey> {
+            <BlsKeys<T>>::get(authority).map(|registration| registration.key)
+        }
+
+        /// Checks that the BLS key is possessed by the authority, and that the registration is
+        /// newer than the one it made before.
+        pub(crate) fn check_bls_key(
+            authority: &T::AuthorityId,
+            signed_registration: &SignedBlsKeyRegistrationOf<T>,
+        ) -> Result<(), Error<T>> {
+            ensure!(
+                <Authorities<T>>::get().contains(authority)
+                    || <NextAuthorities<T>>::get().contains(authority),
+                Error::<T>::NotAnAuthority
+            );
+            let registration = &signed_registration.registration;
+            let session = T::SessionInfoProvider::current_session();
+            ensure!(
+                registration.session == session || registration.session == session + 1,
+                Error::<T>::OutdatedBlsKey
+            );
+            // Only a registration from a later session replaces the registered one, otherwise an
+            // older registration from the same session could be replayed to bring back its key.
+            if let Some(registered) = <BlsKeys<T>>::get(authority) {
+                ensure!(
+                    registered.session < registration.session && registered.key != registration.key,
+                    Error::<T>::OutdatedBlsKey
+                );
+            }
+            ensure!(
+                authority.verify(
+                    &registration.signing_payload(),
+                    &signed_registration.signature
+                ) && registration.has_valid_proof(),
+                Error::<T>::InvalidBlsKey
+            );
+            Ok(())
+        }
+
+        /// Like address registrations, these are signed by the authority and accepted from other
+        /// nodes.
+        fn validate_bls_key(
+            authority: &T::AuthorityId,
+            signed_registration: &SignedBlsKeyRegistrationOf<T>,
+        ) -> TransactionValidity {
+            Self::check_bls_key(authority, signed_registration)
+                .map_err(InvalidTransaction::from)?;
+            ValidTransaction::with_tag_prefix("AlephBlsKey")
+                .and_provides((authority, signed_registration.registration.key))
+                .longevity(BLS_KEY_LONGEVITY)
+                .propagate(true)
+                .build()
+        }
+
+        /// Submits an unsigned `register_bls_key` extrinsic to the local transaction pool.
+        pub fn submit_unsigned_bls_key(
+            authority: T::AuthorityId,
+            signed_registration: SignedBlsKeyRegistrationOf<T>,
+        ) -> Option<()> {
+            let call = Call::register_bls_key {
+                authority,
+                signed_registration,
+            };
+            SubmitTransaction::<T, Call<T>>::submit_unsigned_transaction(call.into()).ok()
+        }
+
+        /// All the registered validator addresses.
+        pub fn registered_validator_addresses(
+        ) -> Vec<(T::AuthorityId, SignedValidatorAddressesOf<T>)> {
+            <ValidatorAddresses<T>>::iter().collect()
+        }
+
+        /// Checks that the addresses are valid, signed by the authority and newer than the ones
+        /// it registered before, i.e. from a later session or with a higher nonce.
+        pub(crate) fn check_validator_addresses(
+            authority: &T::AuthorityId,
+            signed_addresses: &SignedValidatorAddressesOf<T>,
+        ) -> Result<(), Error<T>> {
+            ensure!(
+                <Authorities<T>>::get().contains(authority)
+                    || <NextAuthorities<T>>::get().contains(authority),
+                Error::<T>::NotAnAuthority
+            );
+            let addresses = &signed_addresses.addresses;
+            let session = T::SessionInfoProvider::current_session();
+            ensure!(
+                addresses.session == session || addresses.session == session + 1,
+                Error::<T>::OutdatedValidatorAddresses
+            );
+            if let Some(registered) = <ValidatorAddresses<T>>::get(authority) {
+                ensure!(
+                    (registered.addresses.session, registered.addresses.nonce)
+                        < (addresses.session, addresses.nonce),
+                    Error::<T>::OutdatedValidatorAddresses
+                );
+            }
+            ensure!(
+                addresses.is_valid()
+                    && authority.verify(&addresses.signing_payload(), &signed_addresses.signature),
+                Error::<T>::InvalidValidatorAddresses
+            );
+            Ok(())
+        }
+
+        /// Registrations are signed by the authority, so unlike equivocation reports they are
+        /// accepted from other nodes, which is needed for them to reach the block authors.
+        fn validate_validator_addresses(
+            authority: &T::AuthorityId,
+            signed_addresses: &SignedValidatorAddressesOf<T>,
+        ) -> TransactionValidity {
+            Self::check_validator_addresses(authority, signed_addresses)
+                .map_err(InvalidTransaction::from)?;
+            ValidTransaction::with_tag_prefix("AlephValidatorAddresses")
+                .and_provides((
+                    authority,
+                    signed_addresses.addresses.session,
+                    signed_addresses.addresses.nonce,
+                ))
+                .longevity(VALIDATOR_ADDRESSES_LONGEVITY)
+                .propagate(true)
+                .build()
+        }
+
+        /// Submits an unsigned `register_validator_addresses` extrinsic to the local transaction
+        /// pool.
+        pub fn submit_unsigned_validator_addresses(
+            authority: T::AuthorityId,
+            signed_addresses: SignedValidatorAddressesOf<T>,
+        ) -> Option<()> {
+            let call = Call::register_validator_addresses {
+                authority,
+                signed_addresses,
+            };
+            SubmitTransaction::<T, Call<T>>::submit_unsigned_transaction(call.into()).ok()
+        }
+
+        /// Checks the proof against the authorities of the current session. Returns the session,
+        /// the index of the offender in the committee, the offender itself and the size of the
+        /// committee, all read from the same authorities.
+        pub(crate) fn check_equivocation_proof(
+            equivocation_proof: &EquivocationProofOf<T>,
+        ) -> Result<(SessionIndex, u32, T::AuthorityId, u32), Error<T>> {
+            let coord = equivocation_proof
+                .coord()
+                .ok_or(Error::<T>::InvalidEquivocationProof)?;
+            let session = T::SessionInfoProvider::current_session();
+            ensure!(
+                coord.session == session as u64,
+                Error::<T>::OutdatedEquivocationProof
+            );
+            let offender_index =
+                u32::try_from(coord.creator).map_err(|_| Error::<T>::InvalidEquivocationProof)?;
+            let authorities = <Authorities<T>>::get();
+            let committee_size = authorities.len() as u32;
+            let offender = authorities
+                .get(offender_index as usize)
+                .cloned()
+                .ok_or(Error::<T>::InvalidEquivocationProof)?;
+            for signed_unit in [&equivocation_proof.first, &equivocation_proof.second] {
+                ensure!(
+                    offender.verify(&signed_unit.unit.signing_payload(), &signed_unit.signature),
+                    Error::<T>::InvalidEquivocationProof
+                );
+            }
+            ensure!(
+                !<ReportedEquivocations<T>>::contains_key(session, offender_index),
+                Error::<T>::DuplicateEquivocationReport
+            );
+            Ok((session, offender_index, offender, committee_size))
+        }
+
+        /// Submits an unsigned `report_equivocation` extrinsic to the local transaction pool.
+        pub fn submit_unsigned_equivocation_report(
+            equivocation_proof: EquivocationProofOf<T>,
+        ) -> Option<()> {
+            let call = Call::report_equivocation {
+                equivocation_proof: Box::new(equivocation_proof),
+            };
+            SubmitTransaction::<T, Call<T>>::submit_unsigned_transaction(call.into()).ok()
+        }
     }
 
     #[pallet::call]
@@ -139,6 +503,157 @@ pub mod pallet {
             Self::deposit_event(Event::ChangeEmergencyFinalizer(emergency_finalizer));
             Ok(())
         }
+
+        /// Sets the finality parameters. If called in session `N` they are used from session
+        /// `N+1` onwards, until they get overridden.
+        #[pallet::weight((T::BlockWeights::get().max_block, DispatchClass::Operational))]
+        pub fn set_finality_params(
+            origin: OriginFor<T>,
+            finality_params: FinalityParameters,
+        ) -> DispatchResult {
+            ensure_root(origin)?;
+            ensure!(
+                finality_params.is_valid(),
+                Error::<T>::InvalidFinalityParameters
+            );
+            <NextFinalityParams<T>>::put(finality_params.clone());
+            Self::deposit_event(Event::ChangeFinalityParameters(finality_params));
+            Ok(())
+        }
+
+        /// Reports two different units created by the same committee member for the same round
+        /// of the current session. Submitted as an unsigned extrinsic by the nodes that detect
+        /// the fork. The offender is punished by the `EquivocationHandler`.
+        #[pallet::weight((
+            T::DbWeight::get().reads_writes(4, 1) + 2 * SIGNATURE_VERIFICATION_WEIGHT,
+            DispatchClass::Operational
+        ))]
+        pub fn report_equivocation(
+            origin: OriginFor<T>,
+            equivocation_proof: Box<EquivocationProofOf<T>>,
+        ) -> DispatchResult {
+            ensure_none(origin)?;
+            let (session, offender_index, offender, committee_size) =
+                Self::check_equivocation_proof(&equivocation_proof)?;
+            <ReportedEquivocations<T>>::insert(session, offender_index, offender.clone());
+            T::EquivocationHandler::handle_equivocation(session, &offender, committee_size);
+            Self::deposit_event(Event::EquivocationReported(session, offender));
+            Ok(())
+        }
+
+        /// Registers the network addresses of an authority of the current or next session,
+        /// replacing the ones it registered before. Submitted as an unsigned extrinsic by the node of the authority,
+        /// the addresses have to be signed with its authority key.
+        #[pallet::weight((
+            T::DbWeight::get().reads_writes(4, 1) + SIGNATURE_VERIFICATION_WEIGHT,
+            DispatchClass::Operational
+        ))]
+        pub fn register_validator_addresses(
+            origin: OriginFor<T>,
+            authority: T::AuthorityId,
+            signed_addresses: SignedValidatorAddressesOf<T>,
+        ) -> DispatchResult {
+            ensure_none(origin)?;
+            Self::check_validator_addresses(&authority, &signed_addresses)?;
+            <ValidatorAddresses<T>>::insert(authority.clone(), signed_addresses);
+            Self::deposit_event(Event::ValidatorAddressesRegistered(authority));
+            Ok(())
+        }
+
+        /// Registers the BLS key of an authority of the current or next session. Submitted as an
+        /// unsigned extrinsic by the node of the authority, the registration has to be signed with
+        /// its authority key and prove possession of the BLS key. It replaces only registrations
+        /// from earlier sessions. The key is used in the sessions planned after the registration.
+        #[pallet::weight((
+            T::DbWeight::get().reads_writes(4, 1)
+                + SIGNATURE_VERIFICATION_WEIGHT
+                + PROOF_OF_POSSESSION_VERIFICATION_WEIGHT,
+            DispatchClass::Operational
+        ))]
+        pub fn register_bls_key(
+            origin: OriginFor<T>,
+            authority: T::AuthorityId,
+            signed_registration: SignedBlsKeyRegistrationOf<T>,
+        ) -> DispatchResult {
+            ensure_none(origin)?;
+            Self::check_bls_key(&authority, &signed_registration)?;
+            let registration = signed_registration.registration;
+            let key = registration.key;
+            <BlsKeys<T>>::insert(authority.clone(), registration);
+            Self::deposit_event(Event::BlsKeyRegistered(authority, key));
+            Ok(())
+        }
+    }
+
+    impl<T> From<Error<T>> for InvalidTransaction {
+        fn from(error: Error<T>) -> Self {
+            match error {
+                Error::OutdatedEquivocationProof
+                | Error::DuplicateEquivocationReport
+                | Error::OutdatedValidatorAddresses
+                | Error::OutdatedBlsKey => InvalidTransaction::Stale,
+                _ => InvalidTransaction::BadProof,
+            }
+        }
+    }
+
+    #[pallet::validate_unsigned]
+    impl<T: Config> ValidateUnsigned for Pallet<T> {
+        type Call = Call<T>;
+
+        fn validate_unsigned(source: TransactionSource, call: &Self::Call) -> TransactionValidity {
+            let equivocation_proof = match call {
+                Call::report_equivocation { equivocation_proof } => equivocation_proof,
+                Call::register_validator_addresses {
+                    authority,
+                    signed_addresses,
+                } => return Self::validate_validator_addresses(authority, signed_addresses),
+                Call::register_bls_key {
+                    authority,
+                    signed_registration,
+                } => return Self::validate_bls_key(authority, signed_registration),
+                _ => return InvalidTransaction::Call.into(),
+            };
+            match source {
+                TransactionSource::Local | TransactionSource::InBlock => (),
+                TransactionSource::External => {
+                    log::warn!(
+                        target: "pallet_aleph",
+                        "Rejecting equivocation report from an external source"
+                    );
+                    return InvalidTransaction::Call.into();
+                }
+            }
+            let (session, offender_index, ..) = Self::check_equivocation_proof(equivocation_proof)
+                .map_err(InvalidTransaction::from)?;
+            ValidTransaction::with_tag_prefix("AlephEquivocation")
+                .priority(TransactionPriority::max_value())
+                .and_provides((session, offender_index))
+                .longevity(EQUIVOCATION_REPORT_LONGEVITY)
+                .propagate(false)
+                .build()
+        }
+
+        fn pre_dispatch(call: &Self::Call) -> Result<(), TransactionValidityError> {
+            match call {
+                Call::report_equivocation { equivocation_proof } => {
+                    Self::check_equivocation_proof(equivocation_proof)
+                        .map(|_| ())
+                        .map_err(|e| InvalidTransaction::from(e).into())
+                }
+                Call::register_validator_addresses {
+                    authority,
+                    signed_addresses,
+                } => Self::check_validator_addresses(authority, signed_addresses)
+                    .map_err(|e| InvalidTransaction::from(e).into()),
+                Call::register_bls_key {
+                    authority,
+                    signed_registration,
+                } => Self::check_bls_key(authority, signed_registration)
+                    .map_err(|e| InvalidTransaction::from(e).into()),
+                _ => Err(InvalidTransaction::Call.into()),
+            }
+        }
     }
 
     impl<T: Config> BoundToRuntimeAppPublic for Pallet<T> {
@@ -155,18 +670,29 @@ pub mod pallet {
         {
             let (_, authorities): (Vec<_>, Vec<_>) = validators.unzip();
             Self::initialize_authorities(authorities.as_slice());
+            Self::update_next_authorities(authorities.as_slice());
+            Self::update_bls_keys(authorities.as_slice());
         }
 
-        fn on_new_session<'a, I: 'a>(changed: bool, validators: I, _queued_validators: I)
+        fn on_new_session<'a, I: 'a>(changed: bool, validators: I, queued_validators: I)
         where
             I: Iterator<Item = (&'a T::AccountId, T::AuthorityId)>,
             T::AccountId: 'a,
         {
+            let previous_authorities: Vec<_> = <Authorities<T>>::get()
+                .into_iter()
+                .chain(<NextAuthorities<T>>::get())
+                .collect();
             Self::update_emergency_finalizer();
+            Self::update_finality_params();
             if changed {
                 let (_, authorities): (Vec<_>, Vec<_>) = validators.unzip();
                 Self::update_authorities(authorities.as_slice());
             }
+            let (_, next_authorities): (Vec<_>, Vec<_>) = queued_validators.unzip();
+            Self::update_next_authorities(next_authorities.as_slice());
+            Self::update_bls_keys(next_authorities.as_slice());
+            Self::prune_validator_addresses(&previous_authorities);
         }
 
         fn on_disabled(_validator_index: u32) {}
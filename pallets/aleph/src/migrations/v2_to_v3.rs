@@ -0,0 +1,29 @@
+use frame_support::{
+    log,
+    traits::{Get, PalletInfoAccess, StorageVersion},
+    weights::Weight,
+};
+
+use crate::{Config, NextAuthorities, SessionInfoProvider};
+
+/// Fills in the authorities of the next session, which before were only known to the session
+/// pallet, so that handovers can be proven from the first session after the upgrade.
+pub fn migrate<T: Config, P: PalletInfoAccess>() -> Weight {
+    let mut writes = 0;
+    log::info!(target: "pallet_aleph", "Running migration from STORAGE_VERSION 2 to 3");
+
+    if NextAuthorities::<T>::exists() {
+        log::info!(target: "pallet_aleph", "Storage item NextAuthorities already exists!");
+    } else {
+        let next_authorities = T::SessionInfoProvider::queued_keys::<T::AuthorityId>();
+        log::info!(target: "pallet_aleph", "Setting NextAuthorities to the {} queued authorities", next_authorities.len());
+        NextAuthorities::<T>::put(next_authorities);
+        writes += 1;
+    }
+
+    // store new version
+    StorageVersion::new(3).put::<P>();
+    writes += 1;
+
+    T::DbWeight::get().reads(2) + T::DbWeight::get().writes(writes)
+}
@@ -1,10 +1,14 @@
 #![cfg(test)]
 
+use frame_election_provider_support::{data_provider, ElectionDataProvider, VoteWeight};
 use frame_support::{
     construct_runtime, parameter_types, sp_io,
-    traits::{OnFinalize, OnInitialize},
-    weights::RuntimeDbWeight,
+    traits::{ConstU32, OnFinalize, OnInitialize},
+    weights::{RuntimeDbWeight, Weight},
+    BoundedVec,
 };
+use pallet_elections::{EraInfoProvider, ValidatorRewardsHandler};
+use pallet_session::historical::IdentificationTuple;
 use primitives::AuthorityId;
 use sp_api_hidden_includes_construct_runtime::hidden_include::traits::GenesisBuild;
 use sp_core::H256;
@@ -12,7 +16,13 @@ use sp_runtime::{
     impl_opaque_keys,
     testing::{Header, TestXt, UintAuthorityId},
     traits::{ConvertInto, IdentityLookup, OpaqueKeys},
+    Perbill,
 };
+use sp_staking::{
+    offence::{DisableStrategy, OffenceDetails, OffenceError, OnOffenceHandler, ReportOffence},
+    EraIndex, SessionIndex,
+};
+use sp_std::cell::RefCell;
 
 use super::*;
 use crate as pallet_aleph;
@@ -29,9 +39,11 @@ construct_runtime!(
     {
         System: frame_system::{Pallet, Call, Config, Storage, Event<T>},
         Balances: pallet_balances::{Pallet, Call, Storage, Config<T>, Event<T>},
-        Aleph: pallet_aleph::{Pallet, Storage, Event<T>},
+        Aleph: pallet_aleph::{Pallet, Call, Storage, Event<T>, ValidateUnsigned},
         Session: pallet_session::{Pallet, Call, Storage, Event, Config<T>},
         Timestamp: pallet_timestamp::{Pallet, Call, Storage, Inherent},
+        Offences: pallet_offences::{Pallet, Storage, Event},
+        Elections: pallet_elections::{Pallet, Call, Storage, Config<T>, Event<T>},
     }
 );
 
@@ -111,6 +123,135 @@ impl pallet_session::Config for Test {
     type WeightInfo = ();
 }
 
+impl pallet_session::historical::Config for Test {
+    type FullIdentification = u64;
+    type FullIdentificationOf = ConvertInto;
+}
+
+type Offence = AlephEquivocationOffence<IdentificationTuple<Test>>;
+
+thread_local! {
+    static SLASHES: RefCell<Vec<(IdentificationTuple<Test>, Perbill)>> = RefCell::new(Default::default());
+    static ACTIVE_ERA: RefCell<EraIndex> = RefCell::new(Default::default());
+}
+
+/// The offenders passed to the staking mock, with their slash fractions.
+pub fn slashes() -> Vec<(IdentificationTuple<Test>, Perbill)> {
+    SLASHES.with(|s| s.borrow().clone())
+}
+
+pub fn with_active_era(era: EraIndex) {
+    ACTIVE_ERA.with(|ae| *ae.borrow_mut() = era);
+}
+
+pub struct StakingMock;
+impl OnOffenceHandler<AccountId, IdentificationTuple<Test>, Weight> for StakingMock {
+    fn on_offence(
+        offenders: &[OffenceDetails<AccountId, IdentificationTuple<Test>>],
+        slash_fraction: &[Perbill],
+        _session: SessionIndex,
+        _disable_strategy: DisableStrategy,
+    ) -> Weight {
+        SLASHES.with(|s| {
+            s.borrow_mut().extend(
+                offenders
+                    .iter()
+                    .map(|details| details.offender)
+                    .zip(slash_fraction.iter().cloned()),
+            )
+        });
+        0
+    }
+}
+
+impl pallet_offences::Config for Test {
+    type Event = Event;
+    type IdentificationTuple = IdentificationTuple<Test>;
+    type OnOffenceHandler = StakingMock;
+}
+
+pub const SESSIONS_PER_ERA: SessionIndex = 3;
+
+impl EraInfoProvider for StakingMock {
+    type AccountId = AccountId;
+
+    fn active_era() -> Option<EraIndex> {
+        Some(ACTIVE_ERA.with(|ae| *ae.borrow()))
+    }
+
+    fn era_start_session_index(era: EraIndex) -> Option<SessionIndex> {
+        Some(era * SESSIONS_PER_ERA)
+    }
+
+    fn sessions_per_era() -> SessionIndex {
+        SESSIONS_PER_ERA
+    }
+
+    fn elected_validators(_era: EraIndex) -> Vec<AccountId> {
+        Session::validators()
+    }
+}
+
+impl ValidatorRewardsHandler<Test> for StakingMock {
+    fn validator_totals(_era: EraIndex) -> Vec<(AccountId, u128)> {
+        Vec::new()
+    }
+
+    fn add_rewards(_rewards: impl IntoIterator<Item = (AccountId, u32)>) {}
+}
+
+type Vote = (AccountId, VoteWeight, BoundedVec<AccountId, ConstU32<1>>);
+
+impl ElectionDataProvider for StakingMock {
+    type AccountId = AccountId;
+    type BlockNumber = u64;
+    type MaxVotesPerVoter = ConstU32<1>;
+
+    fn electable_targets(_maybe_max_len: Option<usize>) -> data_provider::Result<Vec<AccountId>> {
+        Ok(Vec::new())
+    }
+
+    fn electing_voters(_maybe_max_len: Option<usize>) -> data_provider::Result<Vec<Vote>> {
+        Ok(Vec::new())
+    }
+
+    fn desired_targets() -> data_provider::Result<u32> {
+        Ok(0)
+    }
+
+    fn next_election_prediction(_now: u64) -> u64 {
+        0
+    }
+}
+
+parameter_types! {
+    pub const SessionPeriod: u32 = 1;
+    pub const BanPeriod: EraIndex = 2;
+}
+
+impl pallet_elections::Config for Test {
+    type EraInfoProvider = StakingMock;
+    type Event = Event;
+    type DataProvider = StakingMock;
+    type SessionPeriod = SessionPeriod;
+    type SessionManager = ();
+    type SessionInfoProvider = Session;
+    type ValidatorRewardsHandler = StakingMock;
+    type BanPeriod = BanPeriod;
+}
+
+/// Fails to report any offence, as when the offence was already reported.
+pub struct FailingOffenceReporter;
+impl ReportOffence<AccountId, IdentificationTuple<Test>, Offence> for FailingOffenceReporter {
+    fn report_offence(_reporters: Vec<AccountId>, _offence: Offence) -> Result<(), OffenceError> {
+        Err(OffenceError::DuplicateReport)
+    }
+
+    fn is_known_offence(_offenders: &[IdentificationTuple<Test>], _time_slot: &u32) -> bool {
+        true
+    }
+}
+
 impl<C> frame_system::offchain::SendTransactionTypes<C> for Test
 where
     Call: From<C>,
@@ -133,6 +274,8 @@ impl pallet_timestamp::Config for Test {
 impl Config for Test {
     type AuthorityId = AuthorityId;
     type Event = Event;
+    type SessionInfoProvider = Session;
+    type EquivocationHandler = EquivocationHandler<Test, Offences, Elections>;
 }
 
 pub fn to_authority(id: &u64) -> AuthorityId {
@@ -176,6 +319,25 @@ pub fn new_test_ext(authorities: &[(u64, u64)]) -> sp_io::TestExternalities {
     t.into()
 }
 
+/// Sets up the session keys of validators `0..keys.len()` to the given authority keys.
+pub fn new_test_ext_with_keys(keys: &[AuthorityId]) -> sp_io::TestExternalities {
+    let mut t = frame_system::GenesisConfig::default()
+        .build_storage::<Test>()
+        .unwrap();
+
+    let session_keys: Vec<_> = keys
+        .iter()
+        .enumerate()
+        .map(|(i, k)| (i as u64, i as u64, TestSessionKeys { aleph: k.clone() }))
+        .collect();
+
+    pallet_session::GenesisConfig::<Test> { keys: session_keys }
+        .assimilate_storage(&mut t)
+        .unwrap();
+
+    t.into()
+}
+
 pub(crate) fn run_session(n: u32) {
     for i in Session::current_index()..n {
         Session::on_finalize(System::block_number());
@@ -2,13 +2,36 @@
 
 use std::collections::HashMap;
 
+use blst::min_pk::SecretKey;
+use codec::Encode;
 use frame_support::{
+    assert_noop, assert_ok,
     storage::migration::{get_storage_value, put_storage_value},
     storage_alias,
     traits::{GetStorageVersion, OneSessionHandler, StorageVersion},
 };
+use pallet_elections::{
+    BannedValidators, CommitteeSeats, CommitteeSize, CurrentEraValidators, EraValidators,
+    NextEraCommitteeSize, NextEraNonReservedValidators,
+};
+use pallet_session::SessionManager;
+use primitives::{
+    storage_keys, AuthorityId, AuthorityPair, AuthoritySignature, BlsKey, BlsKeyRegistration,
+    EquivocationProof, FinalityParameters, SignedBlsKeyRegistration, SignedUnit,
+    SignedValidatorAddresses, ValidatorAddresses, VersionedUnit, BLS_POP_DST,
+    MAX_VALIDATOR_ADDRESSES,
+};
+use sp_core::Pair;
+use sp_runtime::{
+    traits::{BlakeTwo256, Hash},
+    Perbill,
+};
+use sp_staking::offence::Offence;
 
-use crate::{migrations, mock::*, pallet};
+use crate::{
+    migrations, mock::*, pallet, AlephEquivocationOffence, EquivocationHandler, Error,
+    Event as AlephEvent, HandleEquivocation, MIN_EQUIVOCATION_SLASH,
+};
 
 #[storage_alias]
 type SessionForValidatorsChange = StorageValue<Aleph, u32>;
@@ -105,6 +128,55 @@ fn migration_from_v1_to_v2_works() {
     })
 }
 
+#[test]
+fn migration_from_v2_to_v3_works() {
+    new_test_ext(&[(1u64, 1u64), (2u64, 2u64)]).execute_with(|| {
+        pallet::NextAuthorities::<Test>::kill();
+        StorageVersion::new(2).put::<Aleph>();
+
+        let _weight = migrations::v2_to_v3::migrate::<Test, Aleph>();
+
+        assert_eq!(
+            <pallet::Pallet<Test> as GetStorageVersion>::on_chain_storage_version(),
+            StorageVersion::new(3),
+            "Storage version after applying migration should be incremented"
+        );
+        assert_eq!(
+            Aleph::next_authorities(),
+            to_authorities(&[1, 2]),
+            "Migration should fill in the queued authorities"
+        );
+    })
+}
+
+#[test]
+fn migration_from_v2_to_v3_keeps_next_authorities() {
+    new_test_ext(&[(1u64, 1u64), (2u64, 2u64)]).execute_with(|| {
+        pallet::NextAuthorities::<Test>::put(to_authorities(&[3, 4]));
+        StorageVersion::new(2).put::<Aleph>();
+
+        let _weight = migrations::v2_to_v3::migrate::<Test, Aleph>();
+
+        assert_eq!(Aleph::next_authorities(), to_authorities(&[3, 4]));
+    })
+}
+
+#[test]
+fn handover_storage_keys_match_pallet() {
+    assert_eq!(
+        storage_keys::next_authorities(),
+        pallet::NextAuthorities::<Test>::hashed_key().to_vec()
+    );
+    assert_eq!(
+        storage_keys::queued_emergency_finalizer(),
+        pallet::QueuedEmergencyFinalizer::<Test>::hashed_key().to_vec()
+    );
+    assert_eq!(
+        storage_keys::next_session_bls_keys(),
+        pallet::NextSessionBlsKeys::<Test>::hashed_key().to_vec()
+    );
+}
+
 #[test]
 fn test_update_authorities() {
     new_test_ext(&[(1u64, 1u64), (2u64, 2u64)]).execute_with(|| {
@@ -164,6 +236,22 @@ fn test_session_rotation() {
     })
 }
 
+#[test]
+fn test_next_authorities() {
+    new_test_ext(&[(1u64, 1u64), (2u64, 2u64)]).execute_with(|| {
+        assert_eq!(Aleph::next_authorities(), to_authorities(&[1, 2]));
+
+        initialize_session();
+        run_session(1);
+
+        let new_validators = new_session_validators(&[1u64, 2u64]);
+        let queued_validators = new_session_validators(&[3u64, 4u64]);
+        Aleph::on_new_session(false, new_validators, queued_validators);
+        assert_eq!(Aleph::authorities(), to_authorities(&[1, 2]));
+        assert_eq!(Aleph::next_authorities(), to_authorities(&[3, 4]));
+    })
+}
+
 #[test]
 fn test_emergency_signer() {
     new_test_ext(&[(1u64, 1u64), (2u64, 2u64)]).execute_with(|| {
@@ -189,3 +277,626 @@ fn test_emergency_signer() {
         assert_eq!(Aleph::queued_emergency_finalizer(), Some(to_authority(&37)));
     })
 }
+
+fn finality_params(unit_creation_delay: u64) -> FinalityParameters {
+    FinalityParameters {
+        unit_creation_delay,
+        max_data_branch_len: 7,
+        max_proposals_pending: 80_000,
+        max_messages_pending: 40_000,
+        justification_request_delay: 2000,
+    }
+}
+
+#[test]
+fn test_finality_params_change_at_session_boundary() {
+    new_test_ext(&[(1u64, 1u64), (2u64, 2u64)]).execute_with(|| {
+        initialize_session();
+        run_session(1);
+
+        assert_eq!(Aleph::next_session_finality_params(), None);
+
+        assert_ok!(Aleph::set_finality_params(
+            Origin::root(),
+            finality_params(200)
+        ));
+
+        assert_eq!(Aleph::finality_params(), None);
+        assert_eq!(
+            Aleph::next_session_finality_params(),
+            Some(finality_params(200))
+        );
+
+        run_session(2);
+
+        assert_eq!(Aleph::finality_params(), Some(finality_params(200)));
+        assert_eq!(Aleph::next_finality_params(), None);
+        assert_eq!(
+            Aleph::next_session_finality_params(),
+            Some(finality_params(200))
+        );
+
+        assert_ok!(Aleph::set_finality_params(
+            Origin::root(),
+            finality_params(500)
+        ));
+        run_session(3);
+
+        assert_eq!(Aleph::finality_params(), Some(finality_params(500)));
+    })
+}
+
+#[test]
+fn test_set_finality_params_checks_origin_and_values() {
+    new_test_ext(&[(1u64, 1u64), (2u64, 2u64)]).execute_with(|| {
+        initialize_session();
+
+        assert_noop!(
+            Aleph::set_finality_params(Origin::signed(1), finality_params(200)),
+            sp_runtime::DispatchError::BadOrigin
+        );
+        assert_noop!(
+            Aleph::set_finality_params(Origin::root(), finality_params(0)),
+            Error::<Test>::InvalidFinalityParameters
+        );
+    })
+}
+
+fn unit(creator: u64, round: u16, data: u32, session: u64) -> VersionedUnit {
+    VersionedUnit::V1 {
+        creator,
+        round,
+        control_hash_and_data: data.encode(),
+        session,
+    }
+}
+
+fn signed_unit(
+    key: &AuthorityPair,
+    creator: u64,
+    round: u16,
+    data: u32,
+    session: u64,
+) -> SignedUnit<AuthoritySignature> {
+    let unit = unit(creator, round, data, session);
+    let signature = key.sign(&unit.signing_payload());
+    SignedUnit { unit, signature }
+}
+
+fn equivocation_setup() -> (Vec<AuthorityPair>, u64) {
+    initialize_session();
+    let keys: Vec<_> = (0..3u8)
+        .map(|i| AuthorityPair::from_seed(&[i; 32]))
+        .collect();
+    let authorities: Vec<_> = keys.iter().map(|key| key.public()).collect();
+    Aleph::update_authorities(&authorities);
+    (keys, Session::current_index() as u64)
+}
+
+#[test]
+fn test_report_equivocation() {
+    new_test_ext(&[(1u64, 1u64), (2u64, 2u64)]).execute_with(|| {
+        let (keys, session) = equivocation_setup();
+        let proof = EquivocationProof {
+            first: signed_unit(&keys[1], 1, 7, 0, session),
+            second: signed_unit(&keys[1], 1, 7, 1, session),
+        };
+        let (_, offender_index, offender, committee_size) =
+            Aleph::check_equivocation_proof(&proof).unwrap();
+        assert_eq!(
+            (offender_index, offender, committee_size),
+            (1, keys[1].public(), 3)
+        );
+
+        assert_ok!(Aleph::report_equivocation(
+            Origin::none(),
+            Box::new(proof.clone())
+        ));
+
+        assert_eq!(
+            Aleph::reported_equivocations(session as u32, 1),
+            Some(keys[1].public())
+        );
+        assert!(System::events().iter().any(|record| record.event
+            == Event::Aleph(AlephEvent::EquivocationReported(
+                session as u32,
+                keys[1].public()
+            ))));
+        assert_noop!(
+            Aleph::report_equivocation(Origin::none(), Box::new(proof)),
+            Error::<Test>::DuplicateEquivocationReport
+        );
+    });
+}
+
+#[test]
+fn test_report_equivocation_rejects_invalid_proofs() {
+    new_test_ext(&[(1u64, 1u64), (2u64, 2u64)]).execute_with(|| {
+        let (keys, session) = equivocation_setup();
+        let invalid_proofs = [
+            // signed by someone else than the creator
+            EquivocationProof {
+                first: signed_unit(&keys[1], 1, 7, 0, session),
+                second: signed_unit(&keys[2], 1, 7, 1, session),
+            },
+            // different rounds
+            EquivocationProof {
+                first: signed_unit(&keys[1], 1, 7, 0, session),
+                second: signed_unit(&keys[1], 1, 8, 1, session),
+            },
+            // the same unit twice
+            EquivocationProof {
+                first: signed_unit(&keys[1], 1, 7, 0, session),
+                second: signed_unit(&keys[1], 1, 7, 0, session),
+            },
+            // creator outside of the committee
+            EquivocationProof {
+                first: signed_unit(&keys[1], 5, 7, 0, session),
+                second: signed_unit(&keys[1], 5, 7, 1, session),
+            },
+            // signatures of the bare unit hashes, without the AlephBFT context
+            EquivocationProof {
+                first: SignedUnit {
+                    unit: unit(1, 7, 0, session),
+                    signature: keys[1].sign(
+                        BlakeTwo256::hash(&unit(1, 7, 0, session).aleph_bft_encoding()).as_ref(),
+                    ),
+                },
+                second: SignedUnit {
+                    unit: unit(1, 7, 1, session),
+                    signature: keys[1].sign(
+                        BlakeTwo256::hash(&unit(1, 7, 1, session).aleph_bft_encoding()).as_ref(),
+                    ),
+                },
+            },
+        ];
+        for proof in invalid_proofs {
+            assert_noop!(
+                Aleph::report_equivocation(Origin::none(), Box::new(proof)),
+                Error::<Test>::InvalidEquivocationProof
+            );
+        }
+
+        let outdated_proof = EquivocationProof {
+            first: signed_unit(&keys[1], 1, 7, 0, session + 1),
+            second: signed_unit(&keys[1], 1, 7, 1, session + 1),
+        };
+        assert_noop!(
+            Aleph::report_equivocation(Origin::none(), Box::new(outdated_proof)),
+            Error::<Test>::OutdatedEquivocationProof
+        );
+    });
+}
+
+fn equivocation_keys() -> Vec<AuthorityId> {
+    (0..3u8)
+        .map(|i| AuthorityPair::from_seed(&[i; 32]).public())
+        .collect()
+}
+
+fn elections_setup() {
+    with_active_era(1);
+    let seats = CommitteeSeats {
+        reserved_seats: 0,
+        non_reserved_seats: 2,
+    };
+    CommitteeSize::<Test>::put(seats);
+    NextEraCommitteeSize::<Test>::put(seats);
+    NextEraNonReservedValidators::<Test>::put(vec![0, 1, 2]);
+    CurrentEraValidators::<Test>::put(EraValidators {
+        reserved: vec![],
+        non_reserved: vec![0, 1, 2],
+    });
+}
+
+#[test]
+fn test_equivocation_is_slashed_and_banned_from_next_era() {
+    new_test_ext_with_keys(&equivocation_keys()).execute_with(|| {
+        let (keys, session) = equivocation_setup();
+        elections_setup();
+        let proof = EquivocationProof {
+            first: signed_unit(&keys[2], 2, 4, 0, session),
+            second: signed_unit(&keys[2], 2, 4, 1, session),
+        };
+
+        assert_ok!(Aleph::report_equivocation(Origin::none(), Box::new(proof)));
+
+        assert_eq!(slashes(), vec![((2, 2), MIN_EQUIVOCATION_SLASH)]);
+        assert_eq!(BannedValidators::<Test>::get(2), Some(2 + BanPeriod::get()));
+        // The committees of the ongoing era stay as they were.
+        assert_eq!(
+            CurrentEraValidators::<Test>::get().non_reserved,
+            vec![0, 1, 2]
+        );
+
+        for session in 2 * SESSIONS_PER_ERA..3 * SESSIONS_PER_ERA {
+            let committee = <Elections as SessionManager<AccountId>>::new_session(session)
+                .expect("committee should be rotated");
+            assert!(!committee.contains(&2));
+            with_active_era(2);
+        }
+    });
+}
+
+#[test]
+fn test_equivocation_is_not_banned_when_not_reported() {
+    new_test_ext_with_keys(&equivocation_keys()).execute_with(|| {
+        let (keys, session) = equivocation_setup();
+        elections_setup();
+
+        EquivocationHandler::<Test, FailingOffenceReporter, Elections>::handle_equivocation(
+            session as u32,
+            &keys[2].public(),
+            3,
+        );
+
+        assert!(slashes().is_empty());
+        assert!(!BannedValidators::<Test>::contains_key(2));
+    });
+}
+
+#[test]
+fn test_equivocation_slash_fraction() {
+    type TestOffence = AlephEquivocationOffence<()>;
+
+    assert_eq!(TestOffence::slash_fraction(1, 3), MIN_EQUIVOCATION_SLASH);
+    assert_eq!(TestOffence::slash_fraction(1, 10), MIN_EQUIVOCATION_SLASH);
+    assert_eq!(TestOffence::slash_fraction(2, 3), Perbill::one());
+    assert_eq!(TestOffence::slash_fraction(2, 10), Perbill::from_percent(9));
+}
+
+fn signed_addresses(
+    key: &AuthorityPair,
+    addresses: Vec<Vec<u8>>,
+    session: u32,
+) -> SignedValidatorAddresses<AuthoritySignature> {
+    signed_addresses_with_nonce(key, addresses, session, 0)
+}
+
+fn signed_addresses_with_nonce(
+    key: &AuthorityPair,
+    addresses: Vec<Vec<u8>>,
+    session: u32,
+    nonce: u32,
+) -> SignedValidatorAddresses<AuthoritySignature> {
+    let addresses = ValidatorAddresses {
+        addresses,
+        session,
+        nonce,
+    };
+    let signature = key.sign(&addresses.signing_payload());
+    SignedValidatorAddresses {
+        addresses,
+        signature,
+    }
+}
+
+#[test]
+fn test_register_validator_addresses() {
+    new_test_ext(&[(1u64, 1u64), (2u64, 2u64)]).execute_with(|| {
+        let (keys, session) = equivocation_setup();
+        let session = session as u32;
+        let addresses = signed_addresses(&keys[1], vec![vec![1, 2, 3]], session);
+
+        assert_ok!(Aleph::register_validator_addresses(
+            Origin::none(),
+            keys[1].public(),
+            addresses.clone()
+        ));
+
+        assert_eq!(
+            Aleph::validator_addresses(keys[1].public()),
+            Some(addresses)
+        );
+        assert!(System::events().iter().any(|record| record.event
+            == Event::Aleph(AlephEvent::ValidatorAddressesRegistered(keys[1].public()))));
+
+        let again = signed_addresses(&keys[1], vec![vec![4, 5, 6]], session);
+        assert_noop!(
+            Aleph::register_validator_addresses(Origin::none(), keys[1].public(), again),
+            Error::<Test>::OutdatedValidatorAddresses
+        );
+
+        let replacement = signed_addresses_with_nonce(&keys[1], vec![vec![4, 5, 6]], session, 1);
+        assert_ok!(Aleph::register_validator_addresses(
+            Origin::none(),
+            keys[1].public(),
+            replacement.clone()
+        ));
+        assert_eq!(
+            Aleph::validator_addresses(keys[1].public()),
+            Some(replacement)
+        );
+
+        let next_session = signed_addresses(&keys[1], vec![vec![7, 8, 9]], session + 1);
+        assert_ok!(Aleph::register_validator_addresses(
+            Origin::none(),
+            keys[1].public(),
+            next_session.clone()
+        ));
+        assert_eq!(
+            Aleph::validator_addresses(keys[1].public()),
+            Some(next_session)
+        );
+    });
+}
+
+#[test]
+fn test_register_validator_addresses_rejects_invalid_addresses() {
+    new_test_ext(&[(1u64, 1u64), (2u64, 2u64)]).execute_with(|| {
+        let (keys, session) = equivocation_setup();
+        let session = session as u32;
+        let outsider = AuthorityPair::from_seed(&[7; 32]);
+
+        assert_noop!(
+            Aleph::register_validator_addresses(
+                Origin::none(),
+                outsider.public(),
+                signed_addresses(&outsider, vec![vec![1]], session)
+            ),
+            Error::<Test>::NotAnAuthority
+        );
+
+        let invalid_addresses = [
+            // signed by someone else
+            signed_addresses(&keys[2], vec![vec![1]], session),
+            // no addresses
+            signed_addresses(&keys[1], Vec::new(), session),
+            // too many addresses
+            signed_addresses(
+                &keys[1],
+                vec![vec![1]; MAX_VALIDATOR_ADDRESSES + 1],
+                session,
+            ),
+            // too long address
+            signed_addresses(&keys[1], vec![vec![1; 1000]], session),
+        ];
+        for addresses in invalid_addresses {
+            assert_noop!(
+                Aleph::register_validator_addresses(Origin::none(), keys[1].public(), addresses),
+                Error::<Test>::InvalidValidatorAddresses
+            );
+        }
+
+        assert_noop!(
+            Aleph::register_validator_addresses(
+                Origin::none(),
+                keys[1].public(),
+                signed_addresses(&keys[1], vec![vec![1]], session + 2)
+            ),
+            Error::<Test>::OutdatedValidatorAddresses
+        );
+    });
+}
+
+#[test]
+fn test_validator_addresses_are_pruned_with_authorities() {
+    new_test_ext(&[(1u64, 1u64), (2u64, 2u64)]).execute_with(|| {
+        let (keys, session) = equivocation_setup();
+        let session = session as u32;
+        for key in &keys {
+            assert_ok!(Aleph::register_validator_addresses(
+                Origin::none(),
+                key.public(),
+                signed_addresses(key, vec![vec![1]], session)
+            ));
+        }
+
+        let accounts = [0u64, 1u64];
+        let validators = vec![(&accounts[0], keys[0].public())];
+        let queued_validators = vec![(&accounts[1], keys[1].public())];
+        Aleph::on_new_session(true, validators.into_iter(), queued_validators.into_iter());
+
+        assert!(Aleph::validator_addresses(keys[0].public()).is_some());
+        assert!(Aleph::validator_addresses(keys[1].public()).is_some());
+        assert!(Aleph::validator_addresses(keys[2].public()).is_none());
+
+        let validators = vec![(&accounts[1], keys[1].public())];
+        let queued_validators = vec![(&accounts[1], keys[1].public())];
+        Aleph::on_new_session(true, validators.into_iter(), queued_validators.into_iter());
+
+        assert!(Aleph::validator_addresses(keys[0].public()).is_none());
+        assert!(Aleph::validator_addresses(keys[1].public()).is_some());
+    });
+}
+
+fn bls_secret(seed: u8) -> SecretKey {
+    SecretKey::key_gen(&[seed; 32], &[]).unwrap()
+}
+
+fn bls_registration(
+    key: &AuthorityPair,
+    secret: &SecretKey,
+    session: u32,
+) -> SignedBlsKeyRegistration<AuthoritySignature> {
+    let public = secret.sk_to_pk().to_bytes();
+    let registration = BlsKeyRegistration {
+        key: BlsKey(public),
+        proof_of_possession: secret.sign(&public, BLS_POP_DST, &[]).to_bytes(),
+        session,
+    };
+    let signature = key.sign(&registration.signing_payload());
+    SignedBlsKeyRegistration {
+        registration,
+        signature,
+    }
+}
+
+#[test]
+fn test_register_bls_key() {
+    new_test_ext(&[(1u64, 1u64), (2u64, 2u64)]).execute_with(|| {
+        let (keys, session) = equivocation_setup();
+        let session = session as u32;
+        let registration = bls_registration(&keys[1], &bls_secret(1), session);
+        let key = registration.registration.key;
+
+        assert_ok!(Aleph::register_bls_key(
+            Origin::none(),
+            keys[1].public(),
+            registration.clone()
+        ));
+        assert_eq!(Aleph::registered_bls_key(&keys[1].public()), Some(key));
+        assert!(System::events().iter().any(|record| record.event
+            == Event::Aleph(AlephEvent::BlsKeyRegistered(keys[1].public(), key))));
+
+        assert_noop!(
+            Aleph::register_bls_key(Origin::none(), keys[1].public(), registration),
+            Error::<Test>::OutdatedBlsKey
+        );
+
+        let replacement = bls_registration(&keys[1], &bls_secret(2), session + 1);
+        let replacement_key = replacement.registration.key;
+        assert_ok!(Aleph::register_bls_key(
+            Origin::none(),
+            keys[1].public(),
+            replacement
+        ));
+        assert_eq!(
+            Aleph::registered_bls_key(&keys[1].public()),
+            Some(replacement_key)
+        );
+    });
+}
+
+#[test]
+fn test_register_bls_key_rejects_replayed_registrations() {
+    new_test_ext(&[(1u64, 1u64), (2u64, 2u64)]).execute_with(|| {
+        let (keys, session) = equivocation_setup();
+        let session = session as u32;
+        let first = bls_registration(&keys[1], &bls_secret(1), session);
+        let second = bls_registration(&keys[1], &bls_secret(2), session);
+        let second_key = second.registration.key;
+        assert_ok!(Aleph::register_bls_key(
+            Origin::none(),
+            keys[1].public(),
+            first.clone()
+        ));
+
+        // Another registration from the same session could bring back the first key later.
+        assert_noop!(
+            Aleph::register_bls_key(Origin::none(), keys[1].public(), second.clone()),
+            Error::<Test>::OutdatedBlsKey
+        );
+        let next = bls_registration(&keys[1], &bls_secret(2), session + 1);
+        assert_ok!(Aleph::register_bls_key(
+            Origin::none(),
+            keys[1].public(),
+            next
+        ));
+        for replayed in [first, second] {
+            assert_noop!(
+                Aleph::register_bls_key(Origin::none(), keys[1].public(), replayed),
+                Error::<Test>::OutdatedBlsKey
+            );
+        }
+        assert_eq!(
+            Aleph::registered_bls_key(&keys[1].public()),
+            Some(second_key)
+        );
+    });
+}
+
+#[test]
+fn test_register_bls_key_rejects_invalid_registrations() {
+    new_test_ext(&[(1u64, 1u64), (2u64, 2u64)]).execute_with(|| {
+        let (keys, session) = equivocation_setup();
+        let session = session as u32;
+        let outsider = AuthorityPair::from_seed(&[7; 32]);
+
+        assert_noop!(
+            Aleph::register_bls_key(
+                Origin::none(),
+                outsider.public(),
+                bls_registration(&outsider, &bls_secret(1), session)
+            ),
+            Error::<Test>::NotAnAuthority
+        );
+
+        // signed by someone else
+        let wrong_signer = bls_registration(&keys[2], &bls_secret(1), session);
+        // the proof of possession made with a different key, as in a rogue key attack
+        let mut rogue_key = bls_registration(&keys[1], &bls_secret(1), session);
+        rogue_key.registration.key = BlsKey(bls_secret(2).sk_to_pk().to_bytes());
+        rogue_key.signature = keys[1].sign(&rogue_key.registration.signing_payload());
+        // a signature with the key, but not a proof of possession
+        let mut not_a_proof = bls_registration(&keys[1], &bls_secret(1), session);
+        not_a_proof.registration.proof_of_possession = bls_secret(1)
+            .sign(
+                &not_a_proof.registration.key.0,
+                b"not the proof domain",
+                &[],
+            )
+            .to_bytes();
+        not_a_proof.signature = keys[1].sign(&not_a_proof.registration.signing_payload());
+        for registration in [wrong_signer, rogue_key, not_a_proof] {
+            assert_noop!(
+                Aleph::register_bls_key(Origin::none(), keys[1].public(), registration),
+                Error::<Test>::InvalidBlsKey
+            );
+        }
+
+        assert_noop!(
+            Aleph::register_bls_key(
+                Origin::none(),
+                keys[1].public(),
+                bls_registration(&keys[1], &bls_secret(1), session + 2)
+            ),
+            Error::<Test>::OutdatedBlsKey
+        );
+    });
+}
+
+#[test]
+fn test_bls_keys_are_fixed_when_sessions_are_planned() {
+    new_test_ext(&[(1u64, 1u64), (2u64, 2u64)]).execute_with(|| {
+        let (keys, session) = equivocation_setup();
+        let session = session as u32;
+        for (i, key) in keys.iter().enumerate().take(2) {
+            assert_ok!(Aleph::register_bls_key(
+                Origin::none(),
+                key.public(),
+                bls_registration(key, &bls_secret(i as u8), session)
+            ));
+        }
+        let registered: Vec<_> = keys
+            .iter()
+            .take(2)
+            .map(|key| Aleph::registered_bls_key(&key.public()).unwrap())
+            .collect();
+
+        let accounts = [0u64, 1u64, 2u64];
+        let validators = || {
+            accounts
+                .iter()
+                .zip(keys.iter().map(|key| key.public()))
+                .take(2)
+        };
+        Aleph::on_new_session(true, validators(), validators());
+        assert_eq!(Aleph::session_bls_keys(), None);
+        assert_eq!(Aleph::next_session_bls_keys(), Some(registered.clone()));
+
+        // A key registered later does not change the planned session.
+        assert_ok!(Aleph::register_bls_key(
+            Origin::none(),
+            keys[0].public(),
+            bls_registration(&keys[0], &bls_secret(5), session)
+        ));
+        assert_eq!(Aleph::next_session_bls_keys(), Some(registered.clone()));
+
+        // The committee with a member without a key does not get keys at all.
+        let all_validators = || accounts.iter().zip(keys.iter().map(|key| key.public()));
+        Aleph::on_new_session(true, validators(), all_validators());
+        assert_eq!(Aleph::session_bls_keys(), Some(registered));
+        assert_eq!(Aleph::next_session_bls_keys(), None);
+
+        // Keys are not pruned with the authorities.
+        let first_validator = || {
+            accounts
+                .iter()
+                .zip(keys.iter().map(|key| key.public()))
+                .take(1)
+        };
+        Aleph::on_new_session(true, first_validator(), first_validator());
+        assert!(Aleph::registered_bls_key(&keys[1].public()).is_some());
+    });
+}
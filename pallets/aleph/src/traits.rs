@@ -0,0 +1,34 @@
+use codec::Decode;
+use frame_support::sp_runtime::{traits::OpaqueKeys, RuntimeAppPublic};
+use primitives::SessionIndex;
+use sp_std::vec::Vec;
+
+pub trait SessionInfoProvider {
+    /// Returns the index of the current session.
+    fn current_session() -> SessionIndex;
+    /// Returns the keys of the given type of the validators of the next session, skipping the
+    /// ones without such a key.
+    fn queued_keys<K: RuntimeAppPublic + Decode>() -> Vec<K>;
+}
+
+impl<T: pallet_session::Config> SessionInfoProvider for pallet_session::Pallet<T> {
+    fn current_session() -> SessionIndex {
+        pallet_session::Pallet::<T>::current_index()
+    }
+
+    fn queued_keys<K: RuntimeAppPublic + Decode>() -> Vec<K> {
+        pallet_session::Pallet::<T>::queued_keys()
+            .iter()
+            .filter_map(|(_, keys)| keys.get(K::ID))
+            .collect()
+    }
+}
+
+pub trait HandleEquivocation<AuthorityId> {
+    /// Punishes the committee member that equivocated in the given session.
+    fn handle_equivocation(session: SessionIndex, offender: &AuthorityId, committee_size: u32);
+}
+
+impl<AuthorityId> HandleEquivocation<AuthorityId> for () {
+    fn handle_equivocation(_session: SessionIndex, _offender: &AuthorityId, _committee_size: u32) {}
+}
@@ -1,5 +1,6 @@
 use frame_election_provider_support::sp_arithmetic::Perquintill;
-use frame_support::pallet_prelude::Get;
+use frame_support::{log, pallet_prelude::Get};
+use primitives::BanHandler;
 use sp_staking::{EraIndex, SessionIndex};
 use sp_std::{
     collections::{btree_map::BTreeMap, btree_set::BTreeSet},
@@ -8,8 +9,8 @@ use sp_std::{
 
 use crate::{
     traits::{EraInfoProvider, SessionInfoProvider, ValidatorRewardsHandler},
-    CommitteeSeats, CommitteeSize, Config, CurrentEraValidators, EraValidators,
-    NextEraCommitteeSize, NextEraNonReservedValidators, NextEraReservedValidators, Pallet,
+    BannedValidators, CommitteeSeats, CommitteeSize, Config, CurrentEraValidators, EraValidators,
+    Event, NextEraCommitteeSize, NextEraNonReservedValidators, NextEraReservedValidators, Pallet,
     SessionValidatorBlockCount, ValidatorEraTotalReward, ValidatorTotalRewards,
 };
 
@@ -212,6 +213,17 @@ where
         )
     }
 
+    /// Removes the bans that end by the given era.
+    fn lift_expired_bans(era: EraIndex) {
+        let expired: Vec<_> = BannedValidators::<T>::iter()
+            .filter(|(_, banned_until)| *banned_until <= era)
+            .map(|(validator, _)| validator)
+            .collect();
+        for validator in expired {
+            BannedValidators::<T>::remove(validator);
+        }
+    }
+
     fn if_era_starts_do<F: Fn()>(era: EraIndex, start_index: SessionIndex, on_era_start: F) {
         if let Some(era_start_index) = T::EraInfoProvider::era_start_session_index(era) {
             if era_start_index == start_index {
@@ -231,10 +243,13 @@ where
         Self::if_era_starts_do(active_era + 1, session, || {
             let elected_committee =
                 BTreeSet::from_iter(T::EraInfoProvider::elected_validators(active_era + 1));
+            Self::lift_expired_bans(active_era + 1);
 
             let retain_elected = |vals: Vec<T::AccountId>| -> Vec<T::AccountId> {
                 vals.into_iter()
-                    .filter(|v| elected_committee.contains(v))
+                    .filter(|v| {
+                        elected_committee.contains(v) && !BannedValidators::<T>::contains_key(v)
+                    })
                     .collect()
             };
 
@@ -294,6 +309,23 @@ where
     }
 }
 
+impl<T: Config> BanHandler for Pallet<T> {
+    type AccountId = T::AccountId;
+
+    /// Keeps the validator out of the committees of `BanPeriod` eras, starting from the next one.
+    /// The current era is not affected, its validators stay as they are.
+    fn ban_validator(validator: &Self::AccountId) {
+        let next_era = T::EraInfoProvider::active_era().unwrap_or(0) + 1;
+        let banned_until = BannedValidators::<T>::get(validator)
+            .unwrap_or_default()
+            .max(next_era.saturating_add(T::BanPeriod::get()));
+        log::info!(target: "pallet_elections", "Banning validator {:?} until era {:?}", validator, banned_until);
+
+        BannedValidators::<T>::insert(validator, banned_until);
+        Self::deposit_event(Event::ValidatorBanned(validator.clone(), banned_until));
+    }
+}
+
 impl<T> pallet_authorship::EventHandler<T::AccountId, T::BlockNumber> for Pallet<T>
 where
     T: Config,
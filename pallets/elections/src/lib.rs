@@ -27,6 +27,7 @@ use sp_std::{
     collections::{btree_map::BTreeMap, btree_set::BTreeSet},
     prelude::*,
 };
+pub use traits::{EraInfoProvider, SessionInfoProvider, ValidatorRewardsHandler};
 
 const STORAGE_VERSION: StorageVersion = StorageVersion::new(3);
 
@@ -75,6 +76,7 @@ pub mod pallet {
     };
     use pallet_session::SessionManager;
     use primitives::DEFAULT_COMMITTEE_SIZE;
+    use sp_staking::EraIndex;
 
     use super::*;
     use crate::traits::{EraInfoProvider, SessionInfoProvider, ValidatorRewardsHandler};
@@ -98,12 +100,20 @@ pub mod pallet {
         type SessionInfoProvider: SessionInfoProvider<Self>;
         /// Something that handles addition of rewards for validators.
         type ValidatorRewardsHandler: ValidatorRewardsHandler<Self>;
+        /// Nr of eras a banned validator is kept out of committees.
+        #[pallet::constant]
+        type BanPeriod: Get<EraIndex>;
     }
 
     #[pallet::event]
     #[pallet::generate_deposit(pub(super) fn deposit_event)]
     pub enum Event<T: Config> {
         ChangeValidators(Vec<T::AccountId>, Vec<T::AccountId>, CommitteeSeats),
+        /// Validator was banned for misbehaviour and will not be chosen for a committee from the
+        /// next era until the given era.
+        ValidatorBanned(T::AccountId, EraIndex),
+        /// The ban of the validator was lifted by root.
+        ValidatorUnbanned(T::AccountId),
     }
 
     #[pallet::pallet]
@@ -206,6 +216,15 @@ pub mod pallet {
     pub type NextEraNonReservedValidators<T: Config> =
         StorageValue<_, Vec<T::AccountId>, ValueQuery>;
 
+    /// Validators banned for proven misbehaviour, with the first era in which they can be chosen
+    /// for a committee again.
+    ///
+    /// Bans are applied when the validators of a new era are populated, so the current era is not
+    /// affected.
+    #[pallet::storage]
+    pub type BannedValidators<T: Config> =
+        StorageMap<_, Twox64Concat, T::AccountId, EraIndex, OptionQuery>;
+
     /// Count per validator, how many blocks did the validator produced.
     #[pallet::storage]
     pub type SessionValidatorBlockCount<T: Config> =
@@ -252,6 +271,20 @@ pub mod pallet {
 
             Ok(())
         }
+
+        /// Lifts the ban of the validator. It can be chosen for committees again from the next
+        /// era whose validators are populated.
+        #[pallet::weight(T::DbWeight::get().reads_writes(1, 1))]
+        pub fn unban_validator(origin: OriginFor<T>, validator: T::AccountId) -> DispatchResult {
+            ensure_root(origin)?;
+            ensure!(
+                BannedValidators::<T>::contains_key(&validator),
+                Error::<T>::NotBanned
+            );
+            BannedValidators::<T>::remove(&validator);
+            Self::deposit_event(Event::ValidatorUnbanned(validator));
+            Ok(())
+        }
     }
 
     #[pallet::genesis_config]
@@ -340,6 +373,8 @@ pub mod pallet {
         NotEnoughReservedValidators,
         NotEnoughNonReservedValidators,
         NonUniqueListOfValidators,
+        /// The validator is not banned.
+        NotBanned,
     }
 
     impl<T: Config> ElectionProvider for Pallet<T> {
@@ -101,6 +101,7 @@ where
 parameter_types! {
     pub const SessionPeriod: u32 = 5;
     pub const SessionsPerEra: u32 = 5;
+    pub const BanPeriod: EraIndex = 2;
 }
 
 pub struct MockProvider;
@@ -166,6 +167,7 @@ impl Config for Test {
     type SessionManager = ();
     type SessionInfoProvider = MockProvider;
     type ValidatorRewardsHandler = MockProvider;
+    type BanPeriod = BanPeriod;
 }
 
 type MaxVotesPerVoter = ConstU32<1>;
@@ -1,10 +1,16 @@
 #![cfg(test)]
 
 use frame_election_provider_support::{ElectionProvider, Support};
-use frame_support::bounded_vec;
+use frame_support::{assert_noop, assert_ok, bounded_vec};
 use pallet_session::SessionManager;
+use primitives::BanHandler;
+use sp_runtime::traits::BadOrigin;
+use sp_staking::EraIndex;
 
-use crate::{mock::*, CommitteeSeats, CommitteeSize};
+use crate::{
+    mock::*, BannedValidators, CommitteeSeats, CommitteeSize, CurrentEraValidators, Error,
+    Event as ElectionsEvent, NextEraCommitteeSize, NextEraNonReservedValidators,
+};
 
 fn no_support() -> Support<AccountId> {
     Default::default()
@@ -65,3 +71,86 @@ fn session_authorities_must_have_been_elected() {
         assert_eq!(authorities, &[1, 5]);
     });
 }
+
+fn committees_of_era(era: EraIndex) -> Vec<Vec<AccountId>> {
+    // The validators of the era are populated when planning its first session, in the previous
+    // era.
+    with_active_era(era - 1);
+    with_elected_validators(era, vec![1, 2, 5, 6]);
+    let first_session = era * SessionsPerEra::get();
+    let mut committees = vec![
+        <Elections as SessionManager<AccountId>>::new_session(first_session)
+            .expect("committee should be rotated"),
+    ];
+    with_active_era(era);
+    for session in first_session + 1..first_session + SessionsPerEra::get() {
+        committees.push(
+            <Elections as SessionManager<AccountId>>::new_session(session)
+                .expect("committee should be rotated"),
+        );
+    }
+    committees
+}
+
+fn setup_committee() {
+    System::set_block_number(1);
+    CommitteeSize::<Test>::put(CommitteeSeats {
+        reserved_seats: 2,
+        non_reserved_seats: 1,
+    });
+    NextEraCommitteeSize::<Test>::put(CommitteeSeats {
+        reserved_seats: 2,
+        non_reserved_seats: 1,
+    });
+}
+
+#[test]
+fn bans_apply_from_next_era_for_ban_period() {
+    new_test_ext(vec![1, 2], vec![5, 6]).execute_with(|| {
+        setup_committee();
+        assert!(committees_of_era(2).iter().any(|c| c.contains(&5)));
+
+        Elections::ban_validator(&5);
+
+        let banned_until = 3 + BanPeriod::get();
+        assert_eq!(BannedValidators::<Test>::get(5), Some(banned_until));
+        assert!(System::events().iter().any(|record| record.event
+            == Event::Elections(ElectionsEvent::ValidatorBanned(5, banned_until))));
+        // The current era is not affected.
+        assert_eq!(CurrentEraValidators::<Test>::get().non_reserved, vec![5, 6]);
+        assert_eq!(NextEraNonReservedValidators::<Test>::get(), vec![5, 6]);
+
+        for era in 3..banned_until {
+            for committee in committees_of_era(era) {
+                assert!(!committee.contains(&5));
+            }
+            assert_eq!(CurrentEraValidators::<Test>::get().non_reserved, vec![6]);
+        }
+
+        assert!(committees_of_era(banned_until)
+            .iter()
+            .any(|c| c.contains(&5)));
+        assert!(!BannedValidators::<Test>::contains_key(5));
+    });
+}
+
+#[test]
+fn root_can_lift_bans() {
+    new_test_ext(vec![1, 2], vec![5, 6]).execute_with(|| {
+        setup_committee();
+        with_active_era(1);
+        Elections::ban_validator(&5);
+
+        assert_noop!(Elections::unban_validator(Origin::signed(1), 5), BadOrigin);
+        assert_ok!(Elections::unban_validator(Origin::root(), 5));
+        assert_noop!(
+            Elections::unban_validator(Origin::root(), 5),
+            Error::<Test>::NotBanned
+        );
+
+        assert!(System::events()
+            .iter()
+            .any(|record| record.event == Event::Elections(ElectionsEvent::ValidatorUnbanned(5))));
+        assert!(committees_of_era(2).iter().any(|c| c.contains(&5)));
+    });
+}
@@ -1,12 +1,20 @@
 #![allow(clippy::too_many_arguments, clippy::unnecessary_mut_passed)]
 #![cfg_attr(not(feature = "std"), no_std)]
-use codec::{Decode, Encode};
+use codec::{Decode, Encode, Error as CodecError, Input as CodecInput};
+use scale_info::TypeInfo;
 use sp_core::crypto::KeyTypeId;
-use sp_runtime::ConsensusEngineId;
+use sp_runtime::{
+    traits::{BlakeTwo256, Hash as _},
+    ConsensusEngineId,
+};
+use sp_runtime_interface::runtime_interface;
 pub use sp_staking::{EraIndex, SessionIndex};
 use sp_std::vec::Vec;
 
 pub const KEY_TYPE: KeyTypeId = KeyTypeId(*b"alp0");
+/// Key type of the BLS keys used for aggregated justifications. They are kept in the keystore
+/// directory next to the other keys, but Substrate does not know how to use them.
+pub const BLS_KEY_TYPE: KeyTypeId = KeyTypeId(*b"alpb");
 
 // Same as GRANDPA_ENGINE_ID because as of right now substrate sends only
 // grandpa justifications over the network.
@@ -26,6 +34,37 @@ pub type AuthorityId = app::Public;
 
 pub type Balance = u128;
 
+/// Keys of the storage items of the aleph pallet that authority handovers prove, for reading the
+/// items without access to the runtime. The pallet tests check them against the keys the pallet
+/// generates.
+#[cfg(feature = "std")]
+pub mod storage_keys {
+    use sp_core::hashing::twox_128;
+    use sp_std::vec::Vec;
+
+    /// The name of the aleph pallet in the runtime.
+    const PALLET_PREFIX: &[u8] = b"Aleph";
+
+    fn storage_value_key(item: &[u8]) -> Vec<u8> {
+        [twox_128(PALLET_PREFIX), twox_128(item)].concat()
+    }
+
+    /// The authorities of the next session.
+    pub fn next_authorities() -> Vec<u8> {
+        storage_value_key(b"NextAuthorities")
+    }
+
+    /// The emergency finalizer of the next session.
+    pub fn queued_emergency_finalizer() -> Vec<u8> {
+        storage_value_key(b"QueuedEmergencyFinalizer")
+    }
+
+    /// The BLS keys of the authorities of the next session.
+    pub fn next_session_bls_keys() -> Vec<u8> {
+        storage_value_key(b"NextSessionBlsKeys")
+    }
+}
+
 pub const MILLISECS_PER_BLOCK: u64 = 1000;
 
 // Quick sessions for testing purposes
@@ -48,16 +87,40 @@ pub const DEFAULT_COMMITTEE_SIZE: u32 = 4;
 pub const ADDRESSES_ENCODING: u8 = 42;
 pub const DEFAULT_UNIT_CREATION_DELAY: u64 = 300;
 
+/// Maximum number of network addresses a validator can register on chain.
+pub const MAX_VALIDATOR_ADDRESSES: usize = 16;
+/// Maximum length of a single encoded network address registered on chain.
+pub const MAX_VALIDATOR_ADDRESS_LEN: usize = 256;
+
 #[derive(Encode, Decode, PartialEq, Eq, Debug)]
 pub enum ApiError {
     DecodeKey,
 }
 
 /// All the data needed to verify block finalization justifications.
-#[derive(Clone, Debug, Encode, Decode, PartialEq, Eq)]
+#[derive(Clone, Debug, Encode, PartialEq, Eq)]
 pub struct SessionAuthorityData {
     authorities: Vec<AuthorityId>,
     emergency_finalizer: Option<AuthorityId>,
+    bls_keys: Option<Vec<BlsKey>>,
+}
+
+impl Decode for SessionAuthorityData {
+    fn decode<I: CodecInput>(input: &mut I) -> Result<Self, CodecError> {
+        let authorities = Vec::decode(input)?;
+        let emergency_finalizer = Option::decode(input)?;
+        // Runtimes from before the BLS keys return the data without them, and it is always the
+        // last thing they return.
+        let bls_keys = match input.remaining_len()? {
+            Some(0) => None,
+            _ => Option::decode(input)?,
+        };
+        Ok(SessionAuthorityData {
+            authorities,
+            emergency_finalizer,
+            bls_keys,
+        })
+    }
 }
 
 impl SessionAuthorityData {
@@ -65,9 +128,15 @@ impl SessionAuthorityData {
         SessionAuthorityData {
             authorities,
             emergency_finalizer,
+            bls_keys: None,
         }
     }
 
+    /// Adds the BLS keys of the authorities, in the same order as the authorities.
+    pub fn with_bls_keys(self, bls_keys: Option<Vec<BlsKey>>) -> Self {
+        SessionAuthorityData { bls_keys, ..self }
+    }
+
     pub fn authorities(&self) -> &Vec<AuthorityId> {
         &self.authorities
     }
@@ -75,9 +144,269 @@ impl SessionAuthorityData {
     pub fn emergency_finalizer(&self) -> &Option<AuthorityId> {
         &self.emergency_finalizer
     }
+
+    /// The BLS keys of all the authorities, if every one of them registered a key before the
+    /// session was planned. Only then the committee aggregates its signatures of blocks.
+    pub fn bls_keys(&self) -> &Option<Vec<BlsKey>> {
+        &self.bls_keys
+    }
+}
+
+/// Size of a compressed BLS public key, in G1.
+pub const BLS_PUBLIC_KEY_SIZE: usize = 48;
+/// Size of a compressed BLS signature, in G2.
+pub const BLS_SIGNATURE_SIZE: usize = 96;
+/// Domain separation tag of proofs of possession of BLS keys, from the proof of possession
+/// ciphersuite of the IETF BLS signature draft.
+pub const BLS_POP_DST: &[u8] = b"BLS_POP_BLS12381G2_XMD:SHA-256_SSWU_RO_POP_";
+
+/// A compressed BLS public key of an authority, used to verify aggregated justifications.
+#[derive(Clone, Copy, Debug, Encode, Decode, PartialEq, Eq, Hash, TypeInfo)]
+pub struct BlsKey(pub [u8; BLS_PUBLIC_KEY_SIZE]);
+
+/// A BLS key of an authority together with a proof that the authority knows its secret key.
+/// Without the proof anyone could register a key made up from the keys of others, and forge
+/// aggregated signatures of the whole committee.
+#[derive(Clone, Debug, Encode, Decode, PartialEq, Eq, TypeInfo)]
+pub struct BlsKeyRegistration {
+    pub key: BlsKey,
+    /// The signature of the key itself made with the key, with the proof of possession domain.
+    pub proof_of_possession: [u8; BLS_SIGNATURE_SIZE],
+    /// The session in which the key is registered. A registration replaces only ones from earlier
+    /// sessions, so old registrations cannot be replayed.
+    pub session: SessionIndex,
+}
+
+impl BlsKeyRegistration {
+    const CONTEXT: &'static [u8] = b"aleph-bls-key";
+
+    /// The bytes the authority signs with its authority key, binding the BLS key to it. The
+    /// context makes them differ from anything else signed with that key.
+    pub fn signing_payload(&self) -> Vec<u8> {
+        (Self::CONTEXT, self).encode()
+    }
+
+    /// Checks the proof of possession of the key.
+    pub fn has_valid_proof(&self) -> bool {
+        aleph_bls::verify_proof_of_possession(&self.key.0, &self.proof_of_possession)
+    }
+}
+
+/// A BLS key registration signed with the authority key of the registering authority.
+#[derive(Clone, Debug, Encode, Decode, PartialEq, Eq, TypeInfo)]
+pub struct SignedBlsKeyRegistration<Signature> {
+    pub registration: BlsKeyRegistration,
+    pub signature: Signature,
+}
+
+/// BLS cryptography for the runtime, which cannot afford pairings in wasm.
+#[runtime_interface]
+pub trait AlephBls {
+    /// Verifies the proof of possession of the compressed BLS public key.
+    fn verify_proof_of_possession(key: &[u8], proof: &[u8]) -> bool {
+        use blst::{
+            min_pk::{PublicKey, Signature},
+            BLST_ERROR,
+        };
+        let public_key = match PublicKey::key_validate(key) {
+            Ok(public_key) => public_key,
+            Err(_) => return false,
+        };
+        match Signature::from_bytes(proof) {
+            Ok(proof) => {
+                proof.verify(true, key, BLS_POP_DST, &[], &public_key, false)
+                    == BLST_ERROR::BLST_SUCCESS
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+/// Parameters of the finality gadget governed on chain. They change only at session boundaries, so
+/// that the whole committee of a session uses the same values.
+#[derive(Clone, Debug, Encode, Decode, PartialEq, Eq, TypeInfo)]
+pub struct FinalityParameters {
+    /// Delay between the units created by a committee member, in milliseconds.
+    pub unit_creation_delay: u64,
+    /// Maximum number of blocks above the last finalized one allowed in an AlephBFT proposal.
+    pub max_data_branch_len: u32,
+    /// Maximum number of proposals waiting for their blocks in the data store.
+    pub max_proposals_pending: u32,
+    /// Maximum number of AlephBFT messages waiting for their data in the data store.
+    pub max_messages_pending: u32,
+    /// Delay between consecutive justification requests, in milliseconds.
+    pub justification_request_delay: u64,
+}
+
+impl FinalityParameters {
+    /// All the parameters are positive.
+    pub fn is_valid(&self) -> bool {
+        self.unit_creation_delay > 0
+            && self.max_data_branch_len > 0
+            && self.max_proposals_pending > 0
+            && self.max_messages_pending > 0
+            && self.justification_request_delay > 0
+    }
+}
+
+/// Context of the signatures made with authority keys by AlephBFT, so that they cannot be passed
+/// off as signatures of anything else made with those keys.
+pub const ALEPH_BFT_SIGNING_CONTEXT: &[u8] = b"aleph-bft";
+
+/// The first version of `AlephSessionApi` whose committees sign AlephBFT messages with
+/// `ALEPH_BFT_SIGNING_CONTEXT`. Nodes read it from the runtime, so that a whole committee switches
+/// at once.
+pub const ALEPH_BFT_SIGNING_CONTEXT_API_VERSION: u32 = 2;
+
+/// The first version of `AlephSessionApi` providing `next_session_finality_params`. Nodes use their
+/// local configuration in sessions governed by older runtimes.
+pub const FINALITY_PARAMS_API_VERSION: u32 = 2;
+
+/// The bytes signed with the authority key when AlephBFT signs `msg`.
+pub fn aleph_bft_signing_payload(msg: &[u8]) -> Vec<u8> {
+    (ALEPH_BFT_SIGNING_CONTEXT, msg).encode()
+}
+
+/// Identifies the slot an AlephBFT unit occupies: its creator, round and session.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnitCoord {
+    pub creator: u64,
+    pub round: u16,
+    pub session: u64,
+}
+
+/// An AlephBFT unit, in the layout of the AlephBFT version that created it. AlephBFT does not
+/// make its units public, so their layouts are mirrored here. A new layout needs a new variant,
+/// so that the proofs made with the old ones keep their meaning.
+#[derive(Clone, Debug, Encode, Decode, PartialEq, Eq, TypeInfo)]
+pub enum VersionedUnit {
+    /// A full unit of AlephBFT 0.15. It is encoded as its creator, round, control hash, data and
+    /// session, and the control hash and data are kept encoded.
+    V1 {
+        creator: u64,
+        round: u16,
+        control_hash_and_data: Vec<u8>,
+        session: u64,
+    },
+}
+
+impl VersionedUnit {
+    pub fn coord(&self) -> UnitCoord {
+        match self {
+            VersionedUnit::V1 {
+                creator,
+                round,
+                session,
+                ..
+            } => UnitCoord {
+                creator: *creator,
+                round: *round,
+                session: *session,
+            },
+        }
+    }
+
+    /// The unit encoded the way AlephBFT encodes it.
+    pub fn aleph_bft_encoding(&self) -> Vec<u8> {
+        match self {
+            VersionedUnit::V1 {
+                creator,
+                round,
+                control_hash_and_data,
+                session,
+            } => {
+                let mut encoded = (creator, round).encode();
+                encoded.extend_from_slice(control_hash_and_data);
+                session.encode_to(&mut encoded);
+                encoded
+            }
+        }
+    }
+
+    /// The bytes the creator signs. AlephBFT signs the hash of the encoded unit.
+    pub fn signing_payload(&self) -> Vec<u8> {
+        aleph_bft_signing_payload(BlakeTwo256::hash(&self.aleph_bft_encoding()).as_ref())
+    }
+}
+
+/// An AlephBFT unit together with the signature of its creator.
+#[derive(Clone, Debug, Encode, Decode, PartialEq, Eq, TypeInfo)]
+pub struct SignedUnit<Signature> {
+    pub unit: VersionedUnit,
+    pub signature: Signature,
+}
+
+/// Two different units created by the same committee member for the same round of a session.
+#[derive(Clone, Debug, Encode, Decode, PartialEq, Eq, TypeInfo)]
+pub struct EquivocationProof<Signature> {
+    pub first: SignedUnit<Signature>,
+    pub second: SignedUnit<Signature>,
+}
+
+impl<Signature> EquivocationProof<Signature> {
+    /// Returns the common coordinates of both units, if they are different units for the same slot.
+    /// The signatures are not checked.
+    pub fn coord(&self) -> Option<UnitCoord> {
+        let coord = self.first.unit.coord();
+        match self.second.unit.coord() == coord
+            && self.first.unit.aleph_bft_encoding() != self.second.unit.aleph_bft_encoding()
+        {
+            true => Some(coord),
+            false => None,
+        }
+    }
+}
+
+/// Network addresses of a validator, registered on chain so that the committee can connect to it
+/// before learning about it through gossip.
+#[derive(Clone, Debug, Encode, Decode, PartialEq, Eq, TypeInfo)]
+pub struct ValidatorAddresses {
+    /// The addresses, each encoded as the finality gadget encodes them in authentications.
+    pub addresses: Vec<Vec<u8>>,
+    /// The session in which the addresses are registered.
+    pub session: SessionIndex,
+    /// Orders registrations within the session. A registration replaces only ones from earlier
+    /// sessions or with a lower nonce, so old registrations cannot be replayed.
+    pub nonce: u32,
+}
+
+impl ValidatorAddresses {
+    const CONTEXT: &'static [u8] = b"aleph-validator-addresses";
+
+    /// The bytes the validator signs with its authority key. The context makes them differ from
+    /// anything else signed with that key.
+    pub fn signing_payload(&self) -> Vec<u8> {
+        (Self::CONTEXT, self).encode()
+    }
+
+    /// There is at least one address, and neither the number of addresses nor their length
+    /// exceeds the limits.
+    pub fn is_valid(&self) -> bool {
+        !self.addresses.is_empty()
+            && self.addresses.len() <= MAX_VALIDATOR_ADDRESSES
+            && self
+                .addresses
+                .iter()
+                .all(|address| address.len() <= MAX_VALIDATOR_ADDRESS_LEN)
+    }
+}
+
+/// Validator addresses signed with the authority key of the validator.
+#[derive(Clone, Debug, Encode, Decode, PartialEq, Eq, TypeInfo)]
+pub struct SignedValidatorAddresses<Signature> {
+    pub addresses: ValidatorAddresses,
+    pub signature: Signature,
+}
+
+/// Something that can keep validators out of future committees.
+pub trait BanHandler {
+    type AccountId;
+    /// Bans the validator from being chosen for committees for a while.
+    fn ban_validator(validator: &Self::AccountId);
 }
 
 sp_api::decl_runtime_apis! {
+    #[api_version(2)]
     pub trait AlephSessionApi
     {
         fn next_session_authorities() -> Result<Vec<AuthorityId>, ApiError>;
@@ -86,6 +415,31 @@ sp_api::decl_runtime_apis! {
         fn authority_data() -> SessionAuthorityData;
         fn session_period() -> u32;
         fn millisecs_per_block() -> u64;
+        /// Submits an unsigned extrinsic reporting the equivocation. Should only be called by
+        /// nodes, as the extrinsic is submitted to the local transaction pool.
+        fn submit_report_equivocation_unsigned_extrinsic(
+            equivocation_proof: EquivocationProof<AuthoritySignature>,
+        ) -> Option<()>;
+        /// The finality parameters in force in the next session, read from the state of the last
+        /// block of the current one. `None` if they were never set, in which case nodes use their
+        /// local configuration.
+        fn next_session_finality_params() -> Option<FinalityParameters>;
+        /// The network addresses registered by the authorities of the current and next session.
+        fn validator_addresses() -> Vec<(AuthorityId, SignedValidatorAddresses<AuthoritySignature>)>;
+        /// Submits an unsigned extrinsic registering the addresses of the authority. Should only
+        /// be called by nodes, as the extrinsic is submitted to the local transaction pool.
+        fn submit_validator_addresses_unsigned_extrinsic(
+            authority: AuthorityId,
+            addresses: SignedValidatorAddresses<AuthoritySignature>,
+        ) -> Option<()>;
+        /// The BLS key registered by the authority, if any.
+        fn registered_bls_key(authority: AuthorityId) -> Option<BlsKey>;
+        /// Submits an unsigned extrinsic registering the BLS key of the authority. Should only be
+        /// called by nodes, as the extrinsic is submitted to the local transaction pool.
+        fn submit_bls_key_unsigned_extrinsic(
+            authority: AuthorityId,
+            registration: SignedBlsKeyRegistration<AuthoritySignature>,
+        ) -> Option<()>;
     }
 }
 